@@ -0,0 +1,324 @@
+//! API认证与角色权限
+//!
+//! 客户端通过 `Authorization: Bearer <key>` 或 `X-API-Key` 请求头携带API Key，
+//! EventSource 和 WebSocket 无法设置请求头时可以使用 `?api_key=` 查询参数。
+//! 配置中只保存Key的SHA-256摘要（`auth.api_keys`），WebUI密钥启动时在内存中摘要后同样可用。
+//!
+//! 角色从低到高为 viewer（查看状态和事件流）、operator（调用工具、聊天）、admin（设备和系统管理类工具）。
+//! 读请求（GET）需要viewer，写请求需要operator；工具另按 `auth.tool_roles` 和Key的 `allowed_tools` 判断。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use axum::http::{HeaderMap, Method};
+use once_cell::sync::Lazy;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::config::{AuthConfig, CONFIG};
+
+/// WebUI密钥的默认占位值，未修改时不作为API Key使用
+const PLACEHOLDER_SECRET: &str = "change-this-in-production";
+
+/// 角色，按权限从低到高排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// 通过认证的调用方
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Key名称，用于日志
+    pub name: String,
+    pub role: Role,
+    /// 可使用的工具，`None` 表示只按角色判断
+    allowed_tools: Option<HashSet<String>>,
+}
+
+impl Principal {
+    pub fn new(name: impl Into<String>, role: Role) -> Self {
+        Self { name: name.into(), role, allowed_tools: None }
+    }
+
+    /// 限制可使用的工具
+    pub fn with_allowed_tools(mut self, tools: impl IntoIterator<Item = String>) -> Self {
+        self.allowed_tools = Some(tools.into_iter().collect());
+        self
+    }
+
+    /// 认证关闭时的调用方，拥有全部权限
+    pub fn anonymous() -> Self {
+        Self::new("anonymous", Role::Admin)
+    }
+}
+
+/// 认证或授权失败
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum AuthError {
+    #[error("缺少API Key，请通过 Authorization: Bearer <key> 提供")]
+    MissingToken,
+    #[error("API Key无效")]
+    InvalidToken,
+    #[error("需要 {required} 角色，当前为 {actual}")]
+    InsufficientRole { required: Role, actual: Role },
+    #[error("API Key {name} 无权使用工具 {tool}")]
+    ToolForbidden { name: String, tool: String },
+}
+
+impl AuthError {
+    /// 未认证（401）还是无权限（403）
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, AuthError::MissingToken | AuthError::InvalidToken)
+    }
+
+    /// 响应中的错误码
+    pub fn error_code(&self) -> &'static str {
+        if self.is_unauthorized() { "UNAUTHORIZED" } else { "FORBIDDEN" }
+    }
+}
+
+/// 计算API Key的SHA-256摘要（十六进制），用于填写 `auth.api_keys[].key_sha256`
+pub fn hash_api_key(key: &str) -> String {
+    digest(&SHA256, key.as_bytes()).as_ref().iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// 常量时间比较，避免通过响应时间猜测摘要
+fn digests_equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// 认证器：校验API Key并判断角色和工具权限
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    enabled: bool,
+    keys: Vec<(Vec<u8>, Principal)>,
+    tool_roles: HashMap<String, Role>,
+    public_paths: Vec<String>,
+}
+
+impl Authenticator {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, keys: Vec::new(), tool_roles: HashMap::new(), public_paths: Vec::new() }
+    }
+
+    /// 添加API Key，`key_sha256` 为十六进制摘要
+    pub fn with_key(mut self, key_sha256: &str, principal: Principal) -> Self {
+        match decode_hex(key_sha256).filter(|digest| digest.len() == 32) {
+            Some(digest) => self.keys.push((digest, principal)),
+            None => warn!("API Key {} 的 key_sha256 不是有效的SHA-256摘要，已忽略", principal.name),
+        }
+        self
+    }
+
+    /// 设置工具需要的最低角色，未设置的工具需要operator
+    pub fn with_tool_role(mut self, tool: impl Into<String>, role: Role) -> Self {
+        self.tool_roles.insert(tool.into(), role);
+        self
+    }
+
+    /// 设置不需要认证的路径
+    pub fn with_public_paths(mut self, paths: impl IntoIterator<Item = String>) -> Self {
+        self.public_paths.extend(paths);
+        self
+    }
+
+    /// 从配置创建，`webui_secret` 为WebUI使用的明文密钥
+    pub fn from_config(config: &AuthConfig, webui_secret: Option<&str>) -> Self {
+        let mut authenticator = Self::new(config.enabled)
+            .with_public_paths(config.public_paths.iter().cloned());
+        for (tool, role) in &config.tool_roles {
+            authenticator = authenticator.with_tool_role(tool.clone(), *role);
+        }
+        for key in &config.api_keys {
+            let mut principal = Principal::new(key.name.clone(), key.role);
+            if let Some(tools) = &key.allowed_tools {
+                principal = principal.with_allowed_tools(tools.iter().cloned());
+            }
+            authenticator = authenticator.with_key(&key.key_sha256, principal);
+        }
+        match webui_secret.map(str::trim).filter(|secret| !secret.is_empty()) {
+            Some(PLACEHOLDER_SECRET) if config.enabled => warn!("WebUI密钥仍为默认值，未作为API Key启用"),
+            Some(PLACEHOLDER_SECRET) | None => {},
+            Some(secret) => {
+                authenticator = authenticator.with_key(&hash_api_key(secret), Principal::new("webui", config.webui_role));
+            },
+        }
+        authenticator
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 请求需要的最低角色，`None` 表示不需要认证
+    pub fn required_role(&self, method: &Method, path: &str) -> Option<Role> {
+        if !self.enabled || method == Method::OPTIONS || self.public_paths.iter().any(|public| public == path) {
+            return None;
+        }
+        match *method {
+            Method::GET | Method::HEAD => Some(Role::Viewer),
+            _ => Some(Role::Operator),
+        }
+    }
+
+    /// 校验API Key；认证关闭时返回拥有全部权限的匿名调用方
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Principal, AuthError> {
+        if !self.enabled {
+            return Ok(Principal::anonymous());
+        }
+        let token = token.map(str::trim).filter(|token| !token.is_empty()).ok_or(AuthError::MissingToken)?;
+        let presented = digest(&SHA256, token.as_bytes());
+        self.keys.iter()
+            .find(|(digest, _)| digests_equal(digest, presented.as_ref()))
+            .map(|(_, principal)| principal.clone())
+            .ok_or(AuthError::InvalidToken)
+    }
+
+    /// 检查角色
+    pub fn authorize(&self, principal: &Principal, required: Role) -> Result<(), AuthError> {
+        if principal.role >= required {
+            Ok(())
+        } else {
+            Err(AuthError::InsufficientRole { required, actual: principal.role })
+        }
+    }
+
+    /// 检查工具权限：角色满足工具的最低角色，且在Key的工具列表内
+    pub fn authorize_tool(&self, principal: &Principal, tool: &str) -> Result<(), AuthError> {
+        let required = self.tool_roles.get(tool).copied().unwrap_or(Role::Operator);
+        self.authorize(principal, required)?;
+        match &principal.allowed_tools {
+            Some(tools) if !tools.contains(tool) => Err(AuthError::ToolForbidden {
+                name: principal.name.clone(),
+                tool: tool.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+static AUTHENTICATOR: Lazy<Authenticator> = Lazy::new(|| {
+    let webui_secret = std::env::var(&CONFIG.env_keys.webui_secret)
+        .unwrap_or_else(|_| CONFIG.webui.default_secret_key.clone());
+    Authenticator::from_config(&CONFIG.auth, Some(&webui_secret))
+});
+
+/// 全局认证器（按 `CONFIG.auth` 创建）
+pub fn authenticator() -> &'static Authenticator {
+    &AUTHENTICATOR
+}
+
+/// 从请求头或查询参数中取出API Key
+///
+/// 查询参数 `api_key` 按 `application/x-www-form-urlencoded` 解码。
+pub fn extract_token(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    let from_header = headers.get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer ").or_else(|| value.strip_prefix("bearer ")))
+        .or_else(|| headers.get("x-api-key").and_then(|value| value.to_str().ok()));
+    if let Some(token) = from_header {
+        return Some(token.trim().to_string());
+    }
+    form_urlencoded::parse(query?.as_bytes())
+        .find(|(name, _)| name == "api_key")
+        .map(|(_, value)| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(true)
+            .with_public_paths(["/health".to_string()])
+            .with_tool_role("maa_system_management", Role::Admin)
+            .with_key(&hash_api_key("viewer-key"), Principal::new("dashboard", Role::Viewer))
+            .with_key(&hash_api_key("bot-key"), Principal::new("bot", Role::Operator)
+                .with_allowed_tools(["maa_take_screenshot".to_string()]))
+            .with_key(&hash_api_key("admin-key"), Principal::new("root", Role::Admin))
+    }
+
+    #[test]
+    fn test_hash_api_key() {
+        assert_eq!(hash_api_key("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(decode_hex(&hash_api_key("abc")).unwrap().len(), 32);
+        assert!(decode_hex("zz").is_none());
+    }
+
+    #[test]
+    fn test_authenticate_and_roles() {
+        let auth = authenticator();
+        assert_eq!(auth.authenticate(None).unwrap_err(), AuthError::MissingToken);
+        assert_eq!(auth.authenticate(Some("nope")).unwrap_err(), AuthError::InvalidToken);
+        let viewer = auth.authenticate(Some("viewer-key")).unwrap();
+        assert_eq!(viewer.role, Role::Viewer);
+
+        assert_eq!(auth.required_role(&Method::GET, "/health"), None);
+        assert_eq!(auth.required_role(&Method::GET, "/sse/tasks"), Some(Role::Viewer));
+        assert_eq!(auth.required_role(&Method::POST, "/call"), Some(Role::Operator));
+        assert!(auth.authorize(&viewer, Role::Viewer).is_ok());
+        assert!(auth.authorize(&viewer, Role::Operator).is_err());
+
+        assert_eq!(Authenticator::new(false).authenticate(None).unwrap().role, Role::Admin);
+        assert_eq!(Authenticator::new(false).required_role(&Method::POST, "/call"), None);
+    }
+
+    #[test]
+    fn test_tool_permissions() {
+        let auth = authenticator();
+        let viewer = auth.authenticate(Some("viewer-key")).unwrap();
+        let bot = auth.authenticate(Some("bot-key")).unwrap();
+        let admin = auth.authenticate(Some("admin-key")).unwrap();
+
+        assert!(auth.authorize_tool(&viewer, "maa_take_screenshot").is_err());
+        assert!(auth.authorize_tool(&bot, "maa_take_screenshot").is_ok());
+        assert_eq!(
+            auth.authorize_tool(&bot, "maa_combat_enhanced").unwrap_err().error_code(),
+            "FORBIDDEN"
+        );
+        assert!(auth.authorize_tool(&admin, "maa_system_management").is_ok());
+        assert!(matches!(
+            auth.authorize_tool(&Principal::new("ops", Role::Operator), "maa_system_management"),
+            Err(AuthError::InsufficientRole { required: Role::Admin, .. })
+        ));
+    }
+
+    #[test]
+    fn test_extract_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_token(&headers, Some("task_type=Fight&api_key=abc")), Some("abc".to_string()));
+        assert_eq!(extract_token(&headers, Some("api_key=a%2Bb%3D%26c+d")), Some("a+b=&c d".to_string()));
+        headers.insert("x-api-key", "from-header".parse().unwrap());
+        assert_eq!(extract_token(&headers, Some("api_key=abc")), Some("from-header".to_string()));
+        headers.insert("authorization", "Bearer bearer-key".parse().unwrap());
+        assert_eq!(extract_token(&headers, None), Some("bearer-key".to_string()));
+    }
+}
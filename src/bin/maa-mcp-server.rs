@@ -0,0 +1,104 @@
+//! MAA MCP 服务器（stdio 传输）
+//!
+//! 供 Claude Desktop、IDE 等 MCP 客户端以子进程方式启动：
+//! 标准输入每行一条 JSON-RPC 消息，响应写到标准输出，日志写到标准错误。
+//! 工具调用与 `maa-optimized-server` 一样经由任务队列交给 MAA 工作者执行。
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{error, info, warn, Level};
+
+use maa_intelligent_server::config::CONFIG;
+use maa_intelligent_server::copilot_matcher::create_copilot_matcher;
+use maa_intelligent_server::copilot_matcher::feedback::{FeedbackStore, set_global_feedback_store};
+use maa_intelligent_server::function_tools::create_enhanced_function_handler_v2;
+use maa_intelligent_server::maa_core::{
+    create_maa_task_channel_v2, init_task_notification_system, run_supervised_worker, MaaWorkerV2,
+};
+use maa_intelligent_server::mcp::McpServer;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // MaaCore不是Send，工作者需要在LocalSet中运行
+    let local = tokio::task::LocalSet::new();
+    local.run_until(run_stdio_server()).await
+}
+
+async fn run_stdio_server() -> Result<()> {
+    if let Err(e) = dotenvy::dotenv() {
+        eprintln!("Warning: 无法加载 .env 文件: {}", e);
+    }
+
+    // 标准输出留给协议消息，日志只能写到标准错误
+    tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let _task_event_receiver = init_task_notification_system();
+    let (task_sender, task_receiver) = create_maa_task_channel_v2();
+    let (maa_worker, event_broadcaster) = MaaWorkerV2::new();
+    maa_intelligent_server::maa_core::set_global_sse_broadcaster(event_broadcaster);
+
+    match FeedbackStore::open(&CONFIG.copilot.feedback_db_path) {
+        Ok(store) => set_global_feedback_store(Arc::new(store)),
+        Err(e) => warn!("作业反馈存储初始化失败，通关结果不会被记录: {}", e),
+    }
+
+    tokio::task::spawn_local(async move {
+        run_supervised_worker(maa_worker, task_receiver).await;
+    });
+
+    let mut handler = create_enhanced_function_handler_v2(task_sender);
+    match create_copilot_matcher().await {
+        Ok(matcher) => handler = handler.with_copilot_matcher(Arc::new(matcher)),
+        Err(e) => warn!("作业匹配器初始化失败，maa_copilot_match 不可用: {}", e),
+    }
+    let server = McpServer::new(handler);
+    info!("MAA MCP服务器（stdio）已启动");
+
+    // 响应统一由写入任务输出，耗时的工具调用不阻塞后续消息
+    let (outgoing_tx, mut outgoing_rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+    let writer = tokio::task::spawn_local(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = outgoing_rx.recv().await {
+            let mut line = message.to_string();
+            line.push('\n');
+            if stdout.write_all(line.as_bytes()).await.is_err() || stdout.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                error!("读取标准输入失败: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let server = server.clone();
+        let outgoing_tx = outgoing_tx.clone();
+        tokio::task::spawn_local(async move {
+            if let Some(response) = server.handle_text(&line).await {
+                let _ = outgoing_tx.send(response);
+            }
+        });
+    }
+
+    // 标准输入关闭即客户端退出，等待已发出的响应写完
+    drop(outgoing_tx);
+    let _ = writer.await;
+    info!("MCP客户端已断开，服务器退出");
+    Ok(())
+}
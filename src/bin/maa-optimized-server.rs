@@ -14,8 +14,11 @@ use axum::{
     response::{Json, IntoResponse, Sse},
     routing::{get, post},
     Router,
-    extract::{State, Path},
+    extract::{State, Path, Query, Request, Extension},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
 };
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::net::TcpListener;
@@ -31,7 +34,14 @@ use maa_intelligent_server::function_tools::{
     FunctionCall,
     // V2优化版Handler - 减少JSON序列化
     create_enhanced_function_handler_v2,
-    EnhancedMaaFunctionHandlerV2
+    EnhancedMaaFunctionHandlerV2,
+    handler_v2::{CONFIRMATION_REQUIRED_ERROR_CODE, FORBIDDEN_ERROR_CODE, QUEUE_FULL_ERROR_CODE, TOO_MANY_REQUESTS_ERROR_CODE},
+    FunctionResponse,
+    // 高风险操作二次确认
+    confirmation::{self, ChatDecision, ConfirmationError},
+    // 工作流（任务依赖图）
+    WorkflowEngine, WorkflowSpec,
+    workflow
 };
 use maa_intelligent_server::maa_core::{
     // V2组件 - 真正的优化架构
@@ -39,17 +49,46 @@ use maa_intelligent_server::maa_core::{
     // 保留的通知系统
     init_task_notification_system,
     // 任务分类
-    task_classification_v2::is_synchronous_task
+    task_classification_v2::is_synchronous_task,
+    // 任务状态（按队列任务ID）
+    task_status, task_mapping, TaskWaitOutcome,
+    // 设备连接健康状态
+    device_health,
+    // 工作者监督
+    run_supervised_worker, worker_health
 };
 use maa_intelligent_server::config::CONFIG;
+use maa_intelligent_server::auth::{authenticator, extract_token, AuthError, Principal, Role};
+use maa_intelligent_server::copilot_matcher::create_copilot_matcher;
+use maa_intelligent_server::copilot_matcher::feedback::{FeedbackStore, set_global_feedback_store};
 use maa_intelligent_server::ai_client::{AiClient, AiClientConfig, AiProvider, ProviderConfig, AiClientTrait, ChatMessage as AiChatMessage, Tool, FunctionCall as MaaFunctionCall};
-use maa_intelligent_server::sse::{SseManager, create_task_progress_sse, create_single_task_sse};
+use maa_intelligent_server::sse::{SseManager, EventFilter, SseFilterQuery, create_task_progress_sse, create_single_task_sse, last_event_id};
+use maa_intelligent_server::ws::{self, RpcChat, RpcError, WsSession};
+use maa_intelligent_server::mcp::McpServer;
+use maa_intelligent_server::openai_compat::{
+    self, ChatCompletionRequest, completion_chunk, completion_id, completion_response, run_tool_loop, split_content
+};
 use maa_intelligent_server::ai_client::client::Either;
 
 /// Function Calling 请求格式
 #[derive(Debug, Deserialize)]
 struct FunctionCallRequest {
     function_call: FunctionCall,
+    /// 是否等待异步任务结束后再返回
+    #[serde(default)]
+    wait: bool,
+    /// 等待超时（秒），默认按任务预估耗时推导
+    wait_timeout: Option<u64>,
+    /// 只做检查并返回将要提交的MAA任务和参数，不入队
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// 任务等待参数
+#[derive(Debug, Deserialize)]
+struct TaskWaitQuery {
+    /// 等待超时（秒），不超过任务类型的最长等待时间
+    timeout: Option<u64>,
 }
 
 /// 聊天请求格式
@@ -60,6 +99,9 @@ struct ChatRequest {
     tools: Option<Vec<serde_json::Value>>,
     #[allow(dead_code)] 
     system_prompt: Option<String>,
+    /// 试运行会话：工具调用只做检查并返回将要提交的MAA任务和参数，不入队
+    #[serde(default)]
+    dry_run: bool,
 }
 
 /// 聊天消息格式
@@ -75,8 +117,27 @@ struct AppStateV2 {
     enhanced_handler: EnhancedMaaFunctionHandlerV2,
     #[allow(dead_code)] ai_client: Arc<AiClient>,
     sse_manager: SseManager,
-    /// 任务发送器的引用，用于直接访问
-    #[allow(dead_code)] task_sender: MaaTaskSenderV2,
+    workflow_engine: WorkflowEngine,
+    /// 任务发送器的引用，用于查询排队情况
+    task_sender: MaaTaskSenderV2,
+}
+
+impl AppStateV2 {
+    /// 按调用方权限执行工具的状态副本
+    fn for_principal(&self, principal: Principal) -> Self {
+        Self {
+            enhanced_handler: self.enhanced_handler.clone().with_principal(principal),
+            ..self.clone()
+        }
+    }
+    
+    /// 设置处理器是否为试运行会话
+    fn with_dry_run(&self, dry_run: bool) -> Self {
+        Self {
+            enhanced_handler: self.enhanced_handler.clone().with_dry_run(dry_run),
+            ..self.clone()
+        }
+    }
 }
 
 #[tokio::main]
@@ -97,7 +158,9 @@ async fn run_server() -> Result<()> {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             eprintln!("Panic消息: {}", s);
         }
-        eprintln!("优化服务器将退出");
+        if maa_intelligent_server::maa_core::supervisor::in_supervised_worker() {
+            eprintln!("panic发生在MAA工作者中，监督者会将运行中的任务标记为失败并重启工作者");
+        }
     }));
     
     // 加载 .env 配置文件
@@ -127,23 +190,41 @@ async fn run_server() -> Result<()> {
     
     // 创建真正的SSE管理器（连接到工作者事件）
     let sse_manager = SseManager::new(event_broadcaster.clone());
+    // 任务状态变更统一经由状态存储发布，转发到SSE流
+    sse_manager.spawn_task_status_bridge();
     // SSE管理器创建完成
     
     // 设置全局SSE广播器，让MAA Core回调能转发到SSE
     maa_intelligent_server::maa_core::set_global_sse_broadcaster(event_broadcaster.clone());
     // MAA Core回调转发配置完成
     
+    // 打开作业反馈存储，让作业通关结果参与作业排序
+    match FeedbackStore::open(&CONFIG.copilot.feedback_db_path) {
+        Ok(store) => set_global_feedback_store(Arc::new(store)),
+        Err(e) => warn!("作业反馈存储初始化失败，通关结果不会被记录: {}", e),
+    }
+    
     // 启动MAA工作线程V2（解决Send问题，使用task::spawn_local）
+    // 工作者退出或panic时由监督者重建MaaCore和工作者，继续消费同一个任务队列
     tokio::task::spawn_local(async move {
-        maa_worker.run(task_receiver).await;
+        run_supervised_worker(maa_worker, task_receiver).await;
     });
     
     // MAA工作线程V2启动完成
     
     // 使用V2优化版处理器 - 减少JSON序列化
-    let enhanced_handler = create_enhanced_function_handler_v2(task_sender.clone());
+    let mut enhanced_handler = create_enhanced_function_handler_v2(task_sender.clone());
+    // 作业匹配器使用上面打开的反馈存储，提供 maa_copilot_match 工具
+    match create_copilot_matcher().await {
+        Ok(matcher) => enhanced_handler = enhanced_handler.with_copilot_matcher(Arc::new(matcher)),
+        Err(e) => warn!("作业匹配器初始化失败，maa_copilot_match 不可用: {}", e),
+    }
     // Function Calling处理器V2创建完成
     
+    // 工作流节点经由同一处理器提交，节点事件推送到SSE
+    let workflow_engine = WorkflowEngine::new(enhanced_handler.clone())
+        .with_event_sender(event_broadcaster.clone());
+    
     // 创建AI客户端
     let ai_client = match AiClient::from_env() {
         Ok(client) => {
@@ -166,6 +247,7 @@ async fn run_server() -> Result<()> {
         enhanced_handler,
         ai_client: Arc::new(ai_client),
         sse_manager,
+        workflow_engine,
         task_sender,
     };
 
@@ -182,19 +264,41 @@ async fn run_server() -> Result<()> {
         .route("/chat", post(chat_handler))
         .route("/chat/reset", post(reset_chat_handler))
         
+        // OpenAI兼容端点
+        .route("/v1/chat/completions", post(openai_chat_completions_handler))
+        .route("/v1/models", get(openai_models_handler))
+        
         // 新增SSE端点
         .route("/sse/tasks", get(sse_all_tasks_handler))
         .route("/sse/task/{task_id}", get(sse_single_task_handler))
         .route("/sse/test", post(sse_test_handler))
         
+        // WebSocket JSON-RPC端点
+        .route("/ws", get(ws_handler))
+        
         // 任务状态查询端点（优化版）
         .route("/task/{task_id}/status", get(task_status_handler_v2))
+        .route("/task/{task_id}/wait", get(task_wait_handler))
         .route("/tasks", get(all_tasks_handler_v2))
+        .route("/queue", get(queue_handler))
+        
+        // 待确认操作端点
+        .route("/actions", get(pending_actions_handler))
+        .route("/actions/{token}/confirm", post(confirm_action_handler))
+        .route("/actions/{token}/cancel", post(cancel_action_handler))
+        
+        // MCP端点（streamable HTTP）
+        .route("/mcp", post(mcp_handler).get(mcp_stream_handler))
+        
+        // 工作流端点
+        .route("/workflows", post(submit_workflow_handler).get(all_workflows_handler))
+        .route("/workflows/{workflow_id}", get(workflow_status_handler))
         
         // 优化统计端点
         .route("/optimization/stats", get(optimization_stats_handler))
         
         .with_state(app_state)
+        .layer(middleware::from_fn(auth_middleware))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -209,6 +313,9 @@ async fn run_server() -> Result<()> {
         .unwrap_or_else(|_| CONFIG.server.default_port.parse().unwrap_or(8080));
 
     let addr = CONFIG.server.bind_address(Some(&port.to_string()));
+    if !authenticator().is_enabled() {
+        warn!("API认证未启用，所有请求均拥有管理员权限（auth.enabled = false）");
+    }
     warn!("✅ MAA优化服务器V2已启动 - http://localhost:{}", port);
 
     let listener = TcpListener::bind(&addr).await?;
@@ -227,6 +334,46 @@ async fn run_server() -> Result<()> {
     }
 }
 
+/// 认证中间件：校验API Key和请求需要的角色，通过后把调用方放入请求扩展
+async fn auth_middleware(mut request: Request, next: Next) -> axum::response::Response {
+    let auth = authenticator();
+    let required = auth.required_role(request.method(), request.uri().path());
+    let token = extract_token(request.headers(), request.uri().query());
+    let result = match required {
+        Some(role) => auth.authenticate(token.as_deref())
+            .and_then(|principal| auth.authorize(&principal, role).map(|_| principal)),
+        // 公开路径不要求Key，未携带有效Key时只有查看权限
+        None => Ok(auth.authenticate(token.as_deref())
+            .unwrap_or_else(|_| Principal::new("public", Role::Viewer))),
+    };
+    match result {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        },
+        Err(e) => {
+            // 只记录路径：查询参数中可能带有API Key
+            warn!("拒绝请求 {} {}: {}", request.method(), request.uri().path(), e);
+            auth_error_response(&e)
+        }
+    }
+}
+
+/// 401/403 响应，沿用统一的错误格式
+fn auth_error_response(error: &AuthError) -> axum::response::Response {
+    let body = Json(json!({
+        "success": false,
+        "error": error.to_string(),
+        "error_code": error.error_code(),
+        "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }));
+    if error.is_unauthorized() {
+        (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response()
+    } else {
+        (StatusCode::FORBIDDEN, body).into_response()
+    }
+}
+
 /// 根路径处理器
 async fn root_handler() -> impl IntoResponse {
     Json(json!({
@@ -245,8 +392,19 @@ async fn root_handler() -> impl IntoResponse {
             "tools": &CONFIG.server.tools_path, 
             "call": &CONFIG.server.call_path,
             "status": &CONFIG.server.status_path,
-            "sse_all_tasks": "/sse/tasks",
+            "sse_all_tasks": "/sse/tasks?task_type=&event_type=&device=&min_severity=&task_ids=&compact=",
             "sse_single_task": "/sse/task/{task_id}",
+            "websocket": "/ws",
+            "openai_chat_completions": "/v1/chat/completions",
+            "openai_models": "/v1/models",
+            "mcp": "/mcp",
+            "pending_actions": "/actions",
+            "confirm_action": "/actions/{token}/confirm",
+            "task_status": "/task/{task_id}/status",
+            "task_wait": "/task/{task_id}/wait?timeout=",
+            "queue": "/queue",
+            "workflows": "/workflows",
+            "workflow_status": "/workflows/{workflow_id}",
             "optimization_stats": "/optimization/stats"
         },
         "features": {
@@ -264,7 +422,11 @@ async fn health_handler(
     State(state): State<AppStateV2>
 ) -> impl IntoResponse {
     // 获取原始的处理器状态（这个会自动初始化MAA连接）
-    let handler_status = state.enhanced_handler.get_server_status().await;
+    let mut handler_status = state.enhanced_handler.get_server_status().await;
+    // 附加工作者存活状态和重启次数
+    if let Some(status) = handler_status.as_object_mut() {
+        status.insert("worker".to_string(), json!(worker_health()));
+    }
     Json(handler_status)
 }
 
@@ -301,17 +463,34 @@ async fn tools_handler(
 /// Function Calling 处理器（优化版）
 async fn call_handler(
     State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
     Json(request): Json<FunctionCallRequest>
 ) -> impl IntoResponse {
+    let state = state.for_principal(principal);
     debug!("收到优化版Function Call: {} with args: {}", request.function_call.name, request.function_call.arguments);
     
     // 检查任务类型
     let is_sync = is_synchronous_task(&request.function_call.name);
     debug!("任务类型: {} (同步: {})", request.function_call.name, is_sync);
     
+    if request.dry_run {
+        let response = state.enhanced_handler.dry_run(request.function_call).await;
+        return call_response(response, is_sync, false);
+    }
+    
     // 使用优化版处理器执行Function Call
-    let response = state.enhanced_handler.execute_function(request.function_call).await;
+    let waited = request.wait && !is_sync;
+    let response = if waited {
+        state.enhanced_handler.execute_function_and_wait(request.function_call, request.wait_timeout).await
+    } else {
+        state.enhanced_handler.execute_function(request.function_call).await
+    };
     
+    call_response(response, is_sync, waited)
+}
+
+/// Function Call结果的HTTP响应，`/call` 和确认待确认操作共用
+fn call_response(response: FunctionResponse, is_sync: bool, waited: bool) -> axum::response::Response {
     match response.success {
         true => {
             debug!("优化版Function call成功");
@@ -321,7 +500,8 @@ async fn call_handler(
                 "timestamp": response.timestamp,
                 "backend": "optimized-v2",
                 "execution_mode": if is_sync { "synchronous" } else { "asynchronous" },
-                "sse_info": if !is_sync { 
+                "waited": waited,
+                "sse_info": if !is_sync && !waited { 
                     Some(json!({
                         "message": "异步任务已启动，进度将通过SSE推送",
                         "sse_endpoint": "/sse/tasks"
@@ -329,35 +509,69 @@ async fn call_handler(
                 } else { 
                     None 
                 }
-            }))
+            })).into_response()
         }
         false => {
             error!("优化版Function call失败: {:?}", response.error);
-            Json(json!({
+            let error_code = response.error.as_ref().and_then(|e| e.error_code.clone());
+            let retry_after = response.result.as_ref()
+                .and_then(|r| r.get("retry_after_secs"))
+                .and_then(|v| v.as_u64());
+            let body = Json(json!({
                 "success": false,
                 "error": response.error.map(|e| e.message).unwrap_or("Unknown error".to_string()),
+                "error_code": error_code,
+                "result": response.result,
                 "timestamp": response.timestamp,
                 "backend": "optimized-v2"
-            }))
+            }));
+            
+            // 队列满或并发超限：429 + Retry-After
+            match (error_code.as_deref(), retry_after) {
+                (Some(QUEUE_FULL_ERROR_CODE | TOO_MANY_REQUESTS_ERROR_CODE), Some(retry_after)) => (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    body,
+                ).into_response(),
+                (Some(FORBIDDEN_ERROR_CODE), _) => (StatusCode::FORBIDDEN, body).into_response(),
+                // 已登记为待确认操作，尚未执行
+                (Some(CONFIRMATION_REQUIRED_ERROR_CODE), _) => (StatusCode::ACCEPTED, body).into_response(),
+                _ => body.into_response(),
+            }
         }
     }
 }
 
 /// SSE所有任务处理器
+///
+/// 查询参数过滤推送的事件，见 `SseFilterQuery`。
 async fn sse_all_tasks_handler(
     State(state): State<AppStateV2>,
-) -> Sse<impl tokio_stream::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>> + Send + 'static> {
+    Query(query): Query<SseFilterQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
     info!("🔗 前端客户端连接到所有任务SSE流 (/sse/tasks)");
-    create_task_progress_sse(state.sse_manager)
+    match EventFilter::from_query(&query) {
+        Ok(filter) => create_task_progress_sse(state.sse_manager, last_event_id(&headers), filter).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "success": false,
+                "error": e.to_string(),
+                "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+            })),
+        ).into_response(),
+    }
 }
 
 /// SSE单个任务处理器
 async fn sse_single_task_handler(
     State(state): State<AppStateV2>,
     Path(task_id): Path<i32>,
+    headers: HeaderMap,
 ) -> Sse<impl tokio_stream::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>> + Send + 'static> {
     info!("客户端连接到任务 {} 的SSE流", task_id);
-    create_single_task_sse(state.sse_manager, task_id)
+    create_single_task_sse(state.sse_manager, task_id, last_event_id(&headers))
 }
 
 /// SSE测试处理器 - 用于前后端联调测试
@@ -371,10 +585,18 @@ async fn sse_test_handler(
     State(state): State<AppStateV2>,
     Json(request): Json<SseTestRequest>,
 ) -> Json<serde_json::Value> {
-    use maa_intelligent_server::maa_core::worker_v2::TaskProgressEvent;
+    use maa_intelligent_server::maa_core::worker_v2::{TaskEventType, TaskProgressEvent};
     use chrono::Utc;
     
-    let event_type = request.event_type.clone().unwrap_or_else(|| "test".to_string());
+    let requested_type = request.event_type.clone().unwrap_or_else(|| "test".to_string());
+    let Some(event_type) = TaskEventType::parse(&requested_type) else {
+        return Json(json!({
+            "success": false,
+            "error": format!("未知的事件类型: {}", requested_type),
+            "event_type": requested_type,
+            "supported_event_types": TaskEventType::ALL.iter().map(|t| t.as_str()).collect::<Vec<_>>()
+        }));
+    };
     info!("🧪 收到SSE测试请求: {} (事件类型: {})", request.message, event_type);
     
     // 创建测试事件
@@ -398,7 +620,7 @@ async fn sse_test_handler(
             Json(json!({
                 "success": true,
                 "message": "SSE测试事件已发送",
-                "event_type": request.event_type.unwrap_or_else(|| "test".to_string()),
+                "event_type": event_type,
                 "sent_at": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
             }))
         },
@@ -407,24 +629,48 @@ async fn sse_test_handler(
             Json(json!({
                 "success": false,
                 "error": format!("发送失败: {:?}", e),
-                "event_type": request.event_type.unwrap_or_else(|| "test".to_string())
+                "event_type": event_type
             }))
         }
     }
 }
 
-/// 任务状态查询处理器V2（使用内部状态）
+/// 任务状态查询处理器V2（按队列任务ID查询）
 async fn task_status_handler_v2(
     State(_state): State<AppStateV2>,
     Path(task_id): Path<i32>
 ) -> impl IntoResponse {
-    // 注意：由于Worker内部状态管理，这里需要通过其他方式获取状态
-    // 在实际实现中，可以通过消息传递或共享状态获取
+    match task_status::get_task_status(task_id) {
+        Some(status) => Json(json!({
+            "success": true,
+            "task": status,
+            "maa_task_ids": task_mapping::maa_task_ids(task_id),
+            "sse_endpoint": format!("/sse/task/{}", task_id),
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })),
+        None => Json(json!({
+            "success": false,
+            "error": format!("任务不存在: {}", task_id),
+            "task_id": task_id,
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })),
+    }
+}
+
+/// 任务等待处理器（长轮询，任务结束或超时后返回汇总结果）
+///
+/// `timeout` 缺省时按任务类型的预估耗时推导；超过该上限的值会被截断，
+/// 等待超时时结果中的 `wait_timeout_seconds` 和 `wait_timeout_capped` 给出实际等待时间和是否截断。
+async fn task_wait_handler(
+    Path(task_id): Path<i32>,
+    Query(query): Query<TaskWaitQuery>,
+) -> impl IntoResponse {
+    let outcome = task_status::wait_for_task(task_id, query.timeout).await;
+    let found = !matches!(outcome, TaskWaitOutcome::NotFound);
+    
     Json(json!({
-        "message": "任务状态查询已优化为Worker内部管理",
-        "task_id": task_id,
-        "recommendation": "使用SSE流获取实时状态更新",
-        "sse_endpoint": format!("/sse/task/{}", task_id),
+        "success": found,
+        "result": outcome.to_json(),
         "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
     }))
 }
@@ -433,15 +679,190 @@ async fn task_status_handler_v2(
 async fn all_tasks_handler_v2(
     State(_state): State<AppStateV2>
 ) -> impl IntoResponse {
+    let mut tasks = task_status::get_all_tasks();
+    tasks.sort_by_key(|task| task.task_id);
+    
+    let tasks: Vec<serde_json::Value> = tasks.into_iter()
+        .map(|task| {
+            let maa_task_ids = task_mapping::maa_task_ids(task.task_id);
+            json!({
+                "task": task,
+                "maa_task_ids": maa_task_ids
+            })
+        })
+        .collect();
+    
     Json(json!({
-        "message": "任务状态管理已优化为Worker内部处理",
-        "optimization": "减少了全局状态锁竞争",
-        "recommendation": "使用SSE流获取实时任务更新",
+        "success": true,
+        "total": tasks.len(),
+        "tasks": tasks,
         "sse_endpoint": "/sse/tasks",
         "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
     }))
 }
 
+/// 任务队列处理器 - 按执行顺序列出排队中的任务及预计开始时间
+async fn queue_handler(
+    State(state): State<AppStateV2>
+) -> impl IntoResponse {
+    let pending = state.task_sender.pending_tasks();
+    let running: Vec<serde_json::Value> = task_status::get_running_tasks().into_iter()
+        .map(|task| json!({
+            "task_id": task.task_id,
+            "task_type": task.task_type,
+            "started_at": task.started_at,
+        }))
+        .collect();
+    
+    Json(json!({
+        "success": true,
+        "depth": pending.len(),
+        "capacity": state.task_sender.capacity(),
+        "running": running,
+        "pending": pending,
+        "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }))
+}
+
+/// MCP streamable HTTP 端点：每个POST携带JSON-RPC消息，响应直接以JSON返回
+async fn mcp_handler(
+    State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
+    body: String
+) -> axum::response::Response {
+    let server = McpServer::new(state.enhanced_handler.with_principal(principal));
+    match server.handle_text(&body).await {
+        Some(response) => Json(response).into_response(),
+        // 通知和客户端响应没有回复
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// 不提供服务器主动推送的SSE流
+async fn mcp_stream_handler() -> impl IntoResponse {
+    StatusCode::METHOD_NOT_ALLOWED
+}
+
+/// 待确认操作列表
+async fn pending_actions_handler() -> impl IntoResponse {
+    let actions: Vec<serde_json::Value> = confirmation::pending_actions().iter()
+        .map(|action| action.to_json())
+        .collect();
+    Json(json!({
+        "success": true,
+        "total_count": actions.len(),
+        "actions": actions,
+        "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }))
+}
+
+/// 确认并执行待确认操作，响应与 `/call` 相同
+async fn confirm_action_handler(
+    State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
+    Path(token): Path<String>,
+) -> axum::response::Response {
+    let state = state.for_principal(principal);
+    match state.enhanced_handler.confirm_action(&token).await {
+        Ok(response) => {
+            let is_sync = is_synchronous_task(&response.metadata.function_name);
+            call_response(response, is_sync, false)
+        },
+        Err(e) => action_error_response(&e),
+    }
+}
+
+/// 放弃待确认操作
+async fn cancel_action_handler(
+    Path(token): Path<String>,
+) -> axum::response::Response {
+    match confirmation::cancel(&token) {
+        Ok(action) => Json(json!({
+            "success": true,
+            "cancelled": action.token,
+            "summary": action.summary,
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })).into_response(),
+        Err(e) => action_error_response(&e),
+    }
+}
+
+/// 确认码不存在返回404，已过期返回410
+fn action_error_response(error: &ConfirmationError) -> axum::response::Response {
+    let status = match error {
+        ConfirmationError::NotFound(_) => StatusCode::NOT_FOUND,
+        ConfirmationError::Expired(_) => StatusCode::GONE,
+    };
+    (status, Json(json!({
+        "success": false,
+        "error": error.to_string(),
+        "error_code": error.error_code(),
+        "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }))).into_response()
+}
+
+/// 工作流提交处理器
+async fn submit_workflow_handler(
+    State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
+    Json(spec): Json<WorkflowSpec>,
+) -> axum::response::Response {
+    // 工作流节点稍后由引擎执行，提交时按调用方权限逐个检查
+    for node in &spec.nodes {
+        if let Err(e) = authenticator().authorize_tool(&principal, &node.function_call.name) {
+            warn!("拒绝工作流提交: {}", e);
+            return auth_error_response(&e);
+        }
+    }
+    match state.workflow_engine.submit(spec) {
+        Ok(run) => Json(json!({
+            "success": true,
+            "workflow": run,
+            "status_url": format!("/workflows/{}", run.workflow_id),
+            "sse_endpoint": "/sse/tasks",
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })).into_response(),
+        Err(e) => {
+            warn!("工作流定义无效: {}", e);
+            Json(json!({
+                "success": false,
+                "error": e.to_string(),
+                "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+            })).into_response()
+        }
+    }
+}
+
+/// 工作流状态查询处理器
+async fn workflow_status_handler(
+    Path(workflow_id): Path<i32>,
+) -> impl IntoResponse {
+    match workflow::get_workflow(workflow_id) {
+        Some(run) => Json(json!({
+            "success": true,
+            "workflow": run,
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })),
+        None => Json(json!({
+            "success": false,
+            "error": format!("工作流不存在: {}", workflow_id),
+            "workflow_id": workflow_id,
+            "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        })),
+    }
+}
+
+/// 所有工作流处理器
+async fn all_workflows_handler() -> impl IntoResponse {
+    let workflows = workflow::get_all_workflows();
+    Json(json!({
+        "success": true,
+        "total": workflows.len(),
+        "workflows": workflows,
+        "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }))
+}
+
 /// 优化统计处理器
 async fn optimization_stats_handler(
     State(_state): State<AppStateV2>
@@ -496,6 +917,7 @@ async fn status_handler() -> impl IntoResponse {
         "server_status": "running",
         "version": "2.0.0-optimized",
         "backend_mode": "optimized-v2",
+        "device": device_health(),
         "optimizations": {
             "unified_queue": true,
             "internal_task_status": true,
@@ -510,21 +932,37 @@ async fn status_handler() -> impl IntoResponse {
 /// 重构的聊天处理器 - 基于现有架构优化
 async fn chat_handler(
     State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
     Json(request): Json<ChatRequest>
 ) -> impl IntoResponse {
-    debug!("收到聊天请求: {} 条消息", request.messages.len());
+    run_chat(&state.for_principal(principal), request).await
+}
+
+/// 聊天处理流程，`/chat` 和 WebSocket 的 `chat` 方法共用
+async fn run_chat(state: &AppStateV2, request: ChatRequest) -> Json<serde_json::Value> {
+    debug!("收到聊天请求: {} 条消息 (试运行: {})", request.messages.len(), request.dry_run);
     
     // 1. 消息验证和过滤
     if let Some(error_response) = validate_and_filter_messages(&request.messages) {
         return Json(error_response);
     }
     
+    // 试运行会话中的工具调用都不入队
+    let state = &state.with_dry_run(request.dry_run);
+    
+    // 用户对待确认操作的答复不经过AI，试运行会话不执行任何操作
+    if !request.dry_run {
+        if let Some(reply) = handle_confirmation_reply(state, &request.messages).await {
+            return Json(build_text_response(reply));
+        }
+    }
+    
     // 2. 准备AI调用数据
     let (ai_messages, tools) = prepare_ai_request(&request.messages, &state.enhanced_handler).await;
     
     // 3. 调用AI并处理响应
     match state.ai_client.chat_completion_with_tools(ai_messages, tools).await {
-        Ok(ai_result) => handle_ai_response(ai_result, &state).await,
+        Ok(ai_result) => handle_ai_response(ai_result, state).await,
         Err(e) => {
             error!("AI调用失败: {}", e);
             Json(build_error_response("AI服务暂时不可用，请稍后重试"))
@@ -532,6 +970,206 @@ async fn chat_handler(
     }
 }
 
+/// 处理用户对上一条回复中待确认操作的“确认”/“取消”，返回给用户的回复
+///
+/// `/chat`、WebSocket聊天和 `/v1/chat/completions` 共用，答复不经过AI。
+async fn handle_confirmation_reply(state: &AppStateV2, messages: &[ChatMessage]) -> Option<String> {
+    let (last, history) = messages.split_last()?;
+    if last.role != "user" {
+        return None;
+    }
+    let assistant = history.iter().rev().find(|message| message.role == "assistant")?;
+    let lines: Vec<String> = match confirmation::chat_decision(&assistant.content, &last.content)? {
+        ChatDecision::Confirm(tokens) => {
+            let mut lines = Vec::new();
+            for token in tokens {
+                match state.enhanced_handler.confirm_action(&token).await {
+                    Ok(response) => {
+                        let name = response.metadata.function_name.clone();
+                        lines.push(format_results_summary(&[(name, function_result(response))]));
+                    },
+                    Err(e) => lines.push(e.to_string()),
+                }
+            }
+            lines
+        },
+        ChatDecision::Cancel(tokens) => tokens.iter()
+            .map(|token| match confirmation::cancel(token) {
+                Ok(action) => format!("已取消：{}", action.summary),
+                Err(e) => e.to_string(),
+            })
+            .collect(),
+    };
+    Some(lines.join("\n"))
+}
+
+/// WebSocket会话的聊天后端，状态已按连接的调用方限定权限
+struct WsChat {
+    state: AppStateV2,
+    principal: Principal,
+}
+
+#[async_trait::async_trait]
+impl RpcChat for WsChat {
+    async fn chat(&self, params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+        // 连接只要求viewer，聊天和 `/chat` 一样需要operator
+        authenticator().authorize(&self.principal, Role::Operator)
+            .map_err(|e| RpcError::new(ws::FORBIDDEN, e.to_string()))?;
+        let request: ChatRequest = serde_json::from_value(params)
+            .map_err(|e| RpcError::invalid_params(format!("参数错误: {}", e)))?;
+        Ok(run_chat(&self.state, request).await.0)
+    }
+}
+
+/// WebSocket JSON-RPC 端点
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_ws_connection(socket, state, principal))
+}
+
+/// 把WebSocket连接接到JSON-RPC会话：文本帧交给会话处理，会话的响应和通知写回连接
+async fn handle_ws_connection(socket: WebSocket, state: AppStateV2, principal: Principal) {
+    use futures::{SinkExt, StreamExt};
+    
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let (outgoing_tx, mut outgoing_rx) = tokio::sync::mpsc::channel::<serde_json::Value>(ws::OUTGOING_BUFFER_SIZE);
+    let state = state.for_principal(principal.clone());
+    let session = Arc::new(
+        WsSession::new(state.enhanced_handler.clone(), &state.sse_manager, outgoing_tx)
+            .with_chat(Arc::new(WsChat { state: state.clone(), principal }))
+    );
+    info!("WebSocket连接已建立");
+    
+    let writer = tokio::spawn(async move {
+        while let Some(message) = outgoing_rx.recv().await {
+            if ws_tx.send(Message::Text(message.to_string().into())).await.is_err() {
+                break;
+            }
+        }
+    });
+    
+    while let Some(Ok(message)) = ws_rx.next().await {
+        match message {
+            Message::Text(text) => {
+                // 每个请求单独处理，等待任务结束的调用不阻塞后续请求
+                let session = session.clone();
+                tokio::spawn(async move {
+                    session.handle_text(text.as_str()).await;
+                });
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    
+    writer.abort();
+    info!("WebSocket连接已关闭");
+}
+
+/// 流式响应每个分片的字符数
+const OPENAI_STREAM_CHUNK_CHARS: usize = 16;
+
+/// OpenAI兼容的模型列表
+async fn openai_models_handler() -> impl IntoResponse {
+    Json(openai_compat::models_response())
+}
+
+/// OpenAI兼容的聊天补全，工具调用在服务端执行
+async fn openai_chat_completions_handler(
+    State(state): State<AppStateV2>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<ChatCompletionRequest>, axum::extract::rejection::JsonRejection>
+) -> axum::response::Response {
+    let Json(request) = match payload {
+        Ok(request) => request,
+        Err(rejection) => return (
+            StatusCode::BAD_REQUEST,
+            Json(openai_compat::error_body(&rejection.body_text(), "invalid_request_error", None)),
+        ).into_response(),
+    };
+    if request.messages.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(openai_compat::error_body("messages 不能为空", "invalid_request_error", None)),
+        ).into_response();
+    }
+    if let Some(message) = request.unsupported_tools_error() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(openai_compat::error_body(message, "invalid_request_error", Some("unsupported_parameter"))),
+        ).into_response();
+    }
+    let state = state.for_principal(principal);
+    debug!("收到OpenAI兼容请求: {} 条消息, stream={}", request.messages.len(), request.stream);
+    
+    let messages: Vec<ChatMessage> = request.messages.iter()
+        .map(|message| ChatMessage { role: message.role.clone(), content: message.text() })
+        .collect();
+    if let Some(message) = message_length_error(&messages) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(openai_compat::error_body(message, "invalid_request_error", Some("context_length_exceeded"))),
+        ).into_response();
+    }
+    
+    let model = request.model_id();
+    let id = completion_id();
+    // 用户对上一条回复中待确认操作的答复不经过AI
+    let confirmation_reply = handle_confirmation_reply(&state, &messages).await;
+    let (ai_messages, tools) = prepare_ai_request(&messages, &state.enhanced_handler).await;
+    
+    if !request.stream {
+        if let Some(reply) = confirmation_reply {
+            return Json(completion_response(&id, &model, &reply)).into_response();
+        }
+        return match run_tool_loop(state.ai_client.as_ref(), &state.enhanced_handler, ai_messages, tools).await {
+            Ok(outcome) => Json(completion_response(&id, &model, &outcome.reply())).into_response(),
+            Err(e) => {
+                error!("AI调用失败: {}", e);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(openai_compat::error_body(&format!("AI服务暂时不可用: {}", e), "api_error", None)),
+                ).into_response()
+            }
+        };
+    }
+    
+    // 流式：先发送角色分片，工具循环结束后分片发送回复，期间由keep-alive保持连接
+    let created = chrono::Utc::now().timestamp();
+    let stream = async_stream::stream! {
+        let chunk = completion_chunk(&id, &model, created, json!({ "role": "assistant", "content": "" }), None);
+        yield Ok::<_, std::convert::Infallible>(axum::response::sse::Event::default().data(chunk.to_string()));
+        
+        let reply = match confirmation_reply {
+            Some(reply) => Ok(reply),
+            None => run_tool_loop(state.ai_client.as_ref(), &state.enhanced_handler, ai_messages, tools).await
+                .map(|outcome| outcome.reply()),
+        };
+        match reply {
+            Ok(reply) => {
+                for piece in split_content(&reply, OPENAI_STREAM_CHUNK_CHARS) {
+                    let chunk = completion_chunk(&id, &model, created, json!({ "content": piece }), None);
+                    yield Ok(axum::response::sse::Event::default().data(chunk.to_string()));
+                }
+                let chunk = completion_chunk(&id, &model, created, json!({}), Some("stop"));
+                yield Ok(axum::response::sse::Event::default().data(chunk.to_string()));
+            },
+            Err(e) => {
+                error!("AI调用失败: {}", e);
+                let body = openai_compat::error_body(&format!("AI服务暂时不可用: {}", e), "api_error", None);
+                yield Ok(axum::response::sse::Event::default().data(body.to_string()));
+            }
+        }
+        yield Ok(axum::response::sse::Event::default().data("[DONE]"));
+    };
+    Sse::new(stream)
+        .keep_alive(axum::response::sse::KeepAlive::default())
+        .into_response()
+}
+
 /// 聊天重置处理器
 async fn reset_chat_handler() -> impl IntoResponse {
     Json(json!({
@@ -550,18 +1188,23 @@ async fn reset_chat_handler() -> impl IntoResponse {
 
 /// 消息验证和过滤 - 消除嵌套if
 fn validate_and_filter_messages(messages: &[ChatMessage]) -> Option<serde_json::Value> {
+    message_length_error(messages).map(build_error_response)
+}
+
+/// 消息长度检查，超限时返回提示
+fn message_length_error(messages: &[ChatMessage]) -> Option<&'static str> {
     const MAX_TOTAL_LENGTH: usize = 100_000;
     const MAX_SINGLE_LENGTH: usize = 50_000;
     
     let total_length: usize = messages.iter().map(|msg| msg.content.len()).sum();
     
     if total_length > MAX_TOTAL_LENGTH {
-        return Some(build_error_response("消息内容过长，请分段发送或清除历史记录"));
+        return Some("消息内容过长，请分段发送或清除历史记录");
     }
     
     for msg in messages {
         if msg.content.len() > MAX_SINGLE_LENGTH {
-            return Some(build_error_response("检测到历史消息中包含大量数据，请重置对话"));
+            return Some("检测到历史消息中包含大量数据，请重置对话");
         }
     }
     
@@ -663,7 +1306,7 @@ async fn execute_function_calls(
     for function_call in function_calls {
         warn!("执行工具: {} with args: {:?}", function_call.name, function_call.arguments);
         
-        if function_call.name == "maa_take_screenshot" {
+        if function_call.name == "maa_take_screenshot" && !state.enhanced_handler.is_dry_run() {
             screenshot_info = handle_screenshot_call(&function_call, state).await;
         } else {
             let result = execute_single_function(&function_call, state).await;
@@ -707,8 +1350,13 @@ async fn execute_single_function(
         arguments: function_call.arguments.clone(),
     };
     let response = state.enhanced_handler.execute_function(fc).await;
-    
-    if response.success {
+    function_result(response)
+}
+
+/// 聊天中使用的执行结果，待确认操作作为 `pending_confirmation` 状态返回
+fn function_result(response: FunctionResponse) -> Result<serde_json::Value, anyhow::Error> {
+    let error_code = response.error.as_ref().and_then(|e| e.error_code.as_deref());
+    if response.success || error_code == Some(CONFIRMATION_REQUIRED_ERROR_CODE) {
         Ok(response.result.unwrap_or(json!({})))
     } else {
         Err(anyhow::anyhow!("函数执行失败: {:?}", response.error))
@@ -767,9 +1415,26 @@ async fn build_function_results_response(
     let results_summary = format_results_summary(&results);
     
     // 生成AI最终回复
-    let final_response = generate_final_response(&results_summary, state).await
+    let mut final_response = generate_final_response(&results_summary, state).await
         .unwrap_or_else(|| format!("工具执行完成：\n{}", results_summary));
     
+    // 确认提示原样附在回复末尾，用户回复“确认”时从中找回确认码
+    let pending_actions: Vec<&serde_json::Value> = results.iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+        .filter(|data| data.get("status").and_then(|s| s.as_str()) == Some("pending_confirmation"))
+        .collect();
+    for action in &pending_actions {
+        if let Some(prompt) = action.get("message").and_then(|m| m.as_str()) {
+            final_response.push_str("\n\n");
+            final_response.push_str(prompt);
+        }
+    }
+    
+    // 试运行时附上每个工具将要提交的MAA任务和参数
+    let dry_run_results: Option<Vec<&serde_json::Value>> = state.enhanced_handler.is_dry_run().then(|| results.iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+        .collect());
+    
     Json(json!({
         "choices": [{
             "message": {
@@ -778,6 +1443,9 @@ async fn build_function_results_response(
                 "tool_calls": tool_calls_info
             }
         }],
+        "pending_actions": pending_actions,
+        "dry_run": state.enhanced_handler.is_dry_run(),
+        "dry_run_results": dry_run_results,
         "backend": "optimized-v2"
     }))
 }
@@ -1,7 +1,10 @@
 use anyhow::{Result, Context};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
 
+use crate::auth::Role;
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
@@ -12,7 +15,18 @@ pub struct AppConfig {
     pub logging: LogConfig,
     pub ai: AiConfig,
     pub webui: WebUIConfig,
+    /// 新增的配置段缺省时使用默认值，旧的配置文件无需修改即可加载
+    #[serde(default)]
+    pub auth: AuthConfig,
     pub performance: PerformanceConfig,
+    #[serde(default)]
+    pub copilot: CopilotConfig,
+    #[serde(default)]
+    pub task_timeout: TaskTimeoutConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub confirmation: ConfirmationConfig,
     pub messages: MessageConfig,
     pub status_codes: StatusCodeConfig,
     pub env_keys: EnvKeyConfig,
@@ -35,6 +49,9 @@ pub struct DeviceConfig {
     pub touch_mode_playcover: String,
     pub connection_timeout_ms: u64,
     pub retry_attempts: u32,
+    /// 设备连接健康检查间隔（毫秒）
+    #[serde(default = "default_health_check_interval_ms")]
+    pub health_check_interval_ms: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -88,6 +105,34 @@ pub struct WebUIConfig {
     pub default_secret_key: String,
 }
 
+/// 认证配置
+///
+/// 未写的字段使用默认值；不认识的字段视为解析错误，避免拼错的 `enabled` 被静默忽略。
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// 是否启用API认证，关闭时所有请求拥有全部权限
+    pub enabled: bool,
+    /// 不需要认证的路径（如健康检查）
+    pub public_paths: Vec<String>,
+    /// WebUI密钥（`webui.default_secret_key` 或环境变量）作为API Key时的角色
+    pub webui_role: Role,
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// 按Function名称设置需要的最低角色，未设置的工具需要operator
+    pub tool_roles: HashMap<String, Role>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    /// API Key的SHA-256摘要（十六进制），配置中不保存明文
+    pub key_sha256: String,
+    pub role: Role,
+    /// 可使用的工具，未设置时只按角色判断
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PerformanceConfig {
     pub task_queue_buffer_size: usize,
@@ -95,6 +140,63 @@ pub struct PerformanceConfig {
     pub worker_heartbeat_ms: u64,
     pub connection_pool_size: usize,
     pub max_concurrent_requests: usize,
+    /// SSE事件回放缓冲区大小（断线重连时按 Last-Event-ID 补发）
+    #[serde(default = "default_sse_replay_buffer_size")]
+    pub sse_replay_buffer_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopilotConfig {
+    pub feedback_db_path: String,
+    /// 作业站数据和匹配结果缓存
+    pub cache_db_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskTimeoutConfig {
+    pub check_interval_ms: u64,
+    pub stop_on_timeout: bool,
+    pub back_to_home_on_timeout: bool,
+    /// 超时预算相对预估耗时的倍数
+    pub budget_factor: f64,
+    /// 按Function名称覆盖的超时预算（秒），未配置时使用预估耗时
+    #[serde(default)]
+    pub budgets: HashMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetConfig {
+    /// 是否限制理智药、源石和肉鸽投资的消耗
+    pub enabled: bool,
+    /// 每日额度刷新的小时（本地时间），每周额度从周一该时刻开始
+    pub reset_hour: u32,
+    /// 用量记录文件，为空时只在内存中统计
+    pub usage_path: String,
+    #[serde(default)]
+    pub medicine: BudgetCap,
+    #[serde(default)]
+    pub stone: BudgetCap,
+    #[serde(default)]
+    pub roguelike_investment: BudgetCap,
+}
+
+/// 单项资源的额度，未设置的周期不限
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct BudgetCap {
+    #[serde(default)]
+    pub daily: Option<u32>,
+    #[serde(default)]
+    pub weekly: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmationConfig {
+    /// 是否对高风险调用启用二次确认
+    pub enabled: bool,
+    /// 待确认操作的有效期（秒）
+    pub ttl_secs: u64,
+    /// 需要确认的Function名称
+    pub tools: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -142,16 +244,111 @@ pub struct EnvKeyConfig {
     pub https_proxy: String,
 }
 
+fn default_health_check_interval_ms() -> u64 {
+    5000
+}
+
+fn default_sse_replay_buffer_size() -> usize {
+    1000
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            public_paths: vec!["/".to_string(), "/health".to_string(), "/api/health".to_string()],
+            webui_role: Role::Operator,
+            api_keys: Vec::new(),
+            tool_roles: HashMap::from([
+                ("maa_system_management".to_string(), Role::Admin),
+                ("maa_custom_task".to_string(), Role::Admin),
+            ]),
+        }
+    }
+}
+
+impl Default for CopilotConfig {
+    fn default() -> Self {
+        Self {
+            feedback_db_path: "data/copilot_feedback".to_string(),
+            cache_db_path: "data/copilot_cache".to_string(),
+        }
+    }
+}
+
+impl Default for TaskTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_ms: 5000,
+            stop_on_timeout: true,
+            back_to_home_on_timeout: true,
+            budget_factor: 2.0,
+            budgets: HashMap::new(),
+        }
+    }
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reset_hour: 4,
+            usage_path: "data/budget_usage.json".to_string(),
+            medicine: BudgetCap { daily: Some(10), weekly: None },
+            stone: BudgetCap { daily: Some(0), weekly: Some(0) },
+            roguelike_investment: BudgetCap::default(),
+        }
+    }
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 300,
+            tools: vec![
+                "maa_combat_enhanced".to_string(),
+                "maa_closedown".to_string(),
+                "maa_emergency_home".to_string(),
+                "maa_custom_task".to_string(),
+            ],
+        }
+    }
+}
+
 impl AppConfig {
     pub fn load() -> Result<AppConfig> {
         let config_path = Self::find_config_file()?;
         let content = std::fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
         
-        let config: AppConfig = toml::from_str(&content)
-            .with_context(|| "Failed to parse config file")?;
-        
-        Ok(config)
+        Self::parse(&content)
+    }
+
+    /// 解析配置文件内容
+    pub fn parse(content: &str) -> Result<AppConfig> {
+        toml::from_str(content).with_context(|| "Failed to parse config file")
+    }
+
+    /// 加载配置，失败时回退到默认配置
+    ///
+    /// 默认配置不启用认证。配置文件声明了 `[auth]` 却无法解析时拒绝回退，
+    /// 否则一处笔误就会让服务以无认证模式运行。
+    fn load_or_default() -> Result<AppConfig> {
+        let content = Self::find_config_file()
+            .and_then(|path| std::fs::read_to_string(&path).with_context(|| format!("Failed to read config file: {:?}", path)));
+        let error = match content {
+            Ok(content) => match Self::parse(&content) {
+                Ok(config) => return Ok(config),
+                Err(e) if declares_auth(&content) => {
+                    return Err(e.context("配置文件声明了 [auth] 但无法解析，拒绝以未启用认证的默认配置启动"));
+                },
+                Err(e) => e,
+            },
+            Err(e) => e,
+        };
+        eprintln!("Warning: Failed to load config file, using defaults: {:#}", error);
+        Ok(create_default_config())
     }
     
     fn find_config_file() -> Result<std::path::PathBuf> {
@@ -234,12 +431,16 @@ impl AiConfig {
 use once_cell::sync::Lazy;
 
 pub static CONFIG: Lazy<AppConfig> = Lazy::new(|| {
-    AppConfig::load().unwrap_or_else(|e| {
-        eprintln!("Warning: Failed to load config file, using defaults: {}", e);
-        create_default_config()
-    })
+    AppConfig::load_or_default().unwrap_or_else(|e| panic!("{:#}", e))
 });
 
+/// 配置文件是否包含认证配置段（按行判断，语法错误的文件同样适用）
+fn declares_auth(content: &str) -> bool {
+    content.lines()
+        .map(str::trim_start)
+        .any(|line| line.starts_with("[auth]") || line.starts_with("[auth.") || line.starts_with("[[auth."))
+}
+
 fn create_default_config() -> AppConfig {
     // 返回默认配置，避免程序崩溃
     AppConfig {
@@ -257,6 +458,7 @@ fn create_default_config() -> AppConfig {
             touch_mode_playcover: "MacPlayTools".to_string(),
             connection_timeout_ms: 10000,
             retry_attempts: 3,
+            health_check_interval_ms: default_health_check_interval_ms(),
         },
         maa: MaaConfig {
             default_app_path: "/Applications/MAA.app".to_string(),
@@ -301,13 +503,19 @@ fn create_default_config() -> AppConfig {
             default_name: "MAA智能助手".to_string(),
             default_secret_key: "change-this-in-production".to_string(),
         },
+        auth: AuthConfig::default(),
         performance: PerformanceConfig {
             task_queue_buffer_size: 1000,
             response_timeout_ms: 30000,
             worker_heartbeat_ms: 1000,
             connection_pool_size: 10,
             max_concurrent_requests: 100,
+            sse_replay_buffer_size: default_sse_replay_buffer_size(),
         },
+        copilot: CopilotConfig::default(),
+        task_timeout: TaskTimeoutConfig::default(),
+        budget: BudgetConfig::default(),
+        confirmation: ConfirmationConfig::default(),
         messages: MessageConfig {
             success: "Operation completed successfully".to_string(),
             failure: "Operation failed".to_string(),
@@ -348,4 +556,49 @@ fn create_default_config() -> AppConfig {
             https_proxy: "HTTPS_PROXY".to_string(),
         },
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP_TOML: &str = include_str!("../../config/app.toml");
+
+    /// 去掉指定的配置段（含子表）
+    fn without_sections(content: &str, sections: &[&str]) -> String {
+        let mut skipping = false;
+        content.lines()
+            .filter(|line| {
+                let line = line.trim();
+                if line.starts_with('[') {
+                    let name = line.trim_matches(|c| c == '[' || c == ']');
+                    skipping = sections.iter().any(|section| name == *section || name.starts_with(&format!("{}.", section)));
+                }
+                !skipping
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_partial_config_keeps_auth_enabled() {
+        let content = without_sections(APP_TOML, &["auth", "copilot", "task_timeout", "budget", "confirmation"])
+            .replace("sse_replay_buffer_size", "# sse_replay_buffer_size")
+            + "\n[auth]\nenabled = true\n";
+        let config = AppConfig::parse(&content).unwrap();
+        assert!(config.auth.enabled);
+        assert_eq!(config.auth.public_paths, AuthConfig::default().public_paths);
+        assert_eq!(config.auth.tool_roles.get("maa_custom_task"), Some(&Role::Admin));
+        assert_eq!(config.performance.sse_replay_buffer_size, default_sse_replay_buffer_size());
+        assert!(config.confirmation.enabled);
+    }
+
+    #[test]
+    fn test_invalid_auth_section_is_not_ignored() {
+        // 拼错的字段不能被静默忽略
+        let content = without_sections(APP_TOML, &["auth"]) + "\n[auth]\nenable = true\n";
+        assert!(AppConfig::parse(&content).is_err());
+        assert!(declares_auth(&content));
+        assert!(!declares_auth(&without_sections(APP_TOML, &["auth"])));
+    }
+}
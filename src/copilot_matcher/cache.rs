@@ -9,6 +9,7 @@ use chrono::{DateTime, Utc, Duration};
 use serde::{Deserialize, Serialize};
 use sled::Db;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::time::{interval, Duration as TokioDuration};
 
@@ -57,6 +58,29 @@ impl<T> CacheEntry<T> {
     }
 }
 
+/// 缓存条目的时间字段，用于清理和淘汰时免于解析数据
+#[derive(Debug, Deserialize)]
+struct CacheEntryMeta {
+    expires_at: DateTime<Utc>,
+    last_accessed: DateTime<Utc>,
+}
+
+/// LRU索引键：最后访问时间（大端序，可按字节排序）+ 缓存键
+fn lru_index_key(last_accessed: DateTime<Utc>, key: &[u8]) -> Vec<u8> {
+    let nanos = last_accessed.timestamp_nanos_opt().unwrap_or(i64::MAX);
+    // 翻转符号位，使有符号时间戳按无符号字节序排列
+    let mut index_key = ((nanos as u64) ^ (1 << 63)).to_be_bytes().to_vec();
+    index_key.extend_from_slice(key);
+    index_key
+}
+
+/// 从序列化的缓存条目计算其LRU索引键
+fn lru_index_key_of(value: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+    serde_json::from_slice::<CacheEntryMeta>(value)
+        .ok()
+        .map(|meta| lru_index_key(meta.last_accessed, key))
+}
+
 /// 缓存配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
@@ -76,6 +100,10 @@ pub struct CacheConfig {
     pub enable_compression: bool,
     /// 统计采样率（0.0 - 1.0）
     pub stats_sample_rate: f32,
+    /// 上游不可用时是否允许返回过期数据（离线模式）
+    pub serve_stale_on_error: bool,
+    /// 过期数据最长保留时间（秒），仅在允许返回过期数据时生效
+    pub max_stale_age: i64,
 }
 
 impl Default for CacheConfig {
@@ -89,6 +117,8 @@ impl Default for CacheConfig {
             cleanup_interval: 300,     // 5分钟
             enable_compression: true,
             stats_sample_rate: 0.1,   // 10%采样率
+            serve_stale_on_error: false,
+            max_stale_age: 604800,     // 7天
         }
     }
 }
@@ -116,6 +146,21 @@ impl CacheConfig {
         self
     }
 
+    /// 设置是否在上游不可用时返回过期数据
+    pub fn with_serve_stale_on_error(mut self, enable: bool) -> Self {
+        self.serve_stale_on_error = enable;
+        self
+    }
+
+    /// 过期条目在清理前的保留时间
+    fn stale_retention(&self) -> Duration {
+        if self.serve_stale_on_error {
+            Duration::seconds(self.max_stale_age)
+        } else {
+            Duration::zero()
+        }
+    }
+
     /// 验证配置
     pub fn validate(&self) -> CopilotResult<()> {
         if self.db_path.is_empty() {
@@ -130,6 +175,10 @@ impl CacheConfig {
             return Err(CopilotError::ConfigError("Max entries must be positive".to_string()));
         }
 
+        if self.max_stale_age < 0 {
+            return Err(CopilotError::ConfigError("Max stale age must not be negative".to_string()));
+        }
+
         Ok(())
     }
 }
@@ -149,6 +198,10 @@ pub struct CacheStats {
     pub cleanup_runs: u64,
     /// 最后清理时间
     pub last_cleanup: Option<DateTime<Utc>>,
+    /// LRU淘汰条目数
+    pub evictions: u64,
+    /// 返回过期数据次数
+    pub stale_hits: u64,
     /// 启动时间
     pub started_at: DateTime<Utc>,
 }
@@ -163,6 +216,8 @@ impl CacheStats {
             current_entries: 0,
             cleanup_runs: 0,
             last_cleanup: None,
+            evictions: 0,
+            stale_hits: 0,
             started_at: Utc::now(),
         }
     }
@@ -193,6 +248,16 @@ impl CacheStats {
         self.cleanup_runs += 1;
         self.last_cleanup = Some(Utc::now());
     }
+
+    /// 记录LRU淘汰
+    pub fn record_evictions(&mut self, count: u64) {
+        self.evictions += count;
+    }
+
+    /// 记录返回过期数据
+    pub fn record_stale_hit(&mut self) {
+        self.stale_hits += 1;
+    }
 }
 
 impl Default for CacheStats {
@@ -222,6 +287,12 @@ pub trait CacheManagerTrait: Send + Sync {
     /// 获取匹配结果列表
     async fn get_match_results(&self, key: &str) -> CopilotResult<Option<Vec<MatchResult>>>;
 
+    /// 获取作业数据，允许返回过期条目（上游不可用时使用）
+    async fn get_stale_copilot_data(&self, key: &str) -> CopilotResult<Option<CopilotData>>;
+
+    /// 获取匹配结果列表，允许返回过期条目（上游不可用时使用）
+    async fn get_stale_match_results(&self, key: &str) -> CopilotResult<Option<Vec<MatchResult>>>;
+
     /// 删除缓存条目
     async fn remove(&self, key: &str) -> CopilotResult<bool>;
 
@@ -243,6 +314,10 @@ pub trait CacheManagerTrait: Send + Sync {
 pub struct CacheManager {
     config: CacheConfig,
     db: Arc<Db>,
+    /// 按最后访问时间排序的索引，淘汰时无需扫描全部条目
+    lru: sled::Tree,
+    /// 当前条目数，避免每次写入都调用 `Db::len`
+    entries: Arc<AtomicU64>,
     stats: Arc<tokio::sync::Mutex<CacheStats>>,
 }
 
@@ -260,11 +335,29 @@ impl CacheManager {
         // 打开数据库
         let db = sled::open(&config.db_path)
             .map_err(|e| CopilotError::CacheError(format!("Failed to open cache database: {}", e)))?;
+        let lru = db.open_tree("lru_index")?;
+
+        // 启动时统计一次条目数；索引与数据不一致（如旧版本数据库）时重建索引
+        let entries = db.len() as u64;
+        if lru.len() as u64 != entries {
+            lru.clear()?;
+            for item in db.iter() {
+                let (key, value) = item?;
+                if let Some(index_key) = lru_index_key_of(&value, &key) {
+                    lru.insert(index_key, &[])?;
+                }
+            }
+        }
+
+        let mut stats = CacheStats::new();
+        stats.current_entries = entries;
 
         let cache_manager = Self {
             config: config.clone(),
             db: Arc::new(db),
-            stats: Arc::new(tokio::sync::Mutex::new(CacheStats::new())),
+            lru,
+            entries: Arc::new(AtomicU64::new(entries)),
+            stats: Arc::new(tokio::sync::Mutex::new(stats)),
         };
 
         // 启动清理任务
@@ -276,8 +369,11 @@ impl CacheManager {
     /// 启动清理任务
     async fn start_cleanup_task(&self) {
         let db = self.db.clone();
+        let lru = self.lru.clone();
+        let entries = self.entries.clone();
         let stats = self.stats.clone();
         let cleanup_interval = self.config.cleanup_interval;
+        let stale_retention = self.config.stale_retention();
 
         tokio::spawn(async move {
             let mut interval = interval(TokioDuration::from_secs(cleanup_interval));
@@ -285,7 +381,7 @@ impl CacheManager {
             loop {
                 interval.tick().await;
                 
-                if let Err(e) = Self::cleanup_expired_internal(&db, &stats).await {
+                if let Err(e) = Self::cleanup_expired_internal(&db, &lru, &entries, &stats, stale_retention).await {
                     tracing::warn!("Cache cleanup failed: {}", e);
                 }
             }
@@ -293,9 +389,14 @@ impl CacheManager {
     }
 
     /// 内部清理过期条目
+    ///
+    /// 允许返回过期数据时，过期条目会再保留 `stale_retention` 时间。
     async fn cleanup_expired_internal(
         db: &Arc<Db>,
+        lru: &sled::Tree,
+        entries: &AtomicU64,
         stats: &Arc<tokio::sync::Mutex<CacheStats>>,
+        stale_retention: Duration,
     ) -> CopilotResult<u64> {
         let mut removed_count = 0;
         let now = Utc::now();
@@ -303,10 +404,11 @@ impl CacheManager {
         for result in db.iter() {
             let (key, value) = result?;
             
-            // 尝试解析为 CacheEntry<serde_json::Value>
-            if let Ok(entry) = serde_json::from_slice::<CacheEntry<serde_json::Value>>(&value) {
-                if entry.expires_at < now {
-                    db.remove(&key)?;
+            // 只解析时间字段，不关心数据本身
+            if let Ok(entry) = serde_json::from_slice::<CacheEntryMeta>(&value) {
+                if entry.expires_at + stale_retention < now && db.remove(&key)?.is_some() {
+                    lru.remove(lru_index_key(entry.last_accessed, &key))?;
+                    entries.fetch_sub(1, Ordering::Relaxed);
                     removed_count += 1;
                 }
             }
@@ -315,7 +417,7 @@ impl CacheManager {
         // 更新统计信息
         let mut stats_guard = stats.lock().await;
         stats_guard.record_cleanup();
-        stats_guard.current_entries = stats_guard.current_entries.saturating_sub(removed_count);
+        stats_guard.current_entries = entries.load(Ordering::Relaxed);
 
         Ok(removed_count)
     }
@@ -331,15 +433,73 @@ impl CacheManager {
         let serialized = serde_json::to_vec(&entry)
             .map_err(|e| CopilotError::SerializationError(format!("Failed to serialize cache entry: {}", e)))?;
 
-        self.db.insert(key, serialized)?;
+        match self.db.insert(key, serialized)? {
+            Some(old) => self.unindex(&old, key.as_bytes())?,
+            None => {
+                self.entries.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.lru.insert(lru_index_key(entry.last_accessed, key.as_bytes()), &[])?;
+
+        // 超出容量时按最近访问时间淘汰
+        let evicted = self.evict_lru(key)?;
 
         // 更新统计信息
         let mut stats = self.stats.lock().await;
-        stats.current_entries += 1;
+        stats.current_entries = self.entries.load(Ordering::Relaxed);
+        stats.record_evictions(evicted);
+
+        Ok(())
+    }
+
+    /// LRU淘汰：条目数超过 `max_entries` 时删除最久未访问的条目
+    ///
+    /// 按访问时间索引从最旧的条目开始淘汰；`keep` 为刚写入的键，不参与淘汰。
+    fn evict_lru(&self, keep: &str) -> CopilotResult<u64> {
+        let max_entries = self.config.max_entries;
+        let mut evicted = 0;
+        while self.entries.load(Ordering::Relaxed) > max_entries {
+            let Some((index_key, _)) = self.lru.pop_min()? else {
+                break;
+            };
+            let key = &index_key[8..];
+            if key == keep.as_bytes() {
+                // 只剩刚写入的条目可淘汰，放回索引
+                self.lru.insert(index_key, &[])?;
+                break;
+            }
+            if self.db.remove(key)?.is_some() {
+                self.entries.fetch_sub(1, Ordering::Relaxed);
+                evicted += 1;
+            }
+        }
 
+        if evicted > 0 {
+            tracing::debug!("缓存超出容量 {}，已淘汰 {} 条", max_entries, evicted);
+        }
+        Ok(evicted)
+    }
+
+    /// 删除条目在访问时间索引中的记录
+    fn unindex(&self, value: &[u8], key: &[u8]) -> CopilotResult<()> {
+        if let Some(index_key) = lru_index_key_of(value, key) {
+            self.lru.remove(index_key)?;
+        }
         Ok(())
     }
 
+    /// 删除条目，同时维护索引和条目数，返回是否存在
+    fn remove_entry(&self, key: &str) -> CopilotResult<bool> {
+        match self.db.remove(key)? {
+            Some(old) => {
+                self.unindex(&old, key.as_bytes())?;
+                self.entries.fetch_sub(1, Ordering::Relaxed);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     /// 从缓存获取数据
     async fn get_data<T: for<'de> Deserialize<'de> + Serialize>(&self, key: &str) -> CopilotResult<Option<T>> {
         let mut stats = self.stats.lock().await;
@@ -350,15 +510,22 @@ impl CacheManager {
                     .map_err(|e| CopilotError::SerializationError(format!("Failed to deserialize cache entry: {}", e)))?;
 
                 if entry.is_expired() {
+                    if self.config.serve_stale_on_error {
+                        // 保留过期条目，供上游不可用时返回
+                        stats.record_miss();
+                        return Ok(None);
+                    }
+
                     // 过期条目，删除并返回None
                     drop(stats); // 释放锁
-                    self.db.remove(key)?;
+                    self.remove_entry(key)?;
                     let mut stats = self.stats.lock().await;
                     stats.record_miss();
-                    stats.current_entries = stats.current_entries.saturating_sub(1);
+                    stats.current_entries = self.entries.load(Ordering::Relaxed);
                     Ok(None)
                 } else {
-                    // 更新访问统计
+                    // 更新访问统计，同时把索引移到最新位置
+                    let previous_index_key = lru_index_key(entry.last_accessed, key.as_bytes());
                     entry.touch();
                     
                     // 重新序列化更新后的条目
@@ -367,6 +534,8 @@ impl CacheManager {
                     
                     drop(stats); // 释放锁以避免死锁
                     self.db.insert(key, updated_serialized)?;
+                    self.lru.remove(previous_index_key)?;
+                    self.lru.insert(lru_index_key(entry.last_accessed, key.as_bytes()), &[])?;
                     
                     let mut stats = self.stats.lock().await;
                     stats.record_hit();
@@ -380,6 +549,32 @@ impl CacheManager {
         }
     }
 
+    /// 获取数据，过期条目在保留期内仍然返回
+    async fn get_stale_data<T: for<'de> Deserialize<'de>>(&self, key: &str) -> CopilotResult<Option<T>> {
+        if !self.config.serve_stale_on_error {
+            return Ok(None);
+        }
+
+        let Some(serialized) = self.db.get(key)? else {
+            return Ok(None);
+        };
+
+        let entry: CacheEntry<T> = serde_json::from_slice(&serialized)
+            .map_err(|e| CopilotError::SerializationError(format!("Failed to deserialize cache entry: {}", e)))?;
+
+        if entry.expires_at + self.config.stale_retention() < Utc::now() {
+            return Ok(None);
+        }
+
+        if entry.is_expired() {
+            let mut stats = self.stats.lock().await;
+            stats.record_stale_hit();
+            tracing::warn!("返回过期缓存数据: {} (过期于 {})", key, entry.expires_at);
+        }
+
+        Ok(Some(entry.data))
+    }
+
     /// 生成作业数据缓存键
     fn copilot_data_key(&self, id: &str) -> String {
         format!("copilot_data:{}", id)
@@ -426,23 +621,35 @@ impl CacheManagerTrait for CacheManager {
         self.get_data(&cache_key).await
     }
 
+    async fn get_stale_copilot_data(&self, key: &str) -> CopilotResult<Option<CopilotData>> {
+        let cache_key = self.copilot_data_key(key);
+        self.get_stale_data(&cache_key).await
+    }
+
+    async fn get_stale_match_results(&self, key: &str) -> CopilotResult<Option<Vec<MatchResult>>> {
+        let cache_key = format!("match_results:{}", key);
+        self.get_stale_data(&cache_key).await
+    }
+
     async fn remove(&self, key: &str) -> CopilotResult<bool> {
-        let removed = self.db.remove(key)?.is_some();
+        let removed = self.remove_entry(key)?;
         
         if removed {
             let mut stats = self.stats.lock().await;
-            stats.current_entries = stats.current_entries.saturating_sub(1);
+            stats.current_entries = self.entries.load(Ordering::Relaxed);
         }
         
         Ok(removed)
     }
 
     async fn cleanup_expired(&self) -> CopilotResult<u64> {
-        Self::cleanup_expired_internal(&self.db, &self.stats).await
+        Self::cleanup_expired_internal(&self.db, &self.lru, &self.entries, &self.stats, self.config.stale_retention()).await
     }
 
     async fn clear_all(&self) -> CopilotResult<()> {
         self.db.clear()?;
+        self.lru.clear()?;
+        self.entries.store(0, Ordering::Relaxed);
         
         let mut stats = self.stats.lock().await;
         stats.current_entries = 0;
@@ -709,4 +916,92 @@ mod tests {
         let health = manager.health_check().await.unwrap();
         assert!(health);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::new(temp_dir.path().join("test_cache").to_string_lossy().to_string())
+            .with_max_entries(2);
+        let manager = CacheManager::new(config).await.unwrap();
+        let test_data = create_test_copilot_data();
+
+        manager.store_copilot_data("a", &test_data).await.unwrap();
+        manager.store_copilot_data("b", &test_data).await.unwrap();
+
+        // 访问a，使b成为最久未访问的条目
+        assert!(manager.get_copilot_data("a").await.unwrap().is_some());
+
+        manager.store_copilot_data("c", &test_data).await.unwrap();
+
+        assert!(manager.get_copilot_data("a").await.unwrap().is_some());
+        assert!(manager.get_copilot_data("b").await.unwrap().is_none());
+        assert!(manager.get_copilot_data("c").await.unwrap().is_some());
+
+        let stats = manager.get_stats().await.unwrap();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.current_entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction_after_overwrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::new(temp_dir.path().join("test_cache").to_string_lossy().to_string())
+            .with_max_entries(2);
+        let manager = CacheManager::new(config).await.unwrap();
+        let test_data = create_test_copilot_data();
+
+        manager.store_copilot_data("a", &test_data).await.unwrap();
+        manager.store_copilot_data("b", &test_data).await.unwrap();
+        // 覆盖写入不增加条目数，且a变为最近访问
+        manager.store_copilot_data("a", &test_data).await.unwrap();
+        assert_eq!(manager.get_stats().await.unwrap().current_entries, 2);
+
+        manager.store_copilot_data("c", &test_data).await.unwrap();
+
+        assert!(manager.get_copilot_data("a").await.unwrap().is_some());
+        assert!(manager.get_copilot_data("b").await.unwrap().is_none());
+        assert!(manager.get_copilot_data("c").await.unwrap().is_some());
+
+        // 删除后条目数同步减少，不会多淘汰
+        assert!(manager.remove("copilot_data:a").await.unwrap());
+        manager.store_copilot_data("d", &test_data).await.unwrap();
+        assert!(manager.get_copilot_data("c").await.unwrap().is_some());
+        let stats = manager.get_stats().await.unwrap();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.current_entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale_on_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::new(temp_dir.path().join("test_cache").to_string_lossy().to_string())
+            .with_ttl(60, -1, 30)
+            .with_serve_stale_on_error(true);
+        let manager = CacheManager::new(config).await.unwrap();
+
+        manager.store_copilot_data("test_001", &create_test_copilot_data()).await.unwrap();
+
+        // 正常读取视为未命中，但条目被保留
+        assert!(manager.get_copilot_data("test_001").await.unwrap().is_none());
+        assert!(manager.cleanup_expired().await.unwrap() == 0);
+
+        let stale = manager.get_stale_copilot_data("test_001").await.unwrap();
+        assert_eq!(stale.unwrap().id, "test_001");
+
+        let stats = manager.get_stats().await.unwrap();
+        assert_eq!(stats.stale_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_disabled_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::new(temp_dir.path().join("test_cache").to_string_lossy().to_string())
+            .with_ttl(60, -1, 30);
+        let manager = CacheManager::new(config).await.unwrap();
+
+        manager.store_copilot_data("test_001", &create_test_copilot_data()).await.unwrap();
+
+        assert!(manager.get_stale_copilot_data("test_001").await.unwrap().is_none());
+        assert!(manager.get_copilot_data("test_001").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,237 @@
+//! 作业执行反馈
+//!
+//! 记录每个作业在不同干员阵容下的实际通关结果（MAA回调 10002 / 10000），
+//! 作为 `find_jobs` 的排序信号，让本地历史与作业站的 `recommended` 标记共同决定排序。
+
+use super::types::{CopilotError, CopilotResult};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// 作业在某个阵容下的执行记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct FeedbackRecord {
+    /// 成功次数
+    pub successes: u32,
+    /// 失败次数
+    pub failures: u32,
+    /// 最后一次执行时间
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// 最后一次是否成功
+    pub last_success: Option<bool>,
+}
+
+impl FeedbackRecord {
+    /// 总执行次数
+    pub fn total(&self) -> u32 {
+        self.successes + self.failures
+    }
+
+    /// 平滑后的成功率 (拉普拉斯平滑，无记录时为0.5)
+    pub fn success_rate(&self) -> f32 {
+        (self.successes as f32 + 1.0) / (self.total() as f32 + 2.0)
+    }
+
+    /// 记录一次执行结果
+    pub fn record(&mut self, success: bool) {
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+        self.last_run_at = Some(Utc::now());
+        self.last_success = Some(success);
+    }
+
+    /// 合并另一条记录
+    fn merge(&mut self, other: &FeedbackRecord) {
+        self.successes += other.successes;
+        self.failures += other.failures;
+        if other.last_run_at > self.last_run_at {
+            self.last_run_at = other.last_run_at;
+            self.last_success = other.last_success;
+        }
+    }
+}
+
+/// 根据干员名称生成阵容键（与顺序无关）
+pub fn roster_key<I, S>(operators: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut names: Vec<String> = operators.into_iter().map(|s| s.as_ref().to_string()).collect();
+    names.sort();
+    names.dedup();
+    names.join("+")
+}
+
+/// 作业反馈存储
+pub struct FeedbackStore {
+    db: Arc<Db>,
+}
+
+impl FeedbackStore {
+    /// 打开反馈数据库
+    pub fn open(db_path: impl AsRef<Path>) -> CopilotResult<Self> {
+        let db_path = db_path.as_ref();
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| CopilotError::CacheError(format!("Failed to create feedback directory: {}", e)))?;
+        }
+
+        let db = sled::open(db_path)
+            .map_err(|e| CopilotError::CacheError(format!("Failed to open feedback database: {}", e)))?;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    fn record_key(copilot_id: &str, roster_key: &str) -> String {
+        format!("feedback:{}:{}", copilot_id, roster_key)
+    }
+
+    /// 记录一次执行结果
+    pub fn record_outcome(&self, copilot_id: &str, roster_key: &str, success: bool) -> CopilotResult<FeedbackRecord> {
+        let key = Self::record_key(copilot_id, roster_key);
+        let mut record = self.read_record(&key)?.unwrap_or_default();
+        record.record(success);
+
+        self.db.insert(key.as_bytes(), serde_json::to_vec(&record)?)?;
+        self.db.flush()?;
+
+        tracing::debug!("记录作业反馈: {} [{}] success={}", copilot_id, roster_key, success);
+        Ok(record)
+    }
+
+    /// 获取作业在指定阵容下的记录
+    pub fn get_record(&self, copilot_id: &str, roster_key: &str) -> CopilotResult<Option<FeedbackRecord>> {
+        self.read_record(&Self::record_key(copilot_id, roster_key))
+    }
+
+    /// 获取作业在所有阵容下的汇总记录
+    pub fn get_copilot_record(&self, copilot_id: &str) -> CopilotResult<Option<FeedbackRecord>> {
+        let prefix = format!("feedback:{}:", copilot_id);
+        let mut summary: Option<FeedbackRecord> = None;
+
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = item?;
+            let record: FeedbackRecord = serde_json::from_slice(&value)?;
+            summary.get_or_insert_with(FeedbackRecord::default).merge(&record);
+        }
+
+        Ok(summary)
+    }
+
+    fn read_record(&self, key: &str) -> CopilotResult<Option<FeedbackRecord>> {
+        match self.db.get(key.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 全局反馈存储，供MAA回调记录结果
+static GLOBAL_FEEDBACK_STORE: OnceLock<Arc<FeedbackStore>> = OnceLock::new();
+
+/// 正在执行的作业: MAA任务ID -> (作业ID, 阵容键)
+static PENDING_COPILOT_RUNS: Lazy<Mutex<HashMap<i32, (String, String)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 设置全局反馈存储（由服务启动时调用）
+pub fn set_global_feedback_store(store: Arc<FeedbackStore>) {
+    if GLOBAL_FEEDBACK_STORE.set(store).is_err() {
+        tracing::warn!("全局作业反馈存储已设置，忽略重复设置");
+    }
+}
+
+/// 获取全局反馈存储
+pub fn global_feedback_store() -> Option<Arc<FeedbackStore>> {
+    GLOBAL_FEEDBACK_STORE.get().cloned()
+}
+
+/// 登记一次作业执行，等待MAA回调给出结果
+pub fn track_copilot_run(maa_task_id: i32, copilot_id: String, roster_key: String) {
+    PENDING_COPILOT_RUNS.lock().unwrap().insert(maa_task_id, (copilot_id, roster_key));
+}
+
+/// 根据MAA回调结束一次作业执行并记录结果，未登记的任务忽略
+pub fn complete_copilot_run(maa_task_id: i32, success: bool) {
+    let Some((copilot_id, roster_key)) = PENDING_COPILOT_RUNS.lock().unwrap().remove(&maa_task_id) else {
+        return;
+    };
+
+    match global_feedback_store() {
+        Some(store) => {
+            if let Err(e) = store.record_outcome(&copilot_id, &roster_key, success) {
+                tracing::warn!("记录作业反馈失败: {} - {}", copilot_id, e);
+            }
+        }
+        None => tracing::debug!("未设置作业反馈存储，跳过记录: {}", copilot_id),
+    }
+}
+
+/// 作业被手动停止（MAA回调 10004），不计入通关结果，只移除登记
+pub fn discard_copilot_run(maa_task_id: i32) {
+    if let Some((copilot_id, _)) = PENDING_COPILOT_RUNS.lock().unwrap().remove(&maa_task_id) {
+        tracing::debug!("作业执行被手动停止，不记录结果: {}", copilot_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_roster_key_is_order_independent() {
+        assert_eq!(roster_key(["陈", "夏", "陈"]), roster_key(vec!["夏".to_string(), "陈".to_string()]));
+    }
+
+    #[test]
+    fn test_success_rate() {
+        let mut record = FeedbackRecord::default();
+        assert_eq!(record.success_rate(), 0.5);
+
+        record.record(true);
+        record.record(true);
+        record.record(false);
+        assert_eq!(record.total(), 3);
+        assert!(record.success_rate() > 0.5);
+        assert_eq!(record.last_success, Some(false));
+    }
+
+    #[test]
+    fn test_feedback_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = FeedbackStore::open(temp_dir.path().join("feedback")).unwrap();
+
+        let roster_a = roster_key(["夏", "陈"]);
+        let roster_b = roster_key(["夏", "煌"]);
+
+        store.record_outcome("123", &roster_a, true).unwrap();
+        store.record_outcome("123", &roster_a, true).unwrap();
+        store.record_outcome("123", &roster_b, false).unwrap();
+
+        let record = store.get_record("123", &roster_a).unwrap().unwrap();
+        assert_eq!(record.successes, 2);
+        assert_eq!(record.failures, 0);
+
+        let summary = store.get_copilot_record("123").unwrap().unwrap();
+        assert_eq!(summary.total(), 3);
+
+        assert!(store.get_copilot_record("456").unwrap().is_none());
+        // "12" 不应匹配到 "123" 的记录
+        assert!(store.get_copilot_record("12").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_discard_copilot_run() {
+        track_copilot_run(94801, "789".to_string(), roster_key(["夏"]));
+        discard_copilot_run(94801);
+        assert!(!PENDING_COPILOT_RUNS.lock().unwrap().contains_key(&94801));
+    }
+}
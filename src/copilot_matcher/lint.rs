@@ -0,0 +1,427 @@
+//! 作业文件静态检查
+//!
+//! 在作业入队前检查MAA作业JSON的 `actions` 序列：
+//! - 部署的干员必须在 `opers` / `groups` 中声明
+//! - 技能、撤退等操作必须指向已部署的单位
+//! - 击杀数 / 费用条件应单调不减
+//! - 朝向和坐标格式正确
+//!
+//! 检查结果以结构化诊断返回，供作业导入和 `maa_copilot_enhanced` 使用。
+
+use super::types::{CopilotError, CopilotResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// 诊断级别
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    /// 可能导致非预期行为
+    Warning,
+    /// 作业无法正确执行
+    Error,
+}
+
+/// 单条诊断
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LintDiagnostic {
+    /// 诊断级别
+    pub severity: LintSeverity,
+    /// 诊断代码 (如 unknown_operator)
+    pub code: String,
+    /// 相关操作在 `actions` 中的下标
+    pub action_index: Option<usize>,
+    /// 诊断说明
+    pub message: String,
+}
+
+impl LintDiagnostic {
+    fn error(code: &str, action_index: Option<usize>, message: String) -> Self {
+        Self { severity: LintSeverity::Error, code: code.to_string(), action_index, message }
+    }
+
+    fn warning(code: &str, action_index: Option<usize>, message: String) -> Self {
+        Self { severity: LintSeverity::Warning, code: code.to_string(), action_index, message }
+    }
+}
+
+impl std::fmt::Display for LintDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.severity {
+            LintSeverity::Error => "错误",
+            LintSeverity::Warning => "警告",
+        };
+        match self.action_index {
+            Some(index) => write!(f, "[{}] actions[{}] {}: {}", level, index, self.code, self.message),
+            None => write!(f, "[{}] {}: {}", level, self.code, self.message),
+        }
+    }
+}
+
+/// 检查报告
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LintReport {
+    /// 所有诊断
+    pub diagnostics: Vec<LintDiagnostic>,
+}
+
+impl LintReport {
+    /// 作业文件无法读取或解析时的报告
+    pub fn invalid_file(message: String) -> Self {
+        Self { diagnostics: vec![LintDiagnostic::error("invalid_file", None, message)] }
+    }
+
+    /// 是否存在错误级诊断
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == LintSeverity::Error)
+    }
+
+    /// 错误级诊断
+    pub fn errors(&self) -> impl Iterator<Item = &LintDiagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == LintSeverity::Error)
+    }
+
+    /// 警告级诊断
+    pub fn warnings(&self) -> impl Iterator<Item = &LintDiagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == LintSeverity::Warning)
+    }
+
+    fn push(&mut self, diagnostic: LintDiagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+/// 操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ActionKind {
+    Deploy,
+    Skill,
+    Retreat,
+    SkillUsage,
+    SpeedUp,
+    BulletTime,
+    Output,
+    SkillDaemon,
+    MoveCamera,
+}
+
+impl ActionKind {
+    /// 解析操作类型，兼容中英文写法，缺省为部署
+    fn parse(value: Option<&str>) -> Option<Self> {
+        match value.unwrap_or("Deploy").to_lowercase().as_str() {
+            "deploy" | "部署" => Some(Self::Deploy),
+            "skill" | "技能" => Some(Self::Skill),
+            "retreat" | "撤退" => Some(Self::Retreat),
+            "skillusage" | "技能用法" => Some(Self::SkillUsage),
+            "speedup" | "二倍速" => Some(Self::SpeedUp),
+            "bullettime" | "子弹时间" => Some(Self::BulletTime),
+            "output" | "打印" => Some(Self::Output),
+            "skilldaemon" | "摆完挂机" => Some(Self::SkillDaemon),
+            "movecamera" | "移动镜头" => Some(Self::MoveCamera),
+            _ => None,
+        }
+    }
+
+    /// 是否需要指向已部署单位
+    fn targets_deployed(&self) -> bool {
+        matches!(self, Self::Skill | Self::Retreat | Self::SkillUsage)
+    }
+}
+
+const VALID_DIRECTIONS: &[&str] = &["left", "right", "up", "down", "none", "左", "右", "上", "下", "无"];
+
+/// 检查作业文件
+pub fn lint_copilot_file(path: impl AsRef<Path>) -> CopilotResult<LintReport> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| CopilotError::InvalidDataFormat(format!("Failed to read {}: {}", path.display(), e)))?;
+    let copilot: Value = serde_json::from_str(&content)?;
+    Ok(lint_copilot(&copilot))
+}
+
+/// 检查作业JSON
+pub fn lint_copilot(copilot: &Value) -> LintReport {
+    let mut report = LintReport::default();
+
+    if copilot.get("stage_name").and_then(|v| v.as_str()).unwrap_or("").is_empty() {
+        report.push(LintDiagnostic::error("missing_stage_name", None, "缺少 stage_name".to_string()));
+    }
+
+    let declared = declared_units(copilot);
+    if declared.is_empty() {
+        report.push(LintDiagnostic::warning("no_operators", None, "opers 和 groups 均为空".to_string()));
+    }
+
+    let Some(actions) = copilot.get("actions").and_then(|v| v.as_array()) else {
+        report.push(LintDiagnostic::error("missing_actions", None, "缺少 actions 数组".to_string()));
+        return report;
+    };
+
+    // 已部署单位: 名称 -> 坐标
+    let mut deployed: HashMap<String, Option<(i64, i64)>> = HashMap::new();
+    let mut max_kills: Option<(i64, usize)> = None;
+    let mut max_costs: Option<(i64, usize)> = None;
+
+    for (index, action) in actions.iter().enumerate() {
+        let type_name = action.get("type").and_then(|v| v.as_str());
+        let Some(kind) = ActionKind::parse(type_name) else {
+            report.push(LintDiagnostic::error(
+                "unknown_action_type",
+                Some(index),
+                format!("未知的操作类型: {}", type_name.unwrap_or_default()),
+            ));
+            continue;
+        };
+
+        let name = action.get("name").and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+        let location = match action.get("location") {
+            Some(value) => match parse_location(value) {
+                Some(location) => Some(location),
+                None => {
+                    report.push(LintDiagnostic::error(
+                        "invalid_location",
+                        Some(index),
+                        format!("坐标格式错误，应为两个非负整数: {}", value),
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some(direction) = action.get("direction") {
+            let valid = direction.as_str()
+                .map(|d| VALID_DIRECTIONS.contains(&d.to_lowercase().as_str()))
+                .unwrap_or(false);
+            if !valid {
+                report.push(LintDiagnostic::error(
+                    "invalid_direction",
+                    Some(index),
+                    format!("朝向格式错误: {}", direction),
+                ));
+            }
+        }
+
+        check_monotonic(&mut report, action, "kills", index, &mut max_kills);
+        check_monotonic(&mut report, action, "costs", index, &mut max_costs);
+
+        match kind {
+            ActionKind::Deploy => {
+                let Some(name) = name else {
+                    report.push(LintDiagnostic::error("missing_target", Some(index), "部署操作缺少 name".to_string()));
+                    continue;
+                };
+
+                if !declared.contains(name) {
+                    report.push(LintDiagnostic::error(
+                        "unknown_operator",
+                        Some(index),
+                        format!("{} 未在 opers 或 groups 中声明", name),
+                    ));
+                }
+
+                if action.get("location").is_none() {
+                    report.push(LintDiagnostic::error("missing_location", Some(index), format!("部署 {} 缺少 location", name)));
+                }
+
+                if deployed.contains_key(name) {
+                    report.push(LintDiagnostic::warning(
+                        "duplicate_deploy",
+                        Some(index),
+                        format!("{} 在撤退前被重复部署", name),
+                    ));
+                }
+                deployed.insert(name.to_string(), location);
+            }
+            kind if kind.targets_deployed() => {
+                let target = match (name, location) {
+                    (Some(name), _) => deployed.contains_key(name).then(|| name.to_string()),
+                    (None, Some(location)) => deployed.iter()
+                        .find(|(_, deployed_location)| **deployed_location == Some(location))
+                        .map(|(name, _)| name.clone()),
+                    (None, None) => {
+                        report.push(LintDiagnostic::error(
+                            "missing_target",
+                            Some(index),
+                            "操作需要 name 或 location 指定目标".to_string(),
+                        ));
+                        continue;
+                    }
+                };
+
+                match target {
+                    Some(target) => {
+                        if kind == ActionKind::Retreat {
+                            deployed.remove(&target);
+                        }
+                    }
+                    None => report.push(LintDiagnostic::error(
+                        "target_not_deployed",
+                        Some(index),
+                        format!(
+                            "操作目标 {} 尚未部署",
+                            name.map(str::to_string)
+                                .or_else(|| location.map(|(x, y)| format!("({}, {})", x, y)))
+                                .unwrap_or_default()
+                        ),
+                    )),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    report
+}
+
+/// 收集 opers 和 groups 中声明的单位名称
+fn declared_units(copilot: &Value) -> HashSet<String> {
+    ["opers", "groups"]
+        .iter()
+        .filter_map(|key| copilot.get(*key).and_then(|v| v.as_array()))
+        .flatten()
+        .filter_map(|unit| unit.get("name").and_then(|v| v.as_str()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// 解析坐标 [x, y]
+fn parse_location(value: &Value) -> Option<(i64, i64)> {
+    match value.as_array()?.as_slice() {
+        [x, y] => {
+            let (x, y) = (x.as_i64()?, y.as_i64()?);
+            (x >= 0 && y >= 0).then_some((x, y))
+        }
+        _ => None,
+    }
+}
+
+/// 检查条件字段是否单调不减
+fn check_monotonic(
+    report: &mut LintReport,
+    action: &Value,
+    field: &str,
+    index: usize,
+    max_seen: &mut Option<(i64, usize)>,
+) {
+    let Some(value) = action.get(field).and_then(|v| v.as_i64()) else {
+        return;
+    };
+
+    match *max_seen {
+        Some((max, max_index)) if value < max => report.push(LintDiagnostic::warning(
+            &format!("non_monotonic_{}", field),
+            Some(index),
+            format!("{} 条件 {} 小于 actions[{}] 的 {}，该条件会立即满足", field, value, max_index, max),
+        )),
+        _ => *max_seen = Some((value, index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_copilot() -> Value {
+        json!({
+            "stage_name": "1-7",
+            "opers": [{"name": "夏", "skill": 1}, {"name": "陈", "skill": 3}],
+            "groups": [{"name": "奶", "opers": [{"name": "闪灵"}]}],
+            "actions": [
+                {"type": "Deploy", "name": "夏", "location": [5, 5], "direction": "Left", "kills": 0},
+                {"type": "部署", "name": "奶", "location": [4, 5], "direction": "下", "kills": 2},
+                {"type": "Skill", "location": [5, 5], "kills": 3},
+                {"type": "Retreat", "name": "夏", "kills": 5},
+                {"name": "陈", "location": [5, 5], "direction": "Right", "kills": 6},
+                {"type": "SpeedUp"}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_valid_copilot() {
+        let report = lint_copilot(&sample_copilot());
+        assert!(report.diagnostics.is_empty(), "{:?}", report.diagnostics);
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn test_unknown_operator_and_target() {
+        let copilot = json!({
+            "stage_name": "1-7",
+            "opers": [{"name": "夏"}],
+            "actions": [
+                {"type": "Deploy", "name": "煌", "location": [1, 1], "direction": "Left"},
+                {"type": "Skill", "name": "夏"},
+                {"type": "Retreat", "location": [9, 9]},
+                {"type": "Jump"}
+            ]
+        });
+
+        let report = lint_copilot(&copilot);
+        let codes: Vec<&str> = report.errors().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, vec!["unknown_operator", "target_not_deployed", "target_not_deployed", "unknown_action_type"]);
+        assert_eq!(report.diagnostics[0].action_index, Some(0));
+    }
+
+    #[test]
+    fn test_retreated_unit_is_not_deployed() {
+        let copilot = json!({
+            "stage_name": "1-7",
+            "opers": [{"name": "夏"}],
+            "actions": [
+                {"name": "夏", "location": [1, 1], "direction": "Left"},
+                {"type": "Retreat", "name": "夏"},
+                {"type": "Skill", "name": "夏"}
+            ]
+        });
+
+        let report = lint_copilot(&copilot);
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics[0].action_index, Some(2));
+    }
+
+    #[test]
+    fn test_malformed_fields() {
+        let copilot = json!({
+            "stage_name": "1-7",
+            "opers": [{"name": "夏"}],
+            "actions": [
+                {"name": "夏", "location": [1], "direction": "Sideways"},
+                {"type": "Retreat", "name": "夏", "location": [-1, 2]}
+            ]
+        });
+
+        let report = lint_copilot(&copilot);
+        let codes: Vec<&str> = report.errors().map(|d| d.code.as_str()).collect();
+        assert!(codes.contains(&"invalid_location"));
+        assert!(codes.contains(&"invalid_direction"));
+    }
+
+    #[test]
+    fn test_non_monotonic_conditions() {
+        let copilot = json!({
+            "stage_name": "1-7",
+            "opers": [{"name": "夏"}, {"name": "陈"}],
+            "actions": [
+                {"name": "夏", "location": [1, 1], "direction": "Left", "kills": 5, "costs": 20},
+                {"name": "陈", "location": [2, 1], "direction": "Left", "kills": 3, "costs": 10}
+            ]
+        });
+
+        let report = lint_copilot(&copilot);
+        assert!(!report.has_errors());
+        let codes: Vec<&str> = report.warnings().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, vec!["non_monotonic_kills", "non_monotonic_costs"]);
+    }
+
+    #[test]
+    fn test_missing_top_level_fields() {
+        let report = lint_copilot(&json!({}));
+        let codes: Vec<&str> = report.diagnostics.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, vec!["missing_stage_name", "no_operators", "missing_actions"]);
+    }
+}
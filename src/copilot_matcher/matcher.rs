@@ -12,6 +12,12 @@ use super::{
     },
     api_client::{ApiClientTrait, QueryFilter},
     cache::CacheManagerTrait,
+    operator_catalog::{OperatorCatalog, OperatorInfo},
+    feedback::{roster_key, FeedbackStore},
+    report::{
+        MatchReport, OperatorExplanation, OperatorIssue, TrainingPlan, TrainingStep, UpgradeKind,
+        TARGET_GRADE_SCORE,
+    },
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -39,6 +45,8 @@ pub struct MatcherConfig {
     pub smart_match_config: SmartMatchConfig,
     /// 干员替换映射表
     pub operator_substitutions: HashMap<String, Vec<String>>,
+    /// 排序权重配置
+    pub ranking_weights: RankingWeights,
 }
 
 /// 简单匹配权重配置
@@ -67,6 +75,19 @@ pub struct LevelMatchWeights {
     pub mastery: f32,
 }
 
+/// 排序权重配置
+///
+/// 排序分 = 匹配总分 + 本地历史信号 × `local_history` + 推荐标记 × `recommended`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankingWeights {
+    /// 本地执行历史权重
+    pub local_history: f32,
+    /// 作业站推荐标记权重
+    pub recommended: f32,
+    /// 历史记录置信度达到一半所需的执行次数
+    pub history_confidence_runs: u32,
+}
+
 /// 智能匹配配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SmartMatchConfig {
@@ -109,6 +130,11 @@ impl Default for MatcherConfig {
                 core_operator_penalty: 0.2,
             },
             operator_substitutions: HashMap::new(),
+            ranking_weights: RankingWeights {
+                local_history: 0.3,
+                recommended: 0.05,
+                history_confidence_runs: 3,
+            },
         }
     }
 }
@@ -137,6 +163,12 @@ impl MatcherConfig {
         self
     }
 
+    /// 设置排序权重
+    pub fn with_ranking_weights(mut self, weights: RankingWeights) -> Self {
+        self.ranking_weights = weights;
+        self
+    }
+
     /// 添加干员替换映射
     pub fn add_substitution(mut self, operator: String, substitutes: Vec<String>) -> Self {
         self.operator_substitutions.insert(operator, substitutes);
@@ -153,6 +185,10 @@ impl MatcherConfig {
             return Err(CopilotError::ConfigError("Min match score must be between 0.0 and 1.0".to_string()));
         }
 
+        if self.ranking_weights.local_history < 0.0 || self.ranking_weights.recommended < 0.0 {
+            return Err(CopilotError::ConfigError("Ranking weights must not be negative".to_string()));
+        }
+
         if self.match_timeout == 0 {
             return Err(CopilotError::ConfigError("Match timeout must be positive".to_string()));
         }
@@ -236,6 +272,9 @@ pub trait CopilotMatcherTrait: Send + Sync {
     /// 智能匹配
     async fn match_smart(&self, query: &MatchQuery, copilots: &[CopilotData]) -> CopilotResult<Vec<MatchResult>>;
 
+    /// 解释匹配结果，并给出达到A级的养成计划
+    async fn explain_match(&self, query: &MatchQuery, result: &MatchResult) -> CopilotResult<MatchReport>;
+
     /// 获取匹配统计信息
     async fn get_match_stats(&self) -> CopilotResult<MatchStats>;
 }
@@ -275,6 +314,8 @@ pub struct CopilotMatcher {
     config: MatcherConfig,
     api_client: Arc<dyn ApiClientTrait>,
     cache_manager: Option<Arc<dyn CacheManagerTrait>>,
+    operator_catalog: Option<Arc<OperatorCatalog>>,
+    feedback_store: Option<Arc<FeedbackStore>>,
     stats: Arc<RwLock<MatchStats>>,
 }
 
@@ -291,10 +332,84 @@ impl CopilotMatcher {
             config,
             api_client,
             cache_manager,
+            operator_catalog: None,
+            feedback_store: None,
             stats: Arc::new(RwLock::new(MatchStats::default())),
         })
     }
 
+    /// 设置干员元数据目录，用于按职业/稀有度查找替换干员
+    pub fn with_operator_catalog(mut self, catalog: Arc<OperatorCatalog>) -> Self {
+        self.operator_catalog = Some(catalog);
+        self
+    }
+
+    /// 设置作业反馈存储，用于按本地执行历史调整排序
+    pub fn with_feedback_store(mut self, store: Arc<FeedbackStore>) -> Self {
+        self.feedback_store = Some(store);
+        self
+    }
+
+    /// 计算排序分
+    ///
+    /// 优先使用当前阵容的执行记录，没有时退回该作业所有阵容的汇总记录。
+    fn ranking_score(&self, result: &MatchResult) -> f32 {
+        let weights = &self.config.ranking_weights;
+        let mut rank = result.score.total;
+
+        if result.copilot.recommended {
+            rank += weights.recommended;
+        }
+
+        if let Some(store) = &self.feedback_store {
+            let roster = roster_key(result.copilot.operators.iter().map(|op| {
+                result.substitutions.get(&op.name).unwrap_or(&op.name)
+            }));
+
+            let record = store.get_record(&result.copilot.id, &roster)
+                .ok()
+                .flatten()
+                .or_else(|| store.get_copilot_record(&result.copilot.id).ok().flatten());
+
+            if let Some(record) = record {
+                let runs = record.total() as f32;
+                let confidence = runs / (runs + weights.history_confidence_runs.max(1) as f32);
+                rank += weights.local_history * (record.success_rate() - 0.5) * 2.0 * confidence;
+            }
+        }
+
+        rank
+    }
+
+    /// 按排序分重新排序结果
+    fn rank_results(&self, results: &mut [MatchResult]) {
+        let mut ranked: Vec<(f32, MatchResult)> = results.iter()
+            .map(|r| (self.ranking_score(r), r.clone()))
+            .collect();
+        ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        for (slot, (_, result)) in results.iter_mut().zip(ranked) {
+            *slot = result;
+        }
+    }
+
+    /// 获取过期的缓存匹配结果
+    async fn get_stale_results(&self, query: &MatchQuery) -> Option<Vec<MatchResult>> {
+        if !self.config.enable_cache {
+            return None;
+        }
+
+        let cache_manager = self.cache_manager.as_ref()?;
+        let results = cache_manager.get_stale_match_results(&query.generate_hash()).await.ok()??;
+
+        Some(results.into_iter()
+            .map(|r| {
+                let details = format!("{} (离线缓存)", r.details);
+                r.with_details(details)
+            })
+            .collect())
+    }
+
     /// 执行简单匹配计算
     fn calculate_simple_match_score(&self, query: &MatchQuery, copilot: &CopilotData) -> MatchScore {
         let mut score = MatchScore::new();
@@ -350,33 +465,30 @@ impl CopilotMatcher {
                 };
                 level_scores.push(level_ratio);
 
-                // 精英化匹配
-                let elite_match = if available_op.min_elite >= required_op.elite {
+                // 精英化匹配（含技能解锁条件）
+                let required_elite = required_op.required_elite();
+                let elite_match = if available_op.min_elite >= required_elite {
                     1.0
                 } else {
-                    available_op.min_elite as f32 / required_op.elite.max(1) as f32
+                    available_op.min_elite as f32 / required_elite.max(1) as f32
                 };
                 elite_scores.push(elite_match);
 
                 // 技能等级匹配
-                let skill_match = if available_op.skill_level >= required_op.skill {
+                let required_skill_level = required_op.required_skill_level();
+                let skill_match = if available_op.skill_level >= required_skill_level {
                     1.0
                 } else {
-                    available_op.skill_level as f32 / required_op.skill.max(1) as f32
+                    available_op.skill_level as f32 / required_skill_level as f32
                 };
                 skill_scores.push(skill_match);
 
-                // 专精匹配
-                let mastery_match = if let Some((_, mastery_level)) = available_op.mastery {
-                    if mastery_level >= required_op.mastery {
-                        1.0
-                    } else {
-                        mastery_level as f32 / required_op.mastery.max(1) as f32
-                    }
-                } else if required_op.mastery == 0 {
+                // 专精匹配，只计作业所用技能的专精
+                let mastery_level = available_op.mastery_on(required_op.skill);
+                let mastery_match = if mastery_level >= required_op.mastery {
                     1.0
                 } else {
-                    0.0
+                    mastery_level as f32 / required_op.mastery as f32
                 };
                 mastery_scores.push(mastery_match);
             } else {
@@ -471,7 +583,44 @@ impl CopilotMatcher {
             }
         }
 
-        None
+        // 最后根据干员目录查找同职业替换
+        self.find_catalog_substitute(missing_op, available_ops, query)
+    }
+
+    /// 根据干员目录查找同职业替换
+    ///
+    /// 优先同分支职业，其次稀有度差距小、稀有度高的干员。
+    /// 未启用稀有度替换时，只接受稀有度不低于原干员的候选。
+    fn find_catalog_substitute(
+        &self,
+        missing_op: &StageOperator,
+        available_ops: &HashSet<String>,
+        query: &MatchQuery,
+    ) -> Option<String> {
+        let config = &self.config.smart_match_config;
+        if !config.enable_class_substitution {
+            return None;
+        }
+
+        let catalog = self.operator_catalog.as_ref()?;
+        let missing_info = catalog.get(&missing_op.name)?;
+
+        let mut candidates: Vec<(&String, &OperatorInfo)> = available_ops
+            .iter()
+            .filter_map(|name| catalog.get(name).map(|info| (name, info)))
+            .filter(|(_, info)| info.name != missing_info.name && info.class == missing_info.class)
+            .filter(|(_, info)| config.enable_rarity_substitution || info.rarity >= missing_info.rarity)
+            .filter(|(name, _)| self.check_substitute_requirements(name, missing_op, query))
+            .collect();
+
+        candidates.sort_by(|(name_a, a), (name_b, b)| {
+            b.same_subclass(missing_info).cmp(&a.same_subclass(missing_info))
+                .then_with(|| a.rarity.abs_diff(missing_info.rarity).cmp(&b.rarity.abs_diff(missing_info.rarity)))
+                .then_with(|| b.rarity.cmp(&a.rarity))
+                .then_with(|| name_a.cmp(name_b))
+        });
+
+        candidates.first().map(|(name, _)| (*name).clone())
     }
 
     /// 检查替换干员是否满足要求
@@ -487,28 +636,229 @@ impl CopilotMatcher {
                 return false;
             }
 
-            // 检查精英化要求
-            if substitute.min_elite < required_op.elite {
+            // 检查精英化要求（含技能解锁条件）
+            if substitute.min_elite < required_op.required_elite() {
                 return false;
             }
 
-            // 检查技能要求
-            if substitute.skill_level < required_op.skill {
+            // 检查技能等级要求
+            if substitute.skill_level < required_op.required_skill_level() {
                 return false;
             }
 
             // 检查专精要求
-            if let Some((_, mastery_level)) = substitute.mastery {
-                if mastery_level < required_op.mastery {
-                    return false;
+            substitute.mastery_on(required_op.skill) >= required_op.mastery
+        } else {
+            false
+        }
+    }
+
+    /// 生成匹配报告
+    pub fn build_match_report(&self, query: &MatchQuery, result: &MatchResult) -> MatchReport {
+        let copilot = &result.copilot;
+        let available_map: HashMap<&str, &OperatorRequirement> = query.available_operators
+            .iter()
+            .map(|op| (op.name.as_str(), op))
+            .collect();
+
+        let operators: Vec<OperatorExplanation> = copilot.operators
+            .iter()
+            .map(|required_op| {
+                let issues = match available_map.get(required_op.name.as_str()) {
+                    Some(available_op) => Self::collect_operator_issues(required_op, available_op),
+                    None => match result.substitutions.get(&required_op.name) {
+                        Some(by) => vec![OperatorIssue::Substituted { by: by.clone() }],
+                        None => vec![OperatorIssue::Missing],
+                    },
+                };
+
+                OperatorExplanation {
+                    name: required_op.name.clone(),
+                    is_core: required_op.is_core,
+                    issues,
                 }
-            } else if required_op.mastery > 0 {
-                return false;
-            }
+            })
+            .collect();
+
+        // 以完全满足作业要求时的得分为满分，避免权重上限导致等级失真
+        let max_score = self.report_score(&Self::fully_trained_query(query, copilot), copilot, result.stage);
+        let relative_score = Self::relative_score(self.report_score(query, copilot, result.stage), max_score);
+
+        let mut training_plan = self.plan_training(query, copilot, result.stage, max_score);
+        training_plan.blocked_by = operators.iter()
+            .filter(|op| op.issues.contains(&OperatorIssue::Missing))
+            .map(|op| op.name.clone())
+            .collect();
+
+        MatchReport {
+            copilot_id: copilot.id.clone(),
+            copilot_name: copilot.name.clone(),
+            stage_id: copilot.stage_id.clone(),
+            score: result.score.clone(),
+            relative_score,
+            grade: MatchReport::grade_for(relative_score),
+            operators,
+            training_plan,
+            generated_at: Utc::now(),
+        }
+    }
+
+    /// 对比作业要求与持有干员，列出不满足的项目
+    fn collect_operator_issues(required_op: &StageOperator, available_op: &OperatorRequirement) -> Vec<OperatorIssue> {
+        let mut issues = Vec::new();
+
+        if available_op.min_elite < required_op.required_elite() {
+            issues.push(OperatorIssue::NeedsElite { current: available_op.min_elite, required: required_op.required_elite() });
+        }
+
+        if available_op.min_level < required_op.level {
+            issues.push(OperatorIssue::UnderLeveled { current: available_op.min_level, required: required_op.level });
+        }
+
+        if available_op.skill_level < required_op.required_skill_level() {
+            issues.push(OperatorIssue::NeedsSkillLevel { current: available_op.skill_level, required: required_op.required_skill_level() });
+        }
+
+        let current_mastery = available_op.mastery_on(required_op.skill);
+        if current_mastery < required_op.mastery {
+            issues.push(OperatorIssue::NeedsMastery {
+                skill: required_op.skill,
+                current: current_mastery,
+                required: required_op.mastery,
+            });
+        }
+
+        issues
+    }
 
-            true
+    /// 按匹配阶段计算报告使用的得分
+    fn report_score(&self, query: &MatchQuery, copilot: &CopilotData, stage: MatchStage) -> f32 {
+        match stage {
+            MatchStage::Smart => self.calculate_smart_match_score(query, copilot).0.total,
+            _ => self.calculate_level_match_score(query, copilot).total,
+        }
+    }
+
+    /// 计算相对得分
+    fn relative_score(score: f32, max_score: f32) -> f32 {
+        if max_score > 0.0 {
+            (score / max_score).clamp(0.0, 1.0)
         } else {
-            false
+            0.0
+        }
+    }
+
+    /// 构造所有已持有干员都满足作业要求的查询
+    fn fully_trained_query(query: &MatchQuery, copilot: &CopilotData) -> MatchQuery {
+        let mut trained = query.clone();
+
+        for required_op in &copilot.operators {
+            match trained.available_operators.iter_mut().find(|op| op.name == required_op.name) {
+                Some(op) => {
+                    op.min_level = op.min_level.max(required_op.level);
+                    op.min_elite = op.min_elite.max(required_op.required_elite());
+                    op.skill_level = op.skill_level.max(required_op.required_skill_level());
+                    if op.mastery_on(required_op.skill) < required_op.mastery {
+                        op.mastery = Some((required_op.skill, required_op.mastery));
+                    }
+                }
+                None => {
+                    let mut op = OperatorRequirement::new(required_op.name.clone(), required_op.level)
+                        .with_elite(required_op.required_elite())
+                        .with_skill_level(required_op.required_skill_level());
+                    if required_op.mastery > 0 {
+                        op = op.with_mastery(required_op.skill, required_op.mastery);
+                    }
+                    trained.available_operators.push(op);
+                }
+            }
+        }
+
+        trained
+    }
+
+    /// 列出已持有干员的可选养成项目
+    fn upgrade_candidates(query: &MatchQuery, copilot: &CopilotData) -> Vec<(String, UpgradeKind)> {
+        let mut candidates = Vec::new();
+
+        for required_op in &copilot.operators {
+            let Some(op) = query.available_operators.iter().find(|op| op.name == required_op.name) else {
+                continue;
+            };
+
+            if op.min_elite < required_op.required_elite() {
+                candidates.push((op.name.clone(), UpgradeKind::Elite { from: op.min_elite, to: required_op.required_elite() }));
+            }
+            if op.min_level < required_op.level {
+                candidates.push((op.name.clone(), UpgradeKind::Level { from: op.min_level, to: required_op.level }));
+            }
+            if op.skill_level < required_op.required_skill_level() {
+                candidates.push((op.name.clone(), UpgradeKind::SkillLevel { from: op.skill_level, to: required_op.required_skill_level() }));
+            }
+            let current_mastery = op.mastery_on(required_op.skill);
+            if current_mastery < required_op.mastery {
+                candidates.push((op.name.clone(), UpgradeKind::Mastery {
+                    skill: required_op.skill,
+                    from: current_mastery,
+                    to: required_op.mastery,
+                }));
+            }
+        }
+
+        candidates
+    }
+
+    /// 将养成项目应用到查询中的干员
+    fn apply_upgrade(query: &mut MatchQuery, operator: &str, upgrade: &UpgradeKind) {
+        if let Some(op) = query.available_operators.iter_mut().find(|op| op.name == operator) {
+            match upgrade {
+                UpgradeKind::Level { to, .. } => op.min_level = *to,
+                UpgradeKind::Elite { to, .. } => op.min_elite = *to,
+                UpgradeKind::SkillLevel { to, .. } => op.skill_level = *to,
+                UpgradeKind::Mastery { skill, to, .. } => op.mastery = Some((*skill, *to)),
+            }
+        }
+    }
+
+    /// 贪心生成养成计划：每次选择单位成本提升最大的项目，直到达到A级
+    fn plan_training(&self, query: &MatchQuery, copilot: &CopilotData, stage: MatchStage, max_score: f32) -> TrainingPlan {
+        let mut working = query.clone();
+        let mut current = Self::relative_score(self.report_score(&working, copilot, stage), max_score);
+        let mut steps = Vec::new();
+
+        while current < TARGET_GRADE_SCORE {
+            let best = Self::upgrade_candidates(&working, copilot)
+                .into_iter()
+                .filter_map(|(operator, upgrade)| {
+                    let mut trial = working.clone();
+                    Self::apply_upgrade(&mut trial, &operator, &upgrade);
+                    let score = Self::relative_score(self.report_score(&trial, copilot, stage), max_score);
+                    let gain = score - current;
+                    let cost = upgrade.estimated_cost().max(1);
+                    (gain > f32::EPSILON).then(|| (gain / cost as f32, operator, upgrade, trial, score))
+                })
+                .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let Some((_, operator, upgrade, trial, score)) = best else {
+                break;
+            };
+
+            steps.push(TrainingStep {
+                operator,
+                cost: upgrade.estimated_cost(),
+                upgrade,
+                score_after: score,
+            });
+            working = trial;
+            current = score;
+        }
+
+        TrainingPlan {
+            total_cost: steps.iter().map(|step| step.cost).sum(),
+            steps,
+            projected_score: current,
+            reaches_target: current >= TARGET_GRADE_SCORE,
+            blocked_by: Vec::new(),
         }
     }
 
@@ -557,6 +907,8 @@ impl CopilotMatcherTrait for CopilotMatcher {
                 let cache_key = query.generate_hash();
                 if let Ok(Some(cached_results)) = cache_manager.get_match_results(&cache_key).await {
                     cache_hit = true;
+                    let mut cached_results = cached_results;
+                    self.rank_results(&mut cached_results);
                     self.update_stats(MatchStage::Simple, true, start_time.elapsed().as_millis() as u64, cache_hit).await;
                     return Ok(cached_results);
                 }
@@ -567,7 +919,19 @@ impl CopilotMatcherTrait for CopilotMatcher {
         let mut filter = query.filters.clone().unwrap_or_default();
         filter.stage_id = Some(query.stage_id.clone());
 
-        let copilots = self.api_client.get_copilots(Some(filter), None).await?;
+        let copilots = match self.api_client.get_copilots(Some(filter), None).await {
+            Ok(copilots) => copilots,
+            Err(e) => {
+                // 上游不可用时尝试返回过期的缓存结果
+                if let Some(mut stale_results) = self.get_stale_results(query).await {
+                    tracing::warn!("作业站不可用，返回过期缓存结果: {}", e);
+                    self.rank_results(&mut stale_results);
+                    self.update_stats(MatchStage::Simple, true, start_time.elapsed().as_millis() as u64, true).await;
+                    return Ok(stale_results);
+                }
+                return Err(e);
+            }
+        };
         
         if copilots.is_empty() {
             self.update_stats(MatchStage::Simple, false, start_time.elapsed().as_millis() as u64, cache_hit).await;
@@ -600,6 +964,9 @@ impl CopilotMatcherTrait for CopilotMatcher {
         // 过滤低分结果
         results.retain(|r| r.score.total >= self.config.min_match_score);
 
+        // 结合推荐标记与本地执行历史排序
+        self.rank_results(&mut results);
+
         // 缓存结果（如果有结果且启用缓存）
         if !results.is_empty() && self.config.enable_cache {
             if let Some(cache_manager) = &self.cache_manager {
@@ -672,6 +1039,10 @@ impl CopilotMatcherTrait for CopilotMatcher {
         Ok(results)
     }
 
+    async fn explain_match(&self, query: &MatchQuery, result: &MatchResult) -> CopilotResult<MatchReport> {
+        Ok(self.build_match_report(query, result))
+    }
+
     async fn get_match_stats(&self) -> CopilotResult<MatchStats> {
         let stats = self.stats.read().await;
         Ok(stats.clone())
@@ -938,4 +1309,160 @@ mod tests {
         assert!(substitute.is_some());
         assert!(["陈", "山"].contains(&substitute.unwrap().as_str()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_catalog_substitute_finding() {
+        use crate::copilot_matcher::operator_catalog::{OperatorClass, OperatorInfo};
+
+        let mut catalog = OperatorCatalog::new();
+        catalog.insert(OperatorInfo::new("char_017_huang".to_string(), "煌".to_string(), OperatorClass::Guard, 6)
+            .with_subclass("centurion".to_string()));
+        catalog.insert(OperatorInfo::new("char_010_chen".to_string(), "陈".to_string(), OperatorClass::Guard, 6)
+            .with_subclass("swordmaster".to_string()));
+        catalog.insert(OperatorInfo::new("char_155_tiger".to_string(), "因陀罗".to_string(), OperatorClass::Guard, 5)
+            .with_subclass("centurion".to_string()));
+        catalog.insert(OperatorInfo::new("char_123_fang".to_string(), "芬".to_string(), OperatorClass::Vanguard, 3));
+
+        let missing_op = StageOperator::new("煌".to_string(), 1).with_level(50).with_elite(2);
+        let available_ops: HashSet<String> = ["陈", "因陀罗", "芬"].iter().map(|s| s.to_string()).collect();
+        let query = MatchQuery::new("1-7".to_string(), vec![
+            create_test_operator_requirement("陈", 60),
+            create_test_operator_requirement("因陀罗", 60),
+            create_test_operator_requirement("芬", 60),
+        ]);
+
+        let api_client = Arc::new(MockApiClient::new(vec![])) as Arc<dyn ApiClientTrait>;
+        let matcher = CopilotMatcher::new(MatcherConfig::new(), api_client.clone(), None).unwrap()
+            .with_operator_catalog(Arc::new(catalog.clone()));
+
+        // 同分支优先于同稀有度
+        let substitute = matcher.find_substitute(&missing_op, &available_ops, &query);
+        assert_eq!(substitute.as_deref(), Some("因陀罗"));
+
+        // 关闭稀有度替换后只接受不低于原稀有度的干员
+        let mut config = MatcherConfig::new();
+        config.smart_match_config.enable_rarity_substitution = false;
+        let matcher = CopilotMatcher::new(config, api_client.clone(), None).unwrap()
+            .with_operator_catalog(Arc::new(catalog.clone()));
+        let substitute = matcher.find_substitute(&missing_op, &available_ops, &query);
+        assert_eq!(substitute.as_deref(), Some("陈"));
+
+        // 关闭职业替换后不使用目录
+        let mut config = MatcherConfig::new();
+        config.smart_match_config.enable_class_substitution = false;
+        let matcher = CopilotMatcher::new(config, api_client, None).unwrap()
+            .with_operator_catalog(Arc::new(catalog));
+        assert!(matcher.find_substitute(&missing_op, &available_ops, &query).is_none());
+    }
+
+    #[test]
+    fn test_match_report_explanations_and_plan() {
+        let api_client = Arc::new(MockApiClient::new(vec![])) as Arc<dyn ApiClientTrait>;
+        let matcher = CopilotMatcher::new(MatcherConfig::new(), api_client, None).unwrap();
+
+        let mut copilot = create_test_copilot_data("1", "1-7", vec![("夏", 1, 60, 2), ("陈", 2, 80, 2), ("山", 3, 50, 1)]);
+        copilot.operators[1].skill = 3;
+        copilot.operators[1].mastery = 3;
+
+        let query = MatchQuery::new("1-7".to_string(), vec![
+            OperatorRequirement::new("夏".to_string(), 60).with_elite(2).with_skill_level(7),
+            OperatorRequirement::new("陈".to_string(), 40).with_elite(1).with_skill_level(7),
+        ]);
+
+        let score = matcher.calculate_level_match_score(&query, &copilot);
+        let result = MatchResult::new(copilot, score, MatchStage::Level);
+        let report = matcher.build_match_report(&query, &result);
+
+        assert!(report.operators[0].is_satisfied());
+        assert!(report.operators[1].issues.contains(&OperatorIssue::UnderLeveled { current: 40, required: 80 }));
+        assert!(report.operators[1].issues.contains(&OperatorIssue::NeedsElite { current: 1, required: 2 }));
+        assert!(report.operators[1].issues.contains(&OperatorIssue::NeedsMastery { skill: 3, current: 0, required: 3 }));
+        assert_eq!(report.operators[2].issues, vec![OperatorIssue::Missing]);
+        assert_eq!(report.training_plan.blocked_by, vec!["山".to_string()]);
+
+        // 养成计划只涉及已持有的干员，且得分单调提升
+        let plan = &report.training_plan;
+        assert!(!plan.steps.is_empty());
+        assert!(plan.steps.iter().all(|step| step.operator == "陈"));
+        assert!(plan.steps.windows(2).all(|w| w[1].score_after >= w[0].score_after));
+        assert!(plan.projected_score > report.relative_score);
+
+        let message = report.to_chat_message();
+        assert!(message.contains("需要S3M3"));
+        assert!(message.contains("需要先获取干员: 山"));
+    }
+
+    #[test]
+    fn test_skill_index_and_mastery_requirements() {
+        let api_client = Arc::new(MockApiClient::new(vec![])) as Arc<dyn ApiClientTrait>;
+        let matcher = CopilotMatcher::new(MatcherConfig::new(), api_client, None).unwrap();
+
+        // 作业使用3技能（技能序号3），没有专精要求
+        let mut copilot = create_test_copilot_data("1", "1-7", vec![("陈", 1, 50, 1)]);
+        copilot.operators[0].skill = 3;
+
+        // 技能等级3大于技能序号，但精一没有3技能
+        let elite1 = OperatorRequirement::new("陈".to_string(), 50).with_elite(1).with_skill_level(3);
+        let issues = CopilotMatcher::collect_operator_issues(&copilot.operators[0], &elite1);
+        assert_eq!(issues, vec![OperatorIssue::NeedsElite { current: 1, required: 2 }]);
+
+        // 精二、技能等级1：已有3技能，没有专精要求时技能等级不限
+        let elite2 = OperatorRequirement::new("陈".to_string(), 50).with_elite(2).with_skill_level(1);
+        assert!(CopilotMatcher::collect_operator_issues(&copilot.operators[0], &elite2).is_empty());
+        let query = MatchQuery::new("1-7".to_string(), vec![elite2]);
+        assert!(matcher.calculate_level_match_score(&query, &copilot).skill_match > 0.0);
+        let trained = CopilotMatcher::fully_trained_query(&MatchQuery::new("1-7".to_string(), vec![]), &copilot);
+        assert_eq!((trained.available_operators[0].min_elite, trained.available_operators[0].skill_level), (2, 1));
+
+        // 要求S3M3时，1技能的专精不算数
+        copilot.operators[0].mastery = 3;
+        let wrong_skill = OperatorRequirement::new("陈".to_string(), 50).with_elite(2).with_skill_level(7).with_mastery(1, 3);
+        let issues = CopilotMatcher::collect_operator_issues(&copilot.operators[0], &wrong_skill);
+        assert_eq!(issues, vec![OperatorIssue::NeedsMastery { skill: 3, current: 0, required: 3 }]);
+        let right_skill = OperatorRequirement::new("陈".to_string(), 50).with_elite(2).with_skill_level(7).with_mastery(3, 3);
+        assert!(CopilotMatcher::collect_operator_issues(&copilot.operators[0], &right_skill).is_empty());
+
+        let wrong_score = matcher.calculate_level_match_score(&MatchQuery::new("1-7".to_string(), vec![wrong_skill]), &copilot);
+        let right_score = matcher.calculate_level_match_score(&MatchQuery::new("1-7".to_string(), vec![right_skill]), &copilot);
+        assert!(right_score.mastery_match > wrong_score.mastery_match);
+    }
+
+    #[tokio::test]
+    async fn test_feedback_ranking() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(FeedbackStore::open(temp_dir.path().join("feedback")).unwrap());
+
+        let mut recommended = create_test_copilot_data("1", "1-7", vec![("夏", 1, 60, 2)]);
+        recommended.recommended = true;
+        let proven = create_test_copilot_data("2", "1-7", vec![("夏", 1, 60, 2)]);
+
+        let roster = roster_key(["夏"]);
+        for _ in 0..5 {
+            store.record_outcome("2", &roster, true).unwrap();
+            store.record_outcome("1", &roster, false).unwrap();
+        }
+
+        let api_client = Arc::new(MockApiClient::new(vec![recommended, proven])) as Arc<dyn ApiClientTrait>;
+        let config = MatcherConfig::new().with_cache(false).with_min_score(0.1);
+        let query = MatchQuery::new("1-7".to_string(), vec![create_test_operator_requirement("夏", 60)])
+            .with_max_stage(MatchStage::Level);
+
+        // 没有反馈存储时推荐作业排在前面
+        let matcher = CopilotMatcher::new(config.clone(), api_client.clone(), None).unwrap();
+        let results = matcher.find_jobs(&query).await.unwrap();
+        assert_eq!(results[0].copilot.id, "1");
+
+        // 本地历史权重高于推荐标记
+        let matcher = CopilotMatcher::new(config.clone(), api_client.clone(), None).unwrap()
+            .with_feedback_store(store.clone());
+        let results = matcher.find_jobs(&query).await.unwrap();
+        assert_eq!(results[0].copilot.id, "2");
+
+        // 关闭本地历史权重后恢复按推荐排序
+        let weights = RankingWeights { local_history: 0.0, ..config.ranking_weights.clone() };
+        let matcher = CopilotMatcher::new(config.with_ranking_weights(weights), api_client, None).unwrap()
+            .with_feedback_store(store);
+        let results = matcher.find_jobs(&query).await.unwrap();
+        assert_eq!(results[0].copilot.id, "1");
+    }
+}
@@ -11,6 +11,13 @@ pub mod types;
 pub mod api_client;
 pub mod cache;
 pub mod matcher;
+pub mod feedback;
+pub mod lint;
+pub mod source;
+pub mod operator_catalog;
+pub mod report;
+
+use std::sync::Arc;
 
 // 重新导出核心类型和特征
 pub use types::{
@@ -41,8 +48,69 @@ pub use matcher::{
     CopilotMatcher,
     CopilotMatcherTrait,
     MatcherConfig,
+    RankingWeights,
+};
+
+pub use feedback::{
+    FeedbackRecord,
+    FeedbackStore,
+    roster_key,
+};
+
+pub use operator_catalog::{
+    OperatorCatalog,
+    OperatorClass,
+    OperatorInfo,
+};
+
+pub use lint::{
+    LintDiagnostic,
+    LintReport,
+    LintSeverity,
+    lint_copilot,
+    lint_copilot_file,
+};
+
+pub use source::{
+    CopilotSource,
+    load_copilot,
+    save_remote_copilot,
 };
 
+pub use report::{
+    MatchReport,
+    OperatorExplanation,
+    OperatorIssue,
+    TrainingPlan,
+    TrainingStep,
+};
+
+/// 创建服务使用的作业匹配器
+///
+/// 连接作业站并打开 `copilot.cache_db_path` 缓存（打开失败时不使用缓存），
+/// 已设置全局作业反馈存储时按本地通关记录参与排序，需在 `set_global_feedback_store` 之后调用。
+/// MAA资源目录中的干员元数据用于按职业/稀有度查找替换干员，加载失败时只按作业给出的替换建议替换。
+pub async fn create_copilot_matcher() -> CopilotResult<CopilotMatcher> {
+    let api_client = Arc::new(ApiClient::new(ApiConfig::default())?) as Arc<dyn ApiClientTrait>;
+    let cache_manager = match CacheManager::new(CacheConfig::new(crate::config::CONFIG.copilot.cache_db_path.clone())).await {
+        Ok(cache) => Some(Arc::new(cache) as Arc<dyn CacheManagerTrait>),
+        Err(e) => {
+            tracing::warn!("作业缓存打开失败，匹配结果不会被缓存: {}", e);
+            None
+        }
+    };
+
+    let mut matcher = CopilotMatcher::new(MatcherConfig::new(), api_client, cache_manager)?;
+    if let Some(store) = feedback::global_feedback_store() {
+        matcher = matcher.with_feedback_store(store);
+    }
+    match OperatorCatalog::load_from_resource_dir(crate::maa_core::find_resource_path()) {
+        Ok(catalog) => matcher = matcher.with_operator_catalog(Arc::new(catalog)),
+        Err(e) => tracing::warn!("干员元数据加载失败，作业匹配不按职业/稀有度替换干员: {}", e),
+    }
+    Ok(matcher)
+}
+
 /// 作业匹配器模块的便捷重导出
 pub mod prelude {
     pub use super::{
@@ -0,0 +1,335 @@
+//! 干员元数据目录
+//!
+//! 从MAA资源目录（离线文件 `battle_data.json`）加载干员的职业、分支、稀有度和别名，
+//! 供智能匹配阶段进行同职业/同分支的干员替换。
+
+use super::types::{CopilotError, CopilotResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// 干员职业
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OperatorClass {
+    /// 先锋
+    Vanguard,
+    /// 近卫
+    Guard,
+    /// 重装
+    Defender,
+    /// 狙击
+    Sniper,
+    /// 术师
+    Caster,
+    /// 医疗
+    Medic,
+    /// 辅助
+    Supporter,
+    /// 特种
+    Specialist,
+}
+
+impl OperatorClass {
+    /// 从游戏数据中的 profession 字段解析职业
+    pub fn from_profession(profession: &str) -> Option<Self> {
+        match profession.to_uppercase().as_str() {
+            "PIONEER" => Some(Self::Vanguard),
+            "WARRIOR" => Some(Self::Guard),
+            "TANK" => Some(Self::Defender),
+            "SNIPER" => Some(Self::Sniper),
+            "CASTER" => Some(Self::Caster),
+            "MEDIC" => Some(Self::Medic),
+            "SUPPORT" => Some(Self::Supporter),
+            "SPECIAL" => Some(Self::Specialist),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OperatorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperatorClass::Vanguard => write!(f, "先锋"),
+            OperatorClass::Guard => write!(f, "近卫"),
+            OperatorClass::Defender => write!(f, "重装"),
+            OperatorClass::Sniper => write!(f, "狙击"),
+            OperatorClass::Caster => write!(f, "术师"),
+            OperatorClass::Medic => write!(f, "医疗"),
+            OperatorClass::Supporter => write!(f, "辅助"),
+            OperatorClass::Specialist => write!(f, "特种"),
+        }
+    }
+}
+
+/// 干员元数据
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OperatorInfo {
+    /// 游戏内部ID (如 char_002_amiya)
+    pub id: String,
+    /// 干员名称
+    pub name: String,
+    /// 职业
+    pub class: OperatorClass,
+    /// 分支职业 (如 "fearless")，资源文件未提供时为空
+    pub subclass: Option<String>,
+    /// 稀有度 (1-6星)
+    pub rarity: u32,
+    /// 别名 (其他服务器名称等)
+    pub aliases: Vec<String>,
+}
+
+impl OperatorInfo {
+    /// 创建新的干员元数据
+    pub fn new(id: String, name: String, class: OperatorClass, rarity: u32) -> Self {
+        Self {
+            id,
+            name,
+            class,
+            subclass: None,
+            rarity,
+            aliases: Vec::new(),
+        }
+    }
+
+    /// 设置分支职业
+    pub fn with_subclass(mut self, subclass: String) -> Self {
+        self.subclass = Some(subclass);
+        self
+    }
+
+    /// 添加别名
+    pub fn with_alias(mut self, alias: String) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    /// 是否与另一个干员属于同一分支职业
+    pub fn same_subclass(&self, other: &OperatorInfo) -> bool {
+        match (&self.subclass, &other.subclass) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// 干员元数据目录
+#[derive(Debug, Clone, Default)]
+pub struct OperatorCatalog {
+    /// 干员名称 -> 元数据
+    operators: HashMap<String, OperatorInfo>,
+    /// 别名/ID -> 干员名称
+    aliases: HashMap<String, String>,
+}
+
+impl OperatorCatalog {
+    /// 资源目录中的战斗数据文件名
+    pub const BATTLE_DATA_FILE: &'static str = "battle_data.json";
+
+    /// 创建空目录
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从MAA资源目录加载
+    ///
+    /// 同时支持MAA根目录（包含 `resource/` 子目录）和资源目录本身。
+    pub fn load_from_resource_dir(path: impl AsRef<Path>) -> CopilotResult<Self> {
+        let path = path.as_ref();
+        let candidates: [PathBuf; 2] = [
+            path.join(Self::BATTLE_DATA_FILE),
+            path.join("resource").join(Self::BATTLE_DATA_FILE),
+        ];
+
+        let file = candidates.iter()
+            .find(|p| p.exists())
+            .ok_or_else(|| CopilotError::ConfigError(
+                format!("{} not found under {}", Self::BATTLE_DATA_FILE, path.display())
+            ))?;
+
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| CopilotError::ConfigError(format!("Failed to read {}: {}", file.display(), e)))?;
+
+        let catalog = Self::from_battle_data_str(&content)?;
+        tracing::info!("已加载干员元数据 {} 条: {}", catalog.len(), file.display());
+        Ok(catalog)
+    }
+
+    /// 从 battle_data.json 内容解析
+    pub fn from_battle_data_str(content: &str) -> CopilotResult<Self> {
+        let root: Value = serde_json::from_str(content)?;
+        let chars = root.get("chars")
+            .and_then(|c| c.as_object())
+            .ok_or_else(|| CopilotError::InvalidDataFormat("battle_data.json missing `chars` object".to_string()))?;
+
+        let mut catalog = Self::new();
+        for (id, entry) in chars {
+            if let Some(info) = Self::parse_char_entry(id, entry) {
+                catalog.insert(info);
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    /// 解析单个干员条目，召唤物/装置等非干员条目返回None
+    fn parse_char_entry(id: &str, entry: &Value) -> Option<OperatorInfo> {
+        if !id.starts_with("char_") {
+            return None;
+        }
+
+        let name = entry.get("name").and_then(|v| v.as_str())?;
+        let class = entry.get("profession")
+            .and_then(|v| v.as_str())
+            .and_then(OperatorClass::from_profession)?;
+        let rarity = Self::parse_rarity(entry.get("rarity")?)?;
+
+        let mut info = OperatorInfo::new(id.to_string(), name.to_string(), class, rarity);
+
+        if let Some(subclass) = entry.get("subProfessionId")
+            .or_else(|| entry.get("sub_profession"))
+            .and_then(|v| v.as_str())
+        {
+            info = info.with_subclass(subclass.to_string());
+        }
+
+        for key in ["name_en", "name_jp", "name_kr", "name_tw", "appellation"] {
+            if let Some(alias) = entry.get(key).and_then(|v| v.as_str()) {
+                if !alias.is_empty() && alias != name && !info.aliases.iter().any(|a| a == alias) {
+                    info = info.with_alias(alias.to_string());
+                }
+            }
+        }
+
+        Some(info)
+    }
+
+    /// 解析稀有度，兼容数字 (1-6) 和 "TIER_n" 两种格式
+    fn parse_rarity(value: &Value) -> Option<u32> {
+        if let Some(n) = value.as_u64() {
+            return Some(n as u32);
+        }
+        value.as_str()
+            .and_then(|s| s.strip_prefix("TIER_"))
+            .and_then(|n| n.parse().ok())
+    }
+
+    /// 添加干员元数据
+    pub fn insert(&mut self, info: OperatorInfo) {
+        self.aliases.insert(info.id.clone(), info.name.clone());
+        for alias in &info.aliases {
+            self.aliases.insert(alias.clone(), info.name.clone());
+        }
+        self.operators.insert(info.name.clone(), info);
+    }
+
+    /// 根据名称、别名或ID查找干员
+    pub fn get(&self, name: &str) -> Option<&OperatorInfo> {
+        self.operators.get(name).or_else(|| {
+            self.aliases.get(name).and_then(|canonical| self.operators.get(canonical))
+        })
+    }
+
+    /// 将名称或别名解析为目录中的标准名称
+    pub fn canonical_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.get(name).map(|info| info.name.as_str()).unwrap_or(name)
+    }
+
+    /// 获取指定职业的所有干员
+    pub fn operators_of_class(&self, class: OperatorClass) -> Vec<&OperatorInfo> {
+        self.operators.values().filter(|info| info.class == class).collect()
+    }
+
+    /// 目录中的干员数量
+    pub fn len(&self) -> usize {
+        self.operators.len()
+    }
+
+    /// 目录是否为空
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_BATTLE_DATA: &str = r#"{
+        "chars": {
+            "char_017_huang": {
+                "name": "煌", "name_en": "Blaze", "profession": "WARRIOR",
+                "subProfessionId": "centurion", "rarity": 6
+            },
+            "char_010_chen": {
+                "name": "陈", "name_en": "Ch'en", "profession": "WARRIOR",
+                "subProfessionId": "swordmaster", "rarity": "TIER_6"
+            },
+            "char_102_texas": {
+                "name": "德克萨斯", "name_en": "Texas", "profession": "PIONEER",
+                "subProfessionId": "charger", "rarity": 5
+            },
+            "token_10000_silent_healrb": {
+                "name": "医疗无人机", "profession": "TOKEN", "rarity": 1
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_battle_data() {
+        let catalog = OperatorCatalog::from_battle_data_str(SAMPLE_BATTLE_DATA).unwrap();
+
+        assert_eq!(catalog.len(), 3);
+
+        let blaze = catalog.get("煌").unwrap();
+        assert_eq!(blaze.class, OperatorClass::Guard);
+        assert_eq!(blaze.subclass.as_deref(), Some("centurion"));
+        assert_eq!(blaze.rarity, 6);
+
+        // TIER_n 格式的稀有度
+        assert_eq!(catalog.get("陈").unwrap().rarity, 6);
+
+        // 召唤物不进入目录
+        assert!(catalog.get("医疗无人机").is_none());
+    }
+
+    #[test]
+    fn test_alias_lookup() {
+        let catalog = OperatorCatalog::from_battle_data_str(SAMPLE_BATTLE_DATA).unwrap();
+
+        assert_eq!(catalog.get("Blaze").unwrap().name, "煌");
+        assert_eq!(catalog.get("char_102_texas").unwrap().name, "德克萨斯");
+        assert_eq!(catalog.canonical_name("Texas"), "德克萨斯");
+        assert_eq!(catalog.canonical_name("未知干员"), "未知干员");
+    }
+
+    #[test]
+    fn test_operators_of_class() {
+        let catalog = OperatorCatalog::from_battle_data_str(SAMPLE_BATTLE_DATA).unwrap();
+
+        assert_eq!(catalog.operators_of_class(OperatorClass::Guard).len(), 2);
+        assert_eq!(catalog.operators_of_class(OperatorClass::Vanguard).len(), 1);
+        assert!(catalog.operators_of_class(OperatorClass::Medic).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_battle_data() {
+        assert!(OperatorCatalog::from_battle_data_str("{}").is_err());
+        assert!(OperatorCatalog::from_battle_data_str("not json").is_err());
+    }
+
+    #[test]
+    fn test_load_from_resource_dir() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let resource_dir = temp_dir.path().join("resource");
+        std::fs::create_dir_all(&resource_dir).unwrap();
+        std::fs::write(resource_dir.join(OperatorCatalog::BATTLE_DATA_FILE), SAMPLE_BATTLE_DATA).unwrap();
+
+        // MAA根目录和资源目录都可以加载
+        assert_eq!(OperatorCatalog::load_from_resource_dir(temp_dir.path()).unwrap().len(), 3);
+        assert_eq!(OperatorCatalog::load_from_resource_dir(&resource_dir).unwrap().len(), 3);
+
+        let empty_dir = tempfile::TempDir::new().unwrap();
+        assert!(OperatorCatalog::load_from_resource_dir(empty_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! 匹配报告
+//!
+//! 将 `MatchResult` 的分数拆解为逐干员的可读说明，
+//! 并给出把作业提升到A级所需的最低养成成本计划，供对话助手直接展示。
+
+use super::types::MatchScore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 报告的目标等级阈值 (A级)
+pub const TARGET_GRADE_SCORE: f32 = 0.8;
+
+/// 单个干员的问题
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OperatorIssue {
+    /// 未持有且没有可用替换
+    Missing,
+    /// 由其他干员替代
+    Substituted { by: String },
+    /// 等级不足
+    UnderLeveled { current: u32, required: u32 },
+    /// 精英化不足
+    NeedsElite { current: u32, required: u32 },
+    /// 技能等级不足
+    NeedsSkillLevel { current: u32, required: u32 },
+    /// 专精不足
+    NeedsMastery { skill: u32, current: u32, required: u32 },
+}
+
+impl fmt::Display for OperatorIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperatorIssue::Missing => write!(f, "未持有"),
+            OperatorIssue::Substituted { by } => write!(f, "由{}替代", by),
+            OperatorIssue::UnderLeveled { current, required } => {
+                write!(f, "等级不足，差{}级 ({}/{})", required - current, current, required)
+            }
+            OperatorIssue::NeedsElite { required, .. } => write!(f, "需要精英{}", required),
+            OperatorIssue::NeedsSkillLevel { current, required } => {
+                write!(f, "需要技能等级{} (当前{})", required, current)
+            }
+            OperatorIssue::NeedsMastery { skill, required, .. } => write!(f, "需要S{}M{}", skill, required),
+        }
+    }
+}
+
+/// 单个干员的匹配说明
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OperatorExplanation {
+    /// 作业中的干员名称
+    pub name: String,
+    /// 是否为核心干员
+    pub is_core: bool,
+    /// 存在的问题，为空表示完全满足
+    pub issues: Vec<OperatorIssue>,
+}
+
+impl OperatorExplanation {
+    /// 是否完全满足作业要求
+    pub fn is_satisfied(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// 养成项目
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UpgradeKind {
+    /// 升级
+    Level { from: u32, to: u32 },
+    /// 精英化
+    Elite { from: u32, to: u32 },
+    /// 技能升级
+    SkillLevel { from: u32, to: u32 },
+    /// 技能专精
+    Mastery { skill: u32, from: u32, to: u32 },
+}
+
+impl fmt::Display for UpgradeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeKind::Level { from, to } => write!(f, "等级 {} → {}", from, to),
+            UpgradeKind::Elite { from, to } => write!(f, "精英化 {} → {}", from, to),
+            UpgradeKind::SkillLevel { from, to } => write!(f, "技能等级 {} → {}", from, to),
+            UpgradeKind::Mastery { skill, from, to } => write!(f, "S{} 专精 {} → {}", skill, from, to),
+        }
+    }
+}
+
+impl UpgradeKind {
+    /// 估算养成成本（相对单位，用于排序，不对应具体材料）
+    pub fn estimated_cost(&self) -> u32 {
+        match self {
+            UpgradeKind::Level { from, to } => to.saturating_sub(*from),
+            UpgradeKind::Elite { from, to } => (*from..*to)
+                .map(|elite| if elite == 0 { 10 } else { 40 })
+                .sum(),
+            UpgradeKind::SkillLevel { from, to } => to.saturating_sub(*from) * 3,
+            UpgradeKind::Mastery { from, to, .. } => to.saturating_sub(*from) * 30,
+        }
+    }
+}
+
+/// 养成计划中的一步
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrainingStep {
+    /// 干员名称
+    pub operator: String,
+    /// 养成项目
+    pub upgrade: UpgradeKind,
+    /// 估算成本
+    pub cost: u32,
+    /// 完成该步骤后的相对得分
+    pub score_after: f32,
+}
+
+/// 养成计划
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrainingPlan {
+    /// 按执行顺序排列的步骤
+    pub steps: Vec<TrainingStep>,
+    /// 总估算成本
+    pub total_cost: u32,
+    /// 完成计划后的相对得分
+    pub projected_score: f32,
+    /// 完成计划后是否达到A级
+    pub reaches_target: bool,
+    /// 无法通过养成解决的缺失干员
+    pub blocked_by: Vec<String>,
+}
+
+/// 作业匹配报告
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MatchReport {
+    /// 作业ID
+    pub copilot_id: String,
+    /// 作业名称
+    pub copilot_name: String,
+    /// 关卡ID
+    pub stage_id: String,
+    /// 原始匹配得分
+    pub score: MatchScore,
+    /// 相对得分 (当前得分 / 完全满足要求时的得分)
+    pub relative_score: f32,
+    /// 按相对得分计算的等级
+    pub grade: String,
+    /// 逐干员说明
+    pub operators: Vec<OperatorExplanation>,
+    /// 达到A级的养成计划
+    pub training_plan: TrainingPlan,
+    /// 生成时间
+    pub generated_at: DateTime<Utc>,
+}
+
+impl MatchReport {
+    /// 根据相对得分计算等级
+    pub fn grade_for(relative_score: f32) -> String {
+        MatchScore { total: relative_score, ..MatchScore::new() }.get_grade().to_string()
+    }
+
+    /// 格式化为对话助手可直接展示的文本
+    pub fn to_chat_message(&self) -> String {
+        let mut lines = vec![format!(
+            "作业「{}」({}) 评级 {} ({:.0}%)",
+            self.copilot_name, self.stage_id, self.grade, self.relative_score * 100.0
+        )];
+
+        let problems: Vec<&OperatorExplanation> = self.operators.iter()
+            .filter(|op| !op.is_satisfied())
+            .collect();
+
+        if problems.is_empty() {
+            lines.push("所有干员均满足要求。".to_string());
+        } else {
+            lines.push("干员情况:".to_string());
+            for op in problems {
+                let issues: Vec<String> = op.issues.iter().map(|i| i.to_string()).collect();
+                let core = if op.is_core { " [核心]" } else { "" };
+                lines.push(format!("- {}{}: {}", op.name, core, issues.join("，")));
+            }
+        }
+
+        let plan = &self.training_plan;
+        if !plan.steps.is_empty() {
+            lines.push(format!("养成建议 (估算成本 {}):", plan.total_cost));
+            for (i, step) in plan.steps.iter().enumerate() {
+                lines.push(format!("{}. {} {}", i + 1, step.operator, step.upgrade));
+            }
+        }
+
+        if !plan.blocked_by.is_empty() {
+            lines.push(format!("需要先获取干员: {}", plan.blocked_by.join("、")));
+        }
+
+        if self.relative_score < TARGET_GRADE_SCORE {
+            if plan.reaches_target {
+                lines.push("完成以上养成后可达到A级。".to_string());
+            } else {
+                lines.push(format!("完成以上养成后预计 {:.0}%，仍无法达到A级。", plan.projected_score * 100.0));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_display() {
+        assert_eq!(OperatorIssue::UnderLeveled { current: 50, required: 60 }.to_string(), "等级不足，差10级 (50/60)");
+        assert_eq!(OperatorIssue::NeedsMastery { skill: 3, current: 0, required: 3 }.to_string(), "需要S3M3");
+        assert_eq!(OperatorIssue::Substituted { by: "陈".to_string() }.to_string(), "由陈替代");
+    }
+
+    #[test]
+    fn test_upgrade_cost() {
+        assert_eq!(UpgradeKind::Level { from: 50, to: 60 }.estimated_cost(), 10);
+        assert_eq!(UpgradeKind::Elite { from: 0, to: 2 }.estimated_cost(), 50);
+        assert_eq!(UpgradeKind::Mastery { skill: 3, from: 0, to: 3 }.estimated_cost(), 90);
+        assert!(UpgradeKind::Elite { from: 1, to: 2 }.estimated_cost() > UpgradeKind::Level { from: 1, to: 20 }.estimated_cost());
+    }
+
+    #[test]
+    fn test_grade_for() {
+        assert_eq!(MatchReport::grade_for(0.95), "S");
+        assert_eq!(MatchReport::grade_for(0.8), "A");
+        assert_eq!(MatchReport::grade_for(0.1), "F");
+    }
+}
@@ -0,0 +1,160 @@
+//! 作业来源解析
+//!
+//! `maa_copilot_enhanced` 的作业可以是本地作业文件、作业JSON的URL，或作业站的作业ID
+//! （`maa://12345`、纯数字，或文件不在本地时的 `copilot_id`）。
+//! 入队前统一取得作业JSON做静态检查；远程作业保存到本地后再交给MAA，
+//! 保证MAA执行的就是检查过的作业。
+
+use super::types::{CopilotError, CopilotResult};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 作业站地址，按作业ID获取作业JSON
+pub const COPILOT_SITE_URL: &str = "https://prts.maa.plus";
+
+/// 获取远程作业的超时时间（秒）
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// 作业来源
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopilotSource {
+    /// 本地作业文件
+    File(PathBuf),
+    /// 作业JSON的URL
+    Url(String),
+    /// 作业站作业ID
+    Id(String),
+}
+
+impl CopilotSource {
+    /// 从 `filename` 和 `copilot_id` 参数解析作业来源，无法确定来源时返回None
+    pub fn parse(filename: &str, copilot_id: Option<&str>) -> Option<Self> {
+        let filename = filename.trim();
+        if filename.starts_with("http://") || filename.starts_with("https://") {
+            return Some(Self::Url(filename.to_string()));
+        }
+        if let Some(id) = filename.strip_prefix("maa://") {
+            return is_copilot_id(id).then(|| Self::Id(id.to_string()));
+        }
+        let path = PathBuf::from(filename);
+        if path.is_file() {
+            return Some(Self::File(path));
+        }
+        if is_copilot_id(filename) {
+            return Some(Self::Id(filename.to_string()));
+        }
+        copilot_id.map(str::trim)
+            .filter(|id| is_copilot_id(id))
+            .map(|id| Self::Id(id.to_string()))
+    }
+
+    /// 是否需要从网络获取
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, Self::File(_))
+    }
+
+    /// 远程作业保存到本地时使用的文件名
+    fn cache_file_name(&self) -> String {
+        match self {
+            Self::File(path) => path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            Self::Url(url) => {
+                let mut hasher = DefaultHasher::new();
+                url.hash(&mut hasher);
+                format!("url-{:016x}.json", hasher.finish())
+            },
+            Self::Id(id) => format!("{}.json", id),
+        }
+    }
+}
+
+fn is_copilot_id(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// 取得作业JSON
+pub async fn load_copilot(source: &CopilotSource) -> CopilotResult<Value> {
+    let url = match source {
+        CopilotSource::File(path) => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| CopilotError::InvalidDataFormat(format!("Failed to read {}: {}", path.display(), e)))?;
+            return Ok(serde_json::from_str(&content)?);
+        },
+        CopilotSource::Url(url) => url.clone(),
+        CopilotSource::Id(id) => format!("{}/copilot/get/{}", COPILOT_SITE_URL, id),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+        .build()?;
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        return Err(CopilotError::ApiError(format!("获取作业失败: {} 返回 {}", url, response.status())));
+    }
+    let body: Value = response.json().await?;
+    copilot_from_response(body)
+}
+
+/// 从响应中取出作业JSON
+///
+/// 作业站的响应为 `{"data": {"content": "<作业JSON字符串>"}}`，其余URL直接返回作业JSON。
+fn copilot_from_response(body: Value) -> CopilotResult<Value> {
+    match body.pointer("/data/content") {
+        Some(Value::String(content)) => Ok(serde_json::from_str(content)?),
+        Some(_) => Err(CopilotError::InvalidDataFormat("作业站响应的 content 不是字符串".to_string())),
+        None if body.get("status_code").is_some() => Err(CopilotError::CopilotNotFound(
+            body.get("message").and_then(|m| m.as_str()).unwrap_or("作业站未返回作业").to_string(),
+        )),
+        None => Ok(body),
+    }
+}
+
+/// 把远程作业保存到本地，返回交给MAA的文件路径
+pub fn save_remote_copilot(source: &CopilotSource, copilot: &Value) -> CopilotResult<PathBuf> {
+    let dir = std::env::temp_dir().join("maa-copilot");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| CopilotError::InternalError(format!("Failed to create {}: {}", dir.display(), e)))?;
+    let path = dir.join(source.cache_file_name());
+    std::fs::write(&path, serde_json::to_vec_pretty(copilot)?)
+        .map_err(|e| CopilotError::InternalError(format!("Failed to write {}: {}", path.display(), e)))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(CopilotSource::parse("https://example.com/a.json", None), Some(CopilotSource::Url("https://example.com/a.json".to_string())));
+        assert_eq!(CopilotSource::parse("maa://12345", None), Some(CopilotSource::Id("12345".to_string())));
+        assert_eq!(CopilotSource::parse("12345", None), Some(CopilotSource::Id("12345".to_string())));
+        assert_eq!(CopilotSource::parse("missing/1-7.json", Some("678")), Some(CopilotSource::Id("678".to_string())));
+        assert_eq!(CopilotSource::parse("missing/1-7.json", None), None);
+        assert_eq!(CopilotSource::parse("Cargo.toml", Some("678")), Some(CopilotSource::File(PathBuf::from("Cargo.toml"))));
+        assert!(CopilotSource::parse("maa://abc", None).is_none());
+        assert!(CopilotSource::Id("1".to_string()).is_remote());
+    }
+
+    #[test]
+    fn test_copilot_from_response() {
+        let copilot = json!({"stage_name": "1-7", "actions": []});
+        let site = json!({"status_code": 200, "data": {"id": 1, "content": copilot.to_string()}});
+        assert_eq!(copilot_from_response(site).unwrap(), copilot);
+        assert_eq!(copilot_from_response(copilot.clone()).unwrap(), copilot);
+        assert!(copilot_from_response(json!({"status_code": 404, "message": "not found"})).is_err());
+    }
+
+    #[test]
+    fn test_save_remote_copilot() {
+        let copilot = json!({"stage_name": "1-7", "actions": []});
+        let path = save_remote_copilot(&CopilotSource::Id("990001".to_string()), &copilot).unwrap();
+        assert!(path.ends_with("990001.json"));
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, copilot);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -147,6 +147,13 @@ impl OperatorRequirement {
         self.substitution_priority = priority;
         self
     }
+
+    /// 指定技能序号上的专精等级，其他技能的专精不计
+    pub fn mastery_on(&self, skill: u32) -> u32 {
+        self.mastery
+            .filter(|(mastered_skill, _)| *mastered_skill == skill)
+            .map_or(0, |(_, level)| level)
+    }
 }
 
 /// 舞台干员结构
@@ -197,6 +204,16 @@ impl StageOperator {
         self
     }
 
+    /// 精英化要求，包含所用技能的解锁条件（2技能精一解锁，3技能精二解锁）
+    pub fn required_elite(&self) -> u32 {
+        self.elite.max(self.skill.saturating_sub(1).min(2))
+    }
+
+    /// 技能等级要求：要求专精时技能需满7级，否则不限
+    pub fn required_skill_level(&self) -> u32 {
+        if self.mastery > 0 { 7 } else { 1 }
+    }
+
     /// 设置技能
     pub fn with_skill(mut self, skill: u32) -> Self {
         self.skill = skill;
@@ -4,6 +4,7 @@
 //! - maa_roguelike_enhanced: 肉鸽自动化
 //! - maa_copilot_enhanced: 作业自动执行  
 //! - maa_sss_copilot: SSS级作业
+//! - maa_copilot_match: 按持有干员匹配作业站作业（配置了作业匹配器时提供）
 //! - maa_reclamation: 生息演算
 
 use serde_json::json;
@@ -45,12 +46,21 @@ pub fn create_copilot_enhanced_definition() -> FunctionDefinition {
             "properties": {
                 "filename": {
                     "type": "string",
-                    "description": "作业文件名或路径"
+                    "description": "作业文件路径、作业JSON的URL，或作业站作业ID（如 maa://12345）"
                 },
                 "formation": {
                     "type": "boolean",
                     "description": "是否自动编队",
                     "default": false
+                },
+                "copilot_id": {
+                    "type": "string",
+                    "description": "作业站作业ID，提供时记录本次通关结果用于作业排序"
+                },
+                "roster": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "本次实际使用的干员名称（含替换干员）"
                 }
             },
             "required": ["filename"]
@@ -58,6 +68,50 @@ pub fn create_copilot_enhanced_definition() -> FunctionDefinition {
     }
 }
 
+/// 创建作业匹配工具定义
+pub fn create_copilot_match_definition() -> FunctionDefinition {
+    FunctionDefinition {
+        name: "maa_copilot_match".to_string(),
+        description: "按持有的干员从作业站查找可用的关卡作业，返回排序后的作业、需要替换的干员、评级说明、达到A级的养成计划，以及执行时应传给maa_copilot_enhanced的copilot_id与roster".to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "stage_id": {
+                    "type": "string",
+                    "description": "关卡ID，如 1-7、CE-5"
+                },
+                "operators": {
+                    "type": "array",
+                    "description": "持有的干员，可以是干员名称，或带练度的对象",
+                    "items": {
+                        "anyOf": [
+                            {"type": "string"},
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "name": {"type": "string"},
+                                    "elite": {"type": "integer", "minimum": 0, "maximum": 2},
+                                    "level": {"type": "integer", "minimum": 1, "maximum": 90},
+                                    "skill_level": {"type": "integer", "minimum": 1, "maximum": 7}
+                                },
+                                "required": ["name"]
+                            }
+                        ]
+                    }
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": "最多返回的作业数",
+                    "minimum": 1,
+                    "maximum": 20,
+                    "default": 5
+                }
+            },
+            "required": ["stage_id", "operators"]
+        }),
+    }
+}
+
 /// 创建SSS作业工具定义
 pub fn create_sss_copilot_definition() -> FunctionDefinition {
     FunctionDefinition {
@@ -0,0 +1,279 @@
+//! 高风险操作的二次确认
+//!
+//! 使用源石、强制关闭游戏、紧急返回并清空队列、自定义任务等调用不直接执行：
+//! 处理器返回一个待确认操作（摘要 + 确认码），用户通过 `POST /actions/{token}/confirm`
+//! 或在聊天中回复“确认”后才真正入队。待确认操作在 `confirmation.ttl_secs` 后过期。
+//! 需要确认的工具在 `app.toml` 的 `[confirmation]` 中配置。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::info;
+
+use super::types::FunctionCall;
+use crate::config::CONFIG;
+
+/// 聊天回复中确认码的前缀，用于从上一条助手消息中找回确认码
+pub const TOKEN_MARKER: &str = "确认码 ";
+
+/// 确认码的字节数（十六进制后长度翻倍）
+const TOKEN_BYTES: usize = 12;
+
+/// 待确认的操作
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingAction {
+    pub token: String,
+    pub function_call: FunctionCall,
+    /// 给用户看的操作摘要
+    pub summary: String,
+    /// 发起调用的API Key名称
+    pub requested_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PendingAction {
+    /// 提示用户确认的文字，包含确认码
+    pub fn prompt(&self) -> String {
+        format!(
+            "⚠️ 需要确认：{}。回复“确认”执行，回复“取消”放弃（{}{}，{}前有效）",
+            self.summary,
+            TOKEN_MARKER,
+            self.token,
+            self.expires_at.with_timezone(&chrono::Local).format("%H:%M:%S")
+        )
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "status": "pending_confirmation",
+            "token": self.token,
+            "function_name": self.function_call.name,
+            "arguments": self.function_call.arguments,
+            "summary": self.summary,
+            "message": self.prompt(),
+            "requested_by": self.requested_by,
+            "created_at": self.created_at,
+            "expires_at": self.expires_at,
+            "confirm_url": format!("/actions/{}/confirm", self.token),
+            "cancel_url": format!("/actions/{}/cancel", self.token),
+        })
+    }
+}
+
+/// 确认错误
+#[derive(Debug, Error, PartialEq)]
+pub enum ConfirmationError {
+    #[error("待确认操作不存在或已处理: {0}")]
+    NotFound(String),
+    #[error("待确认操作已过期: {0}")]
+    Expired(String),
+}
+
+impl ConfirmationError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "ACTION_NOT_FOUND",
+            Self::Expired(_) => "ACTION_EXPIRED",
+        }
+    }
+}
+
+/// 调用是否需要确认，需要时返回操作摘要
+///
+/// 战斗、关闭游戏、紧急返回只在使用源石、`force`、`stop_tasks` 时需要确认，列表中的其他工具总是需要确认。
+pub fn requires_confirmation(function_call: &FunctionCall) -> Option<String> {
+    let config = &CONFIG.confirmation;
+    if !config.enabled || !config.tools.contains(&function_call.name) {
+        return None;
+    }
+    let args = &function_call.arguments;
+    let flag = |key: &str, default: bool| args.get(key).and_then(|v| v.as_bool()).unwrap_or(default);
+    match function_call.name.as_str() {
+        "maa_combat_enhanced" => flag("use_stone", false).then(|| format!(
+            "使用源石回复理智刷 {} x{}",
+            args.get("stage").and_then(|v| v.as_str()).unwrap_or("1-7"),
+            args.get("times").and_then(|v| v.as_i64()).unwrap_or(1)
+        )),
+        "maa_closedown" => flag("force", false).then(|| "强制关闭游戏".to_string()),
+        "maa_emergency_home" => flag("stop_tasks", true).then(|| "停止当前任务、返回主界面并取消队列中所有排队任务".to_string()),
+        "maa_custom_task" => Some(format!(
+            "执行自定义任务 {}（参数 {}）",
+            args.get("task_name").and_then(|v| v.as_str()).unwrap_or("CustomTask"),
+            args.get("params").cloned().unwrap_or_else(|| json!({}))
+        )),
+        name => Some(format!("执行 {}（参数 {}）", name, args)),
+    }
+}
+
+/// 待确认操作存储
+#[derive(Debug, Default)]
+pub struct ConfirmationStore {
+    actions: HashMap<String, PendingAction>,
+}
+
+impl ConfirmationStore {
+    /// 登记待确认操作
+    pub fn insert(&mut self, token: String, function_call: FunctionCall, summary: String, requested_by: Option<String>, ttl: Duration, now: DateTime<Utc>) -> PendingAction {
+        self.actions.retain(|_, action| action.expires_at > now);
+        let action = PendingAction {
+            token: token.clone(),
+            function_call,
+            summary,
+            requested_by,
+            created_at: now,
+            expires_at: now + ttl,
+        };
+        self.actions.insert(token, action.clone());
+        action
+    }
+
+    /// 取出待确认操作，取出后不能再次确认
+    pub fn take(&mut self, token: &str, now: DateTime<Utc>) -> Result<PendingAction, ConfirmationError> {
+        let action = self.actions.remove(token).ok_or_else(|| ConfirmationError::NotFound(token.to_string()))?;
+        if action.expires_at <= now {
+            return Err(ConfirmationError::Expired(token.to_string()));
+        }
+        Ok(action)
+    }
+
+    /// 未过期的待确认操作，按创建时间排序
+    pub fn pending(&self, now: DateTime<Utc>) -> Vec<PendingAction> {
+        let mut actions: Vec<PendingAction> = self.actions.values()
+            .filter(|action| action.expires_at > now)
+            .cloned()
+            .collect();
+        actions.sort_by_key(|action| action.created_at);
+        actions
+    }
+}
+
+static STORE: Lazy<Mutex<ConfirmationStore>> = Lazy::new(|| Mutex::new(ConfirmationStore::default()));
+
+/// 随机确认码
+fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        // 系统随机数不可用时退化为时间戳，确认码仍然只能使用一次
+        bytes[..8].copy_from_slice(&Utc::now().timestamp_nanos_opt().unwrap_or_default().to_be_bytes());
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 登记待确认操作
+pub fn create(function_call: FunctionCall, summary: String, requested_by: Option<String>) -> PendingAction {
+    let ttl = Duration::seconds(CONFIG.confirmation.ttl_secs.max(1) as i64);
+    let action = STORE.lock().unwrap().insert(new_token(), function_call, summary, requested_by, ttl, Utc::now());
+    info!("登记待确认操作 {}: {}", action.token, action.summary);
+    action
+}
+
+/// 取出待确认操作用于执行
+pub fn take(token: &str) -> Result<PendingAction, ConfirmationError> {
+    STORE.lock().unwrap().take(token, Utc::now())
+}
+
+/// 放弃待确认操作
+pub fn cancel(token: &str) -> Result<PendingAction, ConfirmationError> {
+    let action = take(token)?;
+    info!("已取消待确认操作 {}: {}", action.token, action.summary);
+    Ok(action)
+}
+
+/// 所有未过期的待确认操作
+pub fn pending_actions() -> Vec<PendingAction> {
+    STORE.lock().unwrap().pending(Utc::now())
+}
+
+/// 聊天中对待确认操作的答复
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatDecision {
+    Confirm(Vec<String>),
+    Cancel(Vec<String>),
+}
+
+/// 根据上一条助手消息中的确认码和用户的回复判断是否确认
+///
+/// 只识别简短的肯定/否定回复，其余内容照常交给AI处理。
+pub fn chat_decision(last_assistant: &str, user_reply: &str) -> Option<ChatDecision> {
+    let tokens: Vec<String> = last_assistant.match_indices(TOKEN_MARKER)
+        .map(|(index, _)| last_assistant[index + TOKEN_MARKER.len()..].chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .collect::<String>())
+        .filter(|token| token.len() == TOKEN_BYTES * 2)
+        .collect();
+    if tokens.is_empty() {
+        return None;
+    }
+    let reply = user_reply
+        .trim_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation() || "。！，～".contains(c))
+        .to_lowercase();
+    match reply.as_str() {
+        "yes" | "y" | "ok" | "confirm" | "确认" | "确定" | "是" | "是的" | "好" | "好的" | "执行" | "继续" => Some(ChatDecision::Confirm(tokens)),
+        "no" | "n" | "cancel" | "取消" | "不" | "不要" | "否" | "算了" | "放弃" => Some(ChatDecision::Cancel(tokens)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, arguments: Value) -> FunctionCall {
+        FunctionCall { name: name.to_string(), arguments }
+    }
+
+    #[test]
+    fn test_requires_confirmation() {
+        assert!(requires_confirmation(&call("maa_combat_enhanced", json!({"stage": "1-7"}))).is_none());
+        let summary = requires_confirmation(&call("maa_combat_enhanced", json!({"stage": "CE-5", "times": 3, "use_stone": true}))).unwrap();
+        assert!(summary.contains("CE-5 x3"));
+        assert!(requires_confirmation(&call("maa_closedown", json!({}))).is_none());
+        assert!(requires_confirmation(&call("maa_closedown", json!({"force": true}))).is_some());
+        // stop_tasks 默认为true
+        assert!(requires_confirmation(&call("maa_emergency_home", json!({}))).is_some());
+        assert!(requires_confirmation(&call("maa_emergency_home", json!({"stop_tasks": false}))).is_none());
+        assert!(requires_confirmation(&call("maa_custom_task", json!({"task_name": "Award"}))).is_some());
+        assert!(requires_confirmation(&call("maa_startup", json!({}))).is_none());
+    }
+
+    #[test]
+    fn test_store_take_once_and_expiry() {
+        let mut store = ConfirmationStore::default();
+        let now = Utc::now();
+        let action = store.insert("aa".to_string(), call("maa_closedown", json!({"force": true})), "强制关闭游戏".to_string(), None, Duration::seconds(60), now);
+        assert_eq!(store.pending(now).len(), 1);
+        assert_eq!(store.take(&action.token, now).unwrap().function_call.name, "maa_closedown");
+        assert_eq!(store.take(&action.token, now).unwrap_err(), ConfirmationError::NotFound("aa".to_string()));
+
+        store.insert("bb".to_string(), call("maa_custom_task", json!({})), "x".to_string(), None, Duration::seconds(60), now);
+        let later = now + Duration::seconds(61);
+        assert!(store.pending(later).is_empty());
+        assert_eq!(store.take("bb", later).unwrap_err(), ConfirmationError::Expired("bb".to_string()));
+    }
+
+    #[test]
+    fn test_chat_decision() {
+        let token = "0123456789abcdef01234567";
+        let action = PendingAction {
+            token: token.to_string(),
+            function_call: call("maa_closedown", json!({"force": true})),
+            summary: "强制关闭游戏".to_string(),
+            requested_by: None,
+            created_at: Utc::now(),
+            expires_at: Utc::now(),
+        };
+        let assistant = format!("好的，我来关闭游戏。\n{}", action.prompt());
+        assert_eq!(chat_decision(&assistant, " 确认！"), Some(ChatDecision::Confirm(vec![token.to_string()])));
+        assert_eq!(chat_decision(&assistant, "Yes."), Some(ChatDecision::Confirm(vec![token.to_string()])));
+        assert_eq!(chat_decision(&assistant, "取消"), Some(ChatDecision::Cancel(vec![token.to_string()])));
+        assert_eq!(chat_decision(&assistant, "确认一下现在的理智"), None);
+        assert_eq!(chat_decision("没有待确认的操作", "确认"), None);
+    }
+}
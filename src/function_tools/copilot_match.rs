@@ -0,0 +1,131 @@
+//! 作业匹配工具
+//!
+//! `maa_copilot_match` 不提交MAA任务，直接用服务的作业匹配器查询作业站：
+//! 按持有干员做三阶段匹配，结合推荐标记和本地通关记录排序。
+//! 每个作业附带匹配报告（逐干员说明、评级和达到A级的养成计划）及可直接转述给用户的说明文字。
+//! 返回的 `copilot_id` 和 `roster` 传给 `maa_copilot_enhanced` 后，通关结果会回写到排序信号中。
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use crate::copilot_matcher::matcher::MatchQuery;
+use crate::copilot_matcher::{CopilotMatcher, CopilotMatcherTrait, MatchReport, MatchResult, OperatorRequirement};
+
+/// 作业匹配工具名
+pub const COPILOT_MATCH_FUNCTION: &str = "maa_copilot_match";
+
+/// 默认返回的作业数
+const DEFAULT_MAX_RESULTS: usize = 5;
+/// 最多返回的作业数
+const MAX_RESULTS_LIMIT: usize = 20;
+
+/// 从工具参数构造匹配查询，返回查询和需要返回的作业数
+///
+/// 只给干员名称时按满练度（精二90级、7级技能）匹配。
+pub fn parse_match_query(args: &Value) -> Result<(MatchQuery, usize)> {
+    let stage_id = args.get("stage_id")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|stage| !stage.is_empty())
+        .ok_or_else(|| anyhow!("缺少关卡ID stage_id"))?;
+    let operators = args.get("operators")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow!("operators 必须是干员列表"))?
+        .iter()
+        .map(parse_operator)
+        .collect::<Result<Vec<_>>>()?;
+    let max_results = args.get("max_results")
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_MAX_RESULTS, |n| (n as usize).clamp(1, MAX_RESULTS_LIMIT));
+
+    Ok((MatchQuery::new(stage_id.to_string(), operators), max_results))
+}
+
+fn parse_operator(value: &Value) -> Result<OperatorRequirement> {
+    let (name, elite, level, skill_level) = match value {
+        Value::String(name) => (name.as_str(), 2, 90, 7),
+        Value::Object(fields) => {
+            let number = |key: &str, default: u64| fields.get(key).and_then(|v| v.as_u64()).unwrap_or(default) as u32;
+            let name = fields.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            (name, number("elite", 2), number("level", 90), number("skill_level", 7))
+        },
+        _ => return Err(anyhow!("无效的干员: {}", value)),
+    };
+    if name.trim().is_empty() {
+        return Err(anyhow!("干员名称不能为空: {}", value));
+    }
+    Ok(OperatorRequirement::new(name.trim().to_string(), level)
+        .with_elite(elite)
+        .with_skill_level(skill_level))
+}
+
+/// 执行作业匹配，返回排序后的作业
+pub async fn match_copilots(matcher: &CopilotMatcher, args: &Value) -> Result<Value> {
+    let (query, max_results) = parse_match_query(args)?;
+    let results = matcher.find_jobs(&query).await
+        .map_err(|e| anyhow!("作业匹配失败: {}", e))?;
+
+    let mut jobs = Vec::new();
+    let mut best_explanation = None;
+    for result in results.iter().take(max_results) {
+        let report = matcher.explain_match(&query, result).await
+            .map_err(|e| anyhow!("生成匹配报告失败: {}", e))?;
+        best_explanation.get_or_insert_with(|| report.to_chat_message());
+        jobs.push(job_json(result, &report));
+    }
+    let message = match best_explanation {
+        Some(explanation) => format!("找到 {} 个可用作业，最佳作业：\n{}", jobs.len(), explanation),
+        None => format!("没有找到 {} 的可用作业", query.stage_id),
+    };
+    Ok(json!({
+        "status": "matched",
+        "message": message,
+        "stage_id": query.stage_id,
+        "total": results.len(),
+        "jobs": jobs,
+    }))
+}
+
+fn job_json(result: &MatchResult, report: &MatchReport) -> Value {
+    // 实际上场的干员（替换后），执行作业时作为 roster 传入
+    let roster: Vec<&String> = result.copilot.operators.iter()
+        .map(|op| result.substitutions.get(&op.name).unwrap_or(&op.name))
+        .collect();
+    json!({
+        "copilot_id": result.copilot.id,
+        "name": result.copilot.name,
+        "stage_id": result.copilot.stage_id,
+        "match_stage": result.stage,
+        "score": result.score.total,
+        "recommended": result.copilot.recommended,
+        "missing_operators": result.missing_operators,
+        "substitutions": result.substitutions,
+        "roster": roster,
+        "grade": report.grade,
+        "relative_score": report.relative_score,
+        "operators": report.operators,
+        "training_plan": report.training_plan,
+        "explanation": report.to_chat_message(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_match_query() {
+        let (query, max_results) = parse_match_query(&json!({
+            "stage_id": "1-7",
+            "operators": ["夏", {"name": "陈", "elite": 1, "level": 50}],
+        })).unwrap();
+        assert_eq!(query.stage_id, "1-7");
+        assert_eq!(max_results, DEFAULT_MAX_RESULTS);
+        assert_eq!((query.available_operators[0].min_elite, query.available_operators[0].min_level), (2, 90));
+        assert_eq!((query.available_operators[1].min_elite, query.available_operators[1].min_level), (1, 50));
+
+        assert_eq!(parse_match_query(&json!({"stage_id": "1-7", "operators": [], "max_results": 100})).unwrap().1, MAX_RESULTS_LIMIT);
+        assert!(parse_match_query(&json!({"operators": []})).is_err());
+        assert!(parse_match_query(&json!({"stage_id": "1-7", "operators": [{"elite": 2}]})).is_err());
+    }
+}
@@ -5,14 +5,27 @@
 //! 2. 直接使用统一任务队列
 //! 3. 支持同步/异步任务区别处理
 
+use std::sync::Arc;
+
 use chrono::Utc;
 use serde_json::{json, Value};
+use tokio::sync::Semaphore;
 use tracing::{debug, info, warn, error};
 use anyhow::{Result, anyhow};
 
+use super::confirmation::{self, ConfirmationError, PendingAction};
+use super::copilot_match::{self, COPILOT_MATCH_FUNCTION};
 use super::types::{FunctionCall, FunctionDefinition, FunctionResponse, MaaError, ErrorType, ResponseMetadata};
-use crate::maa_core::{MaaTaskSenderV2, TaskResult};
-use crate::maa_core::task_classification_v2::{classify_task, TaskExecutionMode};
+use crate::auth::{authenticator, AuthError, Principal};
+use crate::config::CONFIG;
+use crate::maa_core::{MaaTaskSenderV2, TaskResult, QueueError};
+use crate::maa_core::budget::{self, BudgetError};
+use crate::maa_core::task_builder::build_maa_task;
+use crate::maa_core::task_classification_v2::{classify_task, estimate_task_duration, is_synchronous_task, TaskExecutionMode};
+use crate::maa_core::task_status::{wait_for_task, TaskStatus, TaskWaitOutcome};
+use crate::copilot_matcher::lint::{lint_copilot, LintReport};
+use crate::copilot_matcher::source::{load_copilot, save_remote_copilot, CopilotSource};
+use crate::copilot_matcher::CopilotMatcher;
 
 // 导入所有功能模块
 use super::advanced_automation::*;
@@ -24,13 +37,73 @@ use super::system_features::*;
 #[derive(Clone)]
 pub struct EnhancedMaaFunctionHandlerV2 {
     task_sender: MaaTaskSenderV2,
+    /// 同时处理中的Function Call上限（`PerformanceConfig.max_concurrent_requests`）
+    in_flight: Arc<Semaphore>,
+    /// 调用方，设置后按其权限过滤工具列表并拒绝无权限的调用
+    principal: Option<Arc<Principal>>,
+    /// 试运行会话：`execute_function` 只做检查和参数构造，不入队
+    dry_run: bool,
+    /// 作业匹配器，设置后提供 `maa_copilot_match` 工具
+    copilot_matcher: Option<Arc<CopilotMatcher>>,
 }
 
+/// 队列满或并发超限时返回的错误码
+pub const QUEUE_FULL_ERROR_CODE: &str = "QUEUE_FULL";
+pub const TOO_MANY_REQUESTS_ERROR_CODE: &str = "TOO_MANY_REQUESTS";
+/// 调用方无权使用该工具时返回的错误码
+pub const FORBIDDEN_ERROR_CODE: &str = "FORBIDDEN";
+/// 资源消耗预算用完时返回的错误码
+pub const BUDGET_EXCEEDED_ERROR_CODE: &str = "BUDGET_EXCEEDED";
+/// 调用需要用户确认时返回的错误码，`result` 中是待确认操作
+pub const CONFIRMATION_REQUIRED_ERROR_CODE: &str = "CONFIRMATION_REQUIRED";
+
 impl EnhancedMaaFunctionHandlerV2 {
     /// 创建新的Function Calling处理器
     pub fn new(task_sender: MaaTaskSenderV2) -> Self {
         info!("创建增强MAA Function Calling处理器 V2");
-        Self { task_sender }
+        Self {
+            task_sender,
+            in_flight: Arc::new(Semaphore::new(CONFIG.performance.max_concurrent_requests.max(1))),
+            principal: None,
+            dry_run: false,
+            copilot_matcher: None,
+        }
+    }
+    
+    /// 以指定调用方的权限执行，共享同一任务队列和并发上限
+    pub fn with_principal(mut self, principal: Principal) -> Self {
+        self.principal = Some(Arc::new(principal));
+        self
+    }
+    
+    /// 设为试运行会话，之后的 `execute_function` 都按 `dry_run` 处理
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+    
+    /// 是否为试运行会话
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+    
+    /// 设置作业匹配器，提供 `maa_copilot_match` 工具
+    pub fn with_copilot_matcher(mut self, matcher: Arc<CopilotMatcher>) -> Self {
+        self.copilot_matcher = Some(matcher);
+        self
+    }
+    
+    /// 调用方能否使用该工具
+    fn authorize_tool(&self, function_name: &str) -> Result<(), AuthError> {
+        match &self.principal {
+            Some(principal) => authenticator().authorize_tool(principal, function_name),
+            None => Ok(()),
+        }
+    }
+    
+    /// 任务队列发送器（用于查询排队情况）
+    pub fn task_sender(&self) -> &MaaTaskSenderV2 {
+        &self.task_sender
     }
 
     /// 获取所有Function Calling工具定义
@@ -48,6 +121,9 @@ impl EnhancedMaaFunctionHandlerV2 {
         definitions.push(create_copilot_enhanced_definition());
         definitions.push(create_sss_copilot_definition());
         definitions.push(create_reclamation_definition());
+        if self.copilot_matcher.is_some() {
+            definitions.push(create_copilot_match_definition());
+        }
 
         // 辅助功能 (4个)
         definitions.push(create_rewards_enhanced_definition());
@@ -65,6 +141,7 @@ impl EnhancedMaaFunctionHandlerV2 {
         definitions.push(create_adjust_task_params_definition());
         definitions.push(create_emergency_home_definition());
 
+        definitions.retain(|definition| self.authorize_tool(&definition.name).is_ok());
         info!("已加载 {} 个增强MAA Function Calling工具", definitions.len());
         definitions
     }
@@ -74,38 +151,152 @@ impl EnhancedMaaFunctionHandlerV2 {
     /// 优化点：
     /// 1. 直接传递JSON参数，避免重复序列化
     /// 2. 根据任务类型选择同步/异步处理
+    /// 3. 试运行会话中不入队，返回 `dry_run` 的结果
     pub async fn execute_function(&self, function_call: FunctionCall) -> FunctionResponse {
+        if self.dry_run {
+            return self.dry_run(function_call).await;
+        }
+        self.execute(function_call, false).await
+    }
+
+    /// 确认并执行待确认操作，权限、参数和预算会重新检查
+    pub async fn confirm_action(&self, token: &str) -> Result<FunctionResponse, ConfirmationError> {
+        let action = confirmation::take(token)?;
+        info!("执行已确认的操作 {}: {}", action.token, action.summary);
+        Ok(self.execute(action.function_call, true).await)
+    }
+
+    /// 试运行Function Call：执行权限、参数、预算检查并构造MAA参数，不入队
+    ///
+    /// 返回实际会下发的MAA任务链类型和参数、执行模式、预估耗时和入队后的预计位置。
+    /// 需要二次确认的调用只标记 `would_require_confirmation`，不登记待确认操作。
+    pub async fn dry_run(&self, mut function_call: FunctionCall) -> FunctionResponse {
+        let function_name = function_call.name.clone();
+        debug!("试运行Function Call: {} with args: {:?}", function_name, function_call.arguments);
+        
+        if let Err(e) = self.authorize_tool(&function_name) {
+            return Self::forbidden_response(&function_name, e);
+        }
+        if let Err(validation_error) = self.validate_function_call(&function_call) {
+            return Self::validation_response(&function_name, validation_error);
+        }
+        if let Err(e) = budget::check_function_call(&function_name, &function_call.arguments) {
+            return Self::budget_response(&function_name, e);
+        }
+        
+        let mut lint_warnings = Vec::new();
+        if let Some(report) = self.lint_copilot_call(&mut function_call).await {
+            if report.has_errors() {
+                return Self::lint_error_response(&function_name, &report);
+            }
+            lint_warnings = report.warnings().map(|d| d.to_string()).collect();
+        }
+        
+        let (execution_mode, priority) = classify_task(&function_name);
+        let plan = build_maa_task(&function_name, &function_call.arguments);
+        let queue = self.task_sender.preview_task(&function_name, &function_call.arguments, priority, execution_mode);
+        let confirmation_summary = confirmation::requires_confirmation(&function_call);
+        let estimated_duration_secs = estimate_task_duration(&function_name);
+        let message = match &plan {
+            Some(plan) => format!(
+                "试运行：将提交MAA任务 {}，参数 {}，预计耗时 {} 秒，排在队列第 {} 位",
+                plan.maa_task_type, plan.params, estimated_duration_secs, queue.position
+            ),
+            None => format!("试运行：{} 不提交MAA任务链，排在队列第 {} 位", function_name, queue.position),
+        };
+        
+        FunctionResponse {
+            success: true,
+            result: Some(json!({
+                "dry_run": true,
+                "status": "dry_run",
+                "message": message,
+                "function_name": function_name,
+                "arguments": function_call.arguments,
+                "maa_task_type": plan.as_ref().map(|plan| &plan.maa_task_type),
+                "params": plan.as_ref().map(|plan| &plan.params),
+                "execution_mode": execution_mode,
+                "priority": priority,
+                "estimated_duration_secs": estimated_duration_secs,
+                "queue": queue,
+                "would_require_confirmation": confirmation_summary.is_some(),
+                "confirmation_summary": confirmation_summary,
+            })),
+            error: None,
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name,
+                recommendations: lint_warnings,
+                next_actions: vec!["去掉 dry_run 后重新调用以实际执行".to_string()],
+                resource_usage: None,
+            },
+        }
+    }
+
+    async fn execute(&self, mut function_call: FunctionCall, confirmed: bool) -> FunctionResponse {
         let start_time = Utc::now();
         let function_name = function_call.name.clone();
         
         debug!("执行Function Call: {} with args: {:?}", function_name, function_call.arguments);
         
+        if let Err(e) = self.authorize_tool(&function_name) {
+            warn!("拒绝无权限的Function Call: {}", e);
+            return Self::forbidden_response(&function_name, e);
+        }
+        
+        // 并发超限时直接拒绝，许可在本次调用结束（含同步任务等待）时释放
+        let _permit = match self.in_flight.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("并发Function Call超过上限 {}，拒绝: {}", CONFIG.performance.max_concurrent_requests, function_name);
+                return Self::backpressure_response(
+                    &function_name,
+                    TOO_MANY_REQUESTS_ERROR_CODE,
+                    format!("同时处理的请求超过上限 {}", CONFIG.performance.max_concurrent_requests),
+                    1,
+                    json!({ "max_concurrent_requests": CONFIG.performance.max_concurrent_requests }),
+                );
+            }
+        };
+        
         // 分类任务
         let (execution_mode, priority) = classify_task(&function_name);
         
         // 验证Function Call
         if let Err(validation_error) = self.validate_function_call(&function_call) {
             warn!("Function call 验证失败: {}", validation_error);
-            return FunctionResponse {
-                success: false,
-                result: None,
-                error: Some(MaaError {
-                    error_type: ErrorType::ParameterError,
-                    message: validation_error.to_string(),
-                    details: None,
-                    suggestion: Some("请检查Function Call参数格式".to_string()),
-                    error_code: Some("VALIDATION_ERROR".to_string()),
-                }),
-                timestamp: Utc::now(),
-                execution_time_ms: Some(0),
-                metadata: ResponseMetadata {
-                    task_id: None,
-                    function_name: function_name.clone(),
-                    recommendations: vec![],
-                    next_actions: vec![],
-                    resource_usage: None,
-                },
-            };
+            return Self::validation_response(&function_name, validation_error);
+        }
+        
+        // 资源消耗预算检查，额度用完时不入队
+        if let Err(e) = budget::check_function_call(&function_name, &function_call.arguments) {
+            warn!("Function call 超出资源预算: {} - {}", function_name, e);
+            return Self::budget_response(&function_name, e);
+        }
+        
+        // 高风险调用先登记为待确认操作，确认后再入队
+        if !confirmed {
+            if let Some(summary) = confirmation::requires_confirmation(&function_call) {
+                let requested_by = self.principal.as_ref().map(|principal| principal.name.clone());
+                let action = confirmation::create(function_call, summary, requested_by);
+                return Self::confirmation_response(&function_name, &action);
+            }
+        }
+        
+        // 作业匹配只查询作业站，不提交MAA任务
+        if function_name == COPILOT_MATCH_FUNCTION {
+            return self.execute_copilot_match(&function_call.arguments, start_time).await;
+        }
+        
+        // 作业入队前静态检查
+        let mut lint_warnings = Vec::new();
+        if let Some(report) = self.lint_copilot_call(&mut function_call).await {
+            if report.has_errors() {
+                return Self::lint_error_response(&function_name, &report);
+            }
+            lint_warnings = report.warnings().map(|d| d.to_string()).collect();
         }
         
         // 发送任务到队列
@@ -137,25 +328,31 @@ impl EnhancedMaaFunctionHandlerV2 {
                     TaskExecutionMode::Asynchronous => {
                         // 异步任务：立即返回任务信息，不等待完成
                         debug!("异步任务已启动: {} (task_id: {})", function_name, task_id);
-                        Ok(TaskResult {
-                            success: true,
-                            task_id,
-                            result: Some(json!({
-                                "task_id": task_id,
-                                "task_type": function_name,
-                                "status": "running",
-                                "message": "异步任务已启动，正在后台执行",
-                                "execution_mode": "asynchronous",
-                                "check_status_url": format!("/task/{}/status", task_id),
-                                "sse_events": "任务进度将通过SSE推送"
-                            })),
-                            error: None,
-                            completed_at: Utc::now(),
-                            duration_seconds: 0.0,
-                        })
+                        Ok(Self::async_task_result(task_id, &function_name, json!({
+                            "status": "running",
+                            "message": "异步任务已启动，正在后台执行",
+                        })))
                     }
                 }
             },
+            Err(QueueError::Duplicate { task_id }) => {
+                info!("相同的任务已在队列中，复用任务: {} (task_id: {})", function_name, task_id);
+                Ok(Self::async_task_result(task_id, &function_name, json!({
+                    "status": "queued",
+                    "deduplicated": true,
+                    "message": "相同的任务已在队列中，未重复提交",
+                })))
+            },
+            Err(QueueError::Full { depth, capacity, retry_after_secs }) => {
+                warn!("任务队列已满 ({}/{})，拒绝: {}", depth, capacity, function_name);
+                return Self::backpressure_response(
+                    &function_name,
+                    QUEUE_FULL_ERROR_CODE,
+                    format!("任务队列已满（{}/{}）", depth, capacity),
+                    retry_after_secs,
+                    json!({ "queue_depth": depth, "queue_capacity": capacity }),
+                );
+            },
             Err(e) => {
                 error!("任务发送失败: {}", e);
                 Err(anyhow!("任务队列发送失败: {}", e))
@@ -177,7 +374,7 @@ impl EnhancedMaaFunctionHandlerV2 {
                     metadata: ResponseMetadata {
                         task_id: Some(result.task_id.to_string()),
                         function_name: function_name.clone(),
-                        recommendations: vec![],
+                        recommendations: lint_warnings,
                         next_actions: vec![],
                         resource_usage: None,
                     },
@@ -211,6 +408,263 @@ impl EnhancedMaaFunctionHandlerV2 {
         }
     }
 
+    /// 执行作业匹配，结果直接返回，不经过任务队列
+    async fn execute_copilot_match(&self, arguments: &Value, start_time: chrono::DateTime<Utc>) -> FunctionResponse {
+        let result = match &self.copilot_matcher {
+            Some(matcher) => copilot_match::match_copilots(matcher, arguments).await,
+            None => Err(anyhow!("未配置作业匹配器")),
+        };
+        let execution_time_ms = Some((Utc::now() - start_time).num_milliseconds().max(0) as u64);
+        let (success, result, error) = match result {
+            Ok(result) => (true, Some(result), None),
+            Err(e) => {
+                warn!("作业匹配失败: {}", e);
+                (false, None, Some(MaaError {
+                    error_type: ErrorType::ParameterError,
+                    message: e.to_string(),
+                    details: None,
+                    suggestion: Some("请检查关卡ID和干员列表，或稍后重试".to_string()),
+                    error_code: Some("COPILOT_MATCH_ERROR".to_string()),
+                }))
+            }
+        };
+        FunctionResponse {
+            success,
+            result,
+            error,
+            timestamp: Utc::now(),
+            execution_time_ms,
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: COPILOT_MATCH_FUNCTION.to_string(),
+                recommendations: vec![],
+                next_actions: if success {
+                    vec!["使用 maa_copilot_enhanced 执行作业，并传入 copilot_id 和 roster 以记录通关结果".to_string()]
+                } else {
+                    vec![]
+                },
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 异步任务提交后的返回信息，`extra` 中的字段会合并进去
+    fn async_task_result(task_id: i32, function_name: &str, extra: Value) -> TaskResult {
+        let mut result = json!({
+            "task_id": task_id,
+            "task_type": function_name,
+            "execution_mode": "asynchronous",
+            "check_status_url": format!("/task/{}/status", task_id),
+            "wait_url": format!("/task/{}/wait", task_id),
+            "sse_events": "任务进度将通过SSE推送"
+        });
+        if let (Some(result), Value::Object(extra)) = (result.as_object_mut(), extra) {
+            result.extend(extra);
+        }
+        
+        TaskResult {
+            success: true,
+            task_id,
+            result: Some(result),
+            error: None,
+            completed_at: Utc::now(),
+            duration_seconds: 0.0,
+        }
+    }
+    
+    /// 队列满或并发超限的拒绝响应
+    ///
+    /// `result` 中带有 `retry_after_secs`，HTTP层据此返回429和Retry-After。
+    fn backpressure_response(function_name: &str, error_code: &str, message: String, retry_after_secs: u64, details: Value) -> FunctionResponse {
+        let mut result = json!({ "retry_after_secs": retry_after_secs });
+        if let (Some(result), Value::Object(details)) = (result.as_object_mut(), details) {
+            result.extend(details);
+        }
+        
+        FunctionResponse {
+            success: false,
+            result: Some(result),
+            error: Some(MaaError {
+                error_type: ErrorType::SystemError,
+                message,
+                details: None,
+                suggestion: Some(format!("请在 {} 秒后重试", retry_after_secs)),
+                error_code: Some(error_code.to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec!["GET /queue 查看排队中的任务".to_string()],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 参数验证失败的响应
+    fn validation_response(function_name: &str, error: anyhow::Error) -> FunctionResponse {
+        FunctionResponse {
+            success: false,
+            result: None,
+            error: Some(MaaError {
+                error_type: ErrorType::ParameterError,
+                message: error.to_string(),
+                details: None,
+                suggestion: Some("请检查Function Call参数格式".to_string()),
+                error_code: Some("VALIDATION_ERROR".to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 作业文件静态检查未通过的响应
+    fn lint_error_response(function_name: &str, report: &LintReport) -> FunctionResponse {
+        let errors: Vec<String> = report.errors().map(|d| d.to_string()).collect();
+        warn!("作业文件检查未通过: {}", errors.join("; "));
+        FunctionResponse {
+            success: false,
+            result: None,
+            error: Some(MaaError {
+                error_type: ErrorType::ParameterError,
+                message: format!("作业文件检查发现{}个错误", errors.len()),
+                details: serde_json::to_string(report).ok(),
+                suggestion: Some(errors.join("\n")),
+                error_code: Some("COPILOT_LINT_ERROR".to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 待确认响应，`result` 中带有确认码和确认地址
+    fn confirmation_response(function_name: &str, action: &PendingAction) -> FunctionResponse {
+        FunctionResponse {
+            success: false,
+            result: Some(action.to_json()),
+            error: Some(MaaError {
+                error_type: ErrorType::ParameterError,
+                message: action.prompt(),
+                details: None,
+                suggestion: Some(format!("POST /actions/{}/confirm 确认执行", action.token)),
+                error_code: Some(CONFIRMATION_REQUIRED_ERROR_CODE.to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![format!("POST /actions/{}/confirm", action.token)],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 资源预算用完的拒绝响应，错误信息可直接转述给用户
+    fn budget_response(function_name: &str, error: BudgetError) -> FunctionResponse {
+        FunctionResponse {
+            success: false,
+            result: Some(json!({ "budget": budget::usage_summary() })),
+            error: Some(MaaError {
+                error_type: ErrorType::GameStateError,
+                message: error.to_string(),
+                details: None,
+                suggestion: Some("请不使用药剂/源石重新执行，或在 app.toml 的 [budget] 中调整额度".to_string()),
+                error_code: Some(BUDGET_EXCEEDED_ERROR_CODE.to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 调用方无权使用工具的拒绝响应，HTTP层据此返回403
+    fn forbidden_response(function_name: &str, error: AuthError) -> FunctionResponse {
+        FunctionResponse {
+            success: false,
+            result: None,
+            error: Some(MaaError {
+                error_type: ErrorType::ParameterError,
+                message: error.to_string(),
+                details: None,
+                suggestion: Some("请使用有该工具权限的API Key".to_string()),
+                error_code: Some(FORBIDDEN_ERROR_CODE.to_string()),
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: Some(0),
+            metadata: ResponseMetadata {
+                task_id: None,
+                function_name: function_name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![],
+                resource_usage: None,
+            },
+        }
+    }
+
+    /// 执行Function Call并等待异步任务结束
+    ///
+    /// 异步任务在MAA回调报告完成或失败后返回汇总结果（掉落、公招标签、错误）；
+    /// `timeout_secs` 为空时按任务预估耗时推导。同步任务与 `execute_function` 行为一致。
+    pub async fn execute_function_and_wait(&self, function_call: FunctionCall, timeout_secs: Option<u64>) -> FunctionResponse {
+        let start_time = Utc::now();
+        let mut response = self.execute_function(function_call).await;
+        
+        let task_id = match response.metadata.task_id.as_deref().and_then(|id| id.parse::<i32>().ok()) {
+            Some(task_id) if response.success && !is_synchronous_task(&response.metadata.function_name) => task_id,
+            _ => return response,
+        };
+        
+        let outcome = wait_for_task(task_id, timeout_secs).await;
+        let summary = outcome.to_json();
+        
+        match &outcome {
+            TaskWaitOutcome::Finished(task) if task.status != TaskStatus::Succeeded => {
+                let message = task.error.clone().unwrap_or_else(|| "任务执行失败".to_string());
+                response.success = false;
+                response.error = Some(MaaError {
+                    error_type: if task.status == TaskStatus::TimedOut { ErrorType::TimeoutError } else { ErrorType::MaaCoreError },
+                    message,
+                    details: Some(summary.to_string()),
+                    suggestion: Some("请查看任务错误信息后重试".to_string()),
+                    error_code: Some("TASK_FAILED".to_string()),
+                });
+            },
+            TaskWaitOutcome::WaitTimedOut { .. } => {
+                response.metadata.next_actions.push(format!("任务仍在执行，可通过 /task/{}/wait 继续等待", task_id));
+            },
+            _ => {}
+        }
+        
+        response.result = Some(summary);
+        response.execution_time_ms = Some((Utc::now() - start_time).num_milliseconds() as u64);
+        response.timestamp = Utc::now();
+        response
+    }
+
     /// 验证Function Call参数
     fn validate_function_call(&self, function_call: &FunctionCall) -> Result<()> {
         // 检查function名称
@@ -227,7 +681,8 @@ impl EnhancedMaaFunctionHandlerV2 {
             "maa_take_screenshot", "maa_get_task_list", "maa_adjust_task_params", "maa_emergency_home"
         ];
         
-        if !supported_functions.contains(&function_call.name.as_str()) {
+        let matcher_available = self.copilot_matcher.is_some() && function_call.name == COPILOT_MATCH_FUNCTION;
+        if !supported_functions.contains(&function_call.name.as_str()) && !matcher_available {
             return Err(anyhow!("不支持的Function: {}", function_call.name));
         }
         
@@ -239,6 +694,39 @@ impl EnhancedMaaFunctionHandlerV2 {
         Ok(())
     }
 
+    /// 对作业做静态检查，非作业任务或无法确定作业来源时返回None
+    ///
+    /// URL和作业站ID先取得作业JSON再检查，并保存到本地、把 `filename` 改为本地副本，
+    /// MAA执行的就是检查过的作业。取不到作业时返回带错误的报告，不入队。
+    async fn lint_copilot_call(&self, function_call: &mut FunctionCall) -> Option<LintReport> {
+        if function_call.name != "maa_copilot_enhanced" {
+            return None;
+        }
+
+        let filename = function_call.arguments.get("filename").and_then(|v| v.as_str())?;
+        let copilot_id = function_call.arguments.get("copilot_id").and_then(|v| v.as_str());
+        let Some(source) = CopilotSource::parse(filename, copilot_id) else {
+            debug!("作业文件不在本地且没有作业ID，跳过静态检查: {}", filename);
+            return None;
+        };
+
+        let copilot = match load_copilot(&source).await {
+            Ok(copilot) => copilot,
+            Err(e) => return Some(LintReport::invalid_file(e.to_string())),
+        };
+        let report = lint_copilot(&copilot);
+        if source.is_remote() && !report.has_errors() {
+            match save_remote_copilot(&source, &copilot) {
+                Ok(path) => {
+                    debug!("远程作业已保存到本地: {:?} -> {}", source, path.display());
+                    function_call.arguments["filename"] = json!(path.to_string_lossy());
+                },
+                Err(e) => return Some(LintReport::invalid_file(e.to_string())),
+            }
+        }
+        Some(report)
+    }
+
     /// 获取服务器状态
     pub async fn get_server_status(&self) -> Value {
         // 自动初始化MAA设备连接
@@ -265,6 +753,7 @@ impl EnhancedMaaFunctionHandlerV2 {
             },
             "architecture": "optimized_v2_single_queue",
             "maa_core": maa_status,
+            "budget": budget::usage_summary(),
             "status": if maa_status.get("connected").and_then(|v| v.as_bool()).unwrap_or(false) {
                 "ready"
             } else {
@@ -376,6 +865,100 @@ mod tests {
         assert!(function_names.contains(&"maa_combat_enhanced".to_string()));
         assert!(function_names.contains(&"maa_closedown".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_copilot_match_tool() {
+        use crate::copilot_matcher::api_client::{ApiClientTrait, MockApiClient};
+        use crate::copilot_matcher::{CopilotData, MatcherConfig, StageOperator};
+        
+        let (sender, _receiver) = create_maa_task_channel();
+        let handler = EnhancedMaaFunctionHandlerV2::new(sender);
+        assert!(handler.get_function_definitions().iter().all(|d| d.name != COPILOT_MATCH_FUNCTION));
+        
+        let copilot = CopilotData::new("42".to_string(), "1-7 速通".to_string(), "1-7".to_string(), vec![
+            StageOperator::new("夏".to_string(), 1).with_level(60).with_elite(1),
+            StageOperator::new("陈".to_string(), 2).with_level(80).with_elite(2),
+        ]);
+        let api_client = Arc::new(MockApiClient::new(vec![copilot])) as Arc<dyn ApiClientTrait>;
+        let matcher = CopilotMatcher::new(MatcherConfig::new(), api_client, None).unwrap();
+        let handler = handler.with_copilot_matcher(Arc::new(matcher));
+        assert!(handler.get_function_definitions().iter().any(|d| d.name == COPILOT_MATCH_FUNCTION));
+        
+        let response = handler.execute_function(FunctionCall {
+            name: COPILOT_MATCH_FUNCTION.to_string(),
+            arguments: json!({"stage_id": "1-7", "operators": ["夏", "陈"]}),
+        }).await;
+        assert!(response.success, "{:?}", response.error);
+        let result = response.result.unwrap();
+        assert_eq!(result["jobs"][0]["copilot_id"], "42");
+        assert_eq!(result["jobs"][0]["roster"], json!(["夏", "陈"]));
+        // 匹配报告和养成计划随结果返回
+        assert!(result["jobs"][0]["grade"].is_string());
+        assert!(result["jobs"][0]["training_plan"]["steps"].is_array());
+        assert!(result["jobs"][0]["explanation"].as_str().unwrap().contains("1-7 速通"));
+        assert!(result["message"].as_str().unwrap().contains("评级"));
+        // 不经过任务队列
+        assert_eq!(handler.task_sender().depth(), 0);
+        
+        let response = handler.execute_function(FunctionCall {
+            name: COPILOT_MATCH_FUNCTION.to_string(),
+            arguments: json!({"operators": []}),
+        }).await;
+        assert_eq!(response.error.unwrap().error_code.as_deref(), Some("COPILOT_MATCH_ERROR"));
+    }
+
+    #[tokio::test]
+    async fn test_principal_restricts_tools() {
+        let (sender, _receiver) = create_maa_task_channel();
+        let viewer = EnhancedMaaFunctionHandlerV2::new(sender.clone())
+            .with_principal(Principal::new("viewer", crate::auth::Role::Viewer));
+        assert!(viewer.get_function_definitions().is_empty());
+        
+        let response = viewer.execute_function(FunctionCall {
+            name: "maa_startup".to_string(),
+            arguments: json!({}),
+        }).await;
+        assert!(!response.success);
+        assert_eq!(response.error.unwrap().error_code.as_deref(), Some(FORBIDDEN_ERROR_CODE));
+        
+        let operator = EnhancedMaaFunctionHandlerV2::new(sender)
+            .with_principal(Principal::new("bot", crate::auth::Role::Operator)
+                .with_allowed_tools(["maa_take_screenshot".to_string()]));
+        let names: Vec<String> = operator.get_function_definitions().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, vec!["maa_take_screenshot".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_enqueue() {
+        let (sender, _receiver) = create_maa_task_channel();
+        let handler = EnhancedMaaFunctionHandlerV2::new(sender);
+        
+        let response = handler.dry_run(FunctionCall {
+            name: "maa_combat_enhanced".to_string(),
+            arguments: json!({"stage": "CE-5", "times": 2}),
+        }).await;
+        assert!(response.success);
+        let result = response.result.unwrap();
+        assert_eq!(result["maa_task_type"], "Fight");
+        assert_eq!(result["params"]["stage"], "CE-5");
+        assert_eq!(result["queue"]["position"], 1);
+        assert_eq!(result["estimated_duration_secs"], 600);
+        assert_eq!(handler.task_sender().depth(), 0);
+        
+        // 需要确认的调用只做标记，不登记待确认操作
+        let response = handler.dry_run(FunctionCall {
+            name: "maa_closedown".to_string(),
+            arguments: json!({"force": true}),
+        }).await;
+        assert_eq!(response.result.unwrap()["would_require_confirmation"], true);
+        assert!(confirmation::pending_actions().iter().all(|action| action.function_call.name != "maa_closedown"));
+        
+        let response = handler.dry_run(FunctionCall {
+            name: "unknown_function".to_string(),
+            arguments: json!({}),
+        }).await;
+        assert_eq!(response.error.unwrap().error_code.as_deref(), Some("VALIDATION_ERROR"));
+    }
 }
 
 /// 创建增强Function Calling处理器V2 - 工厂函数
@@ -9,9 +9,15 @@ pub mod advanced_automation;
 pub mod support_features;  
 pub mod system_features;
 pub mod handler_v2;
+pub mod workflow;
+pub mod confirmation;
+pub mod copilot_match;
 
 // 重新导出核心类型
 pub use types::{FunctionDefinition, FunctionCall, FunctionResponse, TaskContext, GameState};
 
 // 重新导出V2优化版Function Calling处理器
-pub use handler_v2::{EnhancedMaaFunctionHandlerV2, create_enhanced_function_handler_v2};
\ No newline at end of file
+pub use handler_v2::{EnhancedMaaFunctionHandlerV2, create_enhanced_function_handler_v2};
+
+// 重新导出工作流执行器
+pub use workflow::{WorkflowEngine, WorkflowSpec};
\ No newline at end of file
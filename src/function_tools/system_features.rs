@@ -162,13 +162,13 @@ pub fn create_get_task_list_definition() -> FunctionDefinition {
 pub fn create_adjust_task_params_definition() -> FunctionDefinition {
     FunctionDefinition {
         name: "maa_adjust_task_params".to_string(),
-        description: "动态调整运行中任务的参数，支持智能策略".to_string(),
+        description: "动态调整运行中任务的参数，支持智能策略。仅支持刷图(Fight)、肉鸽(Roguelike)、公招(Recruit)任务，返回实际生效的参数差异".to_string(),
         parameters: json!({
             "type": "object",
             "properties": {
                 "task_id": {
                     "type": "integer",
-                    "description": "要调整的任务ID"
+                    "description": "要调整的任务ID（提交任务时返回的task_id）"
                 },
                 "strategy": {
                     "type": "string",
@@ -177,7 +177,7 @@ pub fn create_adjust_task_params_definition() -> FunctionDefinition {
                 },
                 "custom_params": {
                     "type": "object",
-                    "description": "自定义参数(当strategy=custom时使用)",
+                    "description": "自定义参数(当strategy=custom时使用)，字段需为目标任务支持运行中修改的字段",
                     "properties": {
                         "medicine": {
                             "type": "integer",
@@ -219,7 +219,7 @@ pub fn create_adjust_task_params_definition() -> FunctionDefinition {
 pub fn create_emergency_home_definition() -> FunctionDefinition {
     FunctionDefinition {
         name: "maa_emergency_home".to_string(),
-        description: "紧急情况下快速返回游戏主界面，中断当前所有操作".to_string(),
+        description: "紧急情况下快速返回游戏主界面：停止MAA当前运行的任务，返回主界面并截图确认，报告被中断的任务".to_string(),
         parameters: json!({
             "type": "object",
             "properties": {
@@ -230,12 +230,12 @@ pub fn create_emergency_home_definition() -> FunctionDefinition {
                 },
                 "force": {
                     "type": "boolean",
-                    "description": "是否强制返回(跳过安全检查)",
+                    "description": "是否强制返回(停止任务失败时仍继续返回主界面)",
                     "default": false
                 },
                 "stop_tasks": {
                     "type": "boolean",
-                    "description": "是否同时停止所有运行中的任务",
+                    "description": "是否同时取消队列中排队等待的任务(false时保留排队任务)",
                     "default": true
                 }
             },
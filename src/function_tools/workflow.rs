@@ -0,0 +1,785 @@
+//! 工作流（任务依赖图）
+//!
+//! 一个工作流是由Function Call组成的有向无环图，例如：
+//! 基建换班 → （基建成功时）刷1-7 → （无论结果如何）关闭游戏。
+//!
+//! 节点按拓扑顺序依次提交到统一任务队列，由Worker串行执行；
+//! 每个节点等待前一个节点结束后再决定执行或跳过。
+//! 节点状态变更以 `workflow_node` 事件推送到SSE，工作流结束时推送 `workflow_completed`。
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use super::confirmation;
+use super::handler_v2::EnhancedMaaFunctionHandlerV2;
+use super::types::{FunctionCall, FunctionResponse};
+use crate::maa_core::task_status::{self, TaskWaitOutcome};
+use crate::maa_core::worker_v2::{TaskEventType, TaskProgressEvent};
+
+/// 依赖边类型：前置节点以何种结果结束时执行当前节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// 前置节点成功
+    #[default]
+    Success,
+    /// 前置节点失败
+    Failure,
+    /// 前置节点结束即可（包括被跳过）
+    Always,
+}
+
+/// 依赖边
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowEdge {
+    /// 前置节点ID
+    pub node: String,
+    #[serde(default)]
+    pub on: EdgeKind,
+}
+
+/// 条件比较运算符
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Exists,
+    NotExists,
+    /// 数组包含该值，或字符串包含该子串
+    Contains,
+}
+
+/// 基于前置节点结果的执行条件
+///
+/// `path` 为JSON Pointer，指向前置节点的结果，例如 `/status`、`/drops/0/item_name`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeCondition {
+    /// 前置节点ID（必须是当前节点的依赖）
+    pub node: String,
+    pub path: String,
+    pub op: ConditionOp,
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl NodeCondition {
+    /// 对前置节点结果求值
+    pub fn evaluate(&self, result: Option<&Value>) -> bool {
+        let actual = result.and_then(|r| r.pointer(&self.path));
+        match self.op {
+            ConditionOp::Exists => actual.is_some_and(|v| !v.is_null()),
+            ConditionOp::NotExists => matches!(actual, None | Some(Value::Null)),
+            ConditionOp::Eq => actual == Some(&self.value),
+            ConditionOp::Ne => actual != Some(&self.value),
+            ConditionOp::Gt => compare_numbers(actual, &self.value).is_some_and(|o| o.is_gt()),
+            ConditionOp::Gte => compare_numbers(actual, &self.value).is_some_and(|o| o.is_ge()),
+            ConditionOp::Lt => compare_numbers(actual, &self.value).is_some_and(|o| o.is_lt()),
+            ConditionOp::Lte => compare_numbers(actual, &self.value).is_some_and(|o| o.is_le()),
+            ConditionOp::Contains => match (actual, &self.value) {
+                (Some(Value::Array(items)), expected) => items.contains(expected),
+                (Some(Value::String(s)), Value::String(sub)) => s.contains(sub.as_str()),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn compare_numbers(actual: Option<&Value>, expected: &Value) -> Option<std::cmp::Ordering> {
+    actual?.as_f64()?.partial_cmp(&expected.as_f64()?)
+}
+
+/// 是否为合法的JSON Pointer（RFC 6901）：空串或以 `/` 开头，`~` 只能用于 `~0`、`~1` 转义
+fn is_valid_json_pointer(path: &str) -> bool {
+    if !path.is_empty() && !path.starts_with('/') {
+        return false;
+    }
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' && !matches!(chars.next(), Some('0' | '1')) {
+            return false;
+        }
+    }
+    true
+}
+
+/// 工作流节点定义
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowNodeSpec {
+    /// 节点ID，在工作流内唯一
+    pub id: String,
+    pub function_call: FunctionCall,
+    /// 依赖边，全部满足时才执行；为空表示起始节点
+    #[serde(default)]
+    pub depends_on: Vec<WorkflowEdge>,
+    /// 额外的执行条件
+    #[serde(default)]
+    pub condition: Option<NodeCondition>,
+    /// 异步任务的等待超时（秒）
+    #[serde(default)]
+    pub wait_timeout: Option<u64>,
+}
+
+/// 工作流提交请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub nodes: Vec<WorkflowNodeSpec>,
+}
+
+/// 工作流定义错误
+#[derive(Debug, Error, PartialEq)]
+pub enum WorkflowError {
+    #[error("工作流至少需要一个节点")]
+    Empty,
+    #[error("节点ID重复: {0}")]
+    DuplicateNode(String),
+    #[error("节点 {node} 引用了不存在的节点 {missing}")]
+    UnknownNode { node: String, missing: String },
+    #[error("节点 {node} 的条件引用了非依赖节点 {target}")]
+    ConditionNotDependency { node: String, target: String },
+    #[error("节点 {node} 的条件路径不是合法的JSON Pointer: {path}")]
+    InvalidConditionPath { node: String, path: String },
+    #[error("工作流存在循环依赖: {0}")]
+    Cycle(String),
+    #[error("节点 {node} 需要二次确认（{summary}），不能放入工作流，请单独调用并确认")]
+    RequiresConfirmation { node: String, summary: String },
+}
+
+impl WorkflowSpec {
+    /// 校验节点定义并返回拓扑执行顺序（同层按提交顺序）
+    pub fn execution_order(&self) -> Result<Vec<usize>, WorkflowError> {
+        if self.nodes.is_empty() {
+            return Err(WorkflowError::Empty);
+        }
+
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if index.insert(node.id.as_str(), i).is_some() {
+                return Err(WorkflowError::DuplicateNode(node.id.clone()));
+            }
+        }
+
+        let mut in_degree = vec![0usize; self.nodes.len()];
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            for edge in &node.depends_on {
+                let dep = *index.get(edge.node.as_str()).ok_or_else(|| WorkflowError::UnknownNode {
+                    node: node.id.clone(),
+                    missing: edge.node.clone(),
+                })?;
+                dependents[dep].push(i);
+                in_degree[i] += 1;
+            }
+            if let Some(condition) = &node.condition {
+                if !node.depends_on.iter().any(|edge| edge.node == condition.node) {
+                    return Err(WorkflowError::ConditionNotDependency {
+                        node: node.id.clone(),
+                        target: condition.node.clone(),
+                    });
+                }
+                if !is_valid_json_pointer(&condition.path) {
+                    return Err(WorkflowError::InvalidConditionPath {
+                        node: node.id.clone(),
+                        path: condition.path.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut ready: VecDeque<usize> = (0..self.nodes.len()).filter(|&i| in_degree[i] == 0).collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(i) = ready.pop_front() {
+            order.push(i);
+            for &next in &dependents[i] {
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            let scheduled: HashSet<usize> = order.iter().copied().collect();
+            let remaining: Vec<&str> = (0..self.nodes.len())
+                .filter(|i| !scheduled.contains(i))
+                .map(|i| self.nodes[i].id.as_str())
+                .collect();
+            return Err(WorkflowError::Cycle(remaining.join(", ")));
+        }
+        Ok(order)
+    }
+}
+
+/// 节点状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    /// 等待任务结束超时，按失败处理；任务真正结束后才会执行后续节点
+    TimedOut,
+    /// 依赖边或条件不满足，未执行
+    Skipped,
+}
+
+impl NodeStatus {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, NodeStatus::Succeeded | NodeStatus::Failed | NodeStatus::TimedOut | NodeStatus::Skipped)
+    }
+
+    /// 是否按失败处理（`Failure` 依赖边、工作流整体状态）
+    pub fn is_failure(&self) -> bool {
+        matches!(self, NodeStatus::Failed | NodeStatus::TimedOut)
+    }
+}
+
+/// 工作流整体状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowStatus {
+    Running,
+    /// 所有节点均已结束且没有失败节点
+    Succeeded,
+    /// 所有节点均已结束，至少一个节点失败或等待超时
+    Failed,
+}
+
+/// 节点运行状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowNodeState {
+    pub id: String,
+    pub function_name: String,
+    pub status: NodeStatus,
+    /// 对应的队列任务ID
+    pub task_id: Option<i32>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    /// 跳过原因
+    pub skip_reason: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// 工作流运行记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub workflow_id: i32,
+    pub name: Option<String>,
+    pub status: WorkflowStatus,
+    pub nodes: Vec<WorkflowNodeState>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl WorkflowRun {
+    fn node(&self, id: &str) -> Option<&WorkflowNodeState> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+}
+
+static WORKFLOWS: Lazy<Mutex<HashMap<i32, WorkflowRun>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_WORKFLOW_ID: AtomicI32 = AtomicI32::new(1);
+
+/// 查询工作流运行记录
+pub fn get_workflow(workflow_id: i32) -> Option<WorkflowRun> {
+    WORKFLOWS.lock().unwrap().get(&workflow_id).cloned()
+}
+
+/// 获取所有工作流运行记录（按ID排序）
+pub fn get_all_workflows() -> Vec<WorkflowRun> {
+    let mut runs: Vec<WorkflowRun> = WORKFLOWS.lock().unwrap().values().cloned().collect();
+    runs.sort_by_key(|run| run.workflow_id);
+    runs
+}
+
+/// 清理已结束的旧工作流（保留最近24小时的）
+pub fn cleanup_old_workflows() {
+    cleanup_finished_workflows(chrono::Duration::hours(24));
+}
+
+/// 清理结束时间早于 `max_age` 的工作流运行记录，返回清理数量
+pub fn cleanup_finished_workflows(max_age: chrono::Duration) -> usize {
+    let cutoff_time = Utc::now() - max_age;
+    let mut workflows = WORKFLOWS.lock().unwrap();
+    let before = workflows.len();
+    workflows.retain(|_, run| run.finished_at.is_none_or(|t| t > cutoff_time));
+
+    let removed = before - workflows.len();
+    if removed > 0 {
+        info!("清理了 {} 个已结束的工作流记录", removed);
+    }
+    removed
+}
+
+fn update_workflow<R>(workflow_id: i32, f: impl FnOnce(&mut WorkflowRun) -> R) -> Option<R> {
+    WORKFLOWS.lock().unwrap().get_mut(&workflow_id).map(f)
+}
+
+/// 判断节点的依赖边是否全部满足，不满足时返回原因
+fn unmet_dependency(node: &WorkflowNodeSpec, run: &WorkflowRun) -> Option<String> {
+    node.depends_on.iter().find_map(|edge| {
+        let status = run.node(&edge.node).map(|n| n.status)?;
+        let satisfied = match edge.on {
+            EdgeKind::Success => status == NodeStatus::Succeeded,
+            EdgeKind::Failure => status.is_failure(),
+            EdgeKind::Always => status.is_terminal(),
+        };
+        (!satisfied).then(|| format!("依赖节点 {} 状态为 {:?}，要求 {:?}", edge.node, status, edge.on))
+    })
+}
+
+/// 工作流执行器
+///
+/// 节点通过 `EnhancedMaaFunctionHandlerV2::execute_function_and_wait` 提交到任务队列，
+/// 前一个节点结束后才会提交下一个节点。
+#[derive(Clone)]
+pub struct WorkflowEngine {
+    handler: EnhancedMaaFunctionHandlerV2,
+    event_tx: Option<broadcast::Sender<TaskProgressEvent>>,
+}
+
+impl WorkflowEngine {
+    pub fn new(handler: EnhancedMaaFunctionHandlerV2) -> Self {
+        Self { handler, event_tx: None }
+    }
+
+    /// 设置SSE事件广播器，节点状态变更会推送到SSE流
+    pub fn with_event_sender(mut self, event_tx: broadcast::Sender<TaskProgressEvent>) -> Self {
+        self.event_tx = Some(event_tx);
+        self
+    }
+
+    /// 校验并提交工作流，在后台按顺序执行，立即返回初始运行记录
+    ///
+    /// 需要二次确认的节点在后台执行时无人确认，提交时直接拒绝。
+    /// 提交时顺带清理已结束超过24小时的运行记录。
+    pub fn submit(&self, spec: WorkflowSpec) -> Result<WorkflowRun, WorkflowError> {
+        let order = spec.execution_order()?;
+        if let Some((node, summary)) = spec.nodes.iter()
+            .find_map(|node| confirmation::requires_confirmation(&node.function_call).map(|summary| (node.id.clone(), summary)))
+        {
+            return Err(WorkflowError::RequiresConfirmation { node, summary });
+        }
+        cleanup_old_workflows();
+        let run = create_run(&spec);
+        info!("提交工作流 {} ({} 个节点)", run.workflow_id, spec.nodes.len());
+
+        let handler = self.handler.clone();
+        let event_tx = self.event_tx.clone();
+        let workflow_id = run.workflow_id;
+        tokio::spawn(async move {
+            execute_workflow(workflow_id, spec, order, event_tx.as_ref(), |call, timeout| {
+                let handler = handler.clone();
+                async move { handler.execute_function_and_wait(call, timeout).await }
+            }).await;
+        });
+
+        Ok(run)
+    }
+}
+
+fn create_run(spec: &WorkflowSpec) -> WorkflowRun {
+    let workflow_id = NEXT_WORKFLOW_ID.fetch_add(1, Ordering::SeqCst);
+    let run = WorkflowRun {
+        workflow_id,
+        name: spec.name.clone(),
+        status: WorkflowStatus::Running,
+        nodes: spec.nodes.iter().map(|node| WorkflowNodeState {
+            id: node.id.clone(),
+            function_name: node.function_call.name.clone(),
+            status: NodeStatus::Pending,
+            task_id: None,
+            result: None,
+            error: None,
+            skip_reason: None,
+            started_at: None,
+            finished_at: None,
+        }).collect(),
+        created_at: Utc::now(),
+        finished_at: None,
+    };
+    WORKFLOWS.lock().unwrap().insert(workflow_id, run.clone());
+    run
+}
+
+/// 按拓扑顺序执行工作流节点
+async fn execute_workflow<F, Fut>(
+    workflow_id: i32,
+    spec: WorkflowSpec,
+    order: Vec<usize>,
+    event_tx: Option<&broadcast::Sender<TaskProgressEvent>>,
+    execute: F,
+) where
+    F: Fn(FunctionCall, Option<u64>) -> Fut,
+    Fut: Future<Output = FunctionResponse>,
+{
+    for i in order {
+        let node = &spec.nodes[i];
+        let Some(run) = get_workflow(workflow_id) else { return };
+
+        let skip_reason = unmet_dependency(node, &run).or_else(|| {
+            let condition = node.condition.as_ref()?;
+            let result = run.node(&condition.node).and_then(|n| n.result.as_ref());
+            (!condition.evaluate(result)).then(|| format!("条件不满足: {} {} {:?}", condition.node, condition.path, condition.op))
+        });
+
+        if let Some(reason) = skip_reason {
+            debug!("工作流 {} 跳过节点 {}: {}", workflow_id, node.id, reason);
+            finish_node(workflow_id, i, event_tx, |state| {
+                state.status = NodeStatus::Skipped;
+                state.skip_reason = Some(reason);
+            });
+            continue;
+        }
+
+        finish_node(workflow_id, i, event_tx, |state| {
+            state.status = NodeStatus::Running;
+            state.started_at = Some(Utc::now());
+        });
+
+        let response = execute(node.function_call.clone(), node.wait_timeout).await;
+        let task_id = response.metadata.task_id.as_deref().and_then(|id| id.parse::<i32>().ok());
+        let wait_timed_out = response.result.as_ref()
+            .and_then(|r| r.get("wait_timed_out"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let (status, error) = if !response.success {
+            (NodeStatus::Failed, Some(response.error.map(|e| e.message).unwrap_or_else(|| "任务执行失败".to_string())))
+        } else if wait_timed_out {
+            (NodeStatus::TimedOut, Some("等待任务结束超时".to_string()))
+        } else {
+            (NodeStatus::Succeeded, None)
+        };
+
+        finish_node(workflow_id, i, event_tx, |state| {
+            state.status = status;
+            state.task_id = task_id;
+            state.result = response.result;
+            state.error = error;
+        });
+
+        // 超时的节点对应的任务仍在运行，结束后再继续，避免后续节点与其同时执行
+        if let (NodeStatus::TimedOut, Some(task_id)) = (status, task_id) {
+            let outcome = wait_until_finished(task_id).await;
+            debug!("工作流 {} 节点 {} 的任务 {} 已结束", workflow_id, node.id, task_id);
+            finish_node(workflow_id, i, event_tx, |state| state.result = Some(outcome.to_json()));
+        }
+    }
+
+    let summary = update_workflow(workflow_id, |run| {
+        let failed = run.nodes.iter().any(|n| n.status.is_failure());
+        run.status = if failed { WorkflowStatus::Failed } else { WorkflowStatus::Succeeded };
+        run.finished_at = Some(Utc::now());
+        run.clone()
+    });
+    if let Some(run) = summary {
+        info!("工作流 {} 结束: {:?}", workflow_id, run.status);
+        send_event(event_tx, workflow_event(&run, None, TaskEventType::WorkflowCompleted, format!("工作流 {} 已结束", workflow_id)));
+    }
+}
+
+/// 等待队列任务真正结束（不设等待上限，任务本身的超时由Worker处理）
+async fn wait_until_finished(task_id: i32) -> TaskWaitOutcome {
+    loop {
+        match task_status::wait_for_task(task_id, None).await {
+            TaskWaitOutcome::WaitTimedOut { .. } => continue,
+            outcome => return outcome,
+        }
+    }
+}
+
+/// 更新节点状态并推送 `workflow_node` 事件
+fn finish_node(
+    workflow_id: i32,
+    index: usize,
+    event_tx: Option<&broadcast::Sender<TaskProgressEvent>>,
+    update: impl FnOnce(&mut WorkflowNodeState),
+) {
+    let updated = update_workflow(workflow_id, |run| {
+        let state = &mut run.nodes[index];
+        update(state);
+        if state.status.is_terminal() {
+            state.finished_at = Some(Utc::now());
+        }
+        run.clone()
+    });
+
+    if let Some(run) = updated {
+        let state = &run.nodes[index];
+        let message = format!("工作流 {} 节点 {} ({}) 状态: {:?}", workflow_id, state.id, state.function_name, state.status);
+        send_event(event_tx, workflow_event(&run, Some(state), TaskEventType::WorkflowNode, message));
+    }
+}
+
+fn workflow_event(run: &WorkflowRun, node: Option<&WorkflowNodeState>, event_type: TaskEventType, message: String) -> TaskProgressEvent {
+    TaskProgressEvent {
+        // 节点事件使用对应的队列任务ID，便于在单任务SSE流中查看；其余使用0
+        task_id: node.and_then(|n| n.task_id).unwrap_or(0),
+        task_type: "workflow".to_string(),
+        event_type,
+        message,
+        data: Some(json!({
+            "workflow_id": run.workflow_id,
+            "workflow_status": run.status,
+            "node": node,
+        })),
+        timestamp: Utc::now(),
+    }
+}
+
+fn send_event(event_tx: Option<&broadcast::Sender<TaskProgressEvent>>, event: TaskProgressEvent) {
+    if let Some(tx) = event_tx {
+        if tx.send(event).is_err() {
+            warn!("工作流事件没有SSE订阅者");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_tools::types::{ErrorType, MaaError, ResponseMetadata};
+
+    fn node(id: &str, name: &str, depends_on: Vec<(&str, EdgeKind)>) -> WorkflowNodeSpec {
+        WorkflowNodeSpec {
+            id: id.to_string(),
+            function_call: FunctionCall { name: name.to_string(), arguments: json!({}) },
+            depends_on: depends_on.into_iter()
+                .map(|(node, on)| WorkflowEdge { node: node.to_string(), on })
+                .collect(),
+            condition: None,
+            wait_timeout: None,
+        }
+    }
+
+    fn response(name: &str, success: bool, result: Value) -> FunctionResponse {
+        FunctionResponse {
+            success,
+            result: Some(result),
+            error: (!success).then(|| MaaError {
+                error_type: ErrorType::MaaCoreError,
+                message: "模拟失败".to_string(),
+                details: None,
+                suggestion: None,
+                error_code: None,
+            }),
+            timestamp: Utc::now(),
+            execution_time_ms: None,
+            metadata: ResponseMetadata {
+                task_id: Some("1".to_string()),
+                function_name: name.to_string(),
+                recommendations: vec![],
+                next_actions: vec![],
+                resource_usage: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_execution_order_validation() {
+        let spec = WorkflowSpec {
+            name: None,
+            nodes: vec![
+                node("close", "maa_closedown", vec![("fight", EdgeKind::Always)]),
+                node("fight", "maa_combat_enhanced", vec![("infrast", EdgeKind::Success)]),
+                node("infrast", "maa_infrastructure_enhanced", vec![]),
+            ],
+        };
+        assert_eq!(spec.execution_order().unwrap(), vec![2, 1, 0]);
+
+        let cyclic = WorkflowSpec {
+            name: None,
+            nodes: vec![
+                node("a", "maa_startup", vec![("b", EdgeKind::Success)]),
+                node("b", "maa_startup", vec![("a", EdgeKind::Success)]),
+            ],
+        };
+        assert!(matches!(cyclic.execution_order(), Err(WorkflowError::Cycle(_))));
+
+        let unknown = WorkflowSpec { name: None, nodes: vec![node("a", "maa_startup", vec![("x", EdgeKind::Always)])] };
+        assert!(matches!(unknown.execution_order(), Err(WorkflowError::UnknownNode { .. })));
+    }
+
+    #[test]
+    fn test_submit_rejects_confirmable_nodes() {
+        let (sender, _receiver) = crate::maa_core::task_queue_v2::create_maa_task_channel_with_capacity(8);
+        let engine = WorkflowEngine::new(crate::function_tools::create_enhanced_function_handler_v2(sender));
+        // stop_tasks 默认为true，紧急返回需要确认
+        let spec = WorkflowSpec {
+            name: None,
+            nodes: vec![
+                node("fight", "maa_combat_enhanced", vec![]),
+                node("home", "maa_emergency_home", vec![("fight", EdgeKind::Failure)]),
+            ],
+        };
+        assert!(matches!(engine.submit(spec), Err(WorkflowError::RequiresConfirmation { node, .. }) if node == "home"));
+    }
+
+    #[test]
+    fn test_condition_evaluation() {
+        let result = json!({"status": "succeeded", "drops": [{"item_name": "固源岩"}], "sanity": 30});
+        let condition = |path: &str, op, value| NodeCondition { node: "a".to_string(), path: path.to_string(), op, value };
+
+        assert!(condition("/status", ConditionOp::Eq, json!("succeeded")).evaluate(Some(&result)));
+        assert!(condition("/sanity", ConditionOp::Gte, json!(30)).evaluate(Some(&result)));
+        assert!(!condition("/sanity", ConditionOp::Lt, json!(10)).evaluate(Some(&result)));
+        assert!(condition("/drops/0/item_name", ConditionOp::Exists, Value::Null).evaluate(Some(&result)));
+        assert!(condition("/missing", ConditionOp::NotExists, Value::Null).evaluate(Some(&result)));
+        assert!(condition("/status", ConditionOp::Contains, json!("succ")).evaluate(Some(&result)));
+        assert!(!condition("/status", ConditionOp::Eq, json!("failed")).evaluate(None));
+    }
+
+    #[tokio::test]
+    async fn test_failure_skips_success_edge_and_runs_always() {
+        let spec = WorkflowSpec {
+            name: Some("日常".to_string()),
+            nodes: vec![
+                node("infrast", "maa_infrastructure_enhanced", vec![]),
+                node("fight", "maa_combat_enhanced", vec![("infrast", EdgeKind::Success)]),
+                node("notify", "maa_take_screenshot", vec![("infrast", EdgeKind::Failure)]),
+                node("close", "maa_closedown", vec![("fight", EdgeKind::Always)]),
+            ],
+        };
+        let order = spec.execution_order().unwrap();
+        let run = create_run(&spec);
+        let (tx, mut rx) = broadcast::channel(64);
+
+        execute_workflow(run.workflow_id, spec, order, Some(&tx), |call, _| async move {
+            let success = call.name != "maa_infrastructure_enhanced";
+            response(&call.name, success, json!({"status": if success { "succeeded" } else { "failed" }}))
+        }).await;
+
+        let run = get_workflow(run.workflow_id).unwrap();
+        let status = |id: &str| run.node(id).unwrap().status;
+        assert_eq!(status("infrast"), NodeStatus::Failed);
+        assert_eq!(status("fight"), NodeStatus::Skipped);
+        assert_eq!(status("notify"), NodeStatus::Succeeded);
+        assert_eq!(status("close"), NodeStatus::Succeeded);
+        assert_eq!(run.status, WorkflowStatus::Failed);
+
+        let mut event_types = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            event_types.push(event.event_type);
+        }
+        assert_eq!(event_types.last(), Some(&TaskEventType::WorkflowCompleted));
+        assert!(event_types.iter().filter(|t| **t == TaskEventType::WorkflowNode).count() >= 4);
+    }
+
+    #[test]
+    fn test_invalid_condition_path_rejected() {
+        let mut fight = node("fight", "maa_combat_enhanced", vec![("infrast", EdgeKind::Success)]);
+        fight.condition = Some(NodeCondition {
+            node: "infrast".to_string(),
+            path: "drops".to_string(),
+            op: ConditionOp::Exists,
+            value: Value::Null,
+        });
+        let mut spec = WorkflowSpec { name: None, nodes: vec![node("infrast", "maa_infrastructure_enhanced", vec![]), fight] };
+        assert_eq!(spec.execution_order().unwrap_err(), WorkflowError::InvalidConditionPath {
+            node: "fight".to_string(),
+            path: "drops".to_string(),
+        });
+
+        spec.nodes[1].condition.as_mut().unwrap().path = "/a~2b".to_string();
+        assert!(matches!(spec.execution_order(), Err(WorkflowError::InvalidConditionPath { .. })));
+        spec.nodes[1].condition.as_mut().unwrap().path = "/drops/0/item~1name".to_string();
+        assert!(spec.execution_order().is_ok());
+        assert!(is_valid_json_pointer(""));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_node_blocks_dependents() {
+        task_status::register_task(7401, "maa_combat_enhanced".to_string(), json!({}));
+        task_status::start_task(7401);
+        let spec = WorkflowSpec {
+            name: None,
+            nodes: vec![
+                node("fight", "maa_combat_enhanced", vec![]),
+                node("close", "maa_closedown", vec![("fight", EdgeKind::Always)]),
+                node("next", "maa_recruit_enhanced", vec![("fight", EdgeKind::Success)]),
+            ],
+        };
+        let order = spec.execution_order().unwrap();
+        let run = create_run(&spec);
+        tokio::spawn(async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            task_status::complete_task(7401, json!({}));
+        });
+
+        execute_workflow(run.workflow_id, spec, order, None, |call, _| async move {
+            if call.name == "maa_combat_enhanced" {
+                let mut timed_out = response(&call.name, true, json!({"task_id": 7401, "wait_timed_out": true}));
+                timed_out.metadata.task_id = Some("7401".to_string());
+                return timed_out;
+            }
+            // 后续节点只在超时节点的任务结束后执行
+            assert!(task_status::get_task_status(7401).unwrap().is_finished());
+            response(&call.name, true, json!({}))
+        }).await;
+
+        let run = get_workflow(run.workflow_id).unwrap();
+        let fight = run.node("fight").unwrap();
+        assert_eq!(fight.status, NodeStatus::TimedOut);
+        assert_eq!(fight.result.as_ref().unwrap()["status"], "succeeded");
+        assert_eq!(run.node("close").unwrap().status, NodeStatus::Succeeded);
+        assert_eq!(run.node("next").unwrap().status, NodeStatus::Skipped);
+        assert_eq!(run.status, WorkflowStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_condition_skips_node() {
+        let mut fight = node("fight", "maa_combat_enhanced", vec![("infrast", EdgeKind::Success)]);
+        fight.condition = Some(NodeCondition {
+            node: "infrast".to_string(),
+            path: "/drops".to_string(),
+            op: ConditionOp::Contains,
+            value: json!("龙门币"),
+        });
+        let spec = WorkflowSpec { name: None, nodes: vec![node("infrast", "maa_infrastructure_enhanced", vec![]), fight] };
+        let order = spec.execution_order().unwrap();
+        let run = create_run(&spec);
+
+        execute_workflow(run.workflow_id, spec, order, None, |call, _| async move {
+            response(&call.name, true, json!({"drops": []}))
+        }).await;
+
+        let run = get_workflow(run.workflow_id).unwrap();
+        let fight = run.node("fight").unwrap();
+        assert_eq!(fight.status, NodeStatus::Skipped);
+        assert!(fight.skip_reason.as_deref().unwrap().contains("条件不满足"));
+        assert_eq!(run.status, WorkflowStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_cleanup_finished_workflows() {
+        let spec = WorkflowSpec { name: None, nodes: vec![node("infrast", "maa_infrastructure_enhanced", vec![])] };
+        let old = create_run(&spec);
+        let running = create_run(&spec);
+        update_workflow(old.workflow_id, |run| {
+            run.status = WorkflowStatus::Succeeded;
+            run.finished_at = Some(Utc::now() - chrono::Duration::hours(2));
+        });
+
+        assert!(cleanup_finished_workflows(chrono::Duration::hours(1)) >= 1);
+        assert!(get_workflow(old.workflow_id).is_none());
+        assert!(get_workflow(running.workflow_id).is_some());
+    }
+}
@@ -3,6 +3,7 @@
 //! 基于 MCP 协议的 MAA 智能控制中间层，支持多种 AI 的 Function Calling 格式转换
 
 pub mod config;
+pub mod auth;
 pub mod ai_client;
 pub mod maa_adapter;
 pub mod maa_core;
@@ -11,6 +12,9 @@ pub mod function_tools;
 // operator_manager module REMOVED - 功能已集成到 function_tools 中
 pub mod copilot_matcher;
 pub mod sse;
+pub mod ws;
+pub mod openai_compat;
+pub mod mcp;
 
 // 导出核心类型
 pub use config::AppConfig;
@@ -11,6 +11,8 @@ use std::env;
 // MAA FFI 相关导入
 use maa_sys::Assistant;
 
+use super::task_queue_v2::MaaTaskSender;
+
 // 使用线程本地存储来解决Assistant不是Send的问题
 thread_local! {
     static MAA_ASSISTANT: std::cell::RefCell<Option<Assistant>> = std::cell::RefCell::new(None);
@@ -210,57 +212,37 @@ pub async fn get_tasks_list() -> Result<Value> {
 }
 
 /// 动态设置任务参数
-/// 
+///
+/// 参数调整需要队列任务ID到MAA任务ID的映射，且只能在持有MaaCore的Worker线程上执行，
+/// 此处不再直接调用线程本地的Assistant，而是返回错误提示调用方改用任务队列的
+/// `maa_adjust_task_params`。
+///
 /// # 参数
-/// * `task_id` - 任务ID
+/// * `task_id` - 队列任务ID
 /// * `params` - 新的参数JSON
 pub async fn set_task_params(task_id: i32, params: Value) -> Result<Value> {
-    // 动态调整任务 {} 参数
-    
-    execute_maa_operation(|assistant| {
-        let params_str = params.to_string();
-        assistant.set_task_params(task_id, params_str.as_str())?;
-        
-        Ok(json!({
-            "task_id": task_id,
-            "updated_params": params,
-            "status": "updated",
-            "timestamp": Utc::now()
-        }))
-    }).await.or_else(|_| {
-        warn!("MAA Core 不可用，使用 stub 模式");
-        Ok(json!({
-            "task_id": task_id,
-            "updated_params": params,
-            "status": "stub_updated",
-            "timestamp": Utc::now()
-        }))
-    })
+    warn!("set_task_params 不再直接调用MaaCore: task_id={}, params={}", task_id, params);
+    Err(anyhow!(
+        "任务 {} 的参数调整必须通过任务队列进行，请使用 maa_adjust_task_params",
+        task_id
+    ))
 }
 
 /// 快速返回游戏主界面
 /// 
 /// # 返回
-/// 操作结果
+/// 操作结果；MAA Core 不可用时返回错误，不再伪装成功
 pub async fn back_to_home() -> Result<Value> {
-    // 执行快速返回主界面操作
-    
     execute_maa_operation(|assistant| {
-        assistant.back_to_home()?;
+        assistant.back_to_home()
+            .map_err(|e| anyhow!("返回主界面失败: {:?}", e))?;
         
         Ok(json!({
             "action": "back_to_home",
             "status": "executed",
             "timestamp": Utc::now()
         }))
-    }).await.or_else(|_| {
-        warn!("MAA Core 不可用，使用 stub 模式");
-        Ok(json!({
-            "action": "back_to_home", 
-            "status": "stub_executed",
-            "timestamp": Utc::now()
-        }))
-    })
+    }).await
 }
 
 /// 截图操作 (已废弃 - 使用任务队列)
@@ -284,19 +266,48 @@ pub fn take_screenshot() -> Result<Vec<u8>> {
 /// 点击操作ID
 pub fn perform_click(x: i32, y: i32) -> Result<i32> {
     info!("perform_click已废弃，请使用任务队列");
-    info!("尝试点击: ({}, {})", x, y);
     
-    Ok(1)
+    with_initialized_assistant(|assistant| {
+        assistant.async_click(x, y, true)
+            .map_err(|e| anyhow!("点击失败: {:?}", e))
+    })
 }
 
-/// 停止所有MAA任务 (已废弃 - 使用任务队列)
-/// 
+/// 停止所有MAA任务
+///
+/// 队列任务的任务链运行在Worker持有的MaaCore上，停止请求以 `maa_stop_tasks` 任务发送到任务队列，
+/// 由Worker停止MAA、取消运行中和排队中的任务。
+///
+/// # 参数
+/// * `task_sender` - 任务队列发送端
+/// * `reason` - 停止原因
+///
 /// # 返回
-/// 操作结果
-pub fn stop_all_tasks() -> Result<()> {
-    info!("stop_all_tasks已废弃，请使用任务队列");
+/// 被中断和被取消的任务
+pub async fn stop_all_tasks(task_sender: &MaaTaskSender, reason: &str) -> Result<Value> {
+    let (task_id, response_rx) = task_sender
+        .send_sync_task("maa_stop_tasks".to_string(), json!({ "reason": reason }))
+        .map_err(|e| anyhow!("停止任务请求入队失败: {}", e))?;
+    info!("停止全部任务请求已入队 (task_id: {})", task_id);
     
-    Ok(())
+    let result = response_rx.await
+        .map_err(|_| anyhow!("停止任务 {} 未返回结果", task_id))?;
+    if result.success {
+        Ok(result.result.unwrap_or(Value::Null))
+    } else {
+        Err(anyhow!("停止任务失败: {}", result.error.unwrap_or_else(|| "未知错误".to_string())))
+    }
+}
+
+/// 在已初始化的 MAA Assistant 上执行同步操作（不会触发初始化）
+fn with_initialized_assistant<R>(operation: impl FnOnce(&Assistant) -> Result<R>) -> Result<R> {
+    MAA_ASSISTANT.with(|assistant_cell| {
+        let assistant_borrow = assistant_cell.borrow();
+        let assistant = assistant_borrow.as_ref()
+            .ok_or_else(|| anyhow!("MAA Assistant 未初始化"))?;
+        
+        operation(assistant)
+    })
 }
 
 /// 执行启动任务 (已废弃 - 使用任务队列)
@@ -342,38 +353,9 @@ pub async fn execute_infrastructure(facility: &[String], drones: &str, threshold
 }
 
 /// 智能任务参数调整策略
+///
+/// 先校验策略名称，再交给 [`set_task_params`]，因此同样会提示改用 `maa_adjust_task_params`
 pub async fn adjust_task_strategy(task_id: i32, strategy: &str, context: Value) -> Result<Value> {
-    // 应用智能调整策略: {} 到任务 {}
-    
-    let new_params = match strategy {
-        "reduce_difficulty" => {
-            // 降低难度：减少药剂使用，降低目标次数
-            json!({
-                "medicine": 0,
-                "times": 1,
-                "strategy": "conservative"
-            })
-        },
-        "increase_efficiency" => {
-            // 提高效率：增加药剂使用
-            let medicine_count = context.get("available_medicine").and_then(|v| v.as_i64()).unwrap_or(0);
-            json!({
-                "medicine": medicine_count.min(99),
-                "times": 0,
-                "strategy": "aggressive"  
-            })
-        },
-        "emergency_stop" => {
-            // 紧急停止：设置为无限循环防止进一步执行
-            json!({
-                "enable": false,
-                "times": 0
-            })
-        },
-        _ => {
-            return Err(anyhow!("未知的调整策略: {}", strategy));
-        }
-    };
-    
+    let new_params = super::task_params::strategy_params(strategy, &context)?;
     set_task_params(task_id, new_params).await
 }
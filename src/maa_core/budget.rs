@@ -0,0 +1,573 @@
+//! 资源消耗预算
+//!
+//! 按天/按周限制理智药、源石和肉鸽投资的消耗量，额度在 `app.toml` 的 `[budget]` 中配置。
+//! Function Call入队前检查额度，已用完时拒绝并给出可以直接转述给用户的原因；
+//! Worker构造MAA参数时再把使用上限压到剩余额度内，并在任务链提交后预留这部分额度，
+//! 避免已交给MAA、尚未执行的多个任务链合计超额。任务链结束时释放未用完的预留。
+//! 实际用量从MAA回调（UseMedicine / UseStone / RoguelikeInvestment）统计，记录保存在 `budget.usage_path`，重启后继续累计。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Datelike, Duration, Local, TimeZone, Timelike, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use super::task_params;
+use crate::config::{BudgetCap, BudgetConfig, CONFIG};
+
+/// 不限量时下发给MAA的使用数量（与MAA GUI一致）
+pub const UNLIMITED_USES: u32 = 999;
+
+/// 用量记录的保留天数，超过一周的记录不再参与计算
+const RETENTION_DAYS: i64 = 8;
+
+/// 受预算限制的资源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetResource {
+    Medicine,
+    Stone,
+    RoguelikeInvestment,
+}
+
+impl BudgetResource {
+    pub const ALL: [BudgetResource; 3] = [Self::Medicine, Self::Stone, Self::RoguelikeInvestment];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Medicine => "medicine",
+            Self::Stone => "stone",
+            Self::RoguelikeInvestment => "roguelike_investment",
+        }
+    }
+
+    /// 给用户看的名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Medicine => "理智药",
+            Self::Stone => "源石",
+            Self::RoguelikeInvestment => "肉鸽投资",
+        }
+    }
+}
+
+impl fmt::Display for BudgetResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 额度的计算周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetWindow {
+    Daily,
+    Weekly,
+}
+
+impl BudgetWindow {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Daily => "今日",
+            Self::Weekly => "本周",
+        }
+    }
+}
+
+/// 预算检查错误
+#[derive(Debug, Error, PartialEq)]
+pub enum BudgetError {
+    #[error("{}{}额度已用完（已用 {used}/{cap}），本次操作已拒绝", .window.label(), .resource.label())]
+    Exhausted { resource: BudgetResource, window: BudgetWindow, used: u32, cap: u32 },
+}
+
+/// 一次资源消耗
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub resource: BudgetResource,
+    pub amount: u32,
+    pub at: DateTime<Utc>,
+}
+
+/// 下发给一个MAA任务链的使用上限
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub resource: BudgetResource,
+    /// 下发给MAA的使用上限
+    pub granted: u32,
+    /// 已从回调统计到的用量
+    pub consumed: u32,
+    pub at: DateTime<Utc>,
+}
+
+impl Reservation {
+    /// 尚未用掉的预留额度
+    pub fn outstanding(&self) -> u32 {
+        self.granted.saturating_sub(self.consumed)
+    }
+}
+
+/// 资源消耗记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLedger {
+    records: Vec<UsageRecord>,
+    /// MAA任务ID -> 预留额度，只在内存中保存（重启后MAA中的任务链也不存在了）
+    #[serde(skip)]
+    reservations: HashMap<i32, Vec<Reservation>>,
+}
+
+impl UsageLedger {
+    /// 记录一次消耗，同时清理过期记录
+    pub fn record(&mut self, resource: BudgetResource, amount: u32, at: DateTime<Utc>) {
+        if amount == 0 {
+            return;
+        }
+        let cutoff = at - Duration::days(RETENTION_DAYS);
+        self.records.retain(|record| record.at >= cutoff);
+        self.records.push(UsageRecord { resource, amount, at });
+    }
+
+    /// `since` 之后的用量
+    pub fn used_since<Tz: TimeZone>(&self, resource: BudgetResource, since: &DateTime<Tz>) -> u32 {
+        self.records.iter()
+            .filter(|record| record.resource == resource && record.at >= *since)
+            .map(|record| record.amount)
+            .sum()
+    }
+
+    /// 为任务链预留额度，已有预留时更新上限（保留已统计的用量）
+    pub fn reserve(&mut self, maa_task_id: i32, resource: BudgetResource, granted: u32, at: DateTime<Utc>) {
+        let reservations = self.reservations.entry(maa_task_id).or_default();
+        match reservations.iter_mut().find(|r| r.resource == resource) {
+            Some(reservation) => reservation.granted = granted,
+            None => reservations.push(Reservation { resource, granted, consumed: 0, at }),
+        }
+    }
+
+    /// 任务链实际使用了预留的额度
+    pub fn consume(&mut self, maa_task_id: i32, resource: BudgetResource, amount: u32) {
+        if let Some(reservation) = self.reservations.get_mut(&maa_task_id)
+            .and_then(|reservations| reservations.iter_mut().find(|r| r.resource == resource)) {
+            reservation.consumed = reservation.consumed.saturating_add(amount);
+        }
+    }
+
+    /// 任务链结束，释放未用完的预留
+    pub fn release(&mut self, maa_task_id: i32) -> Vec<Reservation> {
+        self.reservations.remove(&maa_task_id).unwrap_or_default()
+    }
+
+    /// `since` 之后预留、尚未用掉的额度
+    pub fn reserved_since<Tz: TimeZone>(&self, resource: BudgetResource, since: &DateTime<Tz>) -> u32 {
+        self.reservations.values()
+            .flatten()
+            .filter(|reservation| reservation.resource == resource && reservation.at >= *since)
+            .map(Reservation::outstanding)
+            .sum()
+    }
+}
+
+/// 周期的开始时间：每天在 `reset_hour` 点刷新，每周从周一的刷新时间开始（与游戏的4点刷新对齐）
+pub fn window_start<Tz: TimeZone>(now: &DateTime<Tz>, reset_hour: u32, window: BudgetWindow) -> DateTime<Tz> {
+    let reset_hour = reset_hour.min(23);
+    let mut day = now.date_naive();
+    if now.hour() < reset_hour {
+        day = day.pred_opt().unwrap_or(day);
+    }
+    if window == BudgetWindow::Weekly {
+        day -= Duration::days(day.weekday().num_days_from_monday() as i64);
+    }
+    let start = day.and_hms_opt(reset_hour, 0, 0).unwrap_or_default();
+    now.timezone().from_local_datetime(&start).earliest().unwrap_or_else(|| now.clone())
+}
+
+/// 预算策略
+#[derive(Debug, Clone)]
+pub struct BudgetPolicy {
+    enabled: bool,
+    reset_hour: u32,
+    caps: HashMap<BudgetResource, BudgetCap>,
+}
+
+impl BudgetPolicy {
+    pub fn new(enabled: bool, reset_hour: u32) -> Self {
+        Self { enabled, reset_hour, caps: HashMap::new() }
+    }
+
+    pub fn with_cap(mut self, resource: BudgetResource, cap: BudgetCap) -> Self {
+        self.caps.insert(resource, cap);
+        self
+    }
+
+    pub fn from_config(config: &BudgetConfig) -> Self {
+        Self::new(config.enabled, config.reset_hour)
+            .with_cap(BudgetResource::Medicine, config.medicine)
+            .with_cap(BudgetResource::Stone, config.stone)
+            .with_cap(BudgetResource::RoguelikeInvestment, config.roguelike_investment)
+    }
+
+    /// 剩余额度，`None` 表示不限；任一周期已用完时返回错误
+    pub fn allowance<Tz: TimeZone>(&self, ledger: &UsageLedger, resource: BudgetResource, now: &DateTime<Tz>) -> Result<Option<u32>, BudgetError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let Some(cap) = self.caps.get(&resource) else {
+            return Ok(None);
+        };
+        let mut allowance: Option<u32> = None;
+        for (window, limit) in [(BudgetWindow::Daily, cap.daily), (BudgetWindow::Weekly, cap.weekly)] {
+            let Some(limit) = limit else { continue };
+            // 已交给MAA、尚未用掉的预留额度视同已用
+            let since = window_start(now, self.reset_hour, window);
+            let used = ledger.used_since(resource, &since) + ledger.reserved_since(resource, &since);
+            if used >= limit {
+                return Err(BudgetError::Exhausted { resource, window, used, cap: limit });
+            }
+            let remaining = limit - used;
+            allowance = Some(allowance.map_or(remaining, |current| current.min(remaining)));
+        }
+        Ok(allowance)
+    }
+
+    /// 各资源的用量和额度
+    pub fn summary<Tz: TimeZone>(&self, ledger: &UsageLedger, now: &DateTime<Tz>) -> Value {
+        let resources: serde_json::Map<String, Value> = BudgetResource::ALL.iter()
+            .map(|resource| {
+                let cap = self.caps.get(resource).copied().unwrap_or_default();
+                let daily_used = ledger.used_since(*resource, &window_start(now, self.reset_hour, BudgetWindow::Daily));
+                let weekly_start = window_start(now, self.reset_hour, BudgetWindow::Weekly);
+                let weekly_used = ledger.used_since(*resource, &weekly_start);
+                (resource.to_string(), json!({
+                    "daily": { "used": daily_used, "cap": cap.daily },
+                    "weekly": { "used": weekly_used, "cap": cap.weekly },
+                    "reserved": ledger.reserved_since(*resource, &weekly_start),
+                }))
+            })
+            .collect();
+        json!({
+            "enabled": self.enabled,
+            "reset_hour": self.reset_hour,
+            "resources": resources,
+        })
+    }
+}
+
+/// 全局预算状态
+struct BudgetState {
+    policy: BudgetPolicy,
+    ledger: UsageLedger,
+    usage_path: Option<PathBuf>,
+}
+
+impl BudgetState {
+    fn load(config: &BudgetConfig) -> Self {
+        let usage_path = Some(config.usage_path.trim())
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let ledger = usage_path.as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| match std::fs::read(path).map(|data| serde_json::from_slice(&data)) {
+                Ok(Ok(ledger)) => Some(ledger),
+                Ok(Err(e)) => { warn!("预算用量记录格式错误，重新开始计算: {}", e); None },
+                Err(e) => { warn!("读取预算用量记录失败: {}", e); None },
+            })
+            .unwrap_or_default();
+        Self { policy: BudgetPolicy::from_config(config), ledger, usage_path }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.usage_path else { return };
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let result = serde_json::to_vec(&self.ledger)
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(path, data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("保存预算用量记录失败: {}", e);
+        }
+    }
+}
+
+static BUDGET: Lazy<Mutex<BudgetState>> = Lazy::new(|| Mutex::new(BudgetState::load(&CONFIG.budget)));
+
+/// 资源当前的剩余额度，`None` 表示不限
+pub fn allowance(resource: BudgetResource) -> Result<Option<u32>, BudgetError> {
+    let state = BUDGET.lock().unwrap();
+    state.policy.allowance(&state.ledger, resource, &Local::now())
+}
+
+/// 记录资源消耗
+pub fn record_usage(resource: BudgetResource, amount: u32) {
+    let mut state = BUDGET.lock().unwrap();
+    state.ledger.record(resource, amount, Utc::now());
+    state.save();
+    info!("记录资源消耗: {} x{}", resource.label(), amount);
+}
+
+/// 从子任务额外信息 (20003) 回调中统计资源消耗，同时扣减该任务链的预留
+pub fn record_sub_task_extra(details: &Value) {
+    let resource = match details.get("what").and_then(|v| v.as_str()) {
+        Some("UseMedicine") => BudgetResource::Medicine,
+        Some("UseStone") => BudgetResource::Stone,
+        Some("RoguelikeInvestment") => BudgetResource::RoguelikeInvestment,
+        _ => return,
+    };
+    let amount = details.get("details")
+        .and_then(|d| d.get("count"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1) as u32;
+    if let Some(maa_task_id) = details.get("taskid").and_then(|v| v.as_i64()) {
+        BUDGET.lock().unwrap().ledger.consume(maa_task_id as i32, resource, amount);
+    }
+    record_usage(resource, amount);
+}
+
+/// MAA参数中的使用上限字段
+const LIMITED_PARAMS: [(&str, BudgetResource); 3] = [
+    ("medicine", BudgetResource::Medicine),
+    ("stone", BudgetResource::Stone),
+    ("investments_count", BudgetResource::RoguelikeInvestment),
+];
+
+/// 任务链提交到MAA后，按下发的使用上限预留额度
+///
+/// 运行中调整参数后再次调用，以新的上限更新预留。
+pub fn reserve_maa_params(maa_task_id: i32, params: &Value) {
+    let mut state = BUDGET.lock().unwrap();
+    for (key, resource) in LIMITED_PARAMS {
+        if key == "investments_count" && params.get("investment_enabled") == Some(&Value::Bool(false)) {
+            continue;
+        }
+        let Some(granted) = params.get(key).and_then(|v| v.as_u64()) else { continue };
+        let granted = granted.min(u32::MAX as u64) as u32;
+        if granted > 0 || state.ledger.reservations.contains_key(&maa_task_id) {
+            state.ledger.reserve(maa_task_id, resource, granted, Utc::now());
+        }
+    }
+}
+
+/// 任务链结束（完成、出错或停止）后释放未用完的预留
+pub fn release_reservation(maa_task_id: i32) {
+    let released = BUDGET.lock().unwrap().ledger.release(maa_task_id);
+    for reservation in released.iter().filter(|r| r.outstanding() > 0) {
+        debug!("MAA任务 {} 结束，释放未用完的{}预留 {}", maa_task_id, reservation.resource.label(), reservation.outstanding());
+    }
+}
+
+/// 各资源的用量和额度
+pub fn usage_summary() -> Value {
+    let state = BUDGET.lock().unwrap();
+    state.policy.summary(&state.ledger, &Local::now())
+}
+
+/// Function Call入队前检查：会消耗的资源中任一额度已用完时拒绝
+pub fn check_function_call(function_name: &str, arguments: &Value) -> Result<(), BudgetError> {
+    for resource in requested_resources(function_name, arguments) {
+        allowance(resource)?;
+    }
+    Ok(())
+}
+
+/// Function Call会消耗的资源
+pub fn requested_resources(function_name: &str, arguments: &Value) -> Vec<BudgetResource> {
+    let flag = |key: &str| arguments.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    let mut resources = Vec::new();
+    match function_name {
+        "maa_combat_enhanced" => {
+            if flag("use_medicine") {
+                resources.push(BudgetResource::Medicine);
+            }
+            if flag("use_stone") {
+                resources.push(BudgetResource::Stone);
+            }
+        },
+        // 模式0只刷蜡烛，投资额度用完时由Worker关闭投资；其余模式以投资为目的，直接拒绝
+        "maa_roguelike_enhanced" if arguments.get("mode").and_then(|v| v.as_i64()).unwrap_or(0) != 0 => {
+            resources.push(BudgetResource::RoguelikeInvestment);
+        },
+        "maa_custom_task" => {
+            let task_name = arguments.get("task_name").and_then(|v| v.as_str()).unwrap_or("");
+            let params = arguments.get("params").cloned().unwrap_or_else(|| json!({}));
+            resources.extend(params_resources(&params));
+            if task_name == "Roguelike" && params.get("investment_enabled") != Some(&Value::Bool(false))
+                && !resources.contains(&BudgetResource::RoguelikeInvestment) {
+                resources.push(BudgetResource::RoguelikeInvestment);
+            }
+        },
+        "maa_adjust_task_params" => {
+            let strategy = arguments.get("strategy").and_then(|v| v.as_str()).unwrap_or("reduce_difficulty");
+            let params = if strategy == "custom" {
+                arguments.get("custom_params").cloned()
+            } else {
+                let context = arguments.get("context").cloned().unwrap_or_else(|| json!({}));
+                task_params::strategy_params(strategy, &context).ok()
+            };
+            if let Some(params) = params {
+                resources.extend(params_resources(&params));
+            }
+        },
+        _ => {},
+    }
+    resources
+}
+
+/// MAA参数中会消耗资源的字段
+fn params_resources(params: &Value) -> Vec<BudgetResource> {
+    let positive = |key: &str| params.get(key).and_then(|v| v.as_i64()).unwrap_or(0) > 0;
+    let mut resources = Vec::new();
+    if positive("medicine") {
+        resources.push(BudgetResource::Medicine);
+    }
+    if positive("stone") {
+        resources.push(BudgetResource::Stone);
+    }
+    if positive("investments_count") || params.get("investment_enabled") == Some(&Value::Bool(true)) {
+        resources.push(BudgetResource::RoguelikeInvestment);
+    }
+    resources
+}
+
+/// 把下发给MAA的任务参数限制在剩余额度内
+///
+/// Fight的 `medicine`/`stone` 压到剩余额度；Roguelike未关闭投资时写入 `investments_count` 上限，额度用完则关闭投资。
+pub fn limit_maa_params(maa_task_type: &str, params: &mut Value) {
+    limit_adjusted_params(params);
+    if maa_task_type != "Roguelike" || params.get("investment_enabled") == Some(&Value::Bool(false)) {
+        return;
+    }
+    let Some(params) = params.as_object_mut() else { return };
+    match allowance(BudgetResource::RoguelikeInvestment) {
+        Ok(None) => {},
+        Ok(Some(remaining)) => {
+            params.entry("investments_count").or_insert_with(|| json!(remaining));
+        },
+        Err(e) => {
+            debug!("{}，关闭肉鸽投资", e);
+            params.insert("investment_enabled".to_string(), json!(false));
+        },
+    }
+}
+
+/// 只限制参数中已有的字段，用于运行中调整参数
+pub fn limit_adjusted_params(params: &mut Value) {
+    let Some(params) = params.as_object_mut() else { return };
+    for (key, resource) in LIMITED_PARAMS {
+        let Some(requested) = params.get(key).and_then(|v| v.as_u64()).filter(|n| *n > 0) else { continue };
+        let limited = match allowance(resource) {
+            Ok(None) => requested,
+            Ok(Some(remaining)) => requested.min(remaining as u64),
+            Err(_) => 0,
+        };
+        if limited != requested {
+            info!("{} 超出预算，使用上限从 {} 调整为 {}", resource.label(), requested, limited);
+            params.insert(key.to_string(), json!(limited));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn at(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date).unwrap()
+    }
+
+    #[test]
+    fn test_window_start() {
+        // 2025-06-11 是周三
+        let now = at("2025-06-11T10:00:00+08:00");
+        assert_eq!(window_start(&now, 4, BudgetWindow::Daily), at("2025-06-11T04:00:00+08:00"));
+        assert_eq!(window_start(&now, 4, BudgetWindow::Weekly), at("2025-06-09T04:00:00+08:00"));
+
+        // 刷新前仍算前一天；周一刷新前仍算上一周
+        let early = at("2025-06-09T03:00:00+08:00");
+        assert_eq!(window_start(&early, 4, BudgetWindow::Daily), at("2025-06-08T04:00:00+08:00"));
+        assert_eq!(window_start(&early, 4, BudgetWindow::Weekly), at("2025-06-02T04:00:00+08:00"));
+    }
+
+    #[test]
+    fn test_allowance_uses_tightest_window() {
+        let policy = BudgetPolicy::new(true, 4)
+            .with_cap(BudgetResource::Stone, BudgetCap { daily: Some(2), weekly: Some(5) })
+            .with_cap(BudgetResource::Medicine, BudgetCap { daily: Some(10), weekly: None });
+        let mut ledger = UsageLedger::default();
+        let now = at("2025-06-11T10:00:00+08:00");
+
+        assert_eq!(policy.allowance(&ledger, BudgetResource::Stone, &now), Ok(Some(2)));
+        assert_eq!(policy.allowance(&ledger, BudgetResource::RoguelikeInvestment, &now), Ok(None));
+
+        // 周一用了4个：今日额度还有2，本周只剩1
+        ledger.record(BudgetResource::Stone, 4, at("2025-06-09T12:00:00+08:00").with_timezone(&Utc));
+        assert_eq!(policy.allowance(&ledger, BudgetResource::Stone, &now), Ok(Some(1)));
+
+        ledger.record(BudgetResource::Stone, 1, at("2025-06-11T09:00:00+08:00").with_timezone(&Utc));
+        let err = policy.allowance(&ledger, BudgetResource::Stone, &now).unwrap_err();
+        assert_eq!(err, BudgetError::Exhausted {
+            resource: BudgetResource::Stone, window: BudgetWindow::Weekly, used: 5, cap: 5,
+        });
+        assert!(err.to_string().contains("本周源石额度已用完"));
+
+        // 关闭预算时不限制
+        let disabled = BudgetPolicy::new(false, 4).with_cap(BudgetResource::Stone, BudgetCap { daily: Some(0), weekly: None });
+        assert_eq!(disabled.allowance(&ledger, BudgetResource::Stone, &now), Ok(None));
+    }
+
+    #[test]
+    fn test_reservations_across_queued_tasks() {
+        let policy = BudgetPolicy::new(true, 4)
+            .with_cap(BudgetResource::Medicine, BudgetCap { daily: Some(10), weekly: None });
+        let mut ledger = UsageLedger::default();
+        let now = at("2025-06-11T10:00:00+08:00");
+        let dispatched = now.with_timezone(&Utc);
+
+        // 两个排队的战斗任务依次提交：第一个拿走全部剩余额度，第二个不能再用药
+        let first = policy.allowance(&ledger, BudgetResource::Medicine, &now).unwrap().unwrap();
+        assert_eq!(first, 10);
+        ledger.reserve(1, BudgetResource::Medicine, first, dispatched);
+        let err = policy.allowance(&ledger, BudgetResource::Medicine, &now).unwrap_err();
+        assert!(matches!(err, BudgetError::Exhausted { used: 10, cap: 10, .. }));
+
+        // 第一个任务实际用了3瓶后结束，释放剩余的7瓶
+        ledger.consume(1, BudgetResource::Medicine, 3);
+        ledger.record(BudgetResource::Medicine, 3, dispatched);
+        assert!(policy.allowance(&ledger, BudgetResource::Medicine, &now).is_err());
+        let released = ledger.release(1);
+        assert_eq!(released[0].outstanding(), 7);
+        assert_eq!(policy.allowance(&ledger, BudgetResource::Medicine, &now), Ok(Some(7)));
+
+        // 第二个任务拿到剩余额度
+        ledger.reserve(2, BudgetResource::Medicine, 7, dispatched);
+        assert!(policy.allowance(&ledger, BudgetResource::Medicine, &now).is_err());
+    }
+
+    #[test]
+    fn test_requested_resources() {
+        assert_eq!(
+            requested_resources("maa_combat_enhanced", &json!({"stage": "1-7", "use_medicine": true, "use_stone": true})),
+            vec![BudgetResource::Medicine, BudgetResource::Stone]
+        );
+        assert!(requested_resources("maa_combat_enhanced", &json!({"stage": "1-7"})).is_empty());
+        assert!(requested_resources("maa_roguelike_enhanced", &json!({"mode": 0})).is_empty());
+        assert_eq!(
+            requested_resources("maa_roguelike_enhanced", &json!({"mode": 1})),
+            vec![BudgetResource::RoguelikeInvestment]
+        );
+        assert_eq!(
+            requested_resources("maa_custom_task", &json!({"task_name": "Fight", "params": {"stage": "1-7", "stone": 3}})),
+            vec![BudgetResource::Stone]
+        );
+        assert_eq!(
+            requested_resources("maa_adjust_task_params", &json!({"task_id": 1, "strategy": "increase_efficiency", "context": {"available_medicine": 2}})),
+            vec![BudgetResource::Medicine]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! 设备连接健康状态
+//!
+//! MAA回调（ConnectionInfo）在Core线程中触发，只能写入全局状态；
+//! Worker定期检查连接，发现断线后按 `DeviceConfig` 的重试次数退避重连，
+//! 并把连接状态变更推送到SSE（`device_connection` 事件），供 `/status` 查询。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// 设备连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// 尚未连接设备
+    Disconnected,
+    Connected,
+    /// 检测到断线，正在按退避策略重连
+    Reconnecting,
+    /// 重连次数用尽，等待下一个任务触发连接
+    Failed,
+}
+
+/// 设备健康信息
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceHealth {
+    pub state: ConnectionState,
+    pub device_address: Option<String>,
+    /// 当前重连轮次中的尝试次数
+    pub reconnect_attempts: u32,
+    /// 累计断线次数
+    pub total_disconnects: u64,
+    /// 累计成功重连次数
+    pub total_reconnects: u64,
+    pub last_error: Option<String>,
+    pub last_connected_at: Option<DateTime<Utc>>,
+    pub last_disconnected_at: Option<DateTime<Utc>>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+}
+
+impl Default for DeviceHealth {
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Disconnected,
+            device_address: None,
+            reconnect_attempts: 0,
+            total_disconnects: 0,
+            total_reconnects: 0,
+            last_error: None,
+            last_connected_at: None,
+            last_disconnected_at: None,
+            last_checked_at: None,
+        }
+    }
+}
+
+static DEVICE_HEALTH: Lazy<Mutex<DeviceHealth>> = Lazy::new(|| Mutex::new(DeviceHealth::default()));
+
+/// 回调报告的断线标记，由Worker的健康检查消费
+static CONNECTION_LOST: AtomicBool = AtomicBool::new(false);
+
+/// 获取设备健康信息快照
+pub fn device_health() -> DeviceHealth {
+    DEVICE_HEALTH.lock().unwrap().clone()
+}
+
+/// 当前连接状态
+pub fn connection_state() -> ConnectionState {
+    DEVICE_HEALTH.lock().unwrap().state
+}
+
+/// 处理MAA ConnectionInfo回调
+pub(crate) fn handle_connection_info(what: &str, details: &Value) {
+    match what {
+        "ConnectFailed" | "Disconnect" => {
+            let why = details.get("why").and_then(|v| v.as_str()).unwrap_or(what);
+            warn!("MAA报告设备连接中断: {} ({})", what, why);
+            CONNECTION_LOST.store(true, Ordering::SeqCst);
+            DEVICE_HEALTH.lock().unwrap().last_error = Some(format!("{}: {}", what, why));
+        },
+        "Reconnected" => {
+            info!("MAA内部重连成功");
+            CONNECTION_LOST.store(false, Ordering::SeqCst);
+        },
+        _ => {}
+    }
+}
+
+/// 取出并清除回调报告的断线标记
+pub(crate) fn take_connection_lost() -> bool {
+    CONNECTION_LOST.swap(false, Ordering::SeqCst)
+}
+
+/// 记录一次健康检查
+pub(crate) fn mark_checked() {
+    DEVICE_HEALTH.lock().unwrap().last_checked_at = Some(Utc::now());
+}
+
+/// 标记连接成功
+pub(crate) fn mark_connected(device_address: &str) {
+    CONNECTION_LOST.store(false, Ordering::SeqCst);
+    update_state(|health| {
+        if health.state == ConnectionState::Reconnecting {
+            health.total_reconnects += 1;
+        }
+        health.state = ConnectionState::Connected;
+        health.device_address = Some(device_address.to_string());
+        health.reconnect_attempts = 0;
+        health.last_connected_at = Some(Utc::now());
+    });
+}
+
+/// 标记检测到断线
+pub(crate) fn mark_disconnected(reason: &str) {
+    update_state(|health| {
+        health.state = ConnectionState::Reconnecting;
+        health.reconnect_attempts = 0;
+        health.total_disconnects += 1;
+        health.last_disconnected_at = Some(Utc::now());
+        health.last_error.get_or_insert_with(|| reason.to_string());
+    });
+}
+
+/// 记录一次重连尝试
+pub(crate) fn mark_reconnect_attempt(attempt: u32, error: Option<String>) {
+    update_state(|health| {
+        health.reconnect_attempts = attempt;
+        if error.is_some() {
+            health.last_error = error;
+        }
+    });
+}
+
+/// 标记重连失败
+pub(crate) fn mark_failed(error: String) {
+    update_state(|health| {
+        health.state = ConnectionState::Failed;
+        health.last_error = Some(error);
+    });
+}
+
+/// 更新健康信息并把变更推送到SSE
+fn update_state(update: impl FnOnce(&mut DeviceHealth)) {
+    let snapshot = {
+        let mut health = DEVICE_HEALTH.lock().unwrap();
+        update(&mut health);
+        health.clone()
+    };
+
+    let message = match snapshot.state {
+        ConnectionState::Connected => "设备已连接".to_string(),
+        ConnectionState::Disconnected => "设备未连接".to_string(),
+        ConnectionState::Reconnecting => format!("设备连接中断，正在重连（第 {} 次）", snapshot.reconnect_attempts),
+        ConnectionState::Failed => "设备重连失败".to_string(),
+    };
+    super::forward_to_sse_global(
+        super::TaskEventType::DeviceConnection,
+        message,
+        serde_json::to_value(&snapshot).unwrap_or(Value::Null),
+    );
+}
+
+/// 第 `attempt` 次重连前的等待时间
+///
+/// 从1秒开始指数退避，不超过连接超时时间。
+pub fn reconnect_backoff(attempt: u32, connection_timeout_ms: u64) -> Duration {
+    let delay_ms = 1000u64.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    Duration::from_millis(delay_ms.min(connection_timeout_ms.max(1000)))
+}
+
+/// 断线中断的任务能否在重连后自动重新执行
+///
+/// 肉鸽、作业、保全派驻等中途中断后无法从断点继续，直接标记失败。
+pub fn is_requeue_safe(task_type: &str) -> bool {
+    !matches!(
+        task_type,
+        "maa_roguelike_enhanced" | "maa_copilot_enhanced" | "maa_sss_copilot"
+            | "maa_reclamation" | "maa_custom_task" | "maa_video_recognition"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        assert_eq!(reconnect_backoff(1, 10000), Duration::from_secs(1));
+        assert_eq!(reconnect_backoff(2, 10000), Duration::from_secs(2));
+        assert_eq!(reconnect_backoff(3, 10000), Duration::from_secs(4));
+        assert_eq!(reconnect_backoff(10, 10000), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_requeue_safety() {
+        assert!(is_requeue_safe("maa_combat_enhanced"));
+        assert!(is_requeue_safe("maa_infrastructure_enhanced"));
+        assert!(!is_requeue_safe("maa_roguelike_enhanced"));
+        assert!(!is_requeue_safe("maa_copilot_enhanced"));
+    }
+
+    #[test]
+    fn test_connection_lifecycle() {
+        mark_connected("127.0.0.1:5555");
+        assert_eq!(connection_state(), ConnectionState::Connected);
+
+        handle_connection_info("Disconnect", &serde_json::json!({"why": "adb closed"}));
+        assert!(take_connection_lost());
+        assert!(!take_connection_lost());
+
+        mark_disconnected("健康检查失败");
+        mark_reconnect_attempt(1, Some("连接超时".to_string()));
+        let health = device_health();
+        assert_eq!(health.state, ConnectionState::Reconnecting);
+        assert_eq!(health.reconnect_attempts, 1);
+        assert!(health.total_disconnects >= 1);
+
+        mark_connected("127.0.0.1:5555");
+        let health = device_health();
+        assert_eq!(health.state, ConnectionState::Connected);
+        assert!(health.total_reconnects >= 1);
+    }
+}
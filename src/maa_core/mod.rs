@@ -23,40 +23,77 @@ pub mod basic_ops;
 pub mod task_status;
 pub mod screenshot;
 pub mod task_notification;
+pub mod task_mapping;
+pub mod task_params;
+pub mod task_builder;
+pub mod budget;
+pub mod connection;
+pub mod supervisor;
 
 // V2 优化模块 - 简化架构
 pub mod task_classification_v2;
 pub mod task_queue_v2;
 pub mod worker_v2;
 
+/// 任务ID -> 完成通知器列表（同一任务可以有多个等待者）
+type TaskNotifiers = HashMap<i32, Vec<oneshot::Sender<serde_json::Value>>>;
+
 /// 全局任务完成通知器
-/// task_id -> oneshot sender
-static GLOBAL_TASK_NOTIFIERS: Lazy<Arc<Mutex<HashMap<i32, oneshot::Sender<serde_json::Value>>>>> = 
+static GLOBAL_TASK_NOTIFIERS: Lazy<Arc<Mutex<TaskNotifiers>>> = 
     Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
 
 /// 注册任务通知器
 pub fn register_task_notifier(task_id: i32, sender: oneshot::Sender<serde_json::Value>) {
     let mut notifiers = GLOBAL_TASK_NOTIFIERS.lock().unwrap();
-    notifiers.insert(task_id, sender);
+    notifiers.entry(task_id).or_default().push(sender);
     debug!("注册任务通知器: task_id={}", task_id);
 }
 
 /// 触发任务完成通知
 pub fn notify_task_completion(task_id: i32, result: serde_json::Value) {
     let mut notifiers = GLOBAL_TASK_NOTIFIERS.lock().unwrap();
-    if let Some(sender) = notifiers.remove(&task_id) {
-        let _ = sender.send(result);
+    if let Some(senders) = notifiers.remove(&task_id) {
+        for sender in senders {
+            let _ = sender.send(result.clone());
+        }
         info!("任务完成通知已发送: task_id={}", task_id);
     }
 }
 
 // 重新导出基础操作
+//
+// 运行中任务的参数调整需要队列任务ID到MAA任务ID的映射，只能经由Worker（`maa_adjust_task_params`）进行；
+// `set_task_params`/`adjust_task_strategy` 保留为公开接口，但会返回错误并提示改用队列接口
 pub use basic_ops::{
     connect_device, execute_fight, get_maa_status, take_screenshot, perform_click,
     stop_all_tasks, execute_recruit, execute_infrastructure, execute_startup,
     get_tasks_list, set_task_params, back_to_home, adjust_task_strategy
 };
 
+/// 查找MAA资源路径：环境变量优先，其次是配置中第一个存在的备用路径，最后是默认路径
+pub fn find_resource_path() -> String {
+    // 从环境变量获取
+    if let Ok(path) = std::env::var(&CONFIG.env_keys.resource_path) {
+        info!("使用环境变量资源路径: {}", path);
+        return path;
+    }
+    
+    info!("未找到环境变量{}，使用备用路径", CONFIG.env_keys.resource_path);
+    
+    // 从配置文件获取备用资源路径
+    let resource_paths = &CONFIG.maa.fallback_resource_paths;
+    
+    for path in resource_paths {
+        if PathBuf::from(path).exists() {
+            info!("找到备用资源路径: {}", path);
+            return path.clone();
+        }
+    }
+    
+    warn!("未找到资源文件，使用默认路径");
+    CONFIG.maa.default_resource_path.clone()
+}
+
 /// 全局SSE事件广播器，用于MAA回调到Worker V2的通信
 static mut GLOBAL_SSE_BROADCASTER: Option<broadcast::Sender<TaskProgressEvent>> = None;
 
@@ -107,6 +144,8 @@ unsafe extern "C" fn maa_callback(
         2 => {
             // ConnectionInfo - 关键的连接事件处理
             if let Some(what) = details_json.get("what").and_then(|v| v.as_str()) {
+                // 断线事件交给连接监控，由Worker的健康检查负责重连
+                connection::handle_connection_info(what, &details_json);
                 match what {
                     "ConnectFailed" => {
                         let why = details_json.get("why").and_then(|v| v.as_str()).unwrap_or("unknown");
@@ -138,15 +177,20 @@ unsafe extern "C" fn maa_callback(
                 .unwrap_or(0);
                 
             // SSE通知
-            forward_to_sse_global("all_tasks_completed", 
+            forward_to_sse_global(TaskEventType::AllTasksCompleted, 
                                  format!("任务链 {} 全部完成，共执行{}个任务", taskchain, finished_tasks), 
                                  details_json.clone());
             
-            // 通知所有已完成的任务
+            // 通知所有已完成的任务（按队列任务ID去重）
             if let Some(finished_tasks_array) = details_json.get("finished_tasks").and_then(|v| v.as_array()) {
+                let mut notified = Vec::new();
                 for task in finished_tasks_array {
-                    if let Some(task_id) = task.as_i64() {
-                        notify_task_completion(task_id as i32, details_json.clone());
+                    if let Some(maa_task_id) = task.as_i64() {
+                        let task_id = task_mapping::resolve_queue_task_id(maa_task_id as i32);
+                        if !notified.contains(&task_id) {
+                            notified.push(task_id);
+                            notify_task_completion(task_id, details_json.clone());
+                        }
                     }
                 }
             }
@@ -157,7 +201,8 @@ unsafe extern "C" fn maa_callback(
         },
         5 => { // Destroyed
             warn!("MAA实例已销毁: {}", details_str);
-            forward_to_sse_global("maa_destroyed", 
+            supervisor::mark_maa_destroyed();
+            forward_to_sse_global(TaskEventType::MaaDestroyed, 
                                  "MAA实例已销毁，需要重新初始化".to_string(), 
                                  details_json.clone());
         },
@@ -166,9 +211,13 @@ unsafe extern "C" fn maa_callback(
         10000 => {
             warn!("任务链错误: {}", details_str);
             // 更新任务状态
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                crate::copilot_matcher::feedback::complete_copilot_run(maa_task_id, false);
+                budget::release_reservation(maa_task_id);
+                task_mapping::mark_maa_task_finished(maa_task_id);
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
+                notify_task_completion(task_id, details_json.clone());
                 
                 // 转发到SSE系统
                 forward_to_sse(task_id, msg, details_json.clone());
@@ -177,8 +226,9 @@ unsafe extern "C" fn maa_callback(
         10001 => {
             debug!("任务链开始: {}", details_str);
             // 更新任务状态
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                task_mapping::mark_maa_task_started(maa_task_id);
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -188,9 +238,28 @@ unsafe extern "C" fn maa_callback(
         10002 => {
             debug!("任务链完成: {}", details_str);
             // 更新任务状态和通知oneshot channel
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                task_status::handle_maa_callback(task_id as i32, msg, details_json.clone());
-                notify_task_completion(task_id as i32, details_json.clone());
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                crate::copilot_matcher::feedback::complete_copilot_run(maa_task_id, true);
+                budget::release_reservation(maa_task_id);
+                let task_id = match task_mapping::mark_maa_task_finished(maa_task_id) {
+                    // 同一队列任务下还有未结束的MAA任务链，仅更新进度
+                    Some(progress) if !progress.all_finished() => {
+                        task_status::update_task_progress(
+                            progress.queue_task_id,
+                            format!("已完成 {}/{} 个任务链", progress.finished, progress.total),
+                        );
+                        progress.queue_task_id
+                    },
+                    progress => {
+                        let task_id = progress.map(|p| p.queue_task_id).unwrap_or(maa_task_id);
+                        task_status::handle_maa_callback(task_id, msg, details_json.clone());
+                        notify_task_completion(task_id, details_json.clone());
+                        task_id
+                    }
+                };
+                
+                // 转发到SSE系统
+                forward_to_sse(task_id, msg, details_json.clone());
             }
         },
         10003 => {
@@ -198,13 +267,23 @@ unsafe extern "C" fn maa_callback(
         },
         10004 => {
             warn!("任务链手动停止: {}", details_str);
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                crate::copilot_matcher::feedback::discard_copilot_run(maa_task_id);
+                budget::release_reservation(maa_task_id);
+                task_mapping::mark_maa_task_finished(maa_task_id);
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
+                task_status::handle_maa_callback(task_id, msg, details_json.clone());
+                
+                // 转发到SSE系统
+                forward_to_sse(task_id, msg, details_json.clone());
+            }
         },
         
         // SubTask Info
         20000 => {
             warn!("子任务错误: {}", details_str);
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -213,8 +292,8 @@ unsafe extern "C" fn maa_callback(
         },
         20001 => {
             debug!("子任务开始: {}", details_str);
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -223,8 +302,8 @@ unsafe extern "C" fn maa_callback(
         },
         20002 => {
             debug!("子任务完成: {}", details_str);
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -233,8 +312,9 @@ unsafe extern "C" fn maa_callback(
         },
         20003 => {
             debug!("子任务额外信息: {}", details_str);
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            budget::record_sub_task_extra(&details_json);
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -243,8 +323,8 @@ unsafe extern "C" fn maa_callback(
         },
         20004 => {
             debug!("子任务手动停止: {}", details_str);
-            if let Some(task_id) = details_json.get("taskid").and_then(|v| v.as_i64()) {
-                let task_id = task_id as i32;
+            if let Some(maa_task_id) = maa_task_id_of(&details_json) {
+                let task_id = task_mapping::resolve_queue_task_id(maa_task_id);
                 task_status::handle_maa_callback(task_id, msg, details_json.clone());
                 
                 // 转发到SSE系统
@@ -258,20 +338,18 @@ unsafe extern "C" fn maa_callback(
     }
 }
 
+/// 从回调详情中提取MAA任务ID
+fn maa_task_id_of(details: &Value) -> Option<i32> {
+    details.get("taskid").and_then(|v| v.as_i64()).map(|id| id as i32)
+}
+
 /// 将MAA回调事件转发到SSE系统
+///
+/// `task_id` 为队列任务ID，原始MAA任务ID保留在 `data.taskid` 中
 fn forward_to_sse(task_id: i32, msg_code: i32, details: Value) {
     unsafe {
         if let Some(ref broadcaster) = GLOBAL_SSE_BROADCASTER {
-            let event_type = match msg_code {
-                10001 => "taskchain_started",
-                10002 => "taskchain_completed",
-                10000 => "taskchain_failed", 
-                20001 => "subtask_started",
-                20002 => "subtask_completed",
-                20003 => "subtask_info",
-                20000 => "subtask_failed",
-                _ => "unknown"
-            };
+            let event_type = TaskEventType::from_callback(msg_code);
             
             let task_name = details.get("details")
                 .and_then(|d| d.get("task"))
@@ -296,6 +374,10 @@ fn forward_to_sse(task_id: i32, msg_code: i32, details: Value) {
                     let task_desc = get_taskchain_description(task_chain);
                     format!("{} 执行失败", task_desc)
                 },
+                10004 => {
+                    let task_desc = get_taskchain_description(task_chain);
+                    format!("{} 已停止", task_desc)
+                },
                 20001 => {
                     let task_desc = get_subtask_description(task_name);
                     format!("开始: {}", task_desc)
@@ -334,7 +416,7 @@ fn forward_to_sse(task_id: i32, msg_code: i32, details: Value) {
             let sse_event = TaskProgressEvent {
                 task_id,
                 task_type: task_chain.to_string(),
-                event_type: event_type.to_string(),
+                event_type,
                 message,
                 data: Some(details),
                 timestamp: Utc::now(),
@@ -351,13 +433,13 @@ fn forward_to_sse(task_id: i32, msg_code: i32, details: Value) {
 }
 
 /// 转发全局事件到SSE系统
-fn forward_to_sse_global(event_type: &str, message: String, details: Value) {
+fn forward_to_sse_global(event_type: TaskEventType, message: String, details: Value) {
     unsafe {
         if let Some(ref broadcaster) = GLOBAL_SSE_BROADCASTER {
             let sse_event = TaskProgressEvent {
                 task_id: 0, // 全局事件使用task_id=0
                 task_type: "system".to_string(),
-                event_type: event_type.to_string(),
+                event_type,
                 message,
                 data: Some(details),
                 timestamp: Utc::now(),
@@ -452,16 +534,19 @@ fn get_facility_name(facility: &str) -> String {
 // V2组件导出
 pub use task_queue_v2::{
     MaaTask as MaaTaskV2, MaaTaskSender as MaaTaskSenderV2, MaaTaskReceiver as MaaTaskReceiverV2, 
-    create_maa_task_channel_v2, TaskResult, TaskStatus as TaskStatusV2
+    create_maa_task_channel_v2, TaskResult, QueueError, QueuedTaskInfo, QueuePreview
 };
-pub use worker_v2::MaaWorkerV2;
-pub use task_status::{MaaTaskStatus, TaskStatus, get_task_status, get_all_tasks, get_running_tasks, cleanup_old_tasks};
+pub use worker_v2::{MaaWorkerV2, TaskEventType, EventSeverity};
+pub use task_status::{
+    MaaTaskStatus, TaskStatus, TaskOutcome, TaskWaitOutcome, TransitionError,
+    get_task_status, get_all_tasks, get_running_tasks, cleanup_old_tasks, wait_for_task
+};
+pub use connection::{ConnectionState, DeviceHealth, device_health};
+pub use supervisor::{WorkerExit, WorkerHealth, worker_health, run_supervised_worker};
 pub use screenshot::{ScreenshotInfo, save_maa_screenshot, get_screenshot_by_id, list_all_screenshots, cleanup_screenshots};
 pub use task_classification_v2::{TaskExecutionMode, get_task_execution_mode, estimate_task_duration, is_synchronous_task};
 pub use task_notification::{
-    TaskStatusEvent, TaskStatus as NotificationTaskStatus, init_task_notification_system, 
-    subscribe_task_events, notify_task_status, notify_task_started, notify_task_progress, 
-    notify_task_completed, notify_task_failed, TaskStatusMonitor
+    TaskStatusEvent, init_task_notification_system, subscribe_task_events, TaskStatusMonitor
 };
 
 /// MAA 状态信息
@@ -508,6 +593,8 @@ pub struct MaaCore {
     /// 资源路径
     resource_path: Option<String>,
     
+    /// 当前正在执行的队列任务ID，提交的MAA任务将绑定到该任务
+    current_queue_task: Option<i32>,
 }
 
 impl MaaCore {
@@ -519,6 +606,7 @@ impl MaaCore {
             assistant: None,
             status: MaaStatus::default(),
             resource_path: None,
+            current_queue_task: None,
         }
     }
     
@@ -629,6 +717,17 @@ impl MaaCore {
         // 异步启动任务执行
         info!("任务已添加到队列，任务ID: {}", task_id);
         
+        // 在启动前绑定队列任务，保证首个回调即可解析到队列任务ID
+        if let Some(queue_task_id) = self.current_queue_task {
+            task_mapping::bind_maa_task(queue_task_id, task_id);
+        }
+        // 记录提交参数，运行中调整参数时用于校验和计算差异
+        task_mapping::record_maa_task_params(
+            task_id,
+            task_type,
+            serde_json::from_str(params).unwrap_or(Value::Null),
+        );
+        
         // 启动任务执行（非阻塞）
         match assistant.start() {
             Ok(_) => {
@@ -649,6 +748,35 @@ impl MaaCore {
         Ok(task_id)
     }
     
+    /// 检查设备连接
+    ///
+    /// 回调报告断线或 `connected()` 返回false时标记为未连接，返回当前是否连接。
+    pub fn refresh_connection(&mut self) -> bool {
+        let lost = connection::take_connection_lost();
+        if self.status.connected && (lost || !self.device_alive()) {
+            self.status.connected = false;
+            self.status.running = false;
+            self.status.active_tasks.clear();
+            self.status.last_updated = Utc::now();
+        }
+        self.status.connected
+    }
+    
+    /// MAA Core 报告的设备连接状态
+    pub fn device_alive(&self) -> bool {
+        self.assistant.as_ref().is_some_and(|assistant| assistant.connected())
+    }
+    
+    /// 最近一次连接的设备地址
+    pub fn device_address(&self) -> Option<&str> {
+        self.status.device_address.as_deref()
+    }
+    
+    /// 设置当前队列任务，之后通过 `execute_task` 提交的MAA任务都归属于该任务
+    pub fn set_current_queue_task(&mut self, queue_task_id: Option<i32>) {
+        self.current_queue_task = queue_task_id;
+    }
+    
     /// 获取状态
     pub fn get_status(&mut self) -> MaaStatus {
         if let Some(assistant) = &self.assistant {
@@ -689,6 +817,30 @@ impl MaaCore {
         Ok(click_id)
     }
     
+    /// 返回游戏主界面
+    pub fn back_to_home(&self) -> Result<()> {
+        let assistant = self.assistant.as_ref()
+            .ok_or_else(|| anyhow!("MAA Assistant 未初始化"))?;
+        
+        assistant.back_to_home()
+            .map_err(|e| anyhow!("返回主界面失败: {:?}", e))?;
+        
+        info!("已请求返回主界面");
+        Ok(())
+    }
+    
+    /// 运行中修改MAA任务参数
+    pub fn set_task_params(&self, maa_task_id: i32, params: &str) -> Result<()> {
+        let assistant = self.assistant.as_ref()
+            .ok_or_else(|| anyhow!("MAA Assistant 未初始化"))?;
+        
+        assistant.set_task_params(maa_task_id, params)
+            .map_err(|e| anyhow!("设置任务参数失败: {:?}", e))?;
+        
+        info!("已更新MAA任务 {} 的参数: {}", maa_task_id, params);
+        Ok(())
+    }
+    
     /// 停止所有任务
     pub fn stop(&mut self) -> Result<()> {
         if let Some(assistant) = &mut self.assistant {
@@ -748,26 +900,7 @@ impl MaaCore {
     
     /// 查找资源路径
     fn find_resource_path(&self) -> Result<String> {
-        // 从环境变量获取
-        if let Ok(path) = std::env::var(&CONFIG.env_keys.resource_path) {
-            info!("使用环境变量资源路径: {}", path);
-            return Ok(path);
-        }
-        
-        info!("未找到环境变量{}，使用备用路径", CONFIG.env_keys.resource_path);
-        
-        // 从配置文件获取备用资源路径
-        let resource_paths = &CONFIG.maa.fallback_resource_paths;
-        
-        for path in resource_paths {
-            if PathBuf::from(path).exists() {
-                info!("找到备用资源路径: {}", path);
-                return Ok(path.clone());
-            }
-        }
-        
-        warn!("未找到资源文件，使用默认路径");
-        Ok(CONFIG.maa.default_resource_path.clone())
+        Ok(find_resource_path())
     }
     
     
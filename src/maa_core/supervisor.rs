@@ -0,0 +1,227 @@
+//! MAA工作者监督
+//!
+//! Worker退出（MAA实例被销毁）或panic时，监督者将运行中的任务标记为失败，
+//! 重新创建 `MaaCore` 和Worker并继续消费同一个任务队列，重启次数在 `/health` 中展示。
+
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use super::budget;
+use super::task_mapping;
+use super::task_queue_v2::MaaTaskReceiver;
+use super::task_status::{self, MaaTaskStatus, TaskStatus};
+use super::worker_v2::{MaaWorkerV2, TaskEventType, TaskProgressEvent};
+
+/// 两次重启之间的最短间隔，避免Worker反复崩溃时空转
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Worker退出原因
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerExit {
+    /// 任务队列已关闭（服务器关闭），不再重启
+    QueueClosed,
+    /// MAA实例被销毁（回调 Destroyed）
+    MaaDestroyed,
+    /// Worker发生panic
+    Panicked(String),
+}
+
+impl std::fmt::Display for WorkerExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkerExit::QueueClosed => write!(f, "任务队列已关闭"),
+            WorkerExit::MaaDestroyed => write!(f, "MAA实例已销毁"),
+            WorkerExit::Panicked(message) => write!(f, "工作线程panic: {}", message),
+        }
+    }
+}
+
+/// Worker健康信息
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerHealth {
+    pub alive: bool,
+    /// 累计重启次数
+    pub restarts: u32,
+    pub started_at: Option<DateTime<Utc>>,
+    pub last_restart_at: Option<DateTime<Utc>>,
+    pub last_exit: Option<WorkerExit>,
+    /// 因Worker退出而失败的任务数
+    pub failed_in_flight_tasks: usize,
+}
+
+static WORKER_HEALTH: Lazy<Mutex<WorkerHealth>> = Lazy::new(|| Mutex::new(WorkerHealth {
+    alive: false,
+    restarts: 0,
+    started_at: None,
+    last_restart_at: None,
+    last_exit: None,
+    failed_in_flight_tasks: 0,
+}));
+
+/// MAA回调报告实例已销毁
+static MAA_DESTROYED: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// 只在轮询受监督的Worker时可见，用于判断panic是否会被监督者捕获
+    static SUPERVISED: ();
+}
+
+/// 当前代码是否运行在受监督的Worker中（panic会被捕获并重启Worker）
+pub fn in_supervised_worker() -> bool {
+    SUPERVISED.try_with(|_| ()).is_ok()
+}
+
+/// 获取Worker健康信息快照
+pub fn worker_health() -> WorkerHealth {
+    WORKER_HEALTH.lock().unwrap().clone()
+}
+
+/// 记录MAA实例被销毁（在回调中调用）
+pub(crate) fn mark_maa_destroyed() {
+    MAA_DESTROYED.store(true, Ordering::SeqCst);
+}
+
+/// MAA实例是否已被销毁
+pub(crate) fn maa_destroyed() -> bool {
+    MAA_DESTROYED.load(Ordering::SeqCst)
+}
+
+/// 将候选任务中运行中的任务标记为失败，返回失败的任务ID
+///
+/// 失败任务的MAA任务ID映射、预算预留和作业执行登记一并释放：旧的MAA实例已销毁，这些任务链不会再有回调。
+pub fn fail_in_flight_tasks(tasks: impl IntoIterator<Item = MaaTaskStatus>, reason: &str) -> Vec<i32> {
+    tasks.into_iter()
+        .filter(|task| matches!(task.status, TaskStatus::Running | TaskStatus::Paused))
+        .filter(|task| task_status::fail_task(task.task_id, format!("MAA工作线程已重启，任务中断: {}", reason)))
+        .map(|task| {
+            release_queue_task_chains(task.task_id);
+            task.task_id
+        })
+        .collect()
+}
+
+/// 释放不会再有回调的队列任务：未结束任务链的预算预留和作业执行登记，以及任务ID映射
+pub fn release_queue_task_chains(queue_task_id: i32) {
+    for maa_task_id in task_mapping::active_maa_task_ids(queue_task_id) {
+        budget::release_reservation(maa_task_id);
+        crate::copilot_matcher::feedback::discard_copilot_run(maa_task_id);
+    }
+    task_mapping::release_queue_task(queue_task_id);
+}
+
+/// 在监督下运行Worker，Worker退出或panic后自动重建
+///
+/// 只有任务队列关闭时才返回。需要在 `LocalSet` 中运行（MaaCore不是Send）。
+pub async fn run_supervised_worker(worker: MaaWorkerV2, mut task_rx: MaaTaskReceiver) {
+    let event_broadcaster = worker.event_broadcaster.clone();
+    let mut worker = Some(worker);
+
+    loop {
+        let mut current = worker.take()
+            .unwrap_or_else(|| MaaWorkerV2::new_with_broadcaster(event_broadcaster.clone()));
+        {
+            let mut health = WORKER_HEALTH.lock().unwrap();
+            health.alive = true;
+            health.started_at = Some(Utc::now());
+        }
+
+        let run = SUPERVISED.scope((), AssertUnwindSafe(current.run_until_exit(&mut task_rx)).catch_unwind());
+        let exit = match run.await {
+            Ok(exit) => exit,
+            Err(payload) => WorkerExit::Panicked(panic_message(payload.as_ref())),
+        };
+        // 先销毁旧的MaaCore，再清除销毁标记，避免旧实例的回调触发新Worker重启
+        drop(current);
+        MAA_DESTROYED.store(false, Ordering::SeqCst);
+
+        if exit == WorkerExit::QueueClosed {
+            WORKER_HEALTH.lock().unwrap().alive = false;
+            info!("任务队列已关闭，Worker监督退出");
+            return;
+        }
+
+        error!("MAA工作者退出: {}，准备重启", exit);
+        let failed = fail_in_flight_tasks(task_status::get_all_tasks(), &exit.to_string()).len();
+        let restarts = {
+            let mut health = WORKER_HEALTH.lock().unwrap();
+            health.alive = false;
+            health.restarts += 1;
+            health.last_restart_at = Some(Utc::now());
+            health.last_exit = Some(exit.clone());
+            health.failed_in_flight_tasks += failed;
+            health.restarts
+        };
+        let _ = event_broadcaster.send(TaskProgressEvent {
+            task_id: 0,
+            task_type: "system".to_string(),
+            event_type: TaskEventType::WorkerRestarted,
+            message: format!("MAA工作者已重启（第 {} 次）：{}", restarts, exit),
+            data: Some(json!({
+                "restarts": restarts,
+                "exit": exit,
+                "failed_tasks": failed,
+            })),
+            timestamp: Utc::now(),
+        });
+
+        warn!("{} 个运行中的任务已标记为失败，{}秒后重启Worker", failed, RESTART_DELAY.as_secs());
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maa_core::task_status::{register_task, start_task};
+
+    #[test]
+    fn test_fail_in_flight_tasks() {
+        register_task(4501, "maa_combat_enhanced".to_string(), serde_json::json!({}));
+        start_task(4501);
+        task_mapping::bind_maa_task(4501, 94501);
+        register_task(4502, "maa_combat_enhanced".to_string(), serde_json::json!({}));
+        register_task(4503, "maa_combat_enhanced".to_string(), serde_json::json!({}));
+        start_task(4503);
+
+        // 只处理传入的候选任务，其他运行中的任务不受影响
+        let candidates = [4501, 4502].map(|id| task_status::get_task_status(id).unwrap());
+        assert_eq!(fail_in_flight_tasks(candidates, "测试"), vec![4501]);
+        let failed = task_status::get_task_status(4501).unwrap();
+        assert_eq!(failed.status, TaskStatus::Failed);
+        assert!(failed.error.unwrap().contains("MAA工作线程已重启"));
+        assert!(task_mapping::queue_task_id_for(94501).is_none());
+        // 排队中的任务留给重启后的Worker继续执行
+        assert_eq!(task_status::get_task_status(4502).unwrap().status, TaskStatus::Queued);
+        assert_eq!(task_status::get_task_status(4503).unwrap().status, TaskStatus::Running);
+    }
+
+    #[tokio::test]
+    async fn test_in_supervised_worker() {
+        assert!(!in_supervised_worker());
+        assert!(SUPERVISED.scope((), async { in_supervised_worker() }).await);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload: Box<dyn std::any::Any + Send> = Box::new("boom");
+        assert_eq!(panic_message(payload.as_ref()), "boom");
+        let payload: Box<dyn std::any::Any + Send> = Box::new(String::from("owned"));
+        assert_eq!(panic_message(payload.as_ref()), "owned");
+    }
+}
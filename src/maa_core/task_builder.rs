@@ -0,0 +1,146 @@
+//! Function Call到MAA任务参数的转换
+//!
+//! Worker提交任务和试运行（dry run）都通过本模块构造MAA任务链类型和参数，
+//! 保证试运行返回的参数与实际下发给MAA Core的一致。药剂、源石和肉鸽投资的使用上限在这里按剩余预算限制。
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::budget;
+
+/// 下发给MAA Core的任务
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MaaTaskPlan {
+    /// MAA任务链类型（如 `Fight`、`Infrast`）
+    pub maa_task_type: String,
+    /// `AsstAppendTask` 的参数
+    pub params: Value,
+}
+
+impl MaaTaskPlan {
+    fn new(maa_task_type: &str, params: Value) -> Self {
+        Self { maa_task_type: maa_task_type.to_string(), params }
+    }
+}
+
+/// 基建默认管理的设施
+const ALL_FACILITIES: [&str; 7] = ["Mfg", "Trade", "Power", "Control", "Reception", "Office", "Dorm"];
+
+/// 构造Function Call对应的MAA任务
+///
+/// 截图、任务列表、参数调整、紧急返回、作业匹配和系统状态查询不提交MAA任务链，返回None。
+pub fn build_maa_task(function_name: &str, parameters: &Value) -> Option<MaaTaskPlan> {
+    let str_arg = |key: &str, default: &'static str| -> String {
+        parameters.get(key).and_then(|v| v.as_str()).unwrap_or(default).to_string()
+    };
+    let int_arg = |key: &str, default: i64| parameters.get(key).and_then(|v| v.as_i64()).unwrap_or(default);
+    let bool_arg = |key: &str, default: bool| parameters.get(key).and_then(|v| v.as_bool()).unwrap_or(default);
+    let str_list = |key: &str, default: &[&str]| -> Vec<String> {
+        parameters.get(key)
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str()).map(String::from).collect())
+            .unwrap_or_else(|| default.iter().map(|s| s.to_string()).collect())
+    };
+
+    let mut plan = match function_name {
+        "maa_take_screenshot" | "maa_get_task_list" | "maa_adjust_task_params" | "maa_emergency_home"
+        | "maa_stop_tasks" | "maa_copilot_match" => return None,
+        "maa_startup" => MaaTaskPlan::new("StartUp", json!({
+            "enable": true,
+            "client_type": str_arg("client_type", "Official"),
+            "start_app": bool_arg("start_app", true),
+        })),
+        "maa_combat_enhanced" => {
+            let uses = |key: &str| if bool_arg(key, false) { budget::UNLIMITED_USES } else { 0 };
+            MaaTaskPlan::new("Fight", json!({
+                "enable": true,
+                "stage": str_arg("stage", "1-7"),
+                "times": int_arg("times", 1),
+                "medicine": uses("use_medicine"),
+                "stone": uses("use_stone"),
+            }))
+        },
+        "maa_infrastructure_enhanced" => {
+            let params = match str_arg("operation_mode", "full_auto").as_str() {
+                "collect_only" => json!({ "enable": true, "mode": 1, "facility": ALL_FACILITIES }),
+                "custom" => json!({
+                    "enable": true,
+                    "mode": 0,
+                    "facility": str_list("facilities", &["Mfg", "Trade", "Power", "Control"]),
+                    "drones": "Money",
+                }),
+                _ => json!({ "enable": true, "mode": 0, "facility": ALL_FACILITIES, "drones": "Money" }),
+            };
+            MaaTaskPlan::new("Infrast", params)
+        },
+        "maa_recruit_enhanced" => MaaTaskPlan::new("Recruit", json!({
+            "enable": true,
+            "select": [3, 4, 5, 6],
+            "confirm": [3, 4, 5, 6],
+            "times": int_arg("max_times", 4),
+            "set_time": true,
+            "expedite": bool_arg("expedite", false),
+            "skip_robot": bool_arg("skip_robot", true),
+        })),
+        "maa_rewards_enhanced" => MaaTaskPlan::new("Award", json!({ "enable": true })),
+        "maa_closedown" => MaaTaskPlan::new("CloseDown", json!({ "enable": true })),
+        "maa_roguelike_enhanced" => MaaTaskPlan::new("Roguelike", json!({
+            "enable": true,
+            "theme": str_arg("theme", "Phantom"),
+            "mode": int_arg("mode", 0),
+        })),
+        "maa_copilot_enhanced" => MaaTaskPlan::new("Copilot", json!({ "enable": true, "filename": str_arg("filename", "") })),
+        "maa_sss_copilot" => MaaTaskPlan::new("SSSCopilot", json!({ "enable": true, "filename": str_arg("filename", "") })),
+        "maa_reclamation" => MaaTaskPlan::new("Reclamation", json!({
+            "enable": true,
+            "theme": str_arg("theme", "Fire"),
+            "mode": int_arg("mode", 0),
+        })),
+        "maa_credit_store_enhanced" => MaaTaskPlan::new("Mall", json!({
+            "enable": true,
+            "buy_first": str_list("buy_first", &["龙门币", "赤金"]),
+        })),
+        "maa_depot_management" => MaaTaskPlan::new("Depot", json!({ "enable": true })),
+        "maa_operator_box" => MaaTaskPlan::new("OperBox", json!({ "enable": true })),
+        "maa_custom_task" => MaaTaskPlan::new(
+            &str_arg("task_name", "CustomTask"),
+            parameters.get("params").cloned().unwrap_or_else(|| json!({})),
+        ),
+        "maa_video_recognition" => MaaTaskPlan::new("VideoRecognition", json!({ "enable": true, "filename": str_arg("video_path", "") })),
+        "maa_system_management" => match str_arg("operation", "status").as_str() {
+            "restart" => MaaTaskPlan::new("StartUp", json!({ "enable": true })),
+            "stop" => MaaTaskPlan::new("CloseDown", json!({ "enable": true })),
+            _ => return None,
+        },
+        _ => MaaTaskPlan::new(function_name, parameters.clone()),
+    };
+
+    budget::limit_maa_params(&plan.maa_task_type, &mut plan.params);
+    Some(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_maa_task() {
+        let plan = build_maa_task("maa_combat_enhanced", &json!({"stage": "CE-5", "times": 3})).unwrap();
+        assert_eq!(plan.maa_task_type, "Fight");
+        assert_eq!(plan.params, json!({"enable": true, "stage": "CE-5", "times": 3, "medicine": 0, "stone": 0}));
+
+        let plan = build_maa_task("maa_infrastructure_enhanced", &json!({"operation_mode": "custom", "facilities": ["Mfg"]})).unwrap();
+        assert_eq!(plan.params["facility"], json!(["Mfg"]));
+
+        // 文件名中的引号不会破坏参数JSON
+        let plan = build_maa_task("maa_copilot_enhanced", &json!({"filename": "a\"b.json"})).unwrap();
+        assert_eq!(plan.params["filename"], "a\"b.json");
+
+        let plan = build_maa_task("maa_custom_task", &json!({"task_name": "Award", "params": {"enable": true}})).unwrap();
+        assert_eq!(plan, MaaTaskPlan::new("Award", json!({"enable": true})));
+
+        assert_eq!(build_maa_task("maa_system_management", &json!({"operation": "stop"})).unwrap().maa_task_type, "CloseDown");
+        assert!(build_maa_task("maa_system_management", &json!({})).is_none());
+        assert!(build_maa_task("maa_take_screenshot", &json!({})).is_none());
+    }
+}
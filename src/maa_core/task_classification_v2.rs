@@ -35,6 +35,16 @@ pub fn classify_task(function_name: &str) -> (TaskExecutionMode, TaskPriority) {
             (TaskExecutionMode::Synchronous, TaskPriority::High)
         },
         
+        // 运行中调整参数、紧急返回、停止全部任务：立即执行并返回结果
+        "maa_adjust_task_params" | "maa_emergency_home" | "maa_stop_tasks" => {
+            (TaskExecutionMode::Synchronous, TaskPriority::High)
+        },
+        
+        // 作业匹配只查询作业站，不占用MAA
+        "maa_copilot_match" => {
+            (TaskExecutionMode::Synchronous, TaskPriority::Normal)
+        },
+        
         // 异步普通优先级任务 - 核心游戏功能 (需要长时间运行)
         "maa_combat_enhanced" | "maa_recruit_enhanced" | "maa_infrastructure_enhanced" => {
             (TaskExecutionMode::Asynchronous, TaskPriority::Normal)
@@ -0,0 +1,242 @@
+//! MAA任务ID映射
+//!
+//! MAA Core `append_task` 返回的任务ID与队列任务ID是两套独立编号，
+//! 一个队列任务可以提交多个MAA任务链。本模块记录两者的对应关系，
+//! 让回调事件、任务状态和SSE推送统一使用队列任务ID。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tracing::debug;
+
+/// 队列任务下的MAA子任务记录
+#[derive(Debug, Default)]
+struct QueueTaskEntry {
+    /// 按提交顺序排列的MAA任务ID
+    maa_task_ids: Vec<i32>,
+    /// 已结束（完成或失败）的MAA任务ID
+    finished: Vec<i32>,
+}
+
+#[derive(Debug, Default)]
+struct TaskMapping {
+    /// MAA任务ID -> 队列任务ID
+    maa_to_queue: HashMap<i32, i32>,
+    /// 队列任务ID -> MAA子任务
+    queue_tasks: HashMap<i32, QueueTaskEntry>,
+    /// MAA任务ID -> 当前生效的任务参数
+    maa_params: HashMap<i32, MaaTaskParams>,
+    /// MAA Core正在执行的任务链（TaskChainStart 到结束之间）
+    running: Option<i32>,
+}
+
+/// 提交到MAA Core的任务链类型及当前参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaaTaskParams {
+    /// MAA任务链类型，如 `Fight`、`Roguelike`
+    pub task_type: String,
+    pub params: Value,
+}
+
+/// 全局任务ID映射表
+static TASK_MAPPING: Lazy<Mutex<TaskMapping>> = Lazy::new(|| Mutex::new(TaskMapping::default()));
+
+/// MAA子任务结束后队列任务的进度
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubTaskProgress {
+    /// 所属队列任务ID
+    pub queue_task_id: i32,
+    /// 已结束的MAA子任务数
+    pub finished: usize,
+    /// MAA子任务总数
+    pub total: usize,
+}
+
+impl SubTaskProgress {
+    /// 队列任务下的所有MAA子任务是否均已结束
+    pub fn all_finished(&self) -> bool {
+        self.finished >= self.total
+    }
+}
+
+/// 将MAA任务ID绑定到队列任务
+pub fn bind_maa_task(queue_task_id: i32, maa_task_id: i32) {
+    let mut mapping = TASK_MAPPING.lock().unwrap();
+    mapping.maa_to_queue.insert(maa_task_id, queue_task_id);
+    let entry = mapping.queue_tasks.entry(queue_task_id).or_default();
+    if !entry.maa_task_ids.contains(&maa_task_id) {
+        entry.maa_task_ids.push(maa_task_id);
+    }
+    debug!("绑定MAA任务: maa_task_id={} -> queue_task_id={}", maa_task_id, queue_task_id);
+}
+
+/// 查询MAA任务所属的队列任务ID
+pub fn queue_task_id_for(maa_task_id: i32) -> Option<i32> {
+    TASK_MAPPING.lock().unwrap().maa_to_queue.get(&maa_task_id).copied()
+}
+
+/// 将MAA任务ID解析为队列任务ID
+///
+/// 未经队列提交的MAA任务（如直接调用MaaCore）没有映射，沿用MAA任务ID。
+pub fn resolve_queue_task_id(maa_task_id: i32) -> i32 {
+    queue_task_id_for(maa_task_id).unwrap_or(maa_task_id)
+}
+
+/// 获取队列任务下的所有MAA任务ID
+pub fn maa_task_ids(queue_task_id: i32) -> Vec<i32> {
+    TASK_MAPPING.lock().unwrap()
+        .queue_tasks
+        .get(&queue_task_id)
+        .map(|entry| entry.maa_task_ids.clone())
+        .unwrap_or_default()
+}
+
+/// 标记MAA任务链开始执行（TaskChainStart）
+pub fn mark_maa_task_started(maa_task_id: i32) {
+    TASK_MAPPING.lock().unwrap().running = Some(maa_task_id);
+}
+
+/// MAA Core正在执行的任务链所属的队列任务
+pub fn running_queue_task_id() -> Option<i32> {
+    let mapping = TASK_MAPPING.lock().unwrap();
+    mapping.running.and_then(|maa_task_id| mapping.maa_to_queue.get(&maa_task_id).copied())
+}
+
+/// 标记MAA子任务结束，返回所属队列任务的进度；未绑定的任务返回None
+pub fn mark_maa_task_finished(maa_task_id: i32) -> Option<SubTaskProgress> {
+    let mut mapping = TASK_MAPPING.lock().unwrap();
+    if mapping.running == Some(maa_task_id) {
+        mapping.running = None;
+    }
+    let queue_task_id = *mapping.maa_to_queue.get(&maa_task_id)?;
+    let entry = mapping.queue_tasks.get_mut(&queue_task_id)?;
+    if !entry.finished.contains(&maa_task_id) {
+        entry.finished.push(maa_task_id);
+    }
+
+    Some(SubTaskProgress {
+        queue_task_id,
+        finished: entry.finished.len(),
+        total: entry.maa_task_ids.len(),
+    })
+}
+
+/// 队列任务下尚未结束的MAA任务ID
+pub fn active_maa_task_ids(queue_task_id: i32) -> Vec<i32> {
+    TASK_MAPPING.lock().unwrap()
+        .queue_tasks
+        .get(&queue_task_id)
+        .map(|entry| entry.maa_task_ids.iter()
+            .filter(|id| !entry.finished.contains(id))
+            .copied()
+            .collect())
+        .unwrap_or_default()
+}
+
+/// 记录提交到MAA Core的任务参数
+pub fn record_maa_task_params(maa_task_id: i32, task_type: &str, params: Value) {
+    TASK_MAPPING.lock().unwrap().maa_params.insert(maa_task_id, MaaTaskParams {
+        task_type: task_type.to_string(),
+        params,
+    });
+}
+
+/// 查询MAA任务的类型及当前参数
+pub fn maa_task_params(maa_task_id: i32) -> Option<MaaTaskParams> {
+    TASK_MAPPING.lock().unwrap().maa_params.get(&maa_task_id).cloned()
+}
+
+/// 运行中调整参数后，把变更合并到记录的参数中
+pub fn merge_maa_task_params(maa_task_id: i32, changes: &Value) {
+    let mut mapping = TASK_MAPPING.lock().unwrap();
+    if let (Some(entry), Some(changes)) = (mapping.maa_params.get_mut(&maa_task_id), changes.as_object()) {
+        if !entry.params.is_object() {
+            entry.params = Value::Object(Default::default());
+        }
+        if let Some(params) = entry.params.as_object_mut() {
+            for (key, value) in changes {
+                params.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// 释放队列任务的映射（任务状态清理时调用）
+pub fn release_queue_task(queue_task_id: i32) {
+    let mut mapping = TASK_MAPPING.lock().unwrap();
+    if let Some(entry) = mapping.queue_tasks.remove(&queue_task_id) {
+        if mapping.running.is_some_and(|running| entry.maa_task_ids.contains(&running)) {
+            mapping.running = None;
+        }
+        for maa_task_id in entry.maa_task_ids {
+            mapping.maa_to_queue.remove(&maa_task_id);
+            mapping.maa_params.remove(&maa_task_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 映射表为全局状态，各测试使用互不重叠的ID
+
+    #[test]
+    fn test_bind_and_resolve() {
+        bind_maa_task(100, 1001);
+        bind_maa_task(100, 1002);
+
+        assert_eq!(queue_task_id_for(1001), Some(100));
+        assert_eq!(resolve_queue_task_id(1002), 100);
+        assert_eq!(maa_task_ids(100), vec![1001, 1002]);
+
+        // 未绑定的MAA任务沿用原ID
+        assert_eq!(queue_task_id_for(1999), None);
+        assert_eq!(resolve_queue_task_id(1999), 1999);
+    }
+
+    #[test]
+    fn test_sub_task_progress() {
+        bind_maa_task(200, 2001);
+        bind_maa_task(200, 2002);
+
+        let progress = mark_maa_task_finished(2001).unwrap();
+        assert_eq!(progress.queue_task_id, 200);
+        assert_eq!((progress.finished, progress.total), (1, 2));
+        assert!(!progress.all_finished());
+
+        // 重复回调不重复计数
+        assert_eq!(mark_maa_task_finished(2001).unwrap().finished, 1);
+        assert!(mark_maa_task_finished(2002).unwrap().all_finished());
+
+        assert!(mark_maa_task_finished(2999).is_none());
+    }
+
+    #[test]
+    fn test_release_queue_task() {
+        bind_maa_task(300, 3001);
+        release_queue_task(300);
+
+        assert!(maa_task_ids(300).is_empty());
+        assert_eq!(queue_task_id_for(3001), None);
+    }
+
+    #[test]
+    fn test_maa_task_params() {
+        bind_maa_task(400, 4001);
+        bind_maa_task(400, 4002);
+        record_maa_task_params(4001, "Fight", serde_json::json!({"stage": "1-7", "medicine": 0}));
+        mark_maa_task_finished(4002);
+
+        assert_eq!(active_maa_task_ids(400), vec![4001]);
+
+        merge_maa_task_params(4001, &serde_json::json!({"medicine": 2}));
+        let params = maa_task_params(4001).unwrap();
+        assert_eq!(params.task_type, "Fight");
+        assert_eq!(params.params, serde_json::json!({"stage": "1-7", "medicine": 2}));
+
+        release_queue_task(400);
+        assert!(maa_task_params(4001).is_none());
+    }
+}
@@ -7,36 +7,24 @@ use serde::{Serialize, Deserialize};
 use std::sync::OnceLock;
 use tracing::{info, debug, warn};
 
-/// 任务状态更新事件
+pub use super::task_status::TaskStatus;
+
+/// 任务状态变更事件
+///
+/// 仅由任务状态存储 (`task_status`) 在状态变更通过校验后发布。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskStatusEvent {
     pub task_id: i32,
     pub task_type: String,
     pub status: TaskStatus,
+    /// 变更前的状态，任务入队事件为None
+    pub previous_status: Option<TaskStatus>,
     pub message: String,
     pub progress: Option<f32>, // 0.0-1.0
     pub details: Option<serde_json::Value>,
     pub timestamp: String,
 }
 
-/// 任务状态枚举
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum TaskStatus {
-    /// 任务已提交，等待执行
-    Pending,
-    /// 任务正在执行中
-    Running,
-    /// 任务成功完成
-    Success,
-    /// 任务执行失败
-    Failed,
-    /// 任务被取消
-    Cancelled,
-    /// 任务暂停
-    Paused,
-}
-
 /// 全局任务状态广播通道
 static TASK_NOTIFIER: OnceLock<broadcast::Sender<TaskStatusEvent>> = OnceLock::new();
 
@@ -70,79 +58,23 @@ pub fn subscribe_task_events() -> broadcast::Receiver<TaskStatusEvent> {
     get_task_notifier().subscribe()
 }
 
-/// 发送任务状态更新事件
-pub fn notify_task_status(event: TaskStatusEvent) {
+/// 发送任务状态更新事件（仅供任务状态存储调用）
+pub(crate) fn notify_task_status(event: TaskStatusEvent) {
     let notifier = get_task_notifier();
     let task_id = event.task_id;
-    let status = event.status.clone();
+    let status = event.status;
     
     match notifier.send(event) {
         Ok(subscriber_count) => {
             debug!("任务状态通知已发送: task_id={}, status={:?}, 订阅者数量={}", 
                    task_id, status, subscriber_count);
         }
-        Err(e) => {
-            warn!("发送任务状态通知失败: task_id={}, error={}", task_id, e);
+        Err(_) => {
+            debug!("任务状态通知无订阅者: task_id={}, status={:?}", task_id, status);
         }
     }
 }
 
-/// 便捷函数：发送任务开始事件
-pub fn notify_task_started(task_id: i32, task_type: String, message: String) {
-    let event = TaskStatusEvent {
-        task_id,
-        task_type,
-        status: TaskStatus::Running,
-        message,
-        progress: Some(0.0),
-        details: None,
-        timestamp: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-    };
-    notify_task_status(event);
-}
-
-/// 便捷函数：发送任务进度事件
-pub fn notify_task_progress(task_id: i32, task_type: String, message: String, progress: f32) {
-    let event = TaskStatusEvent {
-        task_id,
-        task_type,
-        status: TaskStatus::Running,
-        message,
-        progress: Some(progress.clamp(0.0, 1.0)),
-        details: None,
-        timestamp: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-    };
-    notify_task_status(event);
-}
-
-/// 便捷函数：发送任务完成事件
-pub fn notify_task_completed(task_id: i32, task_type: String, message: String, details: Option<serde_json::Value>) {
-    let event = TaskStatusEvent {
-        task_id,
-        task_type,
-        status: TaskStatus::Success,
-        message,
-        progress: Some(1.0),
-        details,
-        timestamp: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-    };
-    notify_task_status(event);
-}
-
-/// 便捷函数：发送任务失败事件
-pub fn notify_task_failed(task_id: i32, task_type: String, message: String, error_details: Option<serde_json::Value>) {
-    let event = TaskStatusEvent {
-        task_id,
-        task_type,
-        status: TaskStatus::Failed,
-        message,
-        progress: None,
-        details: error_details,
-        timestamp: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-    };
-    notify_task_status(event);
-}
-
 /// 任务状态监听器，用于持续监听MAA Core的状态变化
 pub struct TaskStatusMonitor {
     receiver: broadcast::Receiver<TaskStatusEvent>,
@@ -182,20 +114,26 @@ impl TaskStatusMonitor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::maa_core::task_status::{register_task, start_task, complete_task};
     use tokio::time::{sleep, Duration};
 
     #[tokio::test]
     async fn test_task_notification_system() {
         let mut receiver = init_task_notification_system();
         
-        // 发送测试事件
-        notify_task_started(1, "test_task".to_string(), "任务开始".to_string());
+        // 状态存储的变更会发布到事件流
+        register_task(8001, "test_task".to_string(), serde_json::json!({}));
+        start_task(8001);
         
         // 接收事件
-        let event = receiver.recv().await.unwrap();
-        assert_eq!(event.task_id, 1);
-        assert_eq!(event.status, TaskStatus::Running);
-        assert_eq!(event.message, "任务开始");
+        let event = loop {
+            let event = receiver.recv().await.unwrap();
+            if event.task_id == 8001 && event.status == TaskStatus::Running {
+                break event;
+            }
+        };
+        assert_eq!(event.previous_status, Some(TaskStatus::Queued));
+        assert_eq!(event.message, "任务开始执行");
     }
 
     #[tokio::test]
@@ -203,15 +141,18 @@ mod tests {
         let _receiver = init_task_notification_system();
         let mut monitor = TaskStatusMonitor::new();
         
-        // 在后台发送事件
+        register_task(8002, "test_task".to_string(), serde_json::json!({}));
+        start_task(8002);
+        
+        // 在后台完成任务
         tokio::spawn(async {
             sleep(Duration::from_millis(100)).await;
-            notify_task_completed(2, "test_task".to_string(), "任务完成".to_string(), None);
+            complete_task(8002, serde_json::json!({}));
         });
         
         // 等待任务完成
-        let event = monitor.wait_for_task_status(2, TaskStatus::Success).await.unwrap();
-        assert_eq!(event.task_id, 2);
-        assert_eq!(event.status, TaskStatus::Success);
+        let event = monitor.wait_for_task_status(8002, TaskStatus::Succeeded).await.unwrap();
+        assert_eq!(event.task_id, 8002);
+        assert_eq!(event.status, TaskStatus::Succeeded);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,225 @@
+//! 运行中任务参数调整
+//!
+//! MAA Core 的 `AsstSetTaskParams` 只对部分任务链生效，且每种任务链只允许修改部分字段。
+//! 本模块负责把队列任务ID解析为MAA任务ID、校验任务链是否支持运行中调整，
+//! 并计算实际生效的参数差异；真正的FFI调用由Worker持有的 `MaaCore` 完成。
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use super::task_mapping::{self, MaaTaskParams};
+use super::task_status;
+
+/// 支持运行中调整的任务链及允许修改的字段
+const ADJUSTABLE_PARAMS: &[(&str, &[&str])] = &[
+    ("Fight", &["enable", "stage", "medicine", "expiring_medicine", "stone", "times", "series", "drops"]),
+    ("Roguelike", &["enable", "starts_count", "investment_enabled", "investments_count", "stop_when_investment_full"]),
+    ("Recruit", &["enable", "times", "set_time", "expedite", "expedite_times"]),
+];
+
+/// 任务链允许运行中修改的字段，不支持的任务链返回None
+pub fn adjustable_keys(maa_task_type: &str) -> Option<&'static [&'static str]> {
+    ADJUSTABLE_PARAMS.iter()
+        .find(|(task_type, _)| *task_type == maa_task_type)
+        .map(|(_, keys)| *keys)
+}
+
+/// 参数调整错误
+#[derive(Debug, Error, PartialEq)]
+pub enum ParamAdjustError {
+    #[error("任务不存在: {0}")]
+    TaskNotFound(i32),
+    #[error("任务 {task_id} 已结束（{status}），无法调整参数")]
+    TaskFinished { task_id: i32, status: task_status::TaskStatus },
+    #[error("任务 {0} 尚未提交到MAA Core")]
+    NotStarted(i32),
+    #[error("任务类型 {0} 不支持运行中调整参数，仅支持 Fight/Roguelike/Recruit")]
+    Unsupported(String),
+    #[error("{task_type} 任务不支持修改字段 {keys:?}，可修改: {allowed:?}")]
+    UnknownKeys { task_type: String, keys: Vec<String>, allowed: Vec<String> },
+    #[error("调整参数必须是非空JSON对象")]
+    InvalidParams,
+    #[error("未知的调整策略: {0}")]
+    UnknownStrategy(String),
+}
+
+/// 单个字段的变更
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamChange {
+    pub from: Value,
+    pub to: Value,
+}
+
+/// 对单个MAA任务链的调整计划
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamAdjustment {
+    pub maa_task_id: i32,
+    pub maa_task_type: String,
+    /// 需要下发给MAA Core的参数（仅包含有变化的字段）
+    pub params: Value,
+    /// 字段变更明细
+    pub changes: BTreeMap<String, ParamChange>,
+}
+
+impl ParamAdjustment {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// 将智能调整策略转换为具体参数
+pub fn strategy_params(strategy: &str, context: &Value) -> Result<Value, ParamAdjustError> {
+    match strategy {
+        // 降低难度：减少药剂使用，降低目标次数
+        "reduce_difficulty" => Ok(json!({
+            "medicine": 0,
+            "times": 1
+        })),
+        // 提高效率：按可用数量使用药剂
+        "increase_efficiency" => {
+            let medicine_count = context.get("available_medicine").and_then(|v| v.as_i64()).unwrap_or(0);
+            Ok(json!({
+                "medicine": medicine_count.clamp(0, 99)
+            }))
+        },
+        // 紧急停止：禁用任务，MAA会在当前步骤结束后退出
+        "emergency_stop" => Ok(json!({
+            "enable": false
+        })),
+        _ => Err(ParamAdjustError::UnknownStrategy(strategy.to_string())),
+    }
+}
+
+/// 计算请求参数相对当前参数的差异
+pub fn diff_params(current: &Value, requested: &Map<String, Value>) -> BTreeMap<String, ParamChange> {
+    requested.iter()
+        .filter_map(|(key, to)| {
+            let from = current.get(key).cloned().unwrap_or(Value::Null);
+            (from != *to).then(|| (key.clone(), ParamChange { from, to: to.clone() }))
+        })
+        .collect()
+}
+
+/// 为单个MAA任务链生成调整计划
+///
+/// `strict` 为true时请求中出现不可修改的字段直接报错，否则忽略这些字段（用于通用的调整策略）。
+fn plan_for_maa_task(maa_task_id: i32, current: &MaaTaskParams, requested: &Map<String, Value>, strict: bool) -> Result<ParamAdjustment, ParamAdjustError> {
+    let allowed = adjustable_keys(&current.task_type)
+        .ok_or_else(|| ParamAdjustError::Unsupported(current.task_type.clone()))?;
+
+    let unknown: Vec<String> = requested.keys()
+        .filter(|key| !allowed.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    let applicable: Map<String, Value> = requested.iter()
+        .filter(|(key, _)| allowed.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    if strict && !unknown.is_empty() {
+        return Err(ParamAdjustError::UnknownKeys {
+            task_type: current.task_type.clone(),
+            keys: unknown,
+            allowed: allowed.iter().map(|k| k.to_string()).collect(),
+        });
+    }
+
+    let changes = diff_params(&current.params, &applicable);
+    let params = Value::Object(changes.iter().map(|(k, c)| (k.clone(), c.to.clone())).collect());
+    Ok(ParamAdjustment {
+        maa_task_id,
+        maa_task_type: current.task_type.clone(),
+        params,
+        changes,
+    })
+}
+
+/// 为队列任务生成调整计划
+///
+/// 队列任务必须仍在运行；只调整其下尚未结束且支持运行中调整的MAA任务链。
+pub fn plan_adjustment(queue_task_id: i32, requested: &Value, strict: bool) -> Result<Vec<ParamAdjustment>, ParamAdjustError> {
+    let requested = requested.as_object()
+        .filter(|params| !params.is_empty())
+        .ok_or(ParamAdjustError::InvalidParams)?;
+
+    let status = task_status::get_task_status(queue_task_id)
+        .ok_or(ParamAdjustError::TaskNotFound(queue_task_id))?;
+    if status.is_finished() {
+        return Err(ParamAdjustError::TaskFinished { task_id: queue_task_id, status: status.status });
+    }
+
+    let targets: Vec<(i32, MaaTaskParams)> = task_mapping::active_maa_task_ids(queue_task_id)
+        .into_iter()
+        .filter_map(|maa_task_id| task_mapping::maa_task_params(maa_task_id).map(|p| (maa_task_id, p)))
+        .collect();
+    if targets.is_empty() {
+        return Err(ParamAdjustError::NotStarted(queue_task_id));
+    }
+    if targets.iter().all(|(_, current)| adjustable_keys(&current.task_type).is_none()) {
+        let task_types: Vec<&str> = targets.iter().map(|(_, current)| current.task_type.as_str()).collect();
+        return Err(ParamAdjustError::Unsupported(task_types.join(", ")));
+    }
+
+    targets.iter()
+        .filter(|(_, current)| adjustable_keys(&current.task_type).is_some())
+        .map(|(maa_task_id, current)| plan_for_maa_task(*maa_task_id, current, requested, strict))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maa_core::task_status::{register_task, start_task};
+
+    // 任务状态与ID映射为全局状态，使用独立的ID段
+
+    #[test]
+    fn test_plan_fight_adjustment() {
+        register_task(5101, "maa_combat_enhanced".to_string(), json!({}));
+        start_task(5101);
+        task_mapping::bind_maa_task(5101, 51011);
+        task_mapping::record_maa_task_params(51011, "Fight", json!({"stage": "1-7", "medicine": 0, "times": 5}));
+
+        let plan = plan_adjustment(5101, &json!({"medicine": 3, "times": 5}), true).unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].maa_task_id, 51011);
+        assert_eq!(plan[0].params, json!({"medicine": 3}));
+        assert_eq!(plan[0].changes["medicine"], ParamChange { from: json!(0), to: json!(3) });
+        assert!(!plan[0].changes.contains_key("times"));
+
+        let err = plan_adjustment(5101, &json!({"client_type": "Bilibili"}), true).unwrap_err();
+        assert!(matches!(err, ParamAdjustError::UnknownKeys { .. }));
+
+        // 策略调整忽略不可修改的字段
+        let plan = plan_adjustment(5101, &json!({"client_type": "Bilibili", "times": 1}), false).unwrap();
+        assert_eq!(plan[0].params, json!({"times": 1}));
+    }
+
+    #[test]
+    fn test_plan_rejects_unsupported_and_finished() {
+        register_task(5201, "maa_infrastructure_enhanced".to_string(), json!({}));
+        start_task(5201);
+        task_mapping::bind_maa_task(5201, 52011);
+        task_mapping::record_maa_task_params(52011, "Infrast", json!({"facility": ["Mfg"]}));
+        assert_eq!(
+            plan_adjustment(5201, &json!({"enable": false}), true),
+            Err(ParamAdjustError::Unsupported("Infrast".to_string()))
+        );
+
+        register_task(5202, "maa_combat_enhanced".to_string(), json!({}));
+        assert_eq!(plan_adjustment(5202, &json!({"medicine": 1}), true), Err(ParamAdjustError::NotStarted(5202)));
+
+        task_status::cancel_task(5202, "测试取消".to_string());
+        assert!(matches!(plan_adjustment(5202, &json!({"medicine": 1}), true), Err(ParamAdjustError::TaskFinished { .. })));
+        assert_eq!(plan_adjustment(5299, &json!({"medicine": 1}), true), Err(ParamAdjustError::TaskNotFound(5299)));
+    }
+
+    #[test]
+    fn test_strategy_params() {
+        assert_eq!(strategy_params("emergency_stop", &json!({})).unwrap(), json!({"enable": false}));
+        assert_eq!(strategy_params("increase_efficiency", &json!({"available_medicine": 4})).unwrap()["medicine"], 4);
+        assert!(strategy_params("unknown", &json!({})).is_err());
+    }
+}
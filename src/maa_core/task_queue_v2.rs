@@ -4,15 +4,22 @@
 //! 1. 合并双队列为单队列+优先级
 //! 2. 减少枚举variants，使用统一的任务结构
 //! 3. 支持同步/异步执行模式
+//! 4. 有界队列：容量取 `PerformanceConfig.task_queue_buffer_size`，满时拒绝并给出重试时间
+//! 5. 排队中相同的异步任务（类型和参数一致）不重复入队
 
-use anyhow::Result;
 use serde_json::Value;
-use tokio::sync::{oneshot, mpsc};
+use tokio::sync::{oneshot, Notify};
 use serde::{Serialize, Deserialize};
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize};
+use std::sync::{Arc, Mutex};
 use chrono::{DateTime, Utc};
+use thiserror::Error;
 
-use super::task_classification_v2::{TaskPriority, TaskExecutionMode};
+use super::task_classification_v2::{TaskPriority, TaskExecutionMode, estimate_task_duration, is_synchronous_task};
+use super::task_status::{self, MaaTaskStatus};
+use crate::config::CONFIG;
 
 /// 统一的MAA任务结构
 #[derive(Debug)]
@@ -71,8 +78,9 @@ impl Ord for PriorityTask {
             return priority_cmp;
         }
         
-        // 相同优先级按创建时间排序（FIFO）
+        // 相同优先级按创建时间排序（FIFO），同一时刻创建的按任务ID
         other.task.created_at.cmp(&self.task.created_at)
+            .then_with(|| other.task.task_id.cmp(&self.task.task_id))
     }
 }
 
@@ -84,39 +92,141 @@ impl PartialOrd for PriorityTask {
 
 impl PartialEq for PriorityTask {
     fn eq(&self, other: &Self) -> bool {
-        self.task.priority == other.task.priority && self.task.created_at == other.task.created_at
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for PriorityTask {}
 
+/// 任务入队错误
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    #[error("任务队列已满（{depth}/{capacity}），请在 {retry_after_secs} 秒后重试")]
+    Full { depth: usize, capacity: usize, retry_after_secs: u64 },
+    #[error("相同的任务已在队列中 (task_id: {task_id})")]
+    Duplicate { task_id: i32 },
+    #[error("任务队列已关闭")]
+    Closed,
+}
+
+/// 排队中任务的信息（按执行顺序）
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedTaskInfo {
+    /// 执行顺序，从1开始
+    pub position: usize,
+    pub task_id: i32,
+    pub task_type: String,
+    pub priority: TaskPriority,
+    pub execution_mode: TaskExecutionMode,
+    pub created_at: DateTime<Utc>,
+    /// 预计开始执行时间
+    pub estimated_start_at: DateTime<Utc>,
+    pub estimated_wait_secs: u64,
+}
+
+/// 新任务入队后的预计位置，用于试运行，不实际入队
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuePreview {
+    /// 入队后的执行顺序，从1开始
+    pub position: usize,
+    /// 当前排队中的任务数
+    pub depth: usize,
+    pub capacity: usize,
+    /// 预计开始执行时间
+    pub estimated_start_at: DateTime<Utc>,
+    pub estimated_wait_secs: u64,
+    /// 排队中已有相同的异步任务时为其ID，实际提交会复用该任务
+    pub duplicate_of: Option<i32>,
+    /// 队列已满，实际提交会被拒绝
+    pub queue_full: bool,
+}
+
+/// 发送端与接收端共享的优先队列
+struct QueueShared {
+    pending: Mutex<BinaryHeap<PriorityTask>>,
+    notify: Notify,
+    capacity: usize,
+    /// 存活的发送器数量，归零后接收端在队列清空时返回None
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+}
+
 /// MAA任务队列发送器 - V2版本（单队列+优先级）
-#[derive(Clone)]
 pub struct MaaTaskSender {
-    task_tx: mpsc::UnboundedSender<PriorityTask>,
-    task_counter: std::sync::Arc<std::sync::atomic::AtomicI32>,
+    shared: Arc<QueueShared>,
+    task_counter: Arc<AtomicI32>,
 }
 
 /// MAA任务队列接收器 - V2版本
 pub struct MaaTaskReceiver {
-    task_rx: mpsc::UnboundedReceiver<PriorityTask>,
+    shared: Arc<QueueShared>,
+}
+
+impl Clone for MaaTaskSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Self {
+            shared: self.shared.clone(),
+            task_counter: self.task_counter.clone(),
+        }
+    }
+}
+
+impl Drop for MaaTaskSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, std::sync::atomic::Ordering::SeqCst) == 1 {
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl Drop for MaaTaskReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
 }
 
 impl MaaTaskSender {
     /// 发送任务（自动分配优先级）
+    ///
+    /// 队列已满时返回 `QueueError::Full`；排队中已有相同的异步任务时返回 `QueueError::Duplicate`，
+    /// 调用方可以直接使用已有的任务ID。
     pub fn send_task(
         &self,
         task_type: String,
         parameters: Value,
         priority: TaskPriority,
         execution_mode: TaskExecutionMode,
-    ) -> Result<(i32, oneshot::Receiver<TaskResult>), mpsc::error::SendError<PriorityTask>> {
+    ) -> Result<(i32, oneshot::Receiver<TaskResult>), QueueError> {
+        let mut pending = self.shared.pending.lock().unwrap();
+        if !self.shared.receiver_alive.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(QueueError::Closed);
+        }
+        
+        // 同步任务需要各自的执行结果，只对异步任务去重
+        if execution_mode == TaskExecutionMode::Asynchronous {
+            if let Some(existing) = pending.iter().find(|p| p.task.task_type == task_type && p.task.parameters == parameters) {
+                return Err(QueueError::Duplicate { task_id: existing.task.task_id });
+            }
+        }
+        
+        if pending.len() >= self.shared.capacity {
+            return Err(QueueError::Full {
+                depth: pending.len(),
+                capacity: self.shared.capacity,
+                retry_after_secs: retry_after_secs(Utc::now(), &task_status::get_running_tasks()),
+            });
+        }
+        
         // 生成任务ID
         let task_id = self.task_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         
         // 创建响应通道
         let (response_tx, response_rx) = oneshot::channel();
         
+        // 以队列任务ID登记任务状态，MAA回调会映射回该ID
+        task_status::register_task(task_id, task_type.clone(), parameters.clone());
+        
         // 构建任务
         let task = MaaTask {
             task_id,
@@ -128,8 +238,10 @@ impl MaaTaskSender {
             response_tx,
         };
         
-        // 发送到队列
-        self.task_tx.send(PriorityTask::new(task))?;
+        // 放入队列并唤醒Worker
+        pending.push(PriorityTask::new(task));
+        drop(pending);
+        self.shared.notify.notify_one();
         
         Ok((task_id, response_rx))
     }
@@ -139,7 +251,7 @@ impl MaaTaskSender {
         &self,
         task_type: String,
         parameters: Value,
-    ) -> Result<(i32, oneshot::Receiver<TaskResult>), mpsc::error::SendError<PriorityTask>> {
+    ) -> Result<(i32, oneshot::Receiver<TaskResult>), QueueError> {
         self.send_task(task_type, parameters, TaskPriority::High, TaskExecutionMode::Synchronous)
     }
     
@@ -148,81 +260,204 @@ impl MaaTaskSender {
         &self,
         task_type: String,
         parameters: Value,
-    ) -> Result<(i32, oneshot::Receiver<TaskResult>), mpsc::error::SendError<PriorityTask>> {
+    ) -> Result<(i32, oneshot::Receiver<TaskResult>), QueueError> {
         self.send_task(task_type, parameters, TaskPriority::Normal, TaskExecutionMode::Asynchronous)
     }
+    
+    /// 当前排队中的任务数
+    pub fn depth(&self) -> usize {
+        self.shared.pending.lock().unwrap().len()
+    }
+    
+    /// 队列容量
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+    
+    /// 按执行顺序列出排队中的任务，附带预计开始时间
+    pub fn pending_tasks(&self) -> Vec<QueuedTaskInfo> {
+        let pending = self.shared.pending.lock().unwrap();
+        let mut ordered: Vec<&PriorityTask> = pending.iter().collect();
+        ordered.sort_by(|a, b| b.cmp(a));
+        let ordered: Vec<&MaaTask> = ordered.into_iter().map(|p| &p.task).collect();
+        estimate_schedule(Utc::now(), &task_status::get_running_tasks(), &ordered)
+    }
+    
+    /// 预估任务入队后的位置和开始时间，不入队
+    ///
+    /// 新任务排在所有优先级不低于它的排队任务之后（同优先级先进先出）。
+    pub fn preview_task(
+        &self,
+        task_type: &str,
+        parameters: &Value,
+        priority: TaskPriority,
+        execution_mode: TaskExecutionMode,
+    ) -> QueuePreview {
+        let pending = self.shared.pending.lock().unwrap();
+        let duplicate_of = if execution_mode == TaskExecutionMode::Asynchronous {
+            pending.iter()
+                .find(|p| p.task.task_type == task_type && &p.task.parameters == parameters)
+                .map(|p| p.task.task_id)
+        } else {
+            None
+        };
+        
+        let mut ahead: Vec<&PriorityTask> = pending.iter().filter(|p| p.task.priority >= priority).collect();
+        ahead.sort_by(|a, b| b.cmp(a));
+        
+        let now = Utc::now();
+        let mut estimator = ScheduleEstimator::new(now, &task_status::get_running_tasks());
+        for p in &ahead {
+            estimator.push(&p.task.task_type, p.task.execution_mode);
+        }
+        let start_at = estimator.push(task_type, execution_mode);
+        
+        QueuePreview {
+            position: ahead.len() + 1,
+            depth: pending.len(),
+            capacity: self.shared.capacity,
+            estimated_start_at: start_at,
+            estimated_wait_secs: (start_at - now).num_seconds().max(0) as u64,
+            duplicate_of,
+            queue_full: pending.len() >= self.shared.capacity,
+        }
+    }
 }
 
 impl MaaTaskReceiver {
     /// 接收下一个优先级任务
+    ///
+    /// 所有发送器都已释放且队列为空时返回None。取消安全，可以在 `select!` 中使用。
     pub async fn recv(&mut self) -> Option<MaaTask> {
-        match self.task_rx.recv().await {
-            Some(priority_task) => Some(priority_task.task),
-            None => None,
+        loop {
+            let notified = self.shared.notify.notified();
+            if let Some(priority_task) = self.shared.pending.lock().unwrap().pop() {
+                return Some(priority_task.task);
+            }
+            if self.shared.senders.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+                return None;
+            }
+            notified.await;
         }
     }
+    
+    /// 取出当前排队中的所有任务（不等待新任务）
+    pub fn drain_pending(&mut self) -> Vec<MaaTask> {
+        let mut pending = self.shared.pending.lock().unwrap();
+        let mut tasks = Vec::with_capacity(pending.len());
+        while let Some(priority_task) = pending.pop() {
+            tasks.push(priority_task.task);
+        }
+        tasks
+    }
 }
 
-/// 创建V2版本的MAA任务通道（单队列+优先级）
-pub fn create_maa_task_channel_v2() -> (MaaTaskSender, MaaTaskReceiver) {
-    let (task_tx, task_rx) = mpsc::unbounded_channel();
-    let task_counter = std::sync::Arc::new(std::sync::atomic::AtomicI32::new(1));
-    
-    let sender = MaaTaskSender {
-        task_tx,
-        task_counter,
-    };
-    
-    let receiver = MaaTaskReceiver {
-        task_rx,
-    };
+/// Worker和MAA Core空闲的预计时间
+///
+/// 同步任务占用Worker直到完成；异步任务提交后由MAA Core依次执行，
+/// 按开始时间顺序累加预估耗时得到MAA Core空闲时间。
+fn busy_until(now: DateTime<Utc>, running: &[MaaTaskStatus]) -> (DateTime<Utc>, DateTime<Utc>) {
+    let mut running: Vec<&MaaTaskStatus> = running.iter().collect();
+    running.sort_by_key(|task| task.started_at);
     
-    (sender, receiver)
+    let mut worker_free_at = now;
+    let mut maa_free_at: Option<DateTime<Utc>> = None;
+    for task in running {
+        let started_at = task.started_at.unwrap_or(now);
+        let duration = chrono::Duration::seconds(estimate_task_duration(&task.task_type) as i64);
+        if is_synchronous_task(&task.task_type) {
+            worker_free_at = worker_free_at.max(started_at + duration);
+        } else {
+            maa_free_at = Some(maa_free_at.map_or(started_at, |free_at| free_at.max(started_at)) + duration);
+        }
+    }
+    (worker_free_at, maa_free_at.map_or(now, |free_at| free_at.max(now)))
 }
 
-/// 任务状态跟踪 - V2版本（Worker内部管理）
-#[derive(Debug, Clone)]
-pub struct TaskStatus {
-    pub task_id: i32,
-    pub task_type: String,
-    pub status: String,
-    pub created_at: DateTime<Utc>,
-    pub started_at: Option<DateTime<Utc>>,
-    pub completed_at: Option<DateTime<Utc>>,
-    pub result: Option<Value>,
-    pub error: Option<String>,
+/// 队列满时建议的重试等待时间：Worker取出下一个任务即可腾出位置
+fn retry_after_secs(now: DateTime<Utc>, running: &[MaaTaskStatus]) -> u64 {
+    let (worker_free_at, _) = busy_until(now, running);
+    ((worker_free_at - now).num_seconds().max(0) as u64).max(1)
 }
 
-impl TaskStatus {
-    pub fn new(task_id: i32, task_type: String) -> Self {
-        Self {
-            task_id,
-            task_type,
-            status: "pending".to_string(),
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            result: None,
-            error: None,
-        }
+/// 按执行顺序依次估算任务开始时间
+struct ScheduleEstimator {
+    worker_free_at: DateTime<Utc>,
+    maa_free_at: DateTime<Utc>,
+}
+
+impl ScheduleEstimator {
+    fn new(now: DateTime<Utc>, running: &[MaaTaskStatus]) -> Self {
+        let (worker_free_at, maa_free_at) = busy_until(now, running);
+        Self { worker_free_at, maa_free_at }
     }
     
-    pub fn mark_running(&mut self) {
-        self.status = "running".to_string();
-        self.started_at = Some(Utc::now());
+    /// 排入下一个任务，返回其预计开始时间
+    fn push(&mut self, task_type: &str, execution_mode: TaskExecutionMode) -> DateTime<Utc> {
+        let duration = chrono::Duration::seconds(estimate_task_duration(task_type) as i64);
+        match execution_mode {
+            TaskExecutionMode::Synchronous => {
+                let start_at = self.worker_free_at;
+                self.worker_free_at = start_at + duration;
+                start_at
+            },
+            TaskExecutionMode::Asynchronous => {
+                let start_at = self.worker_free_at.max(self.maa_free_at);
+                self.maa_free_at = start_at + duration;
+                start_at
+            },
+        }
     }
+}
+
+/// 按执行顺序估算排队任务的开始时间
+fn estimate_schedule(now: DateTime<Utc>, running: &[MaaTaskStatus], ordered: &[&MaaTask]) -> Vec<QueuedTaskInfo> {
+    let mut estimator = ScheduleEstimator::new(now, running);
     
-    pub fn mark_completed(&mut self, result: Value) {
-        self.status = "completed".to_string();
-        self.completed_at = Some(Utc::now());
-        self.result = Some(result);
-    }
+    ordered.iter().enumerate()
+        .map(|(index, task)| {
+            let start_at = estimator.push(&task.task_type, task.execution_mode);
+            QueuedTaskInfo {
+                position: index + 1,
+                task_id: task.task_id,
+                task_type: task.task_type.clone(),
+                priority: task.priority,
+                execution_mode: task.execution_mode,
+                created_at: task.created_at,
+                estimated_start_at: start_at,
+                estimated_wait_secs: (start_at - now).num_seconds().max(0) as u64,
+            }
+        })
+        .collect()
+}
+
+/// 创建V2版本的MAA任务通道（单队列+优先级）
+///
+/// 队列容量取 `PerformanceConfig.task_queue_buffer_size`。
+pub fn create_maa_task_channel_v2() -> (MaaTaskSender, MaaTaskReceiver) {
+    create_maa_task_channel_with_capacity(CONFIG.performance.task_queue_buffer_size)
+}
+
+/// 创建指定容量的MAA任务通道
+pub fn create_maa_task_channel_with_capacity(capacity: usize) -> (MaaTaskSender, MaaTaskReceiver) {
+    let shared = Arc::new(QueueShared {
+        pending: Mutex::new(BinaryHeap::new()),
+        notify: Notify::new(),
+        capacity: capacity.max(1),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+    });
     
-    pub fn mark_failed(&mut self, error: String) {
-        self.status = "failed".to_string();
-        self.completed_at = Some(Utc::now());
-        self.error = Some(error);
-    }
+    let sender = MaaTaskSender {
+        shared: shared.clone(),
+        task_counter: Arc::new(AtomicI32::new(1)),
+    };
+    
+    let receiver = MaaTaskReceiver {
+        shared,
+    };
+    
+    (sender, receiver)
 }
 
 #[cfg(test)]
@@ -280,4 +515,87 @@ mod tests {
         assert!(received_result.success);
         assert_eq!(received_result.task_id, task_id);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_drain_pending() {
+        let (sender, mut receiver) = create_maa_task_channel_v2();
+        
+        let _ = sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({}));
+        let _ = sender.send_async_task("maa_recruit_enhanced".to_string(), serde_json::json!({}));
+        
+        let drained: Vec<String> = receiver.drain_pending().into_iter().map(|t| t.task_type).collect();
+        assert_eq!(drained, vec!["maa_combat_enhanced", "maa_recruit_enhanced"]);
+        assert!(receiver.drain_pending().is_empty());
+    }
+    
+    #[test]
+    fn test_bounded_queue_and_dedup() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(2);
+        
+        let (first_id, _) = sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({"stage": "1-7"})).unwrap();
+        // 排队中相同的异步任务返回已有任务ID
+        assert_eq!(
+            sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({"stage": "1-7"})).unwrap_err(),
+            QueueError::Duplicate { task_id: first_id }
+        );
+        
+        let _ = sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({"stage": "CE-6"})).unwrap();
+        match sender.send_sync_task("maa_take_screenshot".to_string(), serde_json::json!({})) {
+            Err(QueueError::Full { depth, capacity, retry_after_secs }) => {
+                assert_eq!((depth, capacity), (2, 2));
+                assert!(retry_after_secs >= 1);
+            },
+            other => panic!("队列满时应拒绝: {:?}", other.map(|(id, _)| id)),
+        }
+        assert_eq!(sender.depth(), 2);
+    }
+    
+    #[test]
+    fn test_pending_tasks_in_execution_order() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(10);
+        
+        let _ = sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({}));
+        let _ = sender.send_async_task("maa_recruit_enhanced".to_string(), serde_json::json!({}));
+        let _ = sender.send_sync_task("maa_take_screenshot".to_string(), serde_json::json!({}));
+        
+        let pending = sender.pending_tasks();
+        let order: Vec<&str> = pending.iter().map(|t| t.task_type.as_str()).collect();
+        assert_eq!(order, vec!["maa_take_screenshot", "maa_combat_enhanced", "maa_recruit_enhanced"]);
+        assert_eq!(pending[0].position, 1);
+        // 招募排在战斗之后，预计等待战斗的预估耗时
+        assert!(pending[2].estimated_wait_secs >= pending[1].estimated_wait_secs + 600);
+    }
+    
+    #[test]
+    fn test_preview_task_does_not_enqueue() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(10);
+        
+        let _ = sender.send_async_task("maa_combat_enhanced".to_string(), serde_json::json!({}));
+        let _ = sender.send_sync_task("maa_take_screenshot".to_string(), serde_json::json!({}));
+        
+        // 同步高优先级任务排在排队中的截图之后、战斗之前
+        let preview = sender.preview_task("maa_closedown", &serde_json::json!({}), TaskPriority::High, TaskExecutionMode::Synchronous);
+        assert_eq!(preview.position, 2);
+        assert_eq!(preview.duplicate_of, None);
+        
+        let preview = sender.preview_task("maa_recruit_enhanced", &serde_json::json!({}), TaskPriority::Normal, TaskExecutionMode::Asynchronous);
+        assert_eq!(preview.position, 3);
+        assert!(preview.estimated_wait_secs >= 600);
+        
+        let preview = sender.preview_task("maa_combat_enhanced", &serde_json::json!({}), TaskPriority::Normal, TaskExecutionMode::Asynchronous);
+        assert!(preview.duplicate_of.is_some());
+        assert_eq!(sender.depth(), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_receiver_ends_when_senders_dropped() {
+        let (sender, mut receiver) = create_maa_task_channel_with_capacity(10);
+        let cloned = sender.clone();
+        let _ = sender.send_async_task("maa_rewards_enhanced".to_string(), serde_json::json!({}));
+        drop(sender);
+        drop(cloned);
+        
+        assert!(receiver.recv().await.is_some());
+        assert!(timeout(Duration::from_millis(100), receiver.recv()).await.unwrap().is_none());
+    }
+}
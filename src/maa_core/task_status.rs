@@ -1,15 +1,20 @@
 //! MAA 任务状态管理模块
 //! 
-//! 管理异步MAA任务的状态追踪和查询
+//! 统一的任务状态存储：队列任务从入队到结束的所有状态都记录在这里。
+//! 状态变更经过生命周期校验后发布到 `task_notification` 事件流，SSE推送和HTTP等待都从该事件流获取。
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use once_cell::sync::Lazy;
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{info, debug, warn};
 
+use super::task_classification_v2::estimate_task_duration;
+use super::task_notification::{notify_task_status, subscribe_task_events, TaskStatusEvent};
+
 /// 全局任务状态管理器
 static GLOBAL_TASK_STATUS: Lazy<Arc<Mutex<HashMap<i32, MaaTaskStatus>>>> = 
     Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
@@ -27,6 +32,12 @@ pub struct MaaTaskStatus {
     pub status: TaskStatus,
     /// 任务创建时间
     pub created_at: DateTime<Utc>,
+    /// 任务开始执行时间
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// MAA最近一次开始执行该任务的任务链的时间（TaskChainStart），超时从这里计时
+    #[serde(default)]
+    pub chain_started_at: Option<DateTime<Utc>>,
     /// 任务完成时间
     pub completed_at: Option<DateTime<Utc>>,
     /// 进度信息
@@ -35,22 +46,114 @@ pub struct MaaTaskStatus {
     pub result: Option<Value>,
     /// 错误信息
     pub error: Option<String>,
+    /// 从回调中汇总的执行结果
+    #[serde(default)]
+    pub outcome: TaskOutcome,
+}
+
+/// 从MAA回调中汇总的任务结果（掉落、公招标签、错误）
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaskOutcome {
+    /// 关卡掉落 (StageDrops 回调中的 drops 条目)
+    pub drops: Vec<Value>,
+    /// 识别到的公招标签
+    pub recruit_tags: Vec<String>,
+    /// 执行过程中的错误
+    pub errors: Vec<String>,
 }
 
-/// 任务状态枚举
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+impl TaskOutcome {
+    /// 记录子任务额外信息 (20003) 中的掉落和公招标签
+    pub fn record_sub_task_extra(&mut self, details: &Value) {
+        let extra = details.get("details");
+        match details.get("what").and_then(|v| v.as_str()) {
+            Some("StageDrops") => {
+                if let Some(drops) = extra.and_then(|d| d.get("drops")).and_then(|v| v.as_array()) {
+                    self.drops.extend(drops.iter().cloned());
+                }
+            },
+            Some("RecruitTagsDetected") => {
+                if let Some(tags) = extra.and_then(|d| d.get("tags")).and_then(|v| v.as_array()) {
+                    for tag in tags.iter().filter_map(|t| t.as_str()) {
+                        if !self.recruit_tags.iter().any(|t| t == tag) {
+                            self.recruit_tags.push(tag.to_string());
+                        }
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// 记录错误
+    pub fn record_error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+}
+
+/// 任务生命周期状态
+///
+/// Queued → Running → Succeeded / Failed / Cancelled / TimedOut，运行中可暂停 (Paused) 后恢复；
+/// 设备断线时可重新排队 (Running → Queued)。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskStatus {
-    /// 已创建，等待执行
-    Pending,
+    /// 已入队，等待执行
+    Queued,
     /// 正在执行中
     Running,
     /// 执行成功完成
-    Completed,
+    Succeeded,
     /// 执行失败
     Failed,
-    /// 任务超时
-    Timeout,
+    /// 被取消或手动停止
+    Cancelled,
+    /// 执行超时
+    TimedOut,
+    /// 已暂停
+    Paused,
+}
+
+impl TaskStatus {
+    /// 是否为终止状态
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TaskStatus::Succeeded | TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::TimedOut)
+    }
+    
+    /// 是否允许从当前状态变更到目标状态
+    pub fn can_transition_to(&self, next: TaskStatus) -> bool {
+        use TaskStatus::*;
+        matches!(
+            (self, next),
+            (Queued, Running | Failed | Cancelled)
+                | (Running, Succeeded | Failed | Cancelled | TimedOut | Paused | Queued)
+                | (Paused, Running | Failed | Cancelled | TimedOut | Queued)
+        )
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            TaskStatus::Queued => "排队中",
+            TaskStatus::Running => "执行中",
+            TaskStatus::Succeeded => "已完成",
+            TaskStatus::Failed => "已失败",
+            TaskStatus::Cancelled => "已取消",
+            TaskStatus::TimedOut => "已超时",
+            TaskStatus::Paused => "已暂停",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// 状态变更错误
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TransitionError {
+    #[error("任务不存在: {0}")]
+    NotFound(i32),
+    #[error("非法状态变更: task_id={task_id}, {from:?} -> {to:?}")]
+    Invalid { task_id: i32, from: TaskStatus, to: TaskStatus },
 }
 
 impl MaaTaskStatus {
@@ -60,32 +163,52 @@ impl MaaTaskStatus {
             task_id,
             task_type,
             parameters,
-            status: TaskStatus::Pending,
+            status: TaskStatus::Queued,
             created_at: Utc::now(),
+            started_at: None,
+            chain_started_at: None,
             completed_at: None,
             progress: None,
             result: None,
             error: None,
+            outcome: TaskOutcome::default(),
         }
     }
     
-    /// 标记任务为运行中
-    pub fn start(&mut self) {
-        self.status = TaskStatus::Running;
+    /// 按生命周期变更状态，同时维护开始/结束时间
+    pub fn transition(&mut self, next: TaskStatus) -> Result<(), TransitionError> {
+        if !self.status.can_transition_to(next) {
+            return Err(TransitionError::Invalid { task_id: self.task_id, from: self.status, to: next });
+        }
+        
+        self.status = next;
+        match next {
+            TaskStatus::Running => {
+                self.started_at.get_or_insert_with(Utc::now);
+            },
+            // 重新排队后重新计时
+            TaskStatus::Queued => {
+                self.started_at = None;
+                self.chain_started_at = None;
+            },
+            _ => {}
+        }
+        if next.is_terminal() {
+            self.completed_at = Some(Utc::now());
+        }
+        Ok(())
     }
     
-    /// 标记任务完成
-    pub fn complete(&mut self, result: Value) {
-        self.status = TaskStatus::Completed;
-        self.completed_at = Some(Utc::now());
-        self.result = Some(result);
+    /// 已执行时长（秒），未开始时为0
+    pub fn elapsed_seconds(&self, now: DateTime<Utc>) -> u64 {
+        self.started_at
+            .map(|started| (now - started).num_seconds().max(0) as u64)
+            .unwrap_or(0)
     }
     
-    /// 标记任务失败
-    pub fn fail(&mut self, error: String) {
-        self.status = TaskStatus::Failed;
-        self.completed_at = Some(Utc::now());
-        self.error = Some(error);
+    /// 当前任务链已执行时长（秒），MAA尚未开始执行任务链时为None
+    pub fn chain_elapsed_seconds(&self, now: DateTime<Utc>) -> Option<u64> {
+        self.chain_started_at.map(|started| (now - started).num_seconds().max(0) as u64)
     }
     
     /// 更新任务进度
@@ -93,46 +216,140 @@ impl MaaTaskStatus {
         self.progress = Some(progress);
     }
     
-    /// 检查任务是否已完成（成功或失败）
+    /// 检查任务是否已结束
     pub fn is_finished(&self) -> bool {
-        matches!(self.status, TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Timeout)
+        self.status.is_terminal()
+    }
+    
+    /// 状态事件中携带的详情，终止状态附带结果汇总
+    fn event_details(&self) -> Option<Value> {
+        self.status.is_terminal().then(|| json!({
+            "result": self.result,
+            "error": self.error,
+            "drops": self.outcome.drops,
+            "recruit_tags": self.outcome.recruit_tags,
+            "errors": self.outcome.errors,
+        }))
     }
 }
 
-/// 注册新任务
+/// 注册新任务（排队中）
 pub fn register_task(task_id: i32, task_type: String, parameters: Value) {
-    let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
-    let task_status = MaaTaskStatus::new(task_id, task_type, parameters);
-    tasks.insert(task_id, task_status);
+    let task = MaaTaskStatus::new(task_id, task_type, parameters);
+    let event = status_event(&task, None, "任务已加入队列".to_string());
+    GLOBAL_TASK_STATUS.lock().unwrap().insert(task_id, task);
     debug!("注册任务状态: task_id={}", task_id);
+    notify_task_status(event);
 }
 
-/// 启动任务（设置为运行中状态）
-pub fn start_task(task_id: i32) {
-    let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
-    if let Some(task) = tasks.get_mut(&task_id) {
-        task.start();
-        // 任务开始执行: task_id={}
+/// 变更任务状态并发布状态事件
+///
+/// 目标状态与当前状态相同时不做任何事并返回 `Ok(false)`。
+pub fn transition_task(
+    task_id: i32,
+    next: TaskStatus,
+    message: String,
+    update: impl FnOnce(&mut MaaTaskStatus),
+) -> Result<bool, TransitionError> {
+    let event = {
+        let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
+        let task = tasks.get_mut(&task_id).ok_or(TransitionError::NotFound(task_id))?;
+        if task.status == next {
+            return Ok(false);
+        }
+        
+        let previous = task.status;
+        task.transition(next)?;
+        update(task);
+        status_event(task, Some(previous), message)
+    };
+    
+    notify_task_status(event);
+    Ok(true)
+}
+
+/// 变更任务状态，非法变更仅记录日志；返回是否发生了状态变更
+fn apply_transition(task_id: i32, next: TaskStatus, message: String, update: impl FnOnce(&mut MaaTaskStatus)) -> bool {
+    match transition_task(task_id, next, message, update) {
+        Ok(changed) => changed,
+        Err(TransitionError::NotFound(_)) => false,
+        Err(e) => {
+            warn!("忽略任务状态变更: {}", e);
+            false
+        }
     }
 }
 
-/// 完成任务
-pub fn complete_task(task_id: i32, result: Value) {
-    let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
-    if let Some(task) = tasks.get_mut(&task_id) {
-        task.complete(result);
-        // 任务执行完成: task_id={}
+fn status_event(task: &MaaTaskStatus, previous: Option<TaskStatus>, message: String) -> TaskStatusEvent {
+    TaskStatusEvent {
+        task_id: task.task_id,
+        task_type: task.task_type.clone(),
+        status: task.status,
+        previous_status: previous,
+        message,
+        progress: (task.status == TaskStatus::Succeeded).then_some(1.0),
+        details: task.event_details(),
+        timestamp: Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
     }
 }
 
+/// 启动任务（设置为运行中状态）
+pub fn start_task(task_id: i32) -> bool {
+    apply_transition(task_id, TaskStatus::Running, "任务开始执行".to_string(), |_| {})
+}
+
+/// 完成任务
+pub fn complete_task(task_id: i32, result: Value) -> bool {
+    apply_transition(task_id, TaskStatus::Succeeded, "任务执行完成".to_string(), |task| {
+        task.result = Some(result);
+    })
+}
+
 /// 任务执行失败
-pub fn fail_task(task_id: i32, error: String) {
-    let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
-    if let Some(task) = tasks.get_mut(&task_id) {
-        let error_clone = error.clone();
-        task.fail(error);
-        warn!("任务执行失败: task_id={}, error={}", task_id, error_clone);
+pub fn fail_task(task_id: i32, error: String) -> bool {
+    let changed = apply_transition(task_id, TaskStatus::Failed, format!("任务执行失败: {}", error), |task| {
+        task.error = Some(error.clone());
+    });
+    if changed {
+        warn!("任务执行失败: task_id={}, error={}", task_id, error);
     }
+    changed
+}
+
+/// 标记任务超时，返回是否发生了状态变更
+pub fn timeout_task(task_id: i32, error: String) -> bool {
+    let changed = apply_transition(task_id, TaskStatus::TimedOut, error.clone(), |task| {
+        task.outcome.record_error(error.clone());
+        task.error = Some(error.clone());
+    });
+    if changed {
+        warn!("任务执行超时: task_id={}, error={}", task_id, error);
+    }
+    changed
+}
+
+/// 取消任务
+pub fn cancel_task(task_id: i32, reason: String) -> bool {
+    apply_transition(task_id, TaskStatus::Cancelled, format!("任务已取消: {}", reason), |task| {
+        task.error = Some(reason.clone());
+    })
+}
+
+/// 将中断的任务重新排队（如设备断线后等待重连）
+pub fn requeue_task(task_id: i32, reason: String) -> bool {
+    apply_transition(task_id, TaskStatus::Queued, format!("任务重新排队: {}", reason), |task| {
+        task.update_progress(reason.clone());
+    })
+}
+
+/// 暂停任务
+pub fn pause_task(task_id: i32) -> bool {
+    apply_transition(task_id, TaskStatus::Paused, "任务已暂停".to_string(), |_| {})
+}
+
+/// 恢复暂停的任务
+pub fn resume_task(task_id: i32) -> bool {
+    apply_transition(task_id, TaskStatus::Running, "任务已恢复".to_string(), |_| {})
 }
 
 /// 更新任务进度
@@ -145,6 +362,21 @@ pub fn update_task_progress(task_id: i32, progress: String) {
     }
 }
 
+/// 更新任务的汇总结果
+fn record_outcome(task_id: i32, update: impl FnOnce(&mut TaskOutcome)) {
+    let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
+    if let Some(task) = tasks.get_mut(&task_id) {
+        update(&mut task.outcome);
+    }
+}
+
+/// 记录MAA开始执行任务的一个任务链，每个任务链重新计时
+fn mark_chain_started(task_id: i32) {
+    if let Some(task) = GLOBAL_TASK_STATUS.lock().unwrap().get_mut(&task_id) {
+        task.chain_started_at = Some(Utc::now());
+    }
+}
+
 /// 获取任务状态
 pub fn get_task_status(task_id: i32) -> Option<MaaTaskStatus> {
     let tasks = GLOBAL_TASK_STATUS.lock().unwrap();
@@ -168,21 +400,132 @@ pub fn get_running_tasks() -> Vec<MaaTaskStatus> {
 
 /// 清理已完成的旧任务（保留最近24小时的）
 pub fn cleanup_old_tasks() {
+    cleanup_finished_tasks(chrono::Duration::hours(24));
+}
+
+/// 清理结束时间早于 `max_age` 的任务，返回清理数量
+pub fn cleanup_finished_tasks(max_age: chrono::Duration) -> usize {
     let mut tasks = GLOBAL_TASK_STATUS.lock().unwrap();
-    let cutoff_time = Utc::now() - chrono::Duration::hours(24);
+    let cutoff_time = Utc::now() - max_age;
     
     let old_task_ids: Vec<i32> = tasks.values()
-        .filter(|task| task.is_finished() && task.created_at < cutoff_time)
+        .filter(|task| task.is_finished() && task.completed_at.is_some_and(|t| t <= cutoff_time))
         .map(|task| task.task_id)
         .collect();
     
-    let old_count = old_task_ids.len();
     for task_id in &old_task_ids {
         tasks.remove(task_id);
+        super::task_mapping::release_queue_task(*task_id);
     }
     
     if !old_task_ids.is_empty() {
-        // 清理了 {} 个旧任务
+        info!("清理了 {} 个已结束的任务状态", old_task_ids.len());
+    }
+    old_task_ids.len()
+}
+
+/// 默认等待时间为任务预估耗时的倍数
+pub const WAIT_TIMEOUT_FACTOR: u64 = 2;
+
+/// 等待任务结束的结果
+#[derive(Debug, Clone)]
+pub enum TaskWaitOutcome {
+    /// 任务已结束（成功、失败或超时）
+    Finished(MaaTaskStatus),
+    /// 等待超时，任务仍在执行
+    WaitTimedOut {
+        task: MaaTaskStatus,
+        /// 实际使用的等待时间（秒）
+        timeout_secs: u64,
+        /// 请求的等待时间是否超过上限而被截断
+        capped: bool,
+    },
+    /// 任务不存在
+    NotFound,
+}
+
+impl TaskWaitOutcome {
+    /// 转换为返回给调用方的汇总结果
+    pub fn to_json(&self) -> Value {
+        match self {
+            TaskWaitOutcome::Finished(task) => Self::task_json(task, false),
+            TaskWaitOutcome::WaitTimedOut { task, timeout_secs, capped } => {
+                let mut summary = Self::task_json(task, true);
+                summary["wait_timeout_seconds"] = serde_json::json!(timeout_secs);
+                summary["wait_timeout_capped"] = serde_json::json!(capped);
+                summary
+            },
+            TaskWaitOutcome::NotFound => serde_json::json!({
+                "finished": false,
+                "error": "任务不存在",
+            }),
+        }
+    }
+
+    fn task_json(task: &MaaTaskStatus, wait_timed_out: bool) -> Value {
+        serde_json::json!({
+            "task_id": task.task_id,
+            "task_type": task.task_type,
+            "status": task.status,
+            "finished": task.is_finished(),
+            "wait_timed_out": wait_timed_out,
+            "progress": task.progress,
+            "result": task.result,
+            "error": task.error,
+            "drops": task.outcome.drops,
+            "recruit_tags": task.outcome.recruit_tags,
+            "errors": task.outcome.errors,
+        })
+    }
+}
+
+/// 任务类型允许的最长等待时间（秒），由预估耗时推导
+pub fn max_wait_seconds(task_type: &str) -> u64 {
+    estimate_task_duration(task_type) as u64 * WAIT_TIMEOUT_FACTOR
+}
+
+/// 等待任务结束
+///
+/// `timeout_secs` 为空时使用 `max_wait_seconds`，且不会超过该上限；
+/// 等待超时时结果中带有实际使用的等待时间和是否被截断。
+pub async fn wait_for_task(task_id: i32, timeout_secs: Option<u64>) -> TaskWaitOutcome {
+    let Some(task) = get_task_status(task_id) else {
+        return TaskWaitOutcome::NotFound;
+    };
+    
+    let limit = max_wait_seconds(&task.task_type);
+    let timeout = timeout_secs.map(|t| t.min(limit)).unwrap_or(limit);
+    let capped = timeout_secs.is_some_and(|t| t > limit);
+    if capped {
+        debug!("等待时间超过上限，已截断: task_id={}, requested={}s, limit={}s", task_id, timeout_secs.unwrap_or_default(), limit);
+    }
+    
+    // 先订阅事件流再检查状态，避免错过订阅前已经结束的任务
+    let mut events = subscribe_task_events();
+    if !get_task_status(task_id).is_some_and(|task| task.is_finished()) {
+        debug!("等待任务结束: task_id={}, timeout={}s", task_id, timeout);
+        let wait = async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.task_id == task_id && event.status.is_terminal() => break,
+                    Ok(_) => continue,
+                    // 事件积压时直接以状态存储为准
+                    Err(RecvError::Lagged(_)) => {
+                        if get_task_status(task_id).is_some_and(|task| task.is_finished()) {
+                            break;
+                        }
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(timeout), wait).await;
+    }
+    
+    match get_task_status(task_id) {
+        Some(task) if task.is_finished() => TaskWaitOutcome::Finished(task),
+        Some(task) => TaskWaitOutcome::WaitTimedOut { task, timeout_secs: timeout, capped },
+        None => TaskWaitOutcome::NotFound,
     }
 }
 
@@ -192,6 +535,7 @@ pub fn handle_maa_callback(task_id: i32, msg_code: i32, details: Value) {
         // TaskChain 开始
         10001 => {
             start_task(task_id);
+            mark_chain_started(task_id);
         },
         // TaskChain 完成
         10002 => {
@@ -203,10 +547,26 @@ pub fn handle_maa_callback(task_id: i32, msg_code: i32, details: Value) {
                 .and_then(|v| v.as_str())
                 .unwrap_or("unknown error")
                 .to_string();
+            record_outcome(task_id, |outcome| outcome.record_error(error_msg.clone()));
             fail_task(task_id, error_msg);
         },
+        // TaskChain 手动停止
+        10004 => {
+            cancel_task(task_id, "任务链已停止".to_string());
+        },
+        // SubTask 错误
+        20000 => {
+            let task_name = details.get("details")
+                .and_then(|d| d.get("task"))
+                .and_then(|t| t.as_str())
+                .unwrap_or("unknown");
+            record_outcome(task_id, |outcome| outcome.record_error(format!("子任务失败: {}", task_name)));
+        },
         // SubTask 进度更新
-        20001 | 20002 | 20003 => {
+        20001..=20003 => {
+            if msg_code == 20003 {
+                record_outcome(task_id, |outcome| outcome.record_sub_task_extra(&details));
+            }
             if let Some(task_name) = details.get("details").and_then(|d| d.get("task")).and_then(|t| t.as_str()) {
                 update_task_progress(task_id, format!("执行子任务: {}", task_name));
             }
@@ -215,4 +575,126 @@ pub fn handle_maa_callback(task_id: i32, msg_code: i32, details: Value) {
             // 其他事件暂时忽略
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_outcome_aggregation() {
+        register_task(9001, "maa_combat_enhanced".to_string(), json!({}));
+        handle_maa_callback(9001, 20003, json!({
+            "what": "StageDrops",
+            "details": { "drops": [{ "itemName": "固源岩", "quantity": 2 }] }
+        }));
+        handle_maa_callback(9001, 20003, json!({
+            "what": "RecruitTagsDetected",
+            "details": { "tags": ["高级资深干员", "治疗", "治疗"] }
+        }));
+        handle_maa_callback(9001, 10000, json!({ "what": "TaskChainError" }));
+
+        let task = get_task_status(9001).unwrap();
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.outcome.drops.len(), 1);
+        assert_eq!(task.outcome.recruit_tags, vec!["高级资深干员", "治疗"]);
+        assert_eq!(task.outcome.errors, vec!["TaskChainError"]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_task() {
+        assert!(matches!(wait_for_task(9101, Some(1)).await, TaskWaitOutcome::NotFound));
+
+        register_task(9102, "maa_combat_enhanced".to_string(), json!({}));
+        start_task(9102);
+        tokio::spawn(async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            complete_task(9102, json!({ "done": true }));
+        });
+
+        match wait_for_task(9102, Some(5)).await {
+            TaskWaitOutcome::Finished(task) => assert_eq!(task.status, TaskStatus::Succeeded),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        // 已结束的任务立即返回
+        assert!(matches!(wait_for_task(9102, Some(5)).await, TaskWaitOutcome::Finished(_)));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_task_timeout() {
+        register_task(9103, "maa_combat_enhanced".to_string(), json!({}));
+        start_task(9103);
+
+        let outcome = wait_for_task(9103, Some(0)).await;
+        assert!(matches!(outcome, TaskWaitOutcome::WaitTimedOut { timeout_secs: 0, capped: false, .. }));
+        assert_eq!(outcome.to_json()["wait_timed_out"], true);
+        assert_eq!(outcome.to_json()["wait_timeout_seconds"], 0);
+    }
+
+    #[test]
+    fn test_wait_timeout_capped_json() {
+        let mut task = MaaTaskStatus::new(9104, "maa_combat_enhanced".to_string(), json!({}));
+        task.status = TaskStatus::Running;
+        let limit = max_wait_seconds("maa_combat_enhanced");
+        let summary = TaskWaitOutcome::WaitTimedOut { task, timeout_secs: limit, capped: true }.to_json();
+        assert_eq!(summary["wait_timeout_seconds"], limit);
+        assert_eq!(summary["wait_timeout_capped"], true);
+    }
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        use TaskStatus::*;
+
+        assert!(Queued.can_transition_to(Running));
+        assert!(Running.can_transition_to(Paused));
+        assert!(Paused.can_transition_to(Running));
+        assert!(Running.can_transition_to(TimedOut));
+        assert!(!Queued.can_transition_to(Succeeded));
+        assert!(!Succeeded.can_transition_to(Running));
+        assert!(!TimedOut.can_transition_to(Succeeded));
+        assert!(Running.can_transition_to(Queued));
+        assert!(!Succeeded.can_transition_to(Queued));
+    }
+
+    #[tokio::test]
+    async fn test_store_publishes_validated_transitions() {
+        let mut events = subscribe_task_events();
+
+        register_task(9201, "maa_combat_enhanced".to_string(), json!({}));
+        assert!(start_task(9201));
+        // 重复开始不产生新事件
+        assert!(!start_task(9201));
+        assert!(timeout_task(9201, "超时".to_string()));
+        // 超时后的迟到完成回调被拒绝
+        assert!(!complete_task(9201, json!({})));
+        assert_eq!(get_task_status(9201).unwrap().status, TaskStatus::TimedOut);
+
+        assert_eq!(
+            transition_task(9201, TaskStatus::Running, String::new(), |_| {}),
+            Err(TransitionError::Invalid { task_id: 9201, from: TaskStatus::TimedOut, to: TaskStatus::Running })
+        );
+        assert_eq!(
+            transition_task(9299, TaskStatus::Running, String::new(), |_| {}),
+            Err(TransitionError::NotFound(9299))
+        );
+
+        let mut statuses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.task_id == 9201 {
+                statuses.push((event.previous_status, event.status));
+            }
+        }
+        assert_eq!(statuses, vec![
+            (None, TaskStatus::Queued),
+            (Some(TaskStatus::Queued), TaskStatus::Running),
+            (Some(TaskStatus::Running), TaskStatus::TimedOut),
+        ]);
+    }
+
+    #[test]
+    fn test_max_wait_seconds() {
+        assert_eq!(max_wait_seconds("maa_combat_enhanced"), 600 * WAIT_TIMEOUT_FACTOR);
+    }
+}
@@ -1,46 +1,257 @@
 //! 重构后的MAA工作者
 //! 
 //! 优化点：
-//! 1. 简化任务状态管理，移到worker内部
+//! 1. 任务状态统一写入全局任务状态存储 (`task_status`)
 //! 2. 支持同步/异步任务区别处理
 //! 3. 集成SSE推送机制
 
 use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tracing::{info, debug, warn, error};
 use chrono::Utc;
-use std::collections::HashMap;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 use base64;
 
 use super::{MaaCore, task_queue_v2::*};
+use super::task_classification_v2::{classify_task, estimate_task_duration};
+use super::task_status::{self, MaaTaskStatus, TaskStatus};
+use super::{budget, task_mapping, task_params};
+use super::task_builder::{self, MaaTaskPlan};
+use super::connection::{self, ConnectionState};
+use super::supervisor::{self, WorkerExit};
+use crate::config::CONFIG;
 // use super::task_classification_v2::*; // 未使用的导入已移除
 
-/// SSE事件类型
+/// SSE事件类型 - 事件名称的统一取值
+///
+/// 序列化名称即SSE的 `event` 字段，MAA回调转发、Worker和SSE管理器都使用这里的取值。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskEventType {
+    // 队列任务生命周期
+    Started,
+    Progress,
+    Completed,
+    Failed,
+    Cancelled,
+    #[serde(rename = "task_timeout")]
+    TimedOut,
+    /// 任务状态存储发布的状态变更，`data.status` 为新状态
+    TaskStatus,
+    // MAA任务链回调
+    TaskchainStarted,
+    TaskchainCompleted,
+    TaskchainFailed,
+    TaskchainStopped,
+    // MAA子任务回调
+    SubtaskStarted,
+    SubtaskCompleted,
+    SubtaskInfo,
+    SubtaskFailed,
+    SubtaskStopped,
+    // 系统事件
+    AllTasksCompleted,
+    MaaDestroyed,
+    DeviceConnection,
+    WorkerRestarted,
+    WorkflowNode,
+    WorkflowCompleted,
+    /// 客户端错过了部分事件
+    Gap,
+    /// 前后端联调测试事件
+    Test,
+    Unknown,
+}
+
+impl TaskEventType {
+    /// 所有事件类型
+    pub const ALL: &'static [TaskEventType] = &[
+        TaskEventType::Started, TaskEventType::Progress, TaskEventType::Completed, TaskEventType::Failed,
+        TaskEventType::Cancelled, TaskEventType::TimedOut, TaskEventType::TaskStatus,
+        TaskEventType::TaskchainStarted, TaskEventType::TaskchainCompleted, TaskEventType::TaskchainFailed, TaskEventType::TaskchainStopped,
+        TaskEventType::SubtaskStarted, TaskEventType::SubtaskCompleted, TaskEventType::SubtaskInfo, TaskEventType::SubtaskFailed, TaskEventType::SubtaskStopped,
+        TaskEventType::AllTasksCompleted, TaskEventType::MaaDestroyed, TaskEventType::DeviceConnection, TaskEventType::WorkerRestarted,
+        TaskEventType::WorkflowNode, TaskEventType::WorkflowCompleted, TaskEventType::Gap, TaskEventType::Test, TaskEventType::Unknown,
+    ];
+    
+    /// SSE事件名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskEventType::Started => "started",
+            TaskEventType::Progress => "progress",
+            TaskEventType::Completed => "completed",
+            TaskEventType::Failed => "failed",
+            TaskEventType::Cancelled => "cancelled",
+            TaskEventType::TimedOut => "task_timeout",
+            TaskEventType::TaskStatus => "task_status",
+            TaskEventType::TaskchainStarted => "taskchain_started",
+            TaskEventType::TaskchainCompleted => "taskchain_completed",
+            TaskEventType::TaskchainFailed => "taskchain_failed",
+            TaskEventType::TaskchainStopped => "taskchain_stopped",
+            TaskEventType::SubtaskStarted => "subtask_started",
+            TaskEventType::SubtaskCompleted => "subtask_completed",
+            TaskEventType::SubtaskInfo => "subtask_info",
+            TaskEventType::SubtaskFailed => "subtask_failed",
+            TaskEventType::SubtaskStopped => "subtask_stopped",
+            TaskEventType::AllTasksCompleted => "all_tasks_completed",
+            TaskEventType::MaaDestroyed => "maa_destroyed",
+            TaskEventType::DeviceConnection => "device_connection",
+            TaskEventType::WorkerRestarted => "worker_restarted",
+            TaskEventType::WorkflowNode => "workflow_node",
+            TaskEventType::WorkflowCompleted => "workflow_completed",
+            TaskEventType::Gap => "gap",
+            TaskEventType::Test => "test",
+            TaskEventType::Unknown => "unknown",
+        }
+    }
+    
+    /// 按SSE事件名称查找
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|event_type| event_type.as_str() == name)
+    }
+    
+    /// MAA回调消息码对应的事件类型
+    pub fn from_callback(msg_code: i32) -> Self {
+        match msg_code {
+            10000 => TaskEventType::TaskchainFailed,
+            10001 => TaskEventType::TaskchainStarted,
+            10002 => TaskEventType::TaskchainCompleted,
+            10004 => TaskEventType::TaskchainStopped,
+            20000 => TaskEventType::SubtaskFailed,
+            20001 => TaskEventType::SubtaskStarted,
+            20002 => TaskEventType::SubtaskCompleted,
+            20003 => TaskEventType::SubtaskInfo,
+            20004 => TaskEventType::SubtaskStopped,
+            _ => TaskEventType::Unknown,
+        }
+    }
+}
+
+/// 事件严重程度，用于SSE订阅按最低级别过滤
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSeverity {
+    /// 子任务级别的细节
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl EventSeverity {
+    /// 按名称查找（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(EventSeverity::Debug),
+            "info" => Some(EventSeverity::Info),
+            "warning" | "warn" => Some(EventSeverity::Warning),
+            "error" => Some(EventSeverity::Error),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TaskEventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// SSE事件
 #[derive(Debug, Clone)]
 pub struct TaskProgressEvent {
     pub task_id: i32,
     pub task_type: String,
-    pub event_type: String,  // "started", "progress", "completed", "failed"
+    pub event_type: TaskEventType,
     pub message: String,
     pub data: Option<Value>,
     pub timestamp: chrono::DateTime<Utc>,
 }
 
+impl TaskProgressEvent {
+    /// 事件严重程度
+    ///
+    /// 状态变更和设备连接事件按携带的状态判断，其余按事件类型判断。
+    pub fn severity(&self) -> EventSeverity {
+        let status = self.data.as_ref()
+            .and_then(|data| data.get("status").or_else(|| data.get("state")))
+            .and_then(|status| status.as_str());
+        match self.event_type {
+            TaskEventType::Failed | TaskEventType::TimedOut | TaskEventType::TaskchainFailed
+                | TaskEventType::SubtaskFailed | TaskEventType::MaaDestroyed => EventSeverity::Error,
+            TaskEventType::Cancelled | TaskEventType::TaskchainStopped | TaskEventType::SubtaskStopped
+                | TaskEventType::WorkerRestarted | TaskEventType::Gap => EventSeverity::Warning,
+            TaskEventType::Progress | TaskEventType::SubtaskStarted | TaskEventType::SubtaskCompleted
+                | TaskEventType::SubtaskInfo => EventSeverity::Debug,
+            TaskEventType::TaskStatus | TaskEventType::DeviceConnection => match status {
+                Some("failed" | "timed_out") => EventSeverity::Error,
+                Some("cancelled" | "reconnecting") => EventSeverity::Warning,
+                _ => EventSeverity::Info,
+            },
+            _ => EventSeverity::Info,
+        }
+    }
+    
+    /// 事件表示队列任务已结束时返回最终状态
+    ///
+    /// MAA任务链完成不代表队列任务结束（一个队列任务可能包含多个任务链），
+    /// 队列任务的结束以任务状态存储的状态变更为准。
+    pub fn terminal_status(&self) -> Option<TaskStatus> {
+        match self.event_type {
+            TaskEventType::Completed => Some(TaskStatus::Succeeded),
+            TaskEventType::Failed => Some(TaskStatus::Failed),
+            TaskEventType::Cancelled => Some(TaskStatus::Cancelled),
+            TaskEventType::TimedOut => Some(TaskStatus::TimedOut),
+            TaskEventType::TaskStatus => self.data.as_ref()
+                .and_then(|data| data.get("status"))
+                .and_then(|status| serde_json::from_value::<TaskStatus>(status.clone()).ok())
+                .filter(|status| status.is_terminal()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl TaskProgressEvent {
+    /// 测试用事件，不带数据
+    pub fn for_test(task_id: i32, task_type: &str, event_type: TaskEventType) -> Self {
+        Self {
+            task_id,
+            task_type: task_type.to_string(),
+            event_type,
+            message: String::new(),
+            data: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// 设置事件数据
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
 /// 重构后的MAA工作者 - V2版本
 /// 
 /// 变更：
-/// 1. 内部管理任务状态，不依赖全局状态
+/// 1. 任务状态写入统一的任务状态存储
 /// 2. 支持SSE事件推送
 /// 3. 简化任务处理逻辑
 pub struct MaaWorkerV2 {
     core: MaaCore,
-    /// 内部任务状态映射
-    task_statuses: HashMap<i32, TaskStatus>,
     /// SSE事件广播器
     pub event_broadcaster: broadcast::Sender<TaskProgressEvent>,
+    /// 紧急返回时从队列中取消的任务，由紧急返回任务汇报
+    drained_tasks: Vec<Value>,
 }
 
+/// 紧急返回后等待MAA停止运行的最长时间
+const EMERGENCY_SETTLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// 紧急返回、重连后轮询MAA状态的间隔
+const STATUS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
 impl MaaWorkerV2 {
     /// 创建新的MAA工作者（返回事件广播器的发送端）
     pub fn new() -> (Self, broadcast::Sender<TaskProgressEvent>) {
@@ -51,8 +262,8 @@ impl MaaWorkerV2 {
         
         let worker = Self {
             core: MaaCore::new(),
-            task_statuses: HashMap::new(),
             event_broadcaster: event_broadcaster.clone(),
+            drained_tasks: Vec::new(),
         };
         
         (worker, event_broadcaster)
@@ -60,16 +271,7 @@ impl MaaWorkerV2 {
     
     /// 处理MAA Core回调事件并转发到SSE
     pub fn handle_maa_callback(&mut self, task_id: i32, msg_code: i32, details: Value) {
-        let event_type = match msg_code {
-            10001 => "taskchain_started",
-            10002 => "taskchain_completed", 
-            10000 => "taskchain_failed",
-            20001 => "subtask_started",
-            20002 => "subtask_completed",
-            20003 => "subtask_info",
-            20000 => "subtask_failed",
-            _ => "unknown"
-        };
+        let event_type = TaskEventType::from_callback(msg_code);
         
         // 提取任务详情
         let task_name = details.get("details")
@@ -98,33 +300,14 @@ impl MaaWorkerV2 {
             _ => format!("未知事件: {}", task_name)
         };
         
-        // 更新内部任务状态
-        if let Some(status) = self.task_statuses.get_mut(&task_id) {
-            match msg_code {
-                10002 => {
-                    // 任务链完成，标记为成功完成
-                    status.mark_completed(details.clone());
-                },
-                10000 | 20000 => {
-                    // 任务失败
-                    let error = details.get("what")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("未知错误")
-                        .to_string();
-                    status.mark_failed(error);
-                },
-                _ => {
-                    // 进度更新 - 使用status字段存储进度信息
-                    status.status = format!("running: {}", message);
-                }
-            }
-        }
+        // 更新任务状态存储
+        task_status::handle_maa_callback(task_id, msg_code, details.clone());
         
         // 转发到SSE系统
         let sse_event = TaskProgressEvent {
             task_id,
             task_type: task_chain.to_string(),
-            event_type: event_type.to_string(),
+            event_type,
             message,
             data: Some(details),
             timestamp: Utc::now(),
@@ -142,26 +325,74 @@ impl MaaWorkerV2 {
         
         Self {
             core: MaaCore::new(),
-            task_statuses: HashMap::new(),
             event_broadcaster,
+            drained_tasks: Vec::new(),
         }
     }
     
     /// 启动MAA工作者主循环 - V2版本（单队列+优先级）
+    ///
+    /// 不带监督运行，MAA实例销毁后直接退出；服务器使用 `supervisor::run_supervised_worker`。
     pub async fn run(mut self, mut task_rx: MaaTaskReceiver) {
+        let exit = self.run_until_exit(&mut task_rx).await;
+        warn!("MAA工作者V2退出 - {}", exit);
+    }
+    
+    /// 运行工作者主循环直到任务队列关闭或MAA实例被销毁
+    ///
+    /// 只借用任务队列，退出后监督者可以用新的工作者继续消费同一个队列。
+    pub async fn run_until_exit(&mut self, task_rx: &mut MaaTaskReceiver) -> WorkerExit {
         info!("MAA工作者V2启动，开始处理统一优先级任务队列");
         
-        while let Some(task) = task_rx.recv().await {
-            debug!("收到MAA任务: {} (ID: {}, 优先级: {:?})", task.task_type, task.task_id, task.priority);
+        // 超时看门狗：定期检查运行中的任务是否超出预算
+        let mut watchdog = tokio::time::interval(std::time::Duration::from_millis(
+            CONFIG.task_timeout.check_interval_ms.max(100)
+        ));
+        watchdog.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        
+        // 设备连接健康检查
+        let mut health_check = tokio::time::interval(std::time::Duration::from_millis(
+            CONFIG.device.health_check_interval_ms.max(500)
+        ));
+        health_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        
+        loop {
+            tokio::select! {
+                task = task_rx.recv() => {
+                    let Some(task) = task else { return WorkerExit::QueueClosed };
+                    debug!("收到MAA任务: {} (ID: {}, 优先级: {:?})", task.task_type, task.task_id, task.priority);
+                    
+                    // 紧急返回（默认）和停止全部任务会清空排队中的任务，需要在执行前从队列取出
+                    let drain_reason = match task.task_type.as_str() {
+                        "maa_emergency_home" if emergency_stops_queue(&task.parameters) => Some("紧急返回主界面，排队中的任务已取消"),
+                        "maa_stop_tasks" => Some("已停止全部任务，排队中的任务已取消"),
+                        _ => None,
+                    };
+                    if let Some(reason) = drain_reason {
+                        self.drained_tasks = Self::cancel_pending_tasks(task_rx, reason);
+                    }
+                    
+                    // 处理任务
+                    let result = self.handle_task(task).await;
+                    if let Err(e) = result {
+                        error!("任务处理失败: {:?}", e);
+                    }
+                },
+                _ = watchdog.tick() => {
+                    self.check_task_timeouts();
+                },
+                _ = health_check.tick() => {
+                    if supervisor::maa_destroyed() {
+                        return WorkerExit::MaaDestroyed;
+                    }
+                    self.supervise_connection().await;
+                },
+            }
             
-            // 处理任务
-            let result = self.handle_task(task).await;
-            if let Err(e) = result {
-                error!("任务处理失败: {:?}", e);
+            if supervisor::maa_destroyed() {
+                return WorkerExit::MaaDestroyed;
             }
         }
-        
-        warn!("MAA工作者V2退出 - 任务队列已关闭");
     }
     
     /// 处理单个MAA任务 - 包含完整的SSE推送和状态管理
@@ -170,35 +401,26 @@ impl MaaWorkerV2 {
         let task_type = task.task_type.clone();
         let start_time = Utc::now();
         
-        // 创建任务状态
-        let mut status = TaskStatus::new(task_id, task_type.clone());
-        status.mark_running();
-        self.task_statuses.insert(task_id, status);
-        
-        // 不再手动发送started事件 - 由MAA Core回调统一处理
+        // 状态变更事件由任务状态存储统一发布
         info!("开始执行任务: {} (task_id: {})", task_type, task_id);
+        task_status::start_task(task_id);
         
-        // 执行具体的MAA任务
+        // 执行具体的MAA任务，期间提交的MAA任务链都绑定到该队列任务
+        self.core.set_current_queue_task(Some(task_id));
         let result = self.execute_maa_task(&task).await;
+        self.core.set_current_queue_task(None);
+        self.sync_task_status(task_id, &result);
         
-        // 更新任务状态 - 不再手动发送完成/失败事件，由MAA Core回调统一处理
-        if let Some(status) = self.task_statuses.get_mut(&task_id) {
-            match &result {
-                Ok(task_result) => {
-                    if task_result.success {
-                        status.mark_completed(task_result.result.clone().unwrap_or(json!({})));
-                        info!("任务 {} 执行成功 (task_id: {})", task_type, task_id);
-                    } else {
-                        let error = task_result.error.clone().unwrap_or("未知错误".to_string());
-                        status.mark_failed(error.clone());
-                        warn!("任务 {} 执行失败 (task_id: {}): {}", task_type, task_id, error);
-                    }
-                },
-                Err(e) => {
-                    let error = format!("{}", e);
-                    status.mark_failed(error.clone());
-                    error!("任务 {} 发生错误 (task_id: {}): {}", task_type, task_id, error);
-                }
+        match &result {
+            Ok(task_result) if task_result.success => {
+                info!("任务 {} 执行成功 (task_id: {})", task_type, task_id);
+            },
+            Ok(task_result) => {
+                warn!("任务 {} 执行失败 (task_id: {}): {}", task_type, task_id,
+                      task_result.error.as_deref().unwrap_or("未知错误"));
+            },
+            Err(e) => {
+                error!("任务 {} 发生错误 (task_id: {}): {}", task_type, task_id, e);
             }
         }
         
@@ -223,6 +445,84 @@ impl MaaWorkerV2 {
         Ok(())
     }
     
+    /// 超时看门狗：将超出预算的运行中任务标记为超时
+    ///
+    /// 推送 `task_timeout` 事件并唤醒等待者；超时任务的任务链正在MAA中执行时，按配置停止MAA并返回主界面。
+    fn check_task_timeouts(&mut self) {
+        let now = Utc::now();
+        let timed_out = find_timed_out_tasks(&task_status::get_running_tasks(), now);
+        if timed_out.is_empty() {
+            return;
+        }
+        // 在标记超时前确定正在执行的任务链属于哪个队列任务
+        let running_owner = task_mapping::running_queue_task_id();
+        
+        for (task_id, elapsed, budget) in &timed_out {
+            let Some(task) = task_status::get_task_status(*task_id) else { continue };
+            let error = format!("任务执行超时: 已运行{}秒，预算{}秒", elapsed, budget);
+            if !task_status::timeout_task(*task_id, error.clone()) {
+                continue;
+            }
+            
+            let _ = self.event_broadcaster.send(TaskProgressEvent {
+                task_id: *task_id,
+                task_type: task.task_type.clone(),
+                event_type: TaskEventType::TimedOut,
+                message: error.clone(),
+                data: Some(json!({
+                    "elapsed_seconds": elapsed,
+                    "budget_seconds": budget,
+                    "maa_task_ids": super::task_mapping::maa_task_ids(*task_id),
+                })),
+                timestamp: now,
+            });
+        }
+        
+        // 卡住的任务链会阻塞后续任务，停止MAA并尝试回到主界面；
+        // 其他任务的任务链正在正常执行时不打断
+        let owns_running_chain = running_owner.is_some_and(|owner| timed_out.iter().any(|(task_id, _, _)| *task_id == owner));
+        if !owns_running_chain {
+            debug!("超时任务的任务链未在执行，不停止MAA (当前执行: {:?})", running_owner);
+            return;
+        }
+        if CONFIG.task_timeout.stop_on_timeout && self.core.is_initialized() {
+            match self.core.stop() {
+                Ok(()) => {
+                    let timed_out_ids: Vec<i32> = timed_out.iter().map(|(task_id, _, _)| *task_id).collect();
+                    let failed = abort_chains_after_stop(&timed_out_ids, task_status::get_all_tasks());
+                    if !failed.is_empty() {
+                        warn!("MAA停止后清空了其他任务的任务链，任务 {:?} 已标记失败", failed);
+                    }
+                },
+                Err(e) => warn!("超时后停止MAA失败: {}", e),
+            }
+        }
+        if CONFIG.task_timeout.back_to_home_on_timeout && self.core.is_initialized() {
+            if let Err(e) = self.core.back_to_home() {
+                warn!("超时后返回主界面失败: {}", e);
+            }
+        }
+    }
+    
+    /// 根据执行结果更新任务状态
+    ///
+    /// 提交了MAA任务链的任务由回调结束；同步任务（截图、查询等）没有回调，在此直接结束。
+    fn sync_task_status(&self, task_id: i32, result: &Result<TaskResult>) {
+        match result {
+            Ok(task_result) if task_result.success => {
+                if super::task_mapping::maa_task_ids(task_id).is_empty() {
+                    task_status::complete_task(task_id, task_result.result.clone().unwrap_or(json!({})));
+                }
+            },
+            Ok(task_result) => {
+                task_status::fail_task(task_id, task_result.error.clone().unwrap_or("未知错误".to_string()));
+            },
+            Err(e) => {
+                task_status::fail_task(task_id, format!("{}", e));
+            },
+        }
+    }
+    
     /// 执行具体的MAA任务 - 减少JSON序列化，直接处理参数
     async fn execute_maa_task(&mut self, task: &MaaTask) -> Result<TaskResult> {
         let start_time = Utc::now();
@@ -239,9 +539,13 @@ impl MaaWorkerV2 {
                 .unwrap_or_else(|_| "127.0.0.1:1717".to_string());
             info!("连接到设备: {}", device_address);
             self.core.connect(&device_address)?;
+            connection::mark_connected(&device_address);
         }
         
-        // 根据任务类型执行不同的操作 - 优化：减少JSON序列化
+        // MAA任务链类型和参数与试运行使用同一套构造逻辑
+        let plan = task_builder::build_maa_task(&task.task_type, &task.parameters);
+        
+        // 根据任务类型执行不同的操作
         let result = match task.task_type.as_str() {
             "maa_take_screenshot" => {
                 debug!("执行截图任务");
@@ -268,13 +572,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_bool())
                     .unwrap_or(true);
                 
-                // 优化：直接构造参数字符串，减少JSON序列化
-                let params = format!(
-                    r#"{{"enable": true, "client_type": "{}", "start_app": {}}}"#,
-                    client_type, start_app
-                );
-                
-                match self.core.execute_task("StartUp", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "client_type": client_type,
@@ -293,17 +591,14 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_i64())
                     .unwrap_or(1);
                 
-                // 优化：直接构造参数字符串
-                let params = format!(
-                    r#"{{"enable": true, "stage": "{}", "medicine": {}}}"#,
-                    stage, times
-                );
-                
-                match self.core.execute_task("Fight", &params) {
+                // 药剂和源石的使用上限已按预算限制
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "stage": stage,
                         "times": times,
+                        "medicine": plan.as_ref().map(|plan| &plan.params["medicine"]),
+                        "stone": plan.as_ref().map(|plan| &plan.params["stone"]),
                         "status": "战斗任务已提交到MAA Core"
                     })),
                     Err(e) => Err(anyhow!("战斗任务失败: {}", e))
@@ -315,23 +610,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_str())
                     .unwrap_or("full_auto");
                 
-                // 根据操作模式构造MAA Core期望的参数格式
-                let params = match operation_mode {
-                    "full_auto" => r#"{"enable": true, "mode": 0, "facility": ["Mfg", "Trade", "Power", "Control", "Reception", "Office", "Dorm"], "drones": "Money"}"#.to_string(),
-                    "collect_only" => r#"{"enable": true, "mode": 1, "facility": ["Mfg", "Trade", "Power", "Control", "Reception", "Office", "Dorm"]}"#.to_string(),
-                    "custom" => {
-                        // 从参数中提取自定义设施列表
-                        let facilities = task.parameters.get("facilities")
-                            .and_then(|v| v.as_array())
-                            .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
-                            .unwrap_or_else(|| vec!["Mfg", "Trade", "Power", "Control"]);
-                        let facilities_json = facilities.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(",");
-                        format!(r#"{{"enable": true, "mode": 0, "facility": [{}], "drones": "Money"}}"#, facilities_json)
-                    },
-                    _ => r#"{"enable": true, "mode": 0, "facility": ["Mfg", "Trade", "Power", "Control", "Reception", "Office", "Dorm"], "drones": "Money"}"#.to_string(),
-                };
-                
-                match self.core.execute_task("Infrast", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "operation_mode": operation_mode,
@@ -352,12 +631,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_bool())
                     .unwrap_or(true);
                 
-                let params = format!(
-                    r#"{{"enable": true, "select": [3, 4, 5, 6], "confirm": [3, 4, 5, 6], "times": {}, "set_time": true, "expedite": {}, "skip_robot": {}}}"#,
-                    max_times, expedite, skip_robot
-                );
-                
-                match self.core.execute_task("Recruit", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "max_times": max_times,
@@ -374,9 +648,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_str())
                     .unwrap_or("all");
                 
-                let params = r#"{"enable": true}"#;
-                
-                match self.core.execute_task("Award", params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "award_type": award_type,
@@ -387,9 +659,7 @@ impl MaaWorkerV2 {
             },
             "maa_closedown" => {
                 debug!("执行游戏关闭任务");
-                let params = r#"{"enable": true}"#;
-                
-                match self.core.execute_task("CloseDown", params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "status": "游戏关闭任务已提交到MAA Core"
@@ -406,12 +676,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_i64())
                     .unwrap_or(0);
                 
-                let params = format!(
-                    r#"{{"enable": true, "theme": "{}", "mode": {}}}"#,
-                    theme, mode
-                );
-                
-                match self.core.execute_task("Roguelike", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "theme": theme,
@@ -427,17 +692,26 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
                 
-                let params = format!(
-                    r#"{{"enable": true, "filename": "{}"}}"#,
-                    filename
-                );
-                
-                match self.core.execute_task("Copilot", &params) {
-                    Ok(task_id) => Ok(json!({
-                        "maa_task_id": task_id,
-                        "filename": filename,
-                        "status": "作业任务已提交到MAA Core"
-                    })),
+                match self.submit_plan(plan.as_ref()) {
+                    Ok(task_id) => {
+                        // 带作业ID时登记执行，由MAA回调记录通关结果
+                        if let Some(copilot_id) = task.parameters.get("copilot_id").and_then(|v| v.as_str()) {
+                            let roster = task.parameters.get("roster")
+                                .and_then(|v| v.as_array())
+                                .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+                                .unwrap_or_default();
+                            crate::copilot_matcher::feedback::track_copilot_run(
+                                task_id,
+                                copilot_id.to_string(),
+                                crate::copilot_matcher::feedback::roster_key(roster),
+                            );
+                        }
+                        Ok(json!({
+                            "maa_task_id": task_id,
+                            "filename": filename,
+                            "status": "作业任务已提交到MAA Core"
+                        }))
+                    },
                     Err(e) => Err(anyhow!("作业任务失败: {}", e))
                 }
             },
@@ -447,12 +721,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
                 
-                let params = format!(
-                    r#"{{"enable": true, "filename": "{}"}}"#,
-                    filename
-                );
-                
-                match self.core.execute_task("SSSCopilot", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "filename": filename,
@@ -470,12 +739,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_i64())
                     .unwrap_or(0);
                 
-                let params = format!(
-                    r#"{{"enable": true, "theme": "{}", "mode": {}}}"#,
-                    theme, mode
-                );
-                
-                match self.core.execute_task("Reclamation", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "theme": theme,
@@ -492,13 +756,7 @@ impl MaaWorkerV2 {
                     .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
                     .unwrap_or_else(|| vec!["龙门币", "赤金"]);
                 
-                let buy_first_json = buy_first.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(",");
-                let params = format!(
-                    r#"{{"enable": true, "buy_first": [{}]}}"#,
-                    buy_first_json
-                );
-                
-                match self.core.execute_task("Mall", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "buy_first": buy_first,
@@ -509,9 +767,7 @@ impl MaaWorkerV2 {
             },
             "maa_depot_management" => {
                 debug!("执行仓库管理任务");
-                let params = r#"{"enable": true}"#;
-                
-                match self.core.execute_task("Depot", params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "status": "仓库管理任务已提交到MAA Core"
@@ -521,9 +777,7 @@ impl MaaWorkerV2 {
             },
             "maa_operator_box" => {
                 debug!("执行干员管理任务");
-                let params = r#"{"enable": true}"#;
-                
-                match self.core.execute_task("OperBox", params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "status": "干员管理任务已提交到MAA Core"
@@ -536,13 +790,7 @@ impl MaaWorkerV2 {
                 let task_name = task.parameters.get("task_name")
                     .and_then(|v| v.as_str())
                     .unwrap_or("CustomTask");
-                let custom_params = task.parameters.get("params")
-                    .cloned()
-                    .unwrap_or(json!({}));
-                
-                let params_str = custom_params.to_string();
-                
-                match self.core.execute_task(task_name, &params_str) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "task_name": task_name,
@@ -557,12 +805,7 @@ impl MaaWorkerV2 {
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
                 
-                let params = format!(
-                    r#"{{"enable": true, "filename": "{}"}}"#,
-                    video_path
-                );
-                
-                match self.core.execute_task("VideoRecognition", &params) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "video_path": video_path,
@@ -579,8 +822,7 @@ impl MaaWorkerV2 {
                 
                 match operation {
                     "restart" => {
-                        let params = r#"{"enable": true}"#;
-                        match self.core.execute_task("StartUp", params) {
+                        match self.submit_plan(plan.as_ref()) {
                             Ok(task_id) => Ok(json!({
                                 "maa_task_id": task_id,
                                 "operation": "restart",
@@ -590,8 +832,7 @@ impl MaaWorkerV2 {
                         }
                     },
                     "stop" => {
-                        let params = r#"{"enable": true}"#;
-                        match self.core.execute_task("CloseDown", params) {
+                        match self.submit_plan(plan.as_ref()) {
                             Ok(task_id) => Ok(json!({
                                 "maa_task_id": task_id,
                                 "operation": "stop",
@@ -620,54 +861,22 @@ impl MaaWorkerV2 {
             },
             "maa_adjust_task_params" => {
                 debug!("动态调整任务参数");
-                use crate::maa_core::basic_ops::{set_task_params, adjust_task_strategy};
-                
-                let task_id = task.parameters.get("task_id")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(1) as i32;
-                let strategy = task.parameters.get("strategy")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("reduce_difficulty");
-                    
-                if strategy == "custom" {
-                    // 自定义参数调整
-                    let custom_params = task.parameters.get("custom_params")
-                        .cloned()
-                        .unwrap_or_else(|| json!({}));
-                    match set_task_params(task_id, custom_params).await {
-                        Ok(result) => Ok(result),
-                        Err(e) => Err(anyhow!("自定义参数调整失败: {}", e))
-                    }
-                } else {
-                    // 智能策略调整
-                    let context = task.parameters.get("context")
-                        .cloned()
-                        .unwrap_or_else(|| json!({}));
-                    match adjust_task_strategy(task_id, strategy, context).await {
-                        Ok(result) => Ok(result),
-                        Err(e) => Err(anyhow!("智能策略调整失败: {}", e))
-                    }
-                }
+                self.adjust_task_params(&task.parameters)
             },
             "maa_emergency_home" => {
                 debug!("紧急返回主界面");
-                use crate::maa_core::basic_ops::back_to_home;
-                
-                let reason = task.parameters.get("reason")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("user_request");
-                
-                info!("紧急返回主界面，原因: {}", reason);
-                match back_to_home().await {
-                    Ok(result) => Ok(result),
-                    Err(e) => Err(anyhow!("紧急返回失败: {}", e))
-                }
+                let drained = std::mem::take(&mut self.drained_tasks);
+                self.emergency_home(task.task_id, &task.parameters, drained).await
+            },
+            "maa_stop_tasks" => {
+                debug!("停止全部任务");
+                let drained = std::mem::take(&mut self.drained_tasks);
+                self.stop_tasks(task.task_id, &task.parameters, drained)
             },
             _ => {
-                // 通用任务处理 - 优化：直接传递参数，避免重复序列化
+                // 通用任务处理 - 参数原样传给MAA Core
                 debug!("执行通用任务: {}", task.task_type);
-                let params_str = task.parameters.to_string();
-                match self.core.execute_task(&task.task_type, &params_str) {
+                match self.submit_plan(plan.as_ref()) {
                     Ok(task_id) => Ok(json!({
                         "maa_task_id": task_id,
                         "task_type": task.task_type,
@@ -701,45 +910,429 @@ impl MaaWorkerV2 {
         }
     }
     
-    /// 获取任务状态（内部状态管理）
-    pub fn get_task_status(&self, task_id: i32) -> Option<&TaskStatus> {
-        self.task_statuses.get(&task_id)
+    /// 把构造好的MAA任务提交到MAA Core，返回MAA任务ID
+    ///
+    /// 提交成功后按参数中的药剂/源石/投资上限预留预算，任务链结束时释放。
+    fn submit_plan(&mut self, plan: Option<&MaaTaskPlan>) -> Result<i32> {
+        let plan = plan.ok_or_else(|| anyhow!("该任务不提交MAA任务链"))?;
+        let maa_task_id = self.core.execute_task(&plan.maa_task_type, &plan.params.to_string())?;
+        budget::reserve_maa_params(maa_task_id, &plan.params);
+        Ok(maa_task_id)
+    }
+    
+    /// 设备连接监控
+    ///
+    /// 回调报告断线或 `connected()` 为false时，中断运行中的任务并按退避策略重连；
+    /// 重连成功后重新执行可安全重跑的任务，失败则将其标记为失败。
+    async fn supervise_connection(&mut self) {
+        // 尚未连接过设备时，连接在执行任务时建立
+        let Some(address) = self.core.device_address().map(str::to_string) else { return };
+        connection::mark_checked();
+        if self.core.refresh_connection() || connection::connection_state() == ConnectionState::Failed {
+            return;
+        }
+        
+        warn!("检测到设备连接中断: {}", address);
+        connection::mark_disconnected("设备连接中断");
+        let requeued = self.interrupt_running_tasks();
+        
+        match self.reconnect_with_backoff(&address).await {
+            Ok(()) => {
+                // 清除MAA Core中随断线失效的任务链，再按原顺序重新执行
+                if let Err(e) = self.core.stop() {
+                    warn!("清理失效任务链失败: {}", e);
+                }
+                for status in requeued {
+                    self.rerun_task(status).await;
+                }
+            },
+            Err(e) => {
+                error!("设备重连失败: {}", e);
+                for status in requeued {
+                    task_status::fail_task(status.task_id, format!("设备重连失败，任务未能重新执行: {}", e));
+                }
+            }
+        }
+    }
+    
+    /// 中断运行中的任务：可安全重跑的任务重新排队，其余标记为失败
+    fn interrupt_running_tasks(&mut self) -> Vec<MaaTaskStatus> {
+        let mut requeued = Vec::new();
+        let running = task_status::get_all_tasks().into_iter()
+            .filter(|t| matches!(t.status, TaskStatus::Running | TaskStatus::Paused));
+        
+        for status in running {
+            if connection::is_requeue_safe(&status.task_type)
+                && task_status::requeue_task(status.task_id, "设备连接中断，等待重连后重新执行".to_string())
+            {
+                // 旧的MAA任务链已随断线失效，解除映射避免迟到的回调影响重新执行的任务
+                task_mapping::release_queue_task(status.task_id);
+                requeued.push(status);
+            } else {
+                task_status::fail_task(status.task_id, "设备连接中断，该任务无法自动重新执行".to_string());
+            }
+        }
+        requeued
+    }
+    
+    /// 按 `DeviceConfig` 的重试次数和连接超时退避重连
+    async fn reconnect_with_backoff(&mut self, address: &str) -> Result<()> {
+        let attempts = CONFIG.device.retry_attempts.max(1);
+        let timeout_ms = CONFIG.device.connection_timeout_ms;
+        let mut last_error = String::new();
+        
+        for attempt in 1..=attempts {
+            tokio::time::sleep(connection::reconnect_backoff(attempt, timeout_ms)).await;
+            connection::mark_reconnect_attempt(attempt, None);
+            info!("尝试重连设备 {} (第 {}/{} 次)", address, attempt, attempts);
+            
+            match self.core.connect(address) {
+                Ok(_) if self.wait_until_connected(timeout_ms).await => {
+                    connection::mark_connected(address);
+                    info!("设备重连成功: {}", address);
+                    return Ok(());
+                },
+                Ok(_) => last_error = format!("{}ms 内未确认设备连接", timeout_ms),
+                Err(e) => last_error = e.to_string(),
+            }
+            warn!("第 {} 次重连失败: {}", attempt, last_error);
+        }
+        
+        connection::mark_failed(last_error.clone());
+        Err(anyhow!("重连 {} 次后仍未连接: {}", attempts, last_error))
+    }
+    
+    /// 等待MAA Core确认设备已连接
+    async fn wait_until_connected(&self, timeout_ms: u64) -> bool {
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+        loop {
+            if self.core.device_alive() {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+    }
+    
+    /// 重新执行断线前被中断的任务（沿用原队列任务ID）
+    async fn rerun_task(&mut self, status: MaaTaskStatus) {
+        info!("重新执行断线中断的任务: {} (task_id: {})", status.task_type, status.task_id);
+        let (execution_mode, priority) = classify_task(&status.task_type);
+        // 原请求的响应已返回，重新执行的结果只写入任务状态
+        let (response_tx, _response_rx) = oneshot::channel();
+        let task = MaaTask {
+            task_id: status.task_id,
+            task_type: status.task_type,
+            parameters: status.parameters,
+            priority,
+            execution_mode,
+            created_at: Utc::now(),
+            response_tx,
+        };
+        if let Err(e) = self.handle_task(task).await {
+            error!("重新执行任务失败: {:?}", e);
+        }
+    }
+    
+    /// 取消队列中尚未执行的任务，返回被取消任务的摘要
+    fn cancel_pending_tasks(task_rx: &mut MaaTaskReceiver, reason: &str) -> Vec<Value> {
+        task_rx.drain_pending().into_iter()
+            .map(|pending| {
+                let reason = reason.to_string();
+                task_status::cancel_task(pending.task_id, reason.clone());
+                let _ = pending.response_tx.send(TaskResult {
+                    success: false,
+                    task_id: pending.task_id,
+                    result: None,
+                    error: Some(reason),
+                    completed_at: Utc::now(),
+                    duration_seconds: 0.0,
+                });
+                json!({
+                    "task_id": pending.task_id,
+                    "task_type": pending.task_type,
+                })
+            })
+            .collect()
+    }
+    
+    /// 紧急返回主界面
+    ///
+    /// 停止MAA当前运行的任务链并取消对应的队列任务，再执行MAA的返回主界面，
+    /// 等待MAA停止运行后截图确认，返回被中断和被取消的任务。
+    async fn emergency_home(&mut self, own_task_id: i32, parameters: &Value, drained: Vec<Value>) -> Result<Value> {
+        let reason = parameters.get("reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or("user_request");
+        let force = parameters.get("force")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        warn!("紧急返回主界面，原因: {}", reason);
+        
+        // 停止MAA当前运行的任务链
+        let interrupted_tasks: Vec<MaaTaskStatus> = task_status::get_all_tasks().into_iter()
+            .filter(|t| t.task_id != own_task_id && matches!(t.status, TaskStatus::Running | TaskStatus::Paused))
+            .collect();
+        if let Err(e) = self.core.stop() {
+            if !force {
+                return Err(anyhow!("停止MAA任务失败，未返回主界面: {}", e));
+            }
+            warn!("停止MAA任务失败，强制继续返回主界面: {}", e);
+        }
+        
+        let interrupted = cancel_stopped_tasks(&format!("紧急返回主界面中断: {}", reason), interrupted_tasks);
+        
+        self.core.back_to_home()
+            .map_err(|e| anyhow!("紧急返回失败: {}", e))?;
+        
+        // 等待MAA结束返回主界面的操作后截图确认
+        let deadline = tokio::time::Instant::now() + EMERGENCY_SETTLE_TIMEOUT;
+        while self.core.get_status().running && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+        let maa_running = self.core.get_status().running;
+        let screenshot = match self.core.screenshot() {
+            Ok(image_data) if !image_data.is_empty() => match super::screenshot::save_maa_screenshot(image_data) {
+                Ok(info) => Some(info.id),
+                Err(e) => {
+                    warn!("紧急返回截图保存失败: {}", e);
+                    None
+                }
+            },
+            Ok(_) => None,
+            Err(e) => {
+                warn!("紧急返回截图失败: {}", e);
+                None
+            }
+        };
+        let confirmed = !maa_running && screenshot.is_some();
+        
+        info!("紧急返回完成: 中断 {} 个任务，取消 {} 个排队任务，确认: {}", interrupted.len(), drained.len(), confirmed);
+        Ok(json!({
+            "action": "emergency_home",
+            "reason": reason,
+            "status": if confirmed { "home_confirmed" } else { "home_requested" },
+            "confirmed": confirmed,
+            "maa_running": maa_running,
+            "screenshot_id": screenshot,
+            "interrupted_tasks": interrupted,
+            "cancelled_pending_tasks": drained,
+            "timestamp": Utc::now().to_rfc3339()
+        }))
+    }
+    
+    /// 停止全部任务
+    ///
+    /// 停止MAA中的全部任务链，取消运行中（或暂停）的队列任务并释放其任务链，
+    /// 返回被中断和被取消的排队任务。
+    fn stop_tasks(&mut self, own_task_id: i32, parameters: &Value, drained: Vec<Value>) -> Result<Value> {
+        let reason = parameters.get("reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or("user_request");
+        warn!("停止全部任务，原因: {}", reason);
+        
+        let running_tasks: Vec<MaaTaskStatus> = task_status::get_all_tasks().into_iter()
+            .filter(|t| t.task_id != own_task_id && matches!(t.status, TaskStatus::Running | TaskStatus::Paused))
+            .collect();
+        if self.core.is_initialized() {
+            self.core.stop()
+                .map_err(|e| anyhow!("停止MAA任务失败: {}", e))?;
+        }
+        let interrupted = cancel_stopped_tasks(&format!("任务已停止: {}", reason), running_tasks);
+        
+        info!("停止全部任务完成: 中断 {} 个任务，取消 {} 个排队任务", interrupted.len(), drained.len());
+        Ok(json!({
+            "action": "stop_tasks",
+            "reason": reason,
+            "status": "stopped",
+            "interrupted_tasks": interrupted,
+            "cancelled_pending_tasks": drained,
+            "timestamp": Utc::now().to_rfc3339()
+        }))
+    }
+    
+    /// 运行中调整任务参数
+    ///
+    /// `task_id` 为队列任务ID，解析为MAA任务ID后通过 `AsstSetTaskParams` 下发，
+    /// 返回每个MAA任务链实际生效的参数差异。
+    fn adjust_task_params(&mut self, parameters: &Value) -> Result<Value> {
+        let task_id = parameters.get("task_id")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| anyhow!("缺少要调整的任务ID (task_id)"))? as i32;
+        let strategy = parameters.get("strategy")
+            .and_then(|v| v.as_str())
+            .unwrap_or("reduce_difficulty");
+        
+        // 自定义参数逐项校验；智能策略只应用目标任务支持的字段
+        let (mut requested, strict) = if strategy == "custom" {
+            (parameters.get("custom_params").cloned().unwrap_or_else(|| json!({})), true)
+        } else {
+            let context = parameters.get("context").cloned().unwrap_or_else(|| json!({}));
+            (task_params::strategy_params(strategy, &context)?, false)
+        };
+        
+        budget::limit_adjusted_params(&mut requested);
+        let plan = task_params::plan_adjustment(task_id, &requested, strict)?;
+        let mut applied = Vec::new();
+        for adjustment in plan.iter().filter(|adjustment| !adjustment.is_empty()) {
+            self.core.set_task_params(adjustment.maa_task_id, &adjustment.params.to_string())
+                .map_err(|e| anyhow!("MAA任务 {} 参数调整失败: {}", adjustment.maa_task_id, e))?;
+            task_mapping::merge_maa_task_params(adjustment.maa_task_id, &adjustment.params);
+            if let Some(current) = task_mapping::maa_task_params(adjustment.maa_task_id) {
+                budget::reserve_maa_params(adjustment.maa_task_id, &current.params);
+            }
+            applied.push(adjustment);
+        }
+        
+        info!("任务 {} 参数调整完成，生效 {} 个MAA任务链", task_id, applied.len());
+        Ok(json!({
+            "task_id": task_id,
+            "strategy": strategy,
+            "status": if applied.is_empty() { "unchanged" } else { "updated" },
+            "applied": applied,
+            "message": if applied.is_empty() {
+                "参数与当前值一致，未做修改".to_string()
+            } else {
+                format!("已调整 {} 个MAA任务链的参数", applied.len())
+            },
+        }))
+    }
+    
+    /// 获取任务状态
+    pub fn get_task_status(&self, task_id: i32) -> Option<MaaTaskStatus> {
+        task_status::get_task_status(task_id)
     }
     
     /// 获取所有任务状态
-    pub fn get_all_task_statuses(&self) -> &HashMap<i32, TaskStatus> {
-        &self.task_statuses
+    pub fn get_all_task_statuses(&self) -> Vec<MaaTaskStatus> {
+        task_status::get_all_tasks()
     }
     
     /// 清理旧的已完成任务状态
     pub fn cleanup_old_tasks(&mut self, max_age_minutes: i64) {
-        let cutoff_time = Utc::now() - chrono::Duration::minutes(max_age_minutes);
-        self.task_statuses.retain(|_, status| {
-            status.completed_at.is_none() || 
-            status.completed_at.unwrap() > cutoff_time
-        });
-        
-        info!("清理了 {} 分钟前的旧任务状态", max_age_minutes);
+        let removed = task_status::cleanup_finished_tasks(chrono::Duration::minutes(max_age_minutes));
+        info!("清理了 {} 分钟前的旧任务状态 {} 条", max_age_minutes, removed);
     }
 }
 
+/// 紧急返回是否同时清空排队中的任务（`stop_tasks`，默认true）
+fn emergency_stops_queue(parameters: &Value) -> bool {
+    parameters.get("stop_tasks").and_then(|v| v.as_bool()).unwrap_or(true)
+}
+
+/// 任务的超时预算（秒）
+///
+/// 单次执行的预算优先使用配置覆盖，否则为预估耗时 × `budget_factor`；带 `times` 的任务（战斗）按次数放大。
+pub fn timeout_budget_seconds(task_type: &str, parameters: &Value) -> u64 {
+    let single = CONFIG.task_timeout.budgets.get(task_type)
+        .copied()
+        .unwrap_or_else(|| (estimate_task_duration(task_type) as f64 * CONFIG.task_timeout.budget_factor.max(1.0)).ceil() as u64);
+    let times = parameters.get("times").and_then(|v| v.as_u64()).unwrap_or(1).max(1);
+    single.saturating_mul(times)
+}
+
+/// 找出超出预算的运行中任务，返回 (任务ID, 已运行秒数, 预算秒数)
+///
+/// 从MAA开始执行任务链（TaskChainStart）时计时，在MAA队列中等待的任务链不计入。
+fn find_timed_out_tasks(tasks: &[MaaTaskStatus], now: chrono::DateTime<Utc>) -> Vec<(i32, u64, u64)> {
+    tasks.iter()
+        .filter(|task| task.status == TaskStatus::Running)
+        .filter_map(|task| {
+            let elapsed = task.chain_elapsed_seconds(now)?;
+            let budget = timeout_budget_seconds(&task.task_type, &task.parameters);
+            (elapsed > budget).then_some((task.task_id, elapsed, budget))
+        })
+        .collect()
+}
+
+/// 超时停止MAA后清理被清空的任务链，返回候选任务中被连带标记失败的任务ID
+///
+/// `stop` 会清空MaaCore中排队的全部任务链：超时任务未执行的任务链不会再有回调，
+/// 其他运行中（或暂停）的队列任务的任务链也不会再开始，这些任务各自标记为失败。
+/// 涉及的队列任务一并释放预算预留、作业执行登记和ID映射。
+fn abort_chains_after_stop(timed_out: &[i32], tasks: impl IntoIterator<Item = MaaTaskStatus>) -> Vec<i32> {
+    let failed: Vec<i32> = tasks.into_iter()
+        .filter(|task| matches!(task.status, TaskStatus::Running | TaskStatus::Paused))
+        .filter(|task| !timed_out.contains(&task.task_id))
+        .filter(|task| !task_mapping::active_maa_task_ids(task.task_id).is_empty())
+        .filter(|task| task_status::fail_task(
+            task.task_id,
+            format!("其他任务超时后MAA已停止，任务链被清空: 超时任务 {:?}", timed_out),
+        ))
+        .map(|task| task.task_id)
+        .collect();
+    for task_id in timed_out.iter().chain(&failed) {
+        supervisor::release_queue_task_chains(*task_id);
+    }
+    failed
+}
+
+/// 将MAA停止后被中断的队列任务标记为已取消，返回被中断任务的摘要
+///
+/// 任务链已随 `stop` 清空，取消后一并释放预算预留、作业执行登记和ID映射，
+/// 迟到的停止回调不会再影响这些任务。
+fn cancel_stopped_tasks(reason: &str, tasks: impl IntoIterator<Item = MaaTaskStatus>) -> Vec<Value> {
+    let now = Utc::now();
+    tasks.into_iter()
+        .map(|t| {
+            let maa_task_ids = task_mapping::maa_task_ids(t.task_id);
+            task_status::cancel_task(t.task_id, reason.to_string());
+            supervisor::release_queue_task_chains(t.task_id);
+            json!({
+                "task_id": t.task_id,
+                "task_type": t.task_type,
+                "progress": t.progress,
+                "elapsed_seconds": t.elapsed_seconds(now),
+                "maa_task_ids": maa_task_ids,
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tokio::time::{timeout, Duration};
     
+    #[test]
+    fn test_event_type_vocabulary() {
+        for event_type in TaskEventType::ALL {
+            // SSE事件名称与序列化名称一致
+            assert_eq!(serde_json::to_value(event_type).unwrap(), json!(event_type.as_str()));
+            assert_eq!(TaskEventType::parse(event_type.as_str()), Some(*event_type));
+        }
+        assert_eq!(TaskEventType::from_callback(10002), TaskEventType::TaskchainCompleted);
+        assert_eq!(TaskEventType::from_callback(20004), TaskEventType::SubtaskStopped);
+        
+        let event = |event_type, data| TaskProgressEvent {
+            task_id: 1,
+            task_type: "test".to_string(),
+            event_type,
+            message: String::new(),
+            data,
+            timestamp: Utc::now(),
+        };
+        // 任务链完成不代表队列任务结束
+        assert_eq!(event(TaskEventType::TaskchainCompleted, None).terminal_status(), None);
+        assert_eq!(event(TaskEventType::TimedOut, None).terminal_status(), Some(TaskStatus::TimedOut));
+        assert_eq!(
+            event(TaskEventType::TaskStatus, Some(json!({"status": "cancelled"}))).terminal_status(),
+            Some(TaskStatus::Cancelled)
+        );
+        assert_eq!(event(TaskEventType::TaskStatus, Some(json!({"status": "running"}))).terminal_status(), None);
+    }
+    
     #[tokio::test]
     async fn test_worker_v2_creation() {
-        let (worker, broadcaster) = MaaWorkerV2::new();
-        
-        // 验证工作者和广播器都正确创建
-        assert_eq!(worker.task_statuses.len(), 0);
+        let (_worker, broadcaster) = MaaWorkerV2::new();
         
         // 测试发送事件
         let event = TaskProgressEvent {
             task_id: 1,
             task_type: "test".to_string(),
-            event_type: "started".to_string(),
+            event_type: TaskEventType::Started,
             message: "测试事件".to_string(),
             data: None,
             timestamp: Utc::now(),
@@ -754,16 +1347,84 @@ mod tests {
         let (mut worker, _broadcaster) = MaaWorkerV2::new();
         
         // 添加任务状态
-        let status = TaskStatus::new(1, "test_task".to_string());
-        worker.task_statuses.insert(1, status);
+        task_status::register_task(7001, "test_task".to_string(), json!({}));
         
         // 验证状态存在
-        assert!(worker.get_task_status(1).is_some());
-        assert_eq!(worker.get_all_task_statuses().len(), 1);
+        assert!(worker.get_task_status(7001).is_some());
+        assert!(worker.get_all_task_statuses().iter().any(|task| task.task_id == 7001));
         
         // 测试清理
-        worker.cleanup_old_tasks(0); // 清理所有任务
+        worker.cleanup_old_tasks(60); // 清理一小时前结束的任务
         // 由于任务还未完成，不应该被清理
-        assert_eq!(worker.get_all_task_statuses().len(), 1);
+        assert!(worker.get_task_status(7001).is_some());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_find_timed_out_tasks() {
+        let now = Utc::now();
+        let running = |task_id: i32, task_type: &str, parameters: Value, chain_secs: Option<i64>| {
+            let mut task = MaaTaskStatus::new(task_id, task_type.to_string(), parameters);
+            task.transition(TaskStatus::Running).unwrap();
+            task.started_at = Some(now - chrono::Duration::seconds(7200));
+            task.chain_started_at = chain_secs.map(|secs| now - chrono::Duration::seconds(secs));
+            task
+        };
+        
+        let stuck = running(1, "maa_take_screenshot", json!({}), Some(60));
+        let healthy = running(2, "maa_combat_enhanced", json!({}), Some(60));
+        // 任务链还在MAA队列中等待，不计时
+        let waiting = running(3, "maa_take_screenshot", json!({}), None);
+        // 多次战斗按次数放大预算
+        let long_fight = running(4, "maa_combat_enhanced", json!({"times": 5}), Some(3000));
+        let pending = MaaTaskStatus::new(5, "maa_take_screenshot".to_string(), json!({}));
+        
+        let timed_out = find_timed_out_tasks(&[stuck, healthy, waiting, long_fight, pending], now);
+        assert_eq!(timed_out, vec![(1, 60, timeout_budget_seconds("maa_take_screenshot", &json!({})))]);
+        assert_eq!(
+            timeout_budget_seconds("maa_combat_enhanced", &json!({"times": 5})),
+            timeout_budget_seconds("maa_combat_enhanced", &json!({})) * 5
+        );
+    }
+
+    #[test]
+    fn test_abort_chains_after_stop() {
+        // 4601 超时，4602 的任务链仍在MAA队列中等待，4603 的任务链已全部结束
+        for (task_id, maa_task_ids) in [(4601, vec![94601, 94602]), (4602, vec![94603]), (4603, vec![94604])] {
+            task_status::register_task(task_id, "maa_combat_enhanced".to_string(), json!({}));
+            task_status::start_task(task_id);
+            for maa_task_id in maa_task_ids {
+                task_mapping::bind_maa_task(task_id, maa_task_id);
+            }
+        }
+        task_mapping::mark_maa_task_finished(94604);
+        assert!(task_status::timeout_task(4601, "超时".to_string()));
+
+        let candidates = [4601, 4602, 4603].map(|id| task_status::get_task_status(id).unwrap());
+        assert_eq!(abort_chains_after_stop(&[4601], candidates), vec![4602]);
+        let failed = task_status::get_task_status(4602).unwrap();
+        assert_eq!(failed.status, TaskStatus::Failed);
+        assert!(failed.error.unwrap().contains("4601"));
+        assert_eq!(task_status::get_task_status(4601).unwrap().status, TaskStatus::TimedOut);
+        assert_eq!(task_status::get_task_status(4603).unwrap().status, TaskStatus::Running);
+        // 超时任务和连带失败的任务都释放映射
+        for maa_task_id in [94601, 94602, 94603] {
+            assert!(task_mapping::queue_task_id_for(maa_task_id).is_none());
+        }
+        assert_eq!(task_mapping::queue_task_id_for(94604), Some(4603));
+    }
+
+    #[test]
+    fn test_cancel_stopped_tasks() {
+        task_status::register_task(4701, "maa_combat_enhanced".to_string(), json!({}));
+        task_status::start_task(4701);
+        task_mapping::bind_maa_task(4701, 94701);
+
+        let candidates = [task_status::get_task_status(4701).unwrap()];
+        let interrupted = cancel_stopped_tasks("任务已停止: test", candidates);
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0]["maa_task_ids"], json!([94701]));
+        let cancelled = task_status::get_task_status(4701).unwrap();
+        assert_eq!(cancelled.status, TaskStatus::Cancelled);
+        assert!(task_mapping::queue_task_id_for(94701).is_none());
+    }
+}
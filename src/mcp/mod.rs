@@ -0,0 +1,421 @@
+//! Model Context Protocol (MCP) 服务
+//!
+//! 把 `EnhancedMaaFunctionHandlerV2` 的工具定义作为MCP工具提供，工具调用经由同一任务队列执行；
+//! 任务状态、队列和截图作为MCP资源提供。协议层与传输无关，
+//! stdio 传输见 `maa-mcp-server`，streamable HTTP 传输见 `maa-optimized-server` 的 `/mcp`。
+//!
+//! 资源URI：
+//! - `maa://tasks`、`maa://task/{task_id}`：任务状态
+//! - `maa://queue`：排队中的任务
+//! - `maa://screenshots`、`maa://screenshot/{screenshot_id}`：截图列表和PNG原图
+//!
+//! 需要二次确认的工具调用返回确认码，客户端用 `maa_confirm_action` 工具确认或取消。
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::config::CONFIG;
+use crate::function_tools::{confirmation, EnhancedMaaFunctionHandlerV2, FunctionCall, FunctionResponse};
+use crate::maa_core::screenshot::{get_screenshot_by_id, list_all_screenshots};
+use crate::maa_core::task_status;
+use crate::ws::{rpc_error, rpc_result, RpcError, JSONRPC_VERSION};
+
+/// 支持的协议版本，第一个为默认版本
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// 确认或取消待确认操作的工具名
+pub const CONFIRM_TOOL_NAME: &str = "maa_confirm_action";
+
+/// 资源列表中最多列出的截图数
+const LISTED_SCREENSHOTS: usize = 20;
+
+/// `tools/call` 参数
+#[derive(Debug, Deserialize)]
+struct ToolCallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// `maa_confirm_action` 参数
+#[derive(Debug, Deserialize)]
+struct ConfirmActionArgs {
+    token: String,
+    #[serde(default)]
+    cancel: bool,
+}
+
+/// `resources/read` 参数
+#[derive(Debug, Deserialize)]
+struct ResourceReadParams {
+    uri: String,
+}
+
+/// MCP服务（协议层）
+#[derive(Clone)]
+pub struct McpServer {
+    handler: EnhancedMaaFunctionHandlerV2,
+}
+
+impl McpServer {
+    pub fn new(handler: EnhancedMaaFunctionHandlerV2) -> Self {
+        Self { handler }
+    }
+
+    /// 处理一条文本消息，返回需要回复的消息
+    pub async fn handle_text(&self, text: &str) -> Option<Value> {
+        match serde_json::from_str(text) {
+            Ok(message) => self.handle_message(message).await,
+            Err(e) => Some(rpc_error(Value::Null, RpcError::parse_error(format!("无效的JSON: {}", e)))),
+        }
+    }
+
+    /// 处理一条JSON-RPC消息（或批量消息），通知和客户端响应不需要回复
+    pub async fn handle_message(&self, message: Value) -> Option<Value> {
+        if let Value::Array(messages) = message {
+            let mut responses = Vec::new();
+            for message in messages {
+                if let Some(response) = Box::pin(self.handle_message(message)).await {
+                    responses.push(response);
+                }
+            }
+            return (!responses.is_empty()).then_some(Value::Array(responses));
+        }
+
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+            // 客户端对服务器请求的响应，本服务不发起请求
+            return match id {
+                Some(_) if message.get("result").is_some() || message.get("error").is_some() => None,
+                _ => Some(rpc_error(id.unwrap_or(Value::Null), RpcError::invalid_request("缺少 method"))),
+            };
+        };
+        if message.get("jsonrpc").and_then(|v| v.as_str()) != Some(JSONRPC_VERSION) {
+            return Some(rpc_error(id.unwrap_or(Value::Null), RpcError::invalid_request("jsonrpc 必须为 \"2.0\"")));
+        }
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let Some(id) = id else {
+            debug!("MCP通知: {}", method);
+            return None;
+        };
+        debug!("MCP请求: {} (id: {})", method, id);
+        Some(match self.dispatch(method, params).await {
+            Ok(result) => rpc_result(id, result),
+            Err(error) => rpc_error(id, error),
+        })
+    }
+
+    async fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(initialize_result(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(self.list_tools()),
+            "tools/call" => {
+                let params: ToolCallParams = parse_params(params)?;
+                self.call_tool(params).await
+            },
+            "resources/list" => Ok(self.list_resources()),
+            "resources/templates/list" => Ok(resource_templates()),
+            "resources/read" => {
+                let params: ResourceReadParams = parse_params(params)?;
+                self.read_resource(&params.uri)
+            },
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    fn list_tools(&self) -> Value {
+        let mut tools: Vec<Value> = self.handler.get_function_definitions().into_iter()
+            .map(|def| json!({
+                "name": def.name,
+                "description": def.description,
+                "inputSchema": def.parameters,
+            }))
+            .collect();
+        if CONFIG.confirmation.enabled {
+            tools.push(confirm_tool_definition());
+        }
+        json!({ "tools": tools })
+    }
+
+    /// 执行工具，执行失败以 `isError` 返回给模型而不是协议错误
+    async fn call_tool(&self, params: ToolCallParams) -> Result<Value, RpcError> {
+        if params.name == CONFIRM_TOOL_NAME {
+            let args: ConfirmActionArgs = parse_params(params.arguments)?;
+            return Ok(self.confirm_action(args).await);
+        }
+        if !self.handler.get_function_definitions().iter().any(|def| def.name == params.name) {
+            return Err(RpcError::invalid_params(format!("未知的工具: {}", params.name)));
+        }
+        let arguments = if params.arguments.is_null() { json!({}) } else { params.arguments };
+        let response = self.handler.execute_function(FunctionCall { name: params.name, arguments }).await;
+        Ok(function_tool_result(&response))
+    }
+
+    /// 确认（权限、参数和预算会重新检查）或取消待确认操作
+    async fn confirm_action(&self, args: ConfirmActionArgs) -> Value {
+        let result = if args.cancel {
+            confirmation::cancel(&args.token)
+                .map(|action| tool_result(json!({ "status": "cancelled", "token": action.token, "summary": action.summary }), false))
+        } else {
+            self.handler.confirm_action(&args.token).await
+                .map(|response| function_tool_result(&response))
+        };
+        result.unwrap_or_else(|e| tool_result(json!({ "error": e.to_string(), "error_code": e.error_code() }), true))
+    }
+
+    fn list_resources(&self) -> Value {
+        let mut resources = vec![
+            json!({ "uri": "maa://tasks", "name": "tasks", "title": "全部任务状态", "mimeType": "application/json" }),
+            json!({ "uri": "maa://queue", "name": "queue", "title": "排队中的任务", "mimeType": "application/json" }),
+            json!({ "uri": "maa://screenshots", "name": "screenshots", "title": "截图列表", "mimeType": "application/json" }),
+        ];
+        resources.extend(task_status::get_all_tasks().into_iter()
+            .filter(|task| !task.is_finished())
+            .map(|task| json!({
+                "uri": format!("maa://task/{}", task.task_id),
+                "name": format!("task-{}", task.task_id),
+                "title": format!("任务 {}（{}）", task.task_id, task.task_type),
+                "mimeType": "application/json",
+            })));
+        resources.extend(list_all_screenshots().unwrap_or_default().into_iter()
+            .take(LISTED_SCREENSHOTS)
+            .map(|screenshot| json!({
+                "uri": format!("maa://screenshot/{}", screenshot.id),
+                "name": screenshot.id,
+                "mimeType": "image/png",
+                "size": screenshot.file_size,
+            })));
+        json!({ "resources": resources })
+    }
+
+    fn read_resource(&self, uri: &str) -> Result<Value, RpcError> {
+        let content = match uri {
+            "maa://tasks" => json_content(uri, json!(task_status::get_all_tasks())),
+            "maa://queue" => json_content(uri, json!({
+                "depth": self.handler.task_sender().depth(),
+                "capacity": self.handler.task_sender().capacity(),
+                "pending": self.handler.task_sender().pending_tasks(),
+            })),
+            "maa://screenshots" => {
+                let screenshots: Vec<Value> = list_all_screenshots()
+                    .map_err(|e| RpcError::internal(format!("读取截图列表失败: {}", e)))?
+                    .into_iter()
+                    .map(|screenshot| json!({
+                        "id": screenshot.id,
+                        "uri": format!("maa://screenshot/{}", screenshot.id),
+                        "timestamp": screenshot.timestamp,
+                        "file_size": screenshot.file_size,
+                        "dimensions": screenshot.dimensions,
+                    }))
+                    .collect();
+                json_content(uri, json!(screenshots))
+            },
+            _ => {
+                if let Some(task_id) = uri.strip_prefix("maa://task/") {
+                    let task = task_id.parse().ok()
+                        .and_then(task_status::get_task_status)
+                        .ok_or_else(|| resource_not_found(uri))?;
+                    json_content(uri, json!(task))
+                } else if let Some(screenshot_id) = uri.strip_prefix("maa://screenshot/") {
+                    screenshot_content(uri, screenshot_id)?
+                } else {
+                    return Err(resource_not_found(uri));
+                }
+            },
+        };
+        Ok(json!({ "contents": [content] }))
+    }
+}
+
+/// Function Call响应转换为工具结果
+fn function_tool_result(response: &FunctionResponse) -> Value {
+    let structured = if response.success {
+        response.result.clone().unwrap_or(json!({}))
+    } else {
+        json!({
+            "error": response.error.as_ref().map(|e| e.message.clone()),
+            "error_code": response.error.as_ref().and_then(|e| e.error_code.clone()),
+            "result": response.result,
+        })
+    };
+    tool_result(structured, !response.success)
+}
+
+fn tool_result(structured: Value, is_error: bool) -> Value {
+    let text = serde_json::to_string_pretty(&structured).unwrap_or_default();
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "structuredContent": structured,
+        "isError": is_error,
+    })
+}
+
+fn confirm_tool_definition() -> Value {
+    json!({
+        "name": CONFIRM_TOOL_NAME,
+        "description": "确认或取消需要二次确认的操作（使用源石、强制关闭游戏、紧急返回并清空队列、自定义任务等）。只有在用户明确同意后才能确认。",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "token": { "type": "string", "description": "工具返回的确认码" },
+                "cancel": { "type": "boolean", "description": "为true时取消该操作", "default": false }
+            },
+            "required": ["token"]
+        }
+    })
+}
+
+/// `initialize` 响应，客户端请求的版本受支持时沿用，否则返回默认版本
+fn initialize_result(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(|v| v.as_str());
+    let protocol_version = requested
+        .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": protocol_version,
+        "capabilities": {
+            "tools": { "listChanged": false },
+            "resources": { "subscribe": false, "listChanged": false }
+        },
+        "serverInfo": {
+            "name": "maa-intelligent-server",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "instructions": "控制明日方舟自动化助手MAA。异步工具返回task_id，可读取 maa://task/{task_id} 资源查询进度。返回确认码的操作需征得用户同意后用 maa_confirm_action 确认。"
+    })
+}
+
+fn resource_templates() -> Value {
+    json!({
+        "resourceTemplates": [
+            {
+                "uriTemplate": "maa://task/{task_id}",
+                "name": "task",
+                "title": "任务状态",
+                "mimeType": "application/json"
+            },
+            {
+                "uriTemplate": "maa://screenshot/{screenshot_id}",
+                "name": "screenshot",
+                "title": "截图原图",
+                "mimeType": "image/png"
+            }
+        ]
+    })
+}
+
+/// MCP规定的资源不存在错误码
+fn resource_not_found(uri: &str) -> RpcError {
+    RpcError::new(-32002, format!("资源不存在: {}", uri)).with_data(json!({ "uri": uri }))
+}
+
+fn json_content(uri: &str, value: Value) -> Value {
+    json!({
+        "uri": uri,
+        "mimeType": "application/json",
+        "text": serde_json::to_string_pretty(&value).unwrap_or_default(),
+    })
+}
+
+fn screenshot_content(uri: &str, screenshot_id: &str) -> Result<Value, RpcError> {
+    // 截图ID只用作文件名，拒绝路径分隔符
+    if screenshot_id.is_empty() || screenshot_id.contains(['/', '\\', '.']) {
+        return Err(resource_not_found(uri));
+    }
+    let screenshot = get_screenshot_by_id(screenshot_id).map_err(|_| resource_not_found(uri))?;
+    let data = std::fs::read(&screenshot.file_path)
+        .map_err(|e| RpcError::internal(format!("读取截图失败: {}", e)))?;
+    Ok(json!({
+        "uri": uri,
+        "mimeType": "image/png",
+        "blob": BASE64.encode(data),
+    }))
+}
+
+/// 解析方法参数，缺省参数按空对象处理
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("参数错误: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_tools::create_enhanced_function_handler_v2;
+    use crate::maa_core::task_queue_v2::create_maa_task_channel_with_capacity;
+    use crate::ws::{INVALID_PARAMS, METHOD_NOT_FOUND};
+
+    fn server() -> McpServer {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(8);
+        McpServer::new(create_enhanced_function_handler_v2(sender))
+    }
+
+    async fn request(server: &McpServer, method: &str, params: Value) -> Value {
+        server.handle_message(json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params })).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_list_tools() {
+        let server = server();
+        let response = request(&server, "initialize", json!({ "protocolVersion": "2025-03-26", "capabilities": {} })).await;
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+        assert!(response["result"]["capabilities"]["tools"].is_object());
+        let response = request(&server, "initialize", json!({ "protocolVersion": "1999-01-01" })).await;
+        assert_eq!(response["result"]["protocolVersion"], SUPPORTED_PROTOCOL_VERSIONS[0]);
+
+        assert!(server.handle_message(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await.is_none());
+
+        let response = request(&server, "tools/list", Value::Null).await;
+        let tools = response["result"]["tools"].as_array().unwrap();
+        assert!(tools.iter().any(|tool| tool["name"] == "maa_startup" && tool["inputSchema"].is_object()));
+
+        let response = request(&server, "tools/call", json!({ "name": "nope" })).await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        let response = request(&server, "nope/nope", Value::Null).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_action_tool() {
+        let server = server();
+        let response = request(&server, "tools/list", Value::Null).await;
+        assert!(response["result"]["tools"].as_array().unwrap().iter().any(|tool| tool["name"] == CONFIRM_TOOL_NAME));
+
+        let response = request(&server, "tools/call", json!({ "name": "maa_custom_task", "arguments": { "task_name": "Award" } })).await;
+        assert_eq!(response["result"]["isError"], true);
+        let token = response["result"]["structuredContent"]["result"]["token"].as_str().unwrap().to_string();
+
+        let response = request(&server, "tools/call", json!({ "name": CONFIRM_TOOL_NAME, "arguments": { "token": token, "cancel": true } })).await;
+        assert_eq!(response["result"]["structuredContent"]["status"], "cancelled");
+        let response = request(&server, "tools/call", json!({ "name": CONFIRM_TOOL_NAME, "arguments": { "token": token } })).await;
+        assert_eq!(response["result"]["isError"], true);
+        assert_eq!(response["result"]["structuredContent"]["error_code"], "ACTION_NOT_FOUND");
+        let response = request(&server, "tools/call", json!({ "name": CONFIRM_TOOL_NAME })).await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_task_resources() {
+        let server = server();
+        task_status::register_task(4601, "maa_combat_enhanced".to_string(), json!({ "stage": "1-7" }));
+
+        let response = request(&server, "resources/list", Value::Null).await;
+        let resources = response["result"]["resources"].as_array().unwrap();
+        assert!(resources.iter().any(|resource| resource["uri"] == "maa://task/4601"));
+
+        let response = request(&server, "resources/read", json!({ "uri": "maa://task/4601" })).await;
+        let content = &response["result"]["contents"][0];
+        assert_eq!(content["mimeType"], "application/json");
+        let task: Value = serde_json::from_str(content["text"].as_str().unwrap()).unwrap();
+        assert_eq!(task["task_id"], 4601);
+
+        let response = request(&server, "resources/read", json!({ "uri": "maa://screenshot/../secret" })).await;
+        assert_eq!(response["error"]["code"], -32002);
+        let response = request(&server, "resources/read", json!({ "uri": "maa://nope" })).await;
+        assert_eq!(response["error"]["code"], -32002);
+    }
+}
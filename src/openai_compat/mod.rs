@@ -0,0 +1,418 @@
+//! OpenAI 兼容接口
+//!
+//! `/v1/chat/completions` 和 `/v1/models` 的请求/响应格式，以及服务端的MAA工具循环：
+//! 模型返回工具调用时由服务器执行并把结果交回模型，直到模型给出文本回复，
+//! 客户端只会收到最终的助手消息，不需要自己执行工具。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::ai_client::client::Either;
+use crate::ai_client::{AiClientTrait, AiResult, ChatMessage, Tool};
+use crate::function_tools::{EnhancedMaaFunctionHandlerV2, FunctionCall, FunctionResponse};
+use crate::function_tools::handler_v2::CONFIRMATION_REQUIRED_ERROR_CODE;
+
+/// 对外暴露的模型ID
+pub const ASSISTANT_MODEL_ID: &str = "maa-assistant";
+
+/// 单次请求中工具循环的最大轮数，超过后要求模型直接总结
+pub const MAX_TOOL_ROUNDS: usize = 5;
+
+/// `/v1/chat/completions` 请求
+///
+/// `temperature` 等其余字段被接受但不生效：模型参数由服务器的AI配置决定。
+/// 工具固定为MAA工具集并在服务端执行，客户端自带的 `tools` / `tool_choice` 以及历史中的工具消息
+/// 会被拒绝（见 `unsupported_tools_error`）。
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub messages: Vec<CompatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub tools: Option<Vec<Value>>,
+    #[serde(default)]
+    pub tool_choice: Option<Value>,
+}
+
+impl ChatCompletionRequest {
+    /// 响应中回显的模型ID
+    pub fn model_id(&self) -> String {
+        self.model.clone()
+            .filter(|model| !model.trim().is_empty())
+            .unwrap_or_else(|| ASSISTANT_MODEL_ID.to_string())
+    }
+
+    /// 客户端要求使用自己的工具时返回错误信息
+    ///
+    /// 服务端不会把工具调用交给客户端执行，静默忽略会让客户端以为模型只是没有调用工具。
+    /// 空的 `tools` 和 `tool_choice: "auto"` 与默认行为一致，允许通过。
+    /// 历史中的 `tool` 消息和带 `tool_calls` 的消息只能来自客户端执行的工具，同样拒绝。
+    pub fn unsupported_tools_error(&self) -> Option<&'static str> {
+        if self.tools.as_ref().is_some_and(|tools| !tools.is_empty()) {
+            return Some("不支持客户端自定义 tools：本服务在服务端执行MAA工具，请去掉 tools 参数");
+        }
+        if self.messages.iter().any(|message| message.role == "tool" || message.role == "function") {
+            return Some("不支持 tool 消息：MAA工具在服务端执行，请求历史中不能包含工具结果");
+        }
+        if self.messages.iter().any(CompatMessage::has_tool_calls) {
+            return Some("不支持带 tool_calls 的消息：MAA工具在服务端执行，请求历史中不能包含工具调用");
+        }
+        match &self.tool_choice {
+            None | Some(Value::Null) => None,
+            Some(Value::String(choice)) if choice == "auto" => None,
+            Some(_) => Some("不支持 tool_choice：工具由服务端自动选择，只接受 \"auto\""),
+        }
+    }
+}
+
+/// OpenAI 格式的消息，`content` 可以是字符串、内容片段数组或 null
+#[derive(Debug, Deserialize)]
+pub struct CompatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Value,
+    #[serde(default)]
+    pub tool_calls: Value,
+    #[serde(default)]
+    pub function_call: Value,
+}
+
+impl CompatMessage {
+    /// 是否带有工具调用（`tool_calls` 非空或旧版 `function_call`）
+    pub fn has_tool_calls(&self) -> bool {
+        let present = |value: &Value| match value {
+            Value::Null => false,
+            Value::Array(items) => !items.is_empty(),
+            _ => true,
+        };
+        present(&self.tool_calls) || present(&self.function_call)
+    }
+
+    /// 消息的文本内容，只保留 `text` 片段
+    pub fn text(&self) -> String {
+        match &self.content {
+            Value::String(text) => text.clone(),
+            Value::Array(parts) => parts.iter()
+                .filter(|part| part["type"] == "text")
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        }
+    }
+}
+
+/// 服务端执行过的一次工具调用
+#[derive(Debug, Clone)]
+pub struct ExecutedToolCall {
+    pub name: String,
+    pub arguments: Value,
+    pub response: FunctionResponse,
+}
+
+/// 工具循环的结果
+#[derive(Debug, Clone)]
+pub struct ToolLoopOutcome {
+    /// 最终的助手回复
+    pub content: String,
+    pub tool_calls: Vec<ExecutedToolCall>,
+}
+
+impl ToolLoopOutcome {
+    /// 返回给客户端的回复：模型回复后附上待确认操作的提示
+    ///
+    /// 提示中带有确认码，客户端在下一轮请求中回复“确认”/“取消”时据此找回待确认操作。
+    pub fn reply(&self) -> String {
+        let prompts: Vec<String> = self.tool_calls.iter()
+            .filter(|call| call.response.error.as_ref().and_then(|e| e.error_code.as_deref()) == Some(CONFIRMATION_REQUIRED_ERROR_CODE))
+            .filter_map(|call| call.response.error.as_ref().map(|e| e.message.clone()))
+            .collect();
+        if prompts.is_empty() {
+            return self.content.clone();
+        }
+        format!("{}\n\n{}", self.content, prompts.join("\n"))
+    }
+}
+
+/// 运行MAA工具循环
+///
+/// `messages` 需已包含系统提示词。每轮把工具执行结果作为消息追加后再次请求模型，
+/// 达到 [`MAX_TOOL_ROUNDS`] 轮后不再提供工具，要求模型总结。
+pub async fn run_tool_loop(
+    ai_client: &dyn AiClientTrait,
+    handler: &EnhancedMaaFunctionHandlerV2,
+    mut messages: Vec<ChatMessage>,
+    tools: Vec<Tool>,
+) -> AiResult<ToolLoopOutcome> {
+    let mut executed = Vec::new();
+
+    for round in 0..MAX_TOOL_ROUNDS {
+        let function_calls = match ai_client.chat_completion_with_tools(messages.clone(), tools.clone()).await? {
+            Either::Left(content) => return Ok(ToolLoopOutcome { content, tool_calls: executed }),
+            Either::Right(function_calls) => function_calls,
+        };
+        debug!("工具循环第 {} 轮: {} 个工具调用", round + 1, function_calls.len());
+
+        let mut summaries = Vec::new();
+        for function_call in function_calls {
+            let response = handler.execute_function(FunctionCall {
+                name: function_call.name.clone(),
+                arguments: function_call.arguments.clone(),
+            }).await;
+            summaries.push(summarize_tool_result(&function_call.name, &response));
+            executed.push(ExecutedToolCall {
+                name: function_call.name,
+                arguments: function_call.arguments,
+                response,
+            });
+        }
+
+        let calls = executed[executed.len() - summaries.len()..].iter()
+            .map(|call| format!("{}({})", call.name, call.arguments))
+            .collect::<Vec<_>>()
+            .join(", ");
+        messages.push(ChatMessage::assistant(format!("调用工具: {}", calls)));
+        messages.push(ChatMessage::user(format!(
+            "工具执行结果：\n{}\n\n请根据结果继续；不需要再调用工具时，用简洁友好的中文回复用户。",
+            summaries.join("\n")
+        )));
+    }
+
+    warn!("工具循环达到 {} 轮上限，要求模型直接总结", MAX_TOOL_ROUNDS);
+    let content = ai_client.chat_completion(messages).await?;
+    Ok(ToolLoopOutcome { content, tool_calls: executed })
+}
+
+/// 工具执行结果的一行摘要，不包含截图等原始数据
+pub fn summarize_tool_result(name: &str, response: &FunctionResponse) -> String {
+    if !response.success {
+        let message = response.error.as_ref().map(|e| e.message.as_str()).unwrap_or("未知错误");
+        return format!("工具 {} 执行失败: {}", name, message);
+    }
+    let result = response.result.as_ref();
+    let status = result.and_then(|r| r.get("status")).and_then(|s| s.as_str()).unwrap_or("success");
+    let message = result.and_then(|r| r.get("message")).and_then(|m| m.as_str()).unwrap_or("任务完成");
+    match result.and_then(|r| r.get("task_id")).and_then(|id| id.as_i64()) {
+        Some(task_id) => format!("工具 {} 执行{}: {}（任务ID {}）", name, status, message, task_id),
+        None => format!("工具 {} 执行{}: {}", name, status, message),
+    }
+}
+
+/// 新的补全ID（时间戳 + 进程内序号）
+pub fn completion_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!("chatcmpl-{:x}{:04x}", Utc::now().timestamp_millis(), COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff)
+}
+
+/// 非流式补全响应
+///
+/// 工具循环可能多次调用上游模型，AI客户端也不返回token用量，响应中不带 `usage`，
+/// 避免客户端把0当作真实用量统计。
+pub fn completion_response(id: &str, model: &str, content: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "logprobs": null,
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+/// 流式补全的一个分片，`finish_reason` 只出现在最后一个分片
+pub fn completion_chunk(id: &str, model: &str, created: i64, delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "logprobs": null,
+            "finish_reason": finish_reason
+        }]
+    })
+}
+
+/// 把回复内容切成流式分片的文本
+pub fn split_content(content: &str, chars_per_chunk: usize) -> Vec<String> {
+    let chars: Vec<char> = content.chars().collect();
+    chars.chunks(chars_per_chunk.max(1))
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+/// `/v1/models` 响应
+pub fn models_response() -> Value {
+    json!({
+        "object": "list",
+        "data": [{
+            "id": ASSISTANT_MODEL_ID,
+            "object": "model",
+            "created": 0,
+            "owned_by": "maa-intelligent-server"
+        }]
+    })
+}
+
+/// OpenAI 格式的错误体
+pub fn error_body(message: &str, error_type: &str, code: Option<&str>) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": error_type,
+            "param": null,
+            "code": code
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_client::{AiError, AiProvider, FunctionCall as AiFunctionCall, StreamEvent};
+    use crate::function_tools::{confirmation, create_enhanced_function_handler_v2};
+    use crate::maa_core::task_queue_v2::create_maa_task_channel_with_capacity;
+    use async_trait::async_trait;
+    use futures::Stream;
+    use std::sync::Mutex;
+
+    /// 按脚本返回结果的AI客户端，记录每次收到的消息
+    struct ScriptedClient {
+        replies: Mutex<Vec<Either<String, Vec<AiFunctionCall>>>>,
+        received: Mutex<Vec<Vec<ChatMessage>>>,
+        provider: AiProvider,
+    }
+
+    #[async_trait]
+    impl AiClientTrait for ScriptedClient {
+        async fn chat_completion(&self, messages: Vec<ChatMessage>) -> AiResult<String> {
+            self.received.lock().unwrap().push(messages);
+            Ok("总结".to_string())
+        }
+
+        async fn chat_completion_with_tools(&self, messages: Vec<ChatMessage>, _tools: Vec<Tool>) -> AiResult<Either<String, Vec<AiFunctionCall>>> {
+            self.received.lock().unwrap().push(messages);
+            Ok(self.replies.lock().unwrap().remove(0))
+        }
+
+        async fn chat_completion_stream(&self, _messages: Vec<ChatMessage>) -> AiResult<Box<dyn Stream<Item = AiResult<StreamEvent>> + Send + Unpin>> {
+            Err(AiError::Config("unsupported".to_string()))
+        }
+
+        async fn switch_provider(&mut self, _provider: AiProvider) -> AiResult<()> {
+            Ok(())
+        }
+
+        fn current_provider(&self) -> &AiProvider {
+            &self.provider
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_feeds_results_back() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(8);
+        let handler = create_enhanced_function_handler_v2(sender);
+        let client = ScriptedClient {
+            replies: Mutex::new(vec![
+                Either::Right(vec![AiFunctionCall { name: "unknown_function".to_string(), arguments: json!({}) }]),
+                Either::Left("已处理".to_string()),
+            ]),
+            received: Mutex::new(Vec::new()),
+            provider: AiProvider::OpenAI,
+        };
+
+        let outcome = run_tool_loop(&client, &handler, vec![ChatMessage::user("刷1-7")], Vec::new()).await.unwrap();
+        assert_eq!(outcome.content, "已处理");
+        assert_eq!(outcome.tool_calls.len(), 1);
+        assert!(!outcome.tool_calls[0].response.success);
+
+        let received = client.received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let last = received[1].last().unwrap();
+        assert!(last.content.contains("工具 unknown_function 执行失败"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_reply_carries_confirmation_prompt() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(8);
+        let handler = create_enhanced_function_handler_v2(sender);
+        let client = ScriptedClient {
+            replies: Mutex::new(vec![
+                Either::Right(vec![AiFunctionCall { name: "maa_custom_task".to_string(), arguments: json!({"task_name": "Award"}) }]),
+                Either::Left("需要你确认".to_string()),
+            ]),
+            received: Mutex::new(Vec::new()),
+            provider: AiProvider::OpenAI,
+        };
+
+        let outcome = run_tool_loop(&client, &handler, vec![ChatMessage::user("执行自定义任务")], Vec::new()).await.unwrap();
+        let reply = outcome.reply();
+        assert!(reply.starts_with("需要你确认"));
+        assert!(reply.contains(confirmation::TOKEN_MARKER));
+        let token = outcome.tool_calls[0].response.result.as_ref().unwrap()["token"].as_str().unwrap().to_string();
+        confirmation::cancel(&token).unwrap();
+    }
+
+    #[test]
+    fn test_compat_message_text() {
+        let message: CompatMessage = serde_json::from_value(json!({
+            "role": "user",
+            "content": [{"type": "text", "text": "你好"}, {"type": "image_url", "image_url": {"url": "x"}}, {"type": "text", "text": "刷图"}]
+        })).unwrap();
+        assert_eq!(message.text(), "你好\n刷图");
+        let message: CompatMessage = serde_json::from_value(json!({"role": "assistant", "content": null})).unwrap();
+        assert_eq!(message.text(), "");
+
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "", "messages": [], "temperature": 0.2
+        })).unwrap();
+        assert_eq!(request.model_id(), ASSISTANT_MODEL_ID);
+        assert!(!request.stream);
+        assert!(request.unsupported_tools_error().is_none());
+    }
+
+    #[test]
+    fn test_unsupported_tools() {
+        let request = |extra: Value| -> ChatCompletionRequest {
+            let mut body = json!({"messages": []});
+            body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(body).unwrap()
+        };
+        assert!(request(json!({"tools": [], "tool_choice": "auto"})).unsupported_tools_error().is_none());
+        assert!(request(json!({"tools": [{"type": "function", "function": {"name": "f"}}]})).unsupported_tools_error().is_some());
+        assert!(request(json!({"tool_choice": "required"})).unsupported_tools_error().is_some());
+        assert!(request(json!({"tool_choice": {"type": "function", "function": {"name": "f"}}})).unsupported_tools_error().is_some());
+
+        // 历史中的工具结果和工具调用来自客户端执行的工具
+        let messages = |messages: Value| request(json!({"messages": messages}));
+        assert!(messages(json!([{"role": "tool", "tool_call_id": "c1", "content": "ok"}])).unsupported_tools_error().is_some());
+        assert!(messages(json!([{"role": "assistant", "content": null, "tool_calls": [{"id": "c1", "type": "function"}]}])).unsupported_tools_error().is_some());
+        assert!(messages(json!([{"role": "assistant", "content": "好的", "tool_calls": []}])).unsupported_tools_error().is_none());
+    }
+
+    #[test]
+    fn test_completion_shapes() {
+        let response = completion_response("chatcmpl-1", "maa-assistant", "好的");
+        assert_eq!(response["object"], "chat.completion");
+        assert_eq!(response["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(response["choices"][0]["finish_reason"], "stop");
+        assert!(response.get("usage").is_none());
+
+        let chunk = completion_chunk("chatcmpl-1", "maa-assistant", 0, json!({"content": "好"}), None);
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert!(chunk["choices"][0]["finish_reason"].is_null());
+        assert_eq!(split_content("一二三四五", 2), vec!["一二", "三四", "五"]);
+    }
+}
@@ -0,0 +1,211 @@
+//! SSE订阅过滤
+//!
+//! `/sse/tasks` 的查询参数在服务端过滤事件，列表参数用逗号分隔：
+//! `task_type`、`event_type`、`device`、`min_severity`、`task_ids`，以及去掉 `data` 的 `compact` 模式。
+//! 缺失提示（`gap`）不受过滤影响，客户端总能知道自己错过了事件。
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::SequencedEvent;
+use crate::maa_core::worker_v2::{EventSeverity, TaskEventType};
+
+/// SSE订阅的查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct SseFilterQuery {
+    /// 任务类型，匹配Function名称（如 `maa_combat_enhanced`）或MAA任务链（如 `Fight`）
+    pub task_type: Option<String>,
+    /// 事件类型（如 `task_status,task_timeout`）
+    pub event_type: Option<String>,
+    /// 设备地址
+    pub device: Option<String>,
+    /// 最低严重程度：debug / info / warning / error
+    pub min_severity: Option<String>,
+    /// 队列任务ID
+    pub task_ids: Option<String>,
+    /// 精简模式：不推送原始 `data`
+    #[serde(default)]
+    pub compact: bool,
+}
+
+/// 过滤参数错误
+#[derive(Debug, Error, PartialEq)]
+pub enum FilterError {
+    #[error("未知的事件类型: {0}")]
+    UnknownEventType(String),
+    #[error("未知的严重程度: {0}，可选 debug/info/warning/error")]
+    UnknownSeverity(String),
+    #[error("无效的任务ID: {0}")]
+    InvalidTaskId(String),
+}
+
+/// 解析后的事件过滤条件，未指定的条件不过滤
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    task_types: Option<HashSet<String>>,
+    event_types: Option<HashSet<TaskEventType>>,
+    devices: Option<HashSet<String>>,
+    min_severity: Option<EventSeverity>,
+    task_ids: Option<HashSet<i32>>,
+    /// 精简模式
+    pub compact: bool,
+}
+
+/// 拆分逗号分隔的列表，忽略空项；列表为空时视为未指定
+fn split_list(value: &Option<String>) -> Option<Vec<&str>> {
+    let items: Vec<&str> = value.as_deref()?
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect();
+    (!items.is_empty()).then_some(items)
+}
+
+impl EventFilter {
+    /// 从查询参数构建过滤条件
+    pub fn from_query(query: &SseFilterQuery) -> Result<Self, FilterError> {
+        let event_types = split_list(&query.event_type)
+            .map(|items| items.into_iter()
+                .map(|name| TaskEventType::parse(name).ok_or_else(|| FilterError::UnknownEventType(name.to_string())))
+                .collect::<Result<HashSet<_>, _>>())
+            .transpose()?;
+        let task_ids = split_list(&query.task_ids)
+            .map(|items| items.into_iter()
+                .map(|id| id.parse().map_err(|_| FilterError::InvalidTaskId(id.to_string())))
+                .collect::<Result<HashSet<_>, _>>())
+            .transpose()?;
+        let min_severity = query.min_severity.as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| EventSeverity::parse(name).ok_or_else(|| FilterError::UnknownSeverity(name.to_string())))
+            .transpose()?;
+
+        Ok(Self {
+            task_types: split_list(&query.task_type).map(|items| items.into_iter().map(String::from).collect()),
+            event_types,
+            devices: split_list(&query.device).map(|items| items.into_iter().map(String::from).collect()),
+            min_severity,
+            task_ids,
+            compact: query.compact,
+        })
+    }
+
+    /// 是否没有任何过滤条件
+    pub fn is_empty(&self) -> bool {
+        self.task_types.is_none() && self.event_types.is_none() && self.devices.is_none()
+            && self.min_severity.is_none() && self.task_ids.is_none()
+    }
+
+    /// 事件是否推送给该订阅
+    pub fn matches(&self, sequenced: &SequencedEvent) -> bool {
+        let event = &sequenced.event;
+        if event.event_type == TaskEventType::Gap {
+            return true;
+        }
+        if let Some(event_types) = &self.event_types {
+            if !event_types.contains(&event.event_type) {
+                return false;
+            }
+        }
+        if let Some(min_severity) = self.min_severity {
+            if event.severity() < min_severity {
+                return false;
+            }
+        }
+        if let Some(task_ids) = &self.task_ids {
+            if !task_ids.contains(&event.task_id) {
+                return false;
+            }
+        }
+        if let Some(task_types) = &self.task_types {
+            // MAA回调事件的task_type是任务链名称，同时按编号时解析的Function名称匹配
+            let matched = task_types.contains(&event.task_type)
+                || sequenced.function_name.as_ref().is_some_and(|name| task_types.contains(name));
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(devices) = &self.devices {
+            // 设备地址在事件编号时确定，不随当前连接变化
+            if !sequenced.device_address.as_ref().is_some_and(|device| devices.contains(device)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maa_core::worker_v2::TaskProgressEvent;
+
+    fn event(task_id: i32, task_type: &str, event_type: TaskEventType) -> SequencedEvent {
+        SequencedEvent {
+            id: 1,
+            event: TaskProgressEvent::for_test(task_id, task_type, event_type),
+            function_name: None,
+            device_address: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_from_query() {
+        let query = SseFilterQuery {
+            event_type: Some("task_status, task_timeout".to_string()),
+            task_ids: Some("1,2".to_string()),
+            min_severity: Some("Warning".to_string()),
+            ..Default::default()
+        };
+        let filter = EventFilter::from_query(&query).unwrap();
+        assert!(!filter.is_empty());
+
+        let query = SseFilterQuery { event_type: Some("nope".to_string()), ..Default::default() };
+        assert_eq!(EventFilter::from_query(&query).unwrap_err(), FilterError::UnknownEventType("nope".to_string()));
+        let query = SseFilterQuery { task_ids: Some("1,x".to_string()), ..Default::default() };
+        assert_eq!(EventFilter::from_query(&query).unwrap_err(), FilterError::InvalidTaskId("x".to_string()));
+        assert!(EventFilter::from_query(&SseFilterQuery { task_type: Some(" , ".to_string()), ..Default::default() }).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let query = SseFilterQuery {
+            task_type: Some("Fight".to_string()),
+            min_severity: Some("info".to_string()),
+            ..Default::default()
+        };
+        let filter = EventFilter::from_query(&query).unwrap();
+        assert!(filter.matches(&event(8001, "Fight", TaskEventType::TaskchainCompleted)));
+        // 子任务细节低于info
+        assert!(!filter.matches(&event(8001, "Fight", TaskEventType::SubtaskInfo)));
+        assert!(!filter.matches(&event(8001, "Recruit", TaskEventType::TaskchainCompleted)));
+        // 缺失提示不受过滤影响
+        assert!(filter.matches(&event(0, "system", TaskEventType::Gap)));
+
+        // 任务链事件按队列任务的Function名称匹配
+        let query = SseFilterQuery { task_type: Some("maa_combat_enhanced".to_string()), ..Default::default() };
+        let filter = EventFilter::from_query(&query).unwrap();
+        let mut fight = event(8001, "Fight", TaskEventType::TaskchainCompleted);
+        assert!(!filter.matches(&fight));
+        fight.function_name = Some("maa_combat_enhanced".to_string());
+        assert!(filter.matches(&fight));
+
+        let query = SseFilterQuery { task_ids: Some("8002".to_string()), ..Default::default() };
+        let filter = EventFilter::from_query(&query).unwrap();
+        assert!(filter.matches(&event(8002, "Fight", TaskEventType::SubtaskInfo)));
+        assert!(!filter.matches(&event(8003, "Fight", TaskEventType::SubtaskInfo)));
+
+        let query = SseFilterQuery { device: Some("10.0.0.9:5555".to_string()), ..Default::default() };
+        let filter = EventFilter::from_query(&query).unwrap();
+        let mut device_event = event(0, "system", TaskEventType::DeviceConnection);
+        device_event.device_address = Some("10.0.0.9:5555".to_string());
+        assert!(filter.matches(&device_event));
+        // 按编号时记录的设备过滤，未记录设备的事件不匹配
+        let mut other_device = event(8004, "Fight", TaskEventType::TaskchainCompleted);
+        assert!(!filter.matches(&other_device));
+        other_device.device_address = Some("10.0.0.8:5555".to_string());
+        assert!(!filter.matches(&other_device));
+    }
+}
@@ -1,6 +1,10 @@
 //! Server-Sent Events (SSE) 推送系统
 //! 
 //! 用于实时推送异步任务的执行进度和结果给前端
+//!
+//! 所有任务事件经过回放缓冲区编号后再推送，事件序号即SSE的id。
+//! 客户端断线重连时携带 `Last-Event-ID`，服务器从缓冲区补发错过的事件；
+//! 事件已被挤出缓冲区或客户端消费过慢时推送 `gap` 事件说明缺失的范围，而不是断开连接。
 
 use axum::{
     response::Sse,
@@ -10,33 +14,258 @@ use axum::response::sse::{Event, KeepAlive};
 use tokio_stream::{Stream, StreamExt};
 use tokio::sync::broadcast;
 use serde_json::{json, Value};
-use tracing::{info, debug};
+use tracing::{info, debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use futures::stream;
+use futures::{stream, FutureExt};
 use std::convert::Infallible;
 use chrono::Utc;
 
-use crate::maa_core::worker_v2::TaskProgressEvent;
+use crate::config::CONFIG;
+
+mod filter;
+pub use filter::{EventFilter, FilterError, SseFilterQuery};
+use crate::maa_core::worker_v2::{TaskEventType, TaskProgressEvent};
+use crate::maa_core::task_notification::{subscribe_task_events, TaskStatusEvent};
+use crate::maa_core::task_status::{self, TaskStatus, TaskWaitOutcome};
+use crate::maa_core::connection::device_health;
+
+/// 带序号的任务事件
+#[derive(Debug, Clone)]
+pub struct SequencedEvent {
+    /// 单调递增的事件序号，作为SSE事件id
+    pub id: u64,
+    pub event: TaskProgressEvent,
+    /// 队列任务登记的Function名称，编号时解析，订阅过滤时不再查询任务状态
+    pub function_name: Option<String>,
+    /// 事件发生时的设备地址，编号时确定，回放的事件仍按原设备过滤
+    pub device_address: Option<String>,
+}
+
+/// 推送给单个SSE客户端的条目
+#[derive(Debug, Clone)]
+pub(crate) enum StreamItem {
+    Event(SequencedEvent),
+    /// 客户端错过了序号在 [from_id, to_id] 之间的事件且无法补发
+    Gap { from_id: u64, to_id: u64 },
+}
+
+impl StreamItem {
+    /// 是否推送给只关注 `task_id` 的客户端，缺失提示总是推送
+    fn concerns_task(&self, task_id: i32) -> bool {
+        match self {
+            StreamItem::Event(sequenced) => sequenced.event.task_id == task_id,
+            StreamItem::Gap { .. } => true,
+        }
+    }
+}
+
+/// 事件回放缓冲区（环形，保留最近的事件）
+struct ReplayBuffer {
+    events: VecDeque<SequencedEvent>,
+    capacity: usize,
+    next_id: u64,
+}
+
+impl ReplayBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity.min(4096)),
+            capacity: capacity.max(1),
+            next_id: 1,
+        }
+    }
+    
+    /// 为事件编号并写入缓冲区，超出容量时丢弃最旧的事件
+    fn push(&mut self, event: TaskProgressEvent, function_name: Option<String>) -> SequencedEvent {
+        let device_address = event_device_address(&event);
+        let sequenced = SequencedEvent { id: self.next_id, event, function_name, device_address };
+        self.next_id += 1;
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(sequenced.clone());
+        sequenced
+    }
+    
+    /// 序号大于 `last_id` 的事件，以及已被挤出缓冲区而无法补发的范围
+    fn since(&self, last_id: u64) -> (Option<(u64, u64)>, Vec<SequencedEvent>) {
+        // 客户端的序号来自服务器重启之前，无法续传
+        if last_id >= self.next_id {
+            return (None, Vec::new());
+        }
+        
+        let oldest = self.events.front().map_or(self.next_id, |e| e.id);
+        let gap = (last_id + 1 < oldest).then(|| (last_id + 1, oldest - 1));
+        let events = self.events.iter()
+            .filter(|e| e.id > last_id)
+            .cloned()
+            .collect();
+        (gap, events)
+    }
+}
+
+/// 最多缓存的任务Function名称数，超出后清空重建
+const FUNCTION_NAME_CACHE_SIZE: usize = 1024;
+
+/// 事件所属队列任务登记的Function名称
+///
+/// MAA回调事件的task_type是任务链名称，按Function名称过滤时需要查询任务状态；
+/// 每个任务只查询一次，未登记的任务（如系统事件）不缓存。
+fn resolve_function_name(cache: &mut HashMap<i32, String>, task_id: i32) -> Option<String> {
+    if task_id <= 0 {
+        return None;
+    }
+    if let Some(name) = cache.get(&task_id) {
+        return Some(name.clone());
+    }
+    let name = task_status::get_task_status(task_id)?.task_type;
+    if cache.len() >= FUNCTION_NAME_CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(task_id, name.clone());
+    Some(name)
+}
+
+/// 事件所属的设备地址
+///
+/// 事件自带 `device_address`（如设备连接事件）时以事件为准，否则取编号时连接的设备；
+/// 缺失提示不属于任何设备。
+fn event_device_address(event: &TaskProgressEvent) -> Option<String> {
+    if event.event_type == TaskEventType::Gap {
+        return None;
+    }
+    event.data.as_ref()
+        .and_then(|data| data.get("device_address"))
+        .and_then(|address| address.as_str())
+        .map(String::from)
+        .or_else(|| device_health().device_address)
+}
 
 /// SSE事件管理器
 #[derive(Clone)]
 pub struct SseManager {
     /// 任务事件广播器
     task_event_tx: broadcast::Sender<TaskProgressEvent>,
+    /// 编号后的事件广播器，SSE客户端订阅此通道
+    sequenced_tx: broadcast::Sender<SequencedEvent>,
+    /// 事件回放缓冲区
+    replay: Arc<Mutex<ReplayBuffer>>,
 }
 
 impl SseManager {
     /// 创建新的SSE管理器
+    ///
+    /// 同时启动事件编号任务：订阅任务事件广播器，编号后写入回放缓冲区并转发给SSE客户端。
+    /// 需要在tokio运行时中调用。
     pub fn new(task_event_tx: broadcast::Sender<TaskProgressEvent>) -> Self {
-        info!("创建SSE管理器");
-        Self {
+        Self::with_replay_capacity(task_event_tx, CONFIG.performance.sse_replay_buffer_size)
+    }
+    
+    /// 使用指定的回放缓冲区大小创建SSE管理器
+    pub fn with_replay_capacity(task_event_tx: broadcast::Sender<TaskProgressEvent>, replay_capacity: usize) -> Self {
+        info!("创建SSE管理器（回放缓冲区: {}）", replay_capacity);
+        let (sequenced_tx, _) = broadcast::channel(1000);
+        let manager = Self {
             task_event_tx,
+            sequenced_tx,
+            replay: Arc::new(Mutex::new(ReplayBuffer::new(replay_capacity))),
+        };
+        manager.spawn_sequencer();
+        manager
+    }
+    
+    /// 事件编号任务
+    fn spawn_sequencer(&self) -> tokio::task::JoinHandle<()> {
+        let mut event_rx = self.task_event_tx.subscribe();
+        let sequenced_tx = self.sequenced_tx.clone();
+        let replay = self.replay.clone();
+        
+        tokio::spawn(async move {
+            let mut function_names = HashMap::new();
+            loop {
+                let event = match event_rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        // 编号之前就丢失的事件也记录为一条事件，让客户端知道有缺失
+                        warn!("SSE事件编号积压，丢失 {} 条事件", skipped);
+                        TaskProgressEvent {
+                            task_id: 0,
+                            task_type: "system".to_string(),
+                            event_type: TaskEventType::Gap,
+                            message: format!("服务器事件积压，丢失 {} 条事件", skipped),
+                            data: Some(json!({ "missed": skipped })),
+                            timestamp: Utc::now(),
+                        }
+                    },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                
+                let function_name = resolve_function_name(&mut function_names, event.task_id);
+                // 持有缓冲区锁时转发，保证订阅者看到的回放与实时事件不重不漏
+                let mut replay = replay.lock().unwrap();
+                let sequenced = replay.push(event, function_name);
+                let _ = sequenced_tx.send(sequenced);
+            }
+        })
+    }
+    
+    /// 订阅事件：先补发 `last_event_id` 之后的事件，再推送实时事件
+    ///
+    /// 客户端消费过慢导致实时通道积压时，从缓冲区补发；补发不了的部分以 `Gap` 表示。
+    pub(crate) fn subscribe_from(&self, last_event_id: Option<u64>) -> impl Stream<Item = StreamItem> + Send + 'static {
+        let (mut event_rx, (initial_gap, backlog)) = {
+            let replay = self.replay.lock().unwrap();
+            let event_rx = self.sequenced_tx.subscribe();
+            let backlog = last_event_id.map(|id| replay.since(id)).unwrap_or_default();
+            (event_rx, backlog)
+        };
+        let replay = self.replay.clone();
+        
+        async_stream::stream! {
+            let mut last_id = last_event_id.unwrap_or(0);
+            if let Some((from_id, to_id)) = initial_gap {
+                yield StreamItem::Gap { from_id, to_id };
+            }
+            for sequenced in backlog {
+                last_id = sequenced.id;
+                yield StreamItem::Event(sequenced);
+            }
+            
+            loop {
+                match event_rx.recv().await {
+                    Ok(sequenced) => {
+                        if sequenced.id <= last_id {
+                            continue;
+                        }
+                        last_id = sequenced.id;
+                        yield StreamItem::Event(sequenced);
+                    },
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("SSE客户端消费过慢，跳过 {} 条实时事件，尝试从缓冲区补发", skipped);
+                        let (gap, missed) = replay.lock().unwrap().since(last_id);
+                        if let Some((from_id, to_id)) = gap {
+                            yield StreamItem::Gap { from_id, to_id };
+                        }
+                        for sequenced in missed {
+                            last_id = sequenced.id;
+                            yield StreamItem::Event(sequenced);
+                        }
+                    },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
         }
     }
     
     /// 创建任务进度SSE流
-    pub fn create_task_progress_stream(&self) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
-        let mut event_rx = self.task_event_tx.subscribe();
+    ///
+    /// `last_event_id` 为客户端重连时携带的最后事件序号，用于补发错过的事件；
+    /// 只推送符合 `filter` 的事件。
+    pub fn create_task_progress_stream(&self, last_event_id: Option<u64>, filter: EventFilter) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+        let events = self.subscribe_from(last_event_id);
+        let compact = filter.compact;
         
         // 创建组合流：心跳 + 任务事件
         let heartbeat_stream = tokio_stream::wrappers::IntervalStream::new(
@@ -51,35 +280,33 @@ impl SseManager {
                 }).to_string()))
         });
         
-        let task_event_stream = async_stream::stream! {
-            while let Ok(task_event) = event_rx.recv().await {
-                debug!("📨 SSE接收到任务事件: task_id={}, event_type={}, message={}", 
-                      task_event.task_id, task_event.event_type, task_event.message);
-                
-                // 转换为SSE事件
-                let sse_event = Event::default()
-                    .event(&task_event.event_type)
-                    .id(format!("{}-{}", task_event.task_id, task_event.timestamp.timestamp_millis()))
-                    .data(json!({
-                        "task_id": task_event.task_id,
-                        "task_type": task_event.task_type,
-                        "event_type": task_event.event_type,
-                        "message": task_event.message,
-                        "data": task_event.data,
-                        "timestamp": task_event.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
-                    }).to_string());
-                
-                yield Ok(sse_event);
-            }
-        };
+        let task_event_stream = events
+            .filter(move |item| match item {
+                StreamItem::Event(sequenced) => filter.matches(sequenced),
+                StreamItem::Gap { .. } => true,
+            })
+            .map(move |item| {
+                if let StreamItem::Event(sequenced) = &item {
+                    debug!("📨 SSE接收到任务事件: task_id={}, event_type={}, message={}", 
+                          sequenced.event.task_id, sequenced.event.event_type, sequenced.event.message);
+                }
+                Ok(to_sse_event(&item, compact))
+            });
         
         // 合并心跳和任务事件流
         stream::select(heartbeat_stream, task_event_stream)
     }
     
     /// 创建特定任务的SSE流
-    pub fn create_single_task_stream(&self, task_id: i32) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
-        let mut event_rx = self.task_event_tx.subscribe();
+    ///
+    /// 任务进入任意终止状态（成功、失败、取消、超时）后发送 `stream_end` 汇总事件并结束；
+    /// 连接时任务已经结束的，补发错过的事件后直接发送汇总。
+    pub fn create_single_task_stream(&self, task_id: i32, last_event_id: Option<u64>) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+        let events = self.subscribe_from(last_event_id);
+        // 订阅之后再查询，避免错过订阅前后发生的结束事件
+        let already_finished = task_status::get_task_status(task_id)
+            .filter(|task| task.is_finished())
+            .map(|task| task.status);
         
         async_stream::stream! {
             // 发送初始连接事件
@@ -87,61 +314,179 @@ impl SseManager {
                 .event("connected")
                 .data(json!({
                     "task_id": task_id,
+                    "resumed_from": last_event_id,
                     "message": format!("已连接到任务 {} 的进度流", task_id),
                     "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
                 }).to_string());
             yield Ok(init_event);
             
-            // 过滤并发送特定任务的事件
-            while let Ok(task_event) = event_rx.recv().await {
-                if task_event.task_id == task_id {
-                    debug!("发送任务 {} 的进度事件: {}", task_id, task_event.event_type);
-                    
-                    let sse_event = Event::default()
-                        .event(&task_event.event_type)
-                        .id(format!("{}-{}", task_event.task_id, task_event.timestamp.timestamp_millis()))
-                        .data(json!({
-                            "task_id": task_event.task_id,
-                            "task_type": task_event.task_type,
-                            "event_type": task_event.event_type,
-                            "message": task_event.message,
-                            "data": task_event.data,
-                            "timestamp": task_event.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
-                        }).to_string());
-                    
-                    yield Ok(sse_event);
-                    
-                    // 如果任务完成或失败，发送结束事件后结束流
-                    if task_event.event_type == "completed" || task_event.event_type == "failed" {
-                        debug!("任务 {} 结束，关闭SSE流", task_id);
-                        
-                        let end_event = Event::default()
-                            .event("stream_end")
-                            .data(json!({
-                                "task_id": task_id,
-                                "message": "任务已结束，SSE连接将关闭",
-                                "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
-                            }).to_string());
-                        yield Ok(end_event);
-                        
-                        break;
+            tokio::pin!(events);
+            if let Some(status) = already_finished {
+                // 只补发缓冲区中已有的事件，不等待新事件
+                while let Some(Some(item)) = events.next().now_or_never() {
+                    if item.concerns_task(task_id) {
+                        yield Ok(to_sse_event(&item, false));
                     }
                 }
+                debug!("任务 {} 在连接前已结束，发送汇总后关闭SSE流", task_id);
+                yield Ok(stream_end_event(task_id, status));
+                return;
+            }
+            
+            // 过滤并发送特定任务的事件，缺失提示照常发送
+            while let Some(item) = events.next().await {
+                if !item.concerns_task(task_id) {
+                    continue;
+                }
+                let terminal = match &item {
+                    StreamItem::Event(sequenced) => {
+                        debug!("发送任务 {} 的进度事件: {}", task_id, sequenced.event.event_type);
+                        sequenced.event.terminal_status()
+                    },
+                    StreamItem::Gap { .. } => None,
+                };
+                yield Ok(to_sse_event(&item, false));
+                
+                // 任务进入终止状态，发送汇总事件后结束流
+                if let Some(status) = terminal {
+                    debug!("任务 {} 结束（{:?}），关闭SSE流", task_id, status);
+                    yield Ok(stream_end_event(task_id, status));
+                    break;
+                }
             }
         }
     }
     
+    /// 将任务状态存储的状态变更事件转发到SSE流
+    ///
+    /// 状态变更以 `task_status` 事件推送，与MAA回调的进度事件共用同一条SSE流。
+    pub fn spawn_task_status_bridge(&self) -> tokio::task::JoinHandle<()> {
+        let task_event_tx = self.task_event_tx.clone();
+        let mut status_rx = subscribe_task_events();
+        
+        tokio::spawn(async move {
+            loop {
+                match status_rx.recv().await {
+                    Ok(event) => {
+                        let _ = task_event_tx.send(status_event_to_progress(event));
+                    },
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("任务状态事件积压，跳过 {} 条", skipped);
+                    },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+    
     /// 手动发送任务事件（用于测试）
     pub fn send_task_event(&self, event: TaskProgressEvent) -> Result<(), broadcast::error::SendError<TaskProgressEvent>> {
         self.task_event_tx.send(event).map(|_| ())
     }
 }
 
+/// 将状态变更事件转换为SSE任务事件
+fn status_event_to_progress(event: TaskStatusEvent) -> TaskProgressEvent {
+    TaskProgressEvent {
+        task_id: event.task_id,
+        task_type: event.task_type,
+        event_type: TaskEventType::TaskStatus,
+        message: event.message,
+        data: Some(json!({
+            "status": event.status,
+            "previous_status": event.previous_status,
+            "progress": event.progress,
+            "details": event.details,
+        })),
+        timestamp: Utc::now(),
+    }
+}
+
+/// 任务事件的JSON表示，精简模式不包含原始 `data`
+pub(crate) fn event_payload(task_event: &TaskProgressEvent, compact: bool) -> Value {
+    let mut payload = json!({
+        "task_id": task_event.task_id,
+        "task_type": task_event.task_type,
+        "event_type": task_event.event_type,
+        "severity": task_event.severity(),
+        "message": task_event.message,
+        "timestamp": task_event.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    });
+    if !compact {
+        payload["data"] = json!(task_event.data);
+    }
+    payload
+}
+
+/// 缺失提示的JSON表示
+pub(crate) fn gap_payload(from_id: u64, to_id: u64) -> Value {
+    json!({
+        "from_id": from_id,
+        "to_id": to_id,
+        "missed": to_id - from_id + 1,
+        "message": format!("错过了 {} 条事件，请通过 /tasks 查询最新任务状态", to_id - from_id + 1),
+        "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    })
+}
+
+/// 已结束任务的最终汇总
+pub(crate) fn task_summary(task_id: i32, status: TaskStatus) -> Value {
+    task_status::get_task_status(task_id)
+        .map(|task| {
+            let duration_seconds = task.started_at
+                .zip(task.completed_at)
+                .map(|(started, completed)| (completed - started).num_milliseconds() as f64 / 1000.0);
+            let mut summary = TaskWaitOutcome::Finished(task).to_json();
+            summary["duration_seconds"] = json!(duration_seconds);
+            summary
+        })
+        .unwrap_or_else(|| json!({ "task_id": task_id, "status": status, "finished": true }))
+}
+
+/// 将推送条目转换为SSE事件，精简模式不包含原始 `data`
+fn to_sse_event(item: &StreamItem, compact: bool) -> Event {
+    match item {
+        StreamItem::Event(sequenced) => {
+            Event::default()
+                .event(sequenced.event.event_type.as_str())
+                .id(sequenced.id.to_string())
+                .data(event_payload(&sequenced.event, compact).to_string())
+        },
+        StreamItem::Gap { from_id, to_id } => {
+            // 带上缺失范围的最后序号，客户端再次重连时不会重复收到缺失提示
+            Event::default()
+                .event(TaskEventType::Gap.as_str())
+                .id(to_id.to_string())
+                .data(gap_payload(*from_id, *to_id).to_string())
+        },
+    }
+}
+
+/// 单任务流的结束事件，附带任务的最终汇总
+fn stream_end_event(task_id: i32, status: TaskStatus) -> Event {
+    Event::default()
+        .event("stream_end")
+        .data(json!({
+            "task_id": task_id,
+            "status": status,
+            "summary": task_summary(task_id, status),
+            "message": "任务已结束，SSE连接将关闭",
+            "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        }).to_string())
+}
+
+/// 解析客户端重连时携带的 `Last-Event-ID` 请求头
+pub fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers.get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
 /// 创建通用任务进度SSE响应
-pub fn create_task_progress_sse(sse_manager: SseManager) -> Sse<impl Stream<Item = Result<Event, Infallible>> + Send + 'static> {
-    info!("创建任务进度SSE流");
+pub fn create_task_progress_sse(sse_manager: SseManager, last_event_id: Option<u64>, filter: EventFilter) -> Sse<impl Stream<Item = Result<Event, Infallible>> + Send + 'static> {
+    info!("创建任务进度SSE流（续传自: {:?}，过滤: {:?}）", last_event_id, filter);
     
-    Sse::new(sse_manager.create_task_progress_stream())
+    Sse::new(sse_manager.create_task_progress_stream(last_event_id, filter))
         .keep_alive(
             KeepAlive::new()
                 .interval(Duration::from_secs(30))
@@ -152,11 +497,12 @@ pub fn create_task_progress_sse(sse_manager: SseManager) -> Sse<impl Stream<Item
 /// 创建特定任务SSE响应
 pub fn create_single_task_sse(
     sse_manager: SseManager, 
-    task_id: i32
+    task_id: i32,
+    last_event_id: Option<u64>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>> + Send + 'static> {
-    info!("创建任务 {} 的SSE流", task_id);
+    info!("创建任务 {} 的SSE流（续传自: {:?}）", task_id, last_event_id);
     
-    Sse::new(sse_manager.create_single_task_stream(task_id))
+    Sse::new(sse_manager.create_single_task_stream(task_id, last_event_id))
         .keep_alive(
             KeepAlive::new()
                 .interval(Duration::from_secs(15))
@@ -181,7 +527,7 @@ pub mod events {
         TaskProgressEvent {
             task_id,
             task_type: task_type.to_string(),
-            event_type: "started".to_string(),
+            event_type: TaskEventType::Started,
             message: message.to_string(),
             data: Some(json!({"status": "started"})),
             timestamp: Utc::now(),
@@ -198,7 +544,7 @@ pub mod events {
         TaskProgressEvent {
             task_id,
             task_type: task_type.to_string(),
-            event_type: "progress".to_string(),
+            event_type: TaskEventType::Progress,
             message: message.to_string(),
             data: progress_data,
             timestamp: Utc::now(),
@@ -214,7 +560,7 @@ pub mod events {
         TaskProgressEvent {
             task_id,
             task_type: task_type.to_string(),
-            event_type: "completed".to_string(),
+            event_type: TaskEventType::Completed,
             message: "任务执行完成".to_string(),
             data: Some(result_data),
             timestamp: Utc::now(),
@@ -230,7 +576,7 @@ pub mod events {
         TaskProgressEvent {
             task_id,
             task_type: task_type.to_string(),
-            event_type: "failed".to_string(),
+            event_type: TaskEventType::Failed,
             message: format!("任务执行失败: {}", error),
             data: Some(json!({"error": error})),
             timestamp: Utc::now(),
@@ -250,7 +596,7 @@ mod tests {
         let manager = SseManager::new(tx);
         
         // 测试创建流
-        let mut stream = Box::pin(manager.create_task_progress_stream());
+        let mut stream = Box::pin(manager.create_task_progress_stream(None, EventFilter::default()));
         
         // 应该能够接收心跳事件
         if let Ok(Some(event)) = timeout(Duration::from_secs(1), stream.next()).await {
@@ -265,13 +611,13 @@ mod tests {
         let manager = SseManager::new(tx.clone());
         
         // 创建任务1的专用流
-        let mut task1_stream = Box::pin(manager.create_single_task_stream(1));
+        let mut task1_stream = Box::pin(manager.create_single_task_stream(1, None));
         
         // 发送任务1的事件
         let event1 = TaskProgressEvent {
             task_id: 1,
             task_type: "test_task".to_string(),
-            event_type: "started".to_string(),
+            event_type: TaskEventType::Started,
             message: "任务开始".to_string(),
             data: None,
             timestamp: Utc::now(),
@@ -284,4 +630,141 @@ mod tests {
             assert!(received.is_ok());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_replay_buffer_since() {
+        let mut buffer = ReplayBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(TaskProgressEvent::for_test(i, "test_task", TaskEventType::Progress), None);
+        }
+        
+        // 保留序号3-5，序号1-2已被挤出
+        let (gap, events) = buffer.since(0);
+        assert_eq!(gap, Some((1, 2)));
+        assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![3, 4, 5]);
+        
+        let (gap, events) = buffer.since(3);
+        assert_eq!(gap, None);
+        assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4, 5]);
+        
+        // 来自服务器重启前的序号无法续传
+        assert_eq!(buffer.since(42).1.len(), 0);
+    }
+
+    #[test]
+    fn test_resolve_function_name() {
+        use crate::maa_core::task_status::register_task;
+
+        let mut cache = HashMap::new();
+        register_task(7301, "maa_combat_enhanced".to_string(), json!({}));
+        assert_eq!(resolve_function_name(&mut cache, 7301).as_deref(), Some("maa_combat_enhanced"));
+        assert_eq!(cache.get(&7301).map(String::as_str), Some("maa_combat_enhanced"));
+        // 系统事件和未登记的任务不缓存
+        assert_eq!(resolve_function_name(&mut cache, 0), None);
+        assert_eq!(resolve_function_name(&mut cache, 7302), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_event_device_address() {
+        let mut replay = ReplayBuffer::new(4);
+        let connected = TaskProgressEvent::for_test(0, "system", TaskEventType::DeviceConnection)
+            .with_data(json!({"state": "connected", "device_address": "10.0.0.9:5555"}));
+        assert_eq!(replay.push(connected, None).device_address.as_deref(), Some("10.0.0.9:5555"));
+        let gap = TaskProgressEvent::for_test(0, "system", TaskEventType::Gap);
+        assert_eq!(replay.push(gap, None).device_address, None);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_last_event_id() {
+        let (tx, _rx) = broadcast::channel(100);
+        let manager = SseManager::with_replay_capacity(tx.clone(), 10);
+        
+        for event_type in [TaskEventType::Started, TaskEventType::Progress, TaskEventType::Completed] {
+            let _ = tx.send(TaskProgressEvent::for_test(7001, "test_task", event_type));
+        }
+        timeout(Duration::from_secs(1), async {
+            while manager.replay.lock().unwrap().next_id < 4 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }).await.unwrap();
+        
+        let mut events = Box::pin(manager.subscribe_from(Some(1)));
+        let ids: Vec<u64> = vec![
+            events.next().await.unwrap(),
+            events.next().await.unwrap(),
+        ].into_iter().map(|item| match item {
+            StreamItem::Event(sequenced) => sequenced.id,
+            StreamItem::Gap { .. } => panic!("缓冲区内的事件不应出现缺失"),
+        }).collect();
+        assert_eq!(ids, vec![2, 3]);
+        
+        // 实时事件接在补发的事件之后
+        let _ = tx.send(TaskProgressEvent::for_test(7001, "test_task", TaskEventType::Progress));
+        match timeout(Duration::from_secs(1), events.next()).await.unwrap().unwrap() {
+            StreamItem::Event(sequenced) => assert_eq!(sequenced.id, 4),
+            StreamItem::Gap { .. } => panic!("不应出现缺失"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_single_task_stream_ends_on_cancel() {
+        use crate::maa_core::task_status::{register_task, start_task, cancel_task};
+        
+        let (tx, _rx) = broadcast::channel(100);
+        let manager = SseManager::with_replay_capacity(tx, 10);
+        let _bridge = manager.spawn_task_status_bridge();
+        
+        register_task(7101, "maa_combat_enhanced".to_string(), json!({}));
+        start_task(7101);
+        let stream = manager.create_single_task_stream(7101, None);
+        cancel_task(7101, "用户取消".to_string());
+        
+        // 取消后流应发送汇总并结束
+        let events: Vec<_> = timeout(Duration::from_secs(1), stream.collect::<Vec<_>>()).await.unwrap();
+        let last = format!("{:?}", events.last().unwrap().as_ref().unwrap());
+        assert!(last.contains("stream_end"));
+        assert!(last.contains("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_single_task_stream_for_finished_task() {
+        use crate::maa_core::task_status::{register_task, start_task, complete_task};
+        
+        let (tx, _rx) = broadcast::channel(100);
+        let manager = SseManager::with_replay_capacity(tx, 10);
+        
+        register_task(7102, "maa_rewards_enhanced".to_string(), json!({}));
+        start_task(7102);
+        complete_task(7102, json!({"ok": true}));
+        
+        // 连接时任务已结束：连接事件 + 汇总事件
+        let events: Vec<_> = timeout(Duration::from_secs(1), manager.create_single_task_stream(7102, None).collect::<Vec<_>>()).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(format!("{:?}", events[1].as_ref().unwrap()).contains("stream_end"));
+    }
+
+    #[tokio::test]
+    async fn test_task_status_bridge() {
+        use crate::maa_core::task_status::{register_task, start_task};
+        
+        let (tx, mut rx) = broadcast::channel(100);
+        let manager = SseManager::new(tx);
+        let _bridge = manager.spawn_task_status_bridge();
+        
+        register_task(6001, "test_task".to_string(), json!({}));
+        start_task(6001);
+        
+        let event = timeout(Duration::from_secs(1), async {
+            loop {
+                let event = rx.recv().await.unwrap();
+                if event.task_id == 6001 && event.data.as_ref().unwrap()["status"] == "running" {
+                    break event;
+                }
+            }
+        }).await.unwrap();
+        assert_eq!(event.event_type, TaskEventType::TaskStatus);
+        assert_eq!(event.terminal_status(), None);
+        assert_eq!(event.data.unwrap()["previous_status"], "queued");
+    }
+}
@@ -0,0 +1,568 @@
+//! WebSocket JSON-RPC 2.0 会话
+//!
+//! `/ws` 在同一条连接上接收工具调用、聊天和事件订阅请求，并推送任务事件和工具结果。
+//! 本模块与传输无关：服务器把收到的文本帧交给 [`WsSession::handle_text`]，
+//! 把 `outgoing` 通道中的消息写回连接。`outgoing` 是容量为 [`OUTGOING_BUFFER_SIZE`] 的有界通道：
+//! 响应在通道满时等待，任务事件在通道满时丢弃，通道有空位后先推送一条 `task.gap`。
+//!
+//! 支持的方法：
+//! - `tools.list`：工具定义列表
+//! - `tools.call`：执行工具，参数 `{name, arguments, wait, wait_timeout}`
+//! - `chat`：智能对话，参数与 `/chat` 相同
+//! - `subscribe` / `unsubscribe`：订阅任务事件，过滤参数与 `/sse/tasks` 相同
+//!
+//! 服务器推送的通知：
+//! - `task.event`：任务事件，带 `subscription`（订阅）或 `request_id`（异步工具调用的请求id）
+//! - `task.gap`：错过的事件范围
+//! - `tools.result`：异步工具调用结束，带 `request_id` 和任务汇总
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+
+use crate::function_tools::{EnhancedMaaFunctionHandlerV2, FunctionCall};
+use crate::maa_core::task_classification_v2::is_synchronous_task;
+use crate::maa_core::task_status::{self, TaskStatus};
+use crate::sse::{self, EventFilter, SseFilterQuery, SseManager, StreamItem};
+
+/// JSON-RPC协议版本
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// 每个连接出站通道的容量（条）
+pub const OUTGOING_BUFFER_SIZE: usize = 256;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// 工具执行失败（服务器自定义错误码）
+pub const TOOL_ERROR: i64 = -32000;
+/// 调用方权限不足（服务器自定义错误码）
+pub const FORBIDDEN: i64 = -32001;
+
+/// JSON-RPC错误对象
+#[derive(Debug, Clone, PartialEq, Serialize, Error)]
+#[error("{message}")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self::new(PARSE_ERROR, message)
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(INVALID_REQUEST, message)
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("未知的方法: {}", method))
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(INTERNAL_ERROR, message)
+    }
+}
+
+/// JSON-RPC请求，没有 `id` 的是通知，不返回响应
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// `tools.call` 参数
+#[derive(Debug, Deserialize)]
+struct ToolCallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+    /// 是否等待异步任务结束后再返回
+    #[serde(default)]
+    wait: bool,
+    /// 等待超时（秒）
+    wait_timeout: Option<u64>,
+    /// 只做检查并返回将要提交的MAA任务和参数，不入队
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// `unsubscribe` 参数
+#[derive(Debug, Deserialize)]
+struct UnsubscribeParams {
+    subscription: u64,
+}
+
+/// 聊天后端，由服务器实现（AI客户端在服务器中组装）
+#[async_trait]
+pub trait RpcChat: Send + Sync {
+    /// 处理 `chat` 请求，参数与 `/chat` 的请求体相同
+    async fn chat(&self, params: Value) -> Result<Value, RpcError>;
+}
+
+/// 构造成功响应
+pub fn rpc_result(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": JSONRPC_VERSION, "id": id, "result": result })
+}
+
+/// 构造错误响应
+pub fn rpc_error(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": JSONRPC_VERSION, "id": id, "error": error })
+}
+
+/// 构造服务器通知
+pub fn rpc_notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": JSONRPC_VERSION, "method": method, "params": params })
+}
+
+/// 会话的订阅状态，由事件转发任务和请求处理共享
+#[derive(Default)]
+struct SessionState {
+    next_subscription: u64,
+    /// 订阅id → 过滤条件
+    subscriptions: BTreeMap<u64, EventFilter>,
+    /// 异步工具调用：任务ID → 请求id
+    tracked: HashMap<i32, Value>,
+}
+
+impl SessionState {
+    /// 把一条事件转换为需要推送的通知
+    fn route(&mut self, item: &StreamItem) -> Vec<Value> {
+        let sequenced = match item {
+            StreamItem::Event(sequenced) => sequenced,
+            StreamItem::Gap { from_id, to_id } => {
+                if self.subscriptions.is_empty() && self.tracked.is_empty() {
+                    return Vec::new();
+                }
+                return vec![rpc_notification("task.gap", sse::gap_payload(*from_id, *to_id))];
+            },
+        };
+
+        let mut notifications: Vec<Value> = self.subscriptions.iter()
+            .filter(|(_, filter)| filter.matches(sequenced))
+            .map(|(subscription, filter)| rpc_notification("task.event", json!({
+                "subscription": subscription,
+                "event_id": sequenced.id,
+                "event": sse::event_payload(&sequenced.event, filter.compact),
+            })))
+            .collect();
+
+        let task_id = sequenced.event.task_id;
+        if let Some(request_id) = self.tracked.get(&task_id) {
+            notifications.push(rpc_notification("task.event", json!({
+                "request_id": request_id,
+                "event_id": sequenced.id,
+                "event": sse::event_payload(&sequenced.event, false),
+            })));
+            if let Some(status) = sequenced.event.terminal_status() {
+                if let Some(request_id) = self.tracked.remove(&task_id) {
+                    notifications.push(tool_result_notification(request_id, task_id, status));
+                }
+            }
+        }
+        notifications
+    }
+}
+
+/// 条目覆盖的事件序号范围
+fn item_range(item: &StreamItem) -> (u64, u64) {
+    match item {
+        StreamItem::Event(sequenced) => (sequenced.id, sequenced.id),
+        StreamItem::Gap { from_id, to_id } => (*from_id, *to_id),
+    }
+}
+
+/// 把一条事件的通知写入出站通道，连接已关闭时返回false
+///
+/// 通道满时丢弃该事件余下的通知并把事件序号计入 `dropped`；
+/// 之后有空位时先推送 `dropped` 范围的 `task.gap`，再推送新的通知。
+fn forward_notifications(
+    outgoing: &mpsc::Sender<Value>,
+    dropped: &mut Option<(u64, u64)>,
+    (from_id, to_id): (u64, u64),
+    notifications: Vec<Value>,
+) -> bool {
+    if notifications.is_empty() {
+        return true;
+    }
+    if let Some((gap_from, gap_to)) = *dropped {
+        match outgoing.try_send(rpc_notification("task.gap", sse::gap_payload(gap_from, gap_to))) {
+            Ok(()) => *dropped = None,
+            Err(TrySendError::Full(_)) => {
+                *dropped = Some((gap_from, to_id.max(gap_to)));
+                return true;
+            },
+            Err(TrySendError::Closed(_)) => return false,
+        }
+    }
+    for notification in notifications {
+        match outgoing.try_send(notification) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => {
+                warn!("WebSocket出站通道已满，丢弃事件 {}-{}", from_id, to_id);
+                *dropped = Some((from_id, to_id));
+                break;
+            },
+            Err(TrySendError::Closed(_)) => return false,
+        }
+    }
+    true
+}
+
+/// 异步工具调用结束的通知
+fn tool_result_notification(request_id: Value, task_id: i32, status: TaskStatus) -> Value {
+    rpc_notification("tools.result", json!({
+        "request_id": request_id,
+        "task_id": task_id,
+        "status": status,
+        "summary": sse::task_summary(task_id, status),
+    }))
+}
+
+/// 单个WebSocket连接的JSON-RPC会话
+///
+/// 创建时开始转发任务事件，会话销毁时停止。
+pub struct WsSession {
+    handler: EnhancedMaaFunctionHandlerV2,
+    chat: Option<Arc<dyn RpcChat>>,
+    state: Arc<Mutex<SessionState>>,
+    outgoing: mpsc::Sender<Value>,
+    event_pump: tokio::task::JoinHandle<()>,
+}
+
+impl WsSession {
+    /// 创建会话，响应和通知写入 `outgoing`；需要在tokio运行时中调用
+    pub fn new(handler: EnhancedMaaFunctionHandlerV2, sse_manager: &SseManager, outgoing: mpsc::Sender<Value>) -> Self {
+        let state = Arc::new(Mutex::new(SessionState::default()));
+        let event_pump = Self::spawn_event_pump(sse_manager, state.clone(), outgoing.clone());
+        Self { handler, chat: None, state, outgoing, event_pump }
+    }
+
+    /// 启用 `chat` 方法
+    pub fn with_chat(mut self, chat: Arc<dyn RpcChat>) -> Self {
+        self.chat = Some(chat);
+        self
+    }
+
+    /// 事件转发任务：按订阅和异步工具调用推送任务事件，客户端读取过慢时丢弃事件并推送缺失提示
+    fn spawn_event_pump(
+        sse_manager: &SseManager,
+        state: Arc<Mutex<SessionState>>,
+        outgoing: mpsc::Sender<Value>,
+    ) -> tokio::task::JoinHandle<()> {
+        let events = sse_manager.subscribe_from(None);
+        tokio::spawn(async move {
+            tokio::pin!(events);
+            let mut dropped = None;
+            while let Some(item) = events.next().await {
+                let notifications = state.lock().unwrap().route(&item);
+                if !forward_notifications(&outgoing, &mut dropped, item_range(&item), notifications) {
+                    return;
+                }
+            }
+        })
+    }
+
+    /// 处理一条文本消息，响应写入 `outgoing`
+    pub async fn handle_text(&self, text: &str) {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => {
+                self.send(rpc_error(Value::Null, RpcError::parse_error(format!("无效的JSON: {}", e)))).await;
+                return;
+            },
+        };
+        if value.is_array() {
+            self.send(rpc_error(Value::Null, RpcError::invalid_request("不支持批量请求"))).await;
+            return;
+        }
+
+        let fallback_id = value.get("id").cloned().unwrap_or(Value::Null);
+        let request = match serde_json::from_value::<RpcRequest>(value) {
+            Ok(request) if request.jsonrpc == JSONRPC_VERSION => request,
+            Ok(_) => {
+                self.send(rpc_error(fallback_id, RpcError::invalid_request("jsonrpc 必须为 \"2.0\""))).await;
+                return;
+            },
+            Err(e) => {
+                self.send(rpc_error(fallback_id, RpcError::invalid_request(format!("无效的请求: {}", e)))).await;
+                return;
+            },
+        };
+
+        debug!("WebSocket请求: {} (id: {:?})", request.method, request.id);
+        let result = self.dispatch(&request).await;
+        // 通知不返回响应
+        let Some(id) = request.id.clone() else { return };
+        match result {
+            Ok(result) => {
+                let task_id = tracked_task_id(&request, &result);
+                self.send(rpc_result(id.clone(), result)).await;
+                // 先发送响应再跟踪任务，客户端总是先收到任务ID再收到结果
+                if let Some(task_id) = task_id {
+                    self.track_task(task_id, id).await;
+                }
+            },
+            Err(error) => self.send(rpc_error(id, error)).await,
+        }
+    }
+
+    async fn dispatch(&self, request: &RpcRequest) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "tools.list" => Ok(json!({ "tools": self.handler.get_function_definitions() })),
+            "tools.call" => self.call_tool(parse_params(&request.params)?).await,
+            "chat" => match &self.chat {
+                Some(chat) => chat.chat(request.params.clone()).await,
+                None => Err(RpcError::internal("当前连接未启用聊天")),
+            },
+            "subscribe" => {
+                let query: SseFilterQuery = parse_params(&request.params)?;
+                let filter = EventFilter::from_query(&query)
+                    .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+                let mut state = self.state.lock().unwrap();
+                state.next_subscription += 1;
+                let subscription = state.next_subscription;
+                state.subscriptions.insert(subscription, filter);
+                Ok(json!({ "subscription": subscription }))
+            },
+            "unsubscribe" => {
+                let params: UnsubscribeParams = parse_params(&request.params)?;
+                let removed = self.state.lock().unwrap().subscriptions.remove(&params.subscription).is_some();
+                Ok(json!({ "unsubscribed": removed }))
+            },
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    /// 执行工具调用，失败时返回 `TOOL_ERROR`，`data` 中带有错误码和结果（如 `retry_after_secs`）
+    async fn call_tool(&self, params: ToolCallParams) -> Result<Value, RpcError> {
+        let is_sync = is_synchronous_task(&params.name);
+        let waited = params.wait && !is_sync && !params.dry_run;
+        let function_call = FunctionCall { name: params.name, arguments: params.arguments };
+        let response = if params.dry_run {
+            self.handler.dry_run(function_call).await
+        } else if waited {
+            self.handler.execute_function_and_wait(function_call, params.wait_timeout).await
+        } else {
+            self.handler.execute_function(function_call).await
+        };
+
+        if !response.success {
+            let (message, error_code) = response.error
+                .map(|e| (e.message, e.error_code))
+                .unwrap_or_else(|| ("Unknown error".to_string(), None));
+            return Err(RpcError::new(TOOL_ERROR, message).with_data(json!({
+                "error_code": error_code,
+                "result": response.result,
+            })));
+        }
+        Ok(json!({
+            "result": response.result.unwrap_or(json!({})),
+            "execution_mode": if is_sync { "synchronous" } else { "asynchronous" },
+            "waited": waited,
+            "timestamp": response.timestamp,
+        }))
+    }
+
+    /// 跟踪异步工具调用的任务，任务事件和最终结果带上请求id推送
+    async fn track_task(&self, task_id: i32, request_id: Value) {
+        let finished_request = {
+            let mut state = self.state.lock().unwrap();
+            state.tracked.insert(task_id, request_id);
+            // 跟踪之前任务可能已经结束，直接推送结果
+            task_status::get_task_status(task_id)
+                .filter(|task| task.is_finished())
+                .and_then(|task| state.tracked.remove(&task_id).map(|request_id| (request_id, task.status)))
+        };
+        if let Some((request_id, status)) = finished_request {
+            self.send(tool_result_notification(request_id, task_id, status)).await;
+        }
+    }
+
+    /// 发送响应，出站通道满时等待
+    async fn send(&self, message: Value) {
+        if self.outgoing.send(message).await.is_err() {
+            warn!("WebSocket连接已关闭，丢弃消息");
+        }
+    }
+}
+
+impl Drop for WsSession {
+    fn drop(&mut self) {
+        self.event_pump.abort();
+    }
+}
+
+/// 解析方法参数，缺省参数按空对象处理
+fn parse_params<T: serde::de::DeserializeOwned>(params: &Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params.clone() };
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("参数错误: {}", e)))
+}
+
+/// 需要跟踪的异步任务ID：未等待结束的异步 `tools.call`
+fn tracked_task_id(request: &RpcRequest, result: &Value) -> Option<i32> {
+    if request.method != "tools.call" || result["execution_mode"] != "asynchronous" || result["waited"] == true {
+        return None;
+    }
+    result["result"]["task_id"].as_i64().map(|id| id as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_tools::create_enhanced_function_handler_v2;
+    use crate::maa_core::task_queue_v2::create_maa_task_channel_with_capacity;
+    use crate::maa_core::worker_v2::{TaskEventType, TaskProgressEvent};
+    use crate::sse::SequencedEvent;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    fn event(task_id: i32, event_type: TaskEventType, data: Value) -> TaskProgressEvent {
+        TaskProgressEvent::for_test(task_id, "maa_combat_enhanced", event_type).with_data(data)
+    }
+
+    async fn next(rx: &mut mpsc::Receiver<Value>) -> Value {
+        tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(8);
+        let (event_tx, _) = broadcast::channel(16);
+        let sse_manager = SseManager::with_replay_capacity(event_tx, 16);
+        let (tx, mut rx) = mpsc::channel(OUTGOING_BUFFER_SIZE);
+        let session = WsSession::new(create_enhanced_function_handler_v2(sender), &sse_manager, tx);
+
+        session.handle_text("{not json").await;
+        assert_eq!(next(&mut rx).await["error"]["code"], PARSE_ERROR);
+        session.handle_text(r#"{"jsonrpc":"1.0","id":1,"method":"tools.list"}"#).await;
+        assert_eq!(next(&mut rx).await["error"]["code"], INVALID_REQUEST);
+        session.handle_text(r#"{"jsonrpc":"2.0","id":2,"method":"nope"}"#).await;
+        let response = next(&mut rx).await;
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        session.handle_text(r#"{"jsonrpc":"2.0","id":"a","method":"subscribe","params":{"event_type":"nope"}}"#).await;
+        assert_eq!(next(&mut rx).await["error"]["code"], INVALID_PARAMS);
+        session.handle_text(r#"{"jsonrpc":"2.0","id":3,"method":"chat","params":{"messages":[]}}"#).await;
+        assert_eq!(next(&mut rx).await["error"]["code"], INTERNAL_ERROR);
+
+        session.handle_text(r#"{"jsonrpc":"2.0","id":4,"method":"tools.list"}"#).await;
+        let response = next(&mut rx).await;
+        assert_eq!(response["jsonrpc"], JSONRPC_VERSION);
+        assert!(!response["result"]["tools"].as_array().unwrap().is_empty());
+        // 通知不返回响应
+        session.handle_text(r#"{"jsonrpc":"2.0","method":"tools.list"}"#).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_events() {
+        let (sender, _receiver) = create_maa_task_channel_with_capacity(8);
+        let (event_tx, _) = broadcast::channel(16);
+        let sse_manager = SseManager::with_replay_capacity(event_tx, 16);
+        let (tx, mut rx) = mpsc::channel(OUTGOING_BUFFER_SIZE);
+        let session = WsSession::new(create_enhanced_function_handler_v2(sender), &sse_manager, tx);
+
+        session.handle_text(r#"{"jsonrpc":"2.0","id":1,"method":"subscribe","params":{"task_ids":"7201","compact":true}}"#).await;
+        let subscription = next(&mut rx).await["result"]["subscription"].as_u64().unwrap();
+
+        sse_manager.send_task_event(event(7202, TaskEventType::Progress, json!({}))).unwrap();
+        sse_manager.send_task_event(event(7201, TaskEventType::Progress, json!({"progress": 50}))).unwrap();
+        let notification = next(&mut rx).await;
+        assert_eq!(notification["method"], "task.event");
+        assert_eq!(notification["params"]["subscription"], subscription);
+        assert_eq!(notification["params"]["event"]["task_id"], 7201);
+        assert!(notification["params"]["event"].get("data").is_none());
+
+        session.handle_text(&format!(r#"{{"jsonrpc":"2.0","id":2,"method":"unsubscribe","params":{{"subscription":{}}}}}"#, subscription)).await;
+        assert_eq!(next(&mut rx).await["result"]["unsubscribed"], true);
+        sse_manager.send_task_event(event(7201, TaskEventType::Progress, json!({}))).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_tracked_task_result() {
+        let mut state = SessionState::default();
+        state.tracked.insert(7203, json!("call-1"));
+
+        let progress = StreamItem::Event(SequencedEvent {
+            id: 1,
+            event: event(7203, TaskEventType::Progress, json!({})),
+            function_name: None,
+            device_address: None,
+        });
+        let notifications = state.route(&progress);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0]["params"]["request_id"], "call-1");
+
+        let finished = StreamItem::Event(SequencedEvent {
+            id: 2,
+            event: event(7203, TaskEventType::TaskStatus, json!({"status": "cancelled"})),
+            function_name: None,
+            device_address: None,
+        });
+        let notifications = state.route(&finished);
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[1]["method"], "tools.result");
+        assert_eq!(notifications[1]["params"]["request_id"], "call-1");
+        assert_eq!(notifications[1]["params"]["status"], "cancelled");
+        assert!(state.tracked.is_empty());
+        // 没有订阅时不推送缺失提示
+        assert!(state.route(&StreamItem::Gap { from_id: 3, to_id: 4 }).is_empty());
+    }
+
+    #[test]
+    fn test_overflow_sends_gap() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut dropped = None;
+        let notification = |id: u64| vec![rpc_notification("task.event", json!({ "event_id": id }))];
+
+        assert!(forward_notifications(&tx, &mut dropped, (1, 1), notification(1)));
+        // 通道已满，事件2、3被丢弃
+        assert!(forward_notifications(&tx, &mut dropped, (2, 2), notification(2)));
+        assert!(forward_notifications(&tx, &mut dropped, (3, 3), notification(3)));
+        assert_eq!(dropped, Some((2, 3)));
+        assert_eq!(rx.try_recv().unwrap()["params"]["event_id"], 1);
+
+        // 有空位后先推送缺失提示，新的事件因通道再次占满而计入下一段缺失
+        assert!(forward_notifications(&tx, &mut dropped, (4, 4), notification(4)));
+        let gap = rx.try_recv().unwrap();
+        assert_eq!(gap["method"], "task.gap");
+        assert_eq!(gap["params"]["from_id"], 2);
+        assert_eq!(gap["params"]["to_id"], 3);
+        assert_eq!(dropped, Some((4, 4)));
+
+        drop(rx);
+        assert!(!forward_notifications(&tx, &mut dropped, (5, 5), notification(5)));
+    }
+}